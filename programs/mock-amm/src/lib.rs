@@ -0,0 +1,211 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+
+declare_id!("53ej9RmgufoFcGqQFQD84Hqfc6hNZGuk44USPTqKEpvu");
+
+/// Swap fee charged on the input amount (0.3% = 30 basis points)
+pub const FEE_BPS: u64 = 30;
+
+/// Basis points (10000 = 100%)
+pub const BPS_SCALE: u64 = 10000;
+
+/// Minimal constant-product AMM for tests
+///
+/// Its `swap` instruction uses the account layout the lending program's swap
+/// adapter expects: `[pool, authority, source, destination, pool_source_vault,
+/// pool_destination_vault, token_program]`.
+#[program]
+pub mod mock_amm {
+    use super::*;
+
+    /// Create a pool for a token pair
+    pub fn initialize_pool(ctx: Context<InitializePool>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.mint_a = ctx.accounts.mint_a.key();
+        pool.mint_b = ctx.accounts.mint_b.key();
+        pool.vault_a = ctx.accounts.vault_a.key();
+        pool.vault_b = ctx.accounts.vault_b.key();
+        pool.bump = ctx.bumps.pool;
+        Ok(())
+    }
+
+    /// Add liquidity to both sides of the pool (no LP tokens)
+    pub fn add_liquidity(ctx: Context<AddLiquidity>, amount_a: u64, amount_b: u64) -> Result<()> {
+        for (from, to, amount) in [
+            (&ctx.accounts.provider_a, &ctx.accounts.vault_a, amount_a),
+            (&ctx.accounts.provider_b, &ctx.accounts.vault_b, amount_b),
+        ] {
+            let transfer_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: from.to_account_info(),
+                    to: to.to_account_info(),
+                    authority: ctx.accounts.provider.to_account_info(),
+                },
+            );
+            token::transfer(transfer_ctx, amount)?;
+        }
+        Ok(())
+    }
+
+    /// Swap `amount_in` of one side of the pool for the other
+    pub fn swap(ctx: Context<Swap>, amount_in: u64, min_amount_out: u64) -> Result<()> {
+        require!(amount_in > 0, MockAmmError::ZeroAmount);
+
+        let pool = &ctx.accounts.pool;
+        let source_vault = &ctx.accounts.pool_source_vault;
+        let destination_vault = &ctx.accounts.pool_destination_vault;
+
+        let amount_in_after_fee = amount_in as u128 * (BPS_SCALE - FEE_BPS) as u128 / BPS_SCALE as u128;
+        let amount_out = (destination_vault.amount as u128 * amount_in_after_fee
+            / (source_vault.amount as u128 + amount_in_after_fee)) as u64;
+        require!(amount_out >= min_amount_out, MockAmmError::SlippageExceeded);
+
+        let transfer_in_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.source.to_account_info(),
+                to: source_vault.to_account_info(),
+                authority: ctx.accounts.authority.to_account_info(),
+            },
+        );
+        token::transfer(transfer_in_ctx, amount_in)?;
+
+        let seeds = &[
+            b"pool",
+            pool.mint_a.as_ref(),
+            pool.mint_b.as_ref(),
+            &[pool.bump],
+        ];
+        let signer = &[&seeds[..]];
+        let transfer_out_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: destination_vault.to_account_info(),
+                to: ctx.accounts.destination.to_account_info(),
+                authority: pool.to_account_info(),
+            },
+            signer,
+        );
+        token::transfer(transfer_out_ctx, amount_out)?;
+
+        Ok(())
+    }
+}
+
+#[account]
+pub struct Pool {
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    pub vault_a: Pubkey,
+    pub vault_b: Pubkey,
+    pub bump: u8,
+}
+
+impl Pool {
+    pub const SIZE: usize = 8 + 32 + 32 + 32 + 32 + 1;
+
+    /// Whether the two vaults are this pool's, in either order
+    fn has_vaults(&self, source_vault: &Pubkey, destination_vault: &Pubkey) -> bool {
+        (*source_vault == self.vault_a && *destination_vault == self.vault_b)
+            || (*source_vault == self.vault_b && *destination_vault == self.vault_a)
+    }
+}
+
+#[derive(Accounts)]
+pub struct InitializePool<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub mint_a: Account<'info, Mint>,
+    pub mint_b: Account<'info, Mint>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = Pool::SIZE,
+        seeds = [b"pool", mint_a.key().as_ref(), mint_b.key().as_ref()],
+        bump
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"vault", pool.key().as_ref(), mint_a.key().as_ref()],
+        bump,
+        token::mint = mint_a,
+        token::authority = pool
+    )]
+    pub vault_a: Account<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"vault", pool.key().as_ref(), mint_b.key().as_ref()],
+        bump,
+        token::mint = mint_b,
+        token::authority = pool
+    )]
+    pub vault_b: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AddLiquidity<'info> {
+    pub provider: Signer<'info>,
+
+    #[account(has_one = vault_a, has_one = vault_b)]
+    pub pool: Account<'info, Pool>,
+
+    #[account(mut)]
+    pub vault_a: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub vault_b: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub provider_a: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub provider_b: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct Swap<'info> {
+    #[account(
+        mut,
+        constraint = pool.has_vaults(&pool_source_vault.key(), &pool_destination_vault.key()) @ MockAmmError::InvalidVault
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+
+    #[account(mut, constraint = source.mint == pool_source_vault.mint @ MockAmmError::InvalidVault)]
+    pub source: Account<'info, TokenAccount>,
+
+    #[account(mut, constraint = destination.mint == pool_destination_vault.mint @ MockAmmError::InvalidVault)]
+    pub destination: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub pool_source_vault: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub pool_destination_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[error_code]
+pub enum MockAmmError {
+    #[msg("Vault does not belong to the pool")]
+    InvalidVault,
+    #[msg("Swap output is below the minimum")]
+    SlippageExceeded,
+    #[msg("Amount must be greater than zero")]
+    ZeroAmount,
+}
@@ -0,0 +1,52 @@
+use anchor_lang::prelude::*;
+
+declare_id!("D8WYz1eJuEBsq4LsjfmeC5cbJbno6Je1p54nSJmVCeF8");
+
+/// Size of a Pyth v2 price account, up to and including the aggregate price
+pub const PRICE_ACCOUNT_LEN: usize = 240;
+
+/// Pyth price account magic number
+const PYTH_MAGIC: u32 = 0xa1b2c3d4;
+
+/// Pyth account type for price accounts
+const PYTH_PRICE_ACCOUNT_TYPE: u32 = 3;
+
+/// Pyth aggregate price status: trading
+const PYTH_STATUS_TRADING: u32 = 1;
+
+/// Settable price feed for tests
+///
+/// Writes the Pyth v2 layout the lending program's oracle reader expects to
+/// accounts created with this program as owner.
+#[program]
+pub mod mock_oracle {
+    use super::*;
+
+    /// Publish `price * 10^expo` to a price account, timestamped now
+    pub fn set_price(ctx: Context<SetPrice>, price: i64, expo: i32) -> Result<()> {
+        let mut data = ctx.accounts.price_account.try_borrow_mut_data()?;
+        require!(data.len() >= PRICE_ACCOUNT_LEN, MockOracleError::InvalidPriceAccount);
+
+        let publish_time = Clock::get()?.unix_timestamp;
+        data[0..4].copy_from_slice(&PYTH_MAGIC.to_le_bytes());
+        data[8..12].copy_from_slice(&PYTH_PRICE_ACCOUNT_TYPE.to_le_bytes());
+        data[20..24].copy_from_slice(&expo.to_le_bytes());
+        data[96..104].copy_from_slice(&publish_time.to_le_bytes());
+        data[208..216].copy_from_slice(&price.to_le_bytes());
+        data[224..228].copy_from_slice(&PYTH_STATUS_TRADING.to_le_bytes());
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct SetPrice<'info> {
+    /// CHECK: Raw Pyth-layout price account, created by the caller and owned by this program
+    #[account(mut, owner = crate::ID)]
+    pub price_account: UncheckedAccount<'info>,
+}
+
+#[error_code]
+pub enum MockOracleError {
+    #[msg("Price account is too small")]
+    InvalidPriceAccount,
+}
@@ -0,0 +1,280 @@
+use anchor_lang::prelude::*;
+use crate::constants::*;
+use crate::errors::LendingError;
+use crate::math::*;
+
+/// What the allocation engine knows about a vault's position in one market
+///
+/// Plain data so target allocations can be computed (and tested) off-chain.
+#[derive(Clone, Copy, Default)]
+pub struct MarketConditions {
+    /// Configured target share of the vault's assets in basis points
+    pub target_bps: u16,
+    /// Maximum assets the vault may supply to the market
+    pub cap: u64,
+    /// Assets the vault's supply tokens are currently worth
+    pub current_amount: u64,
+    /// Assets the market could pay out right now
+    pub available_liquidity: u64,
+    /// Market total supplied, including the vault's position
+    pub total_supplied: u64,
+    /// Market total borrowed
+    pub total_borrowed: u64,
+}
+
+impl MarketConditions {
+    /// Current utilization in basis points
+    pub fn utilization_bps(&self) -> Result<u16> {
+        self.utilization_bps_at(self.current_amount)
+    }
+
+    /// Current supply rate per second
+    pub fn supply_rate(&self) -> Result<u64> {
+        self.supply_rate_at(self.current_amount)
+    }
+
+    /// Smallest position the vault can reach, limited by available liquidity
+    pub fn floor(&self) -> u64 {
+        self.current_amount.saturating_sub(self.available_liquidity)
+    }
+
+    /// Utilization if the vault's position were `amount`
+    fn utilization_bps_at(&self, amount: u64) -> Result<u16> {
+        let supplied = (self.total_supplied.saturating_sub(self.current_amount) as u128)
+            .checked_add(amount as u128)
+            .ok_or(LendingError::MathOverflow)?;
+        if supplied <= self.total_borrowed as u128 {
+            return Ok(if supplied == 0 { 0 } else { BPS_SCALE });
+        }
+
+        Ok((self.total_borrowed as u128 * BPS_SCALE as u128 / supplied) as u16)
+    }
+
+    /// Supply rate per second if the vault's position were `amount`
+    fn supply_rate_at(&self, amount: u64) -> Result<u64> {
+        let utilization_bps = self.utilization_bps_at(amount)?;
+        calculate_supply_rate(calculate_borrow_rate(utilization_bps)?, utilization_bps)
+    }
+}
+
+/// Target amount for each market under a vault strategy
+///
+/// Every target respects the market's cap and never asks for more to be
+/// withdrawn than the market can pay out. Assets no market can take stay idle.
+/// - Conservative: configured targets among markets at or below
+///   `CONSERVATIVE_MAX_UTILIZATION_BPS`, each limited to
+///   `CONSERVATIVE_MAX_MARKET_SHARE_BPS` of the vault, with the excess spread
+///   over markets that still have room
+/// - Balanced: configured targets as they are
+/// - Aggressive: greedily puts each chunk where it earns the highest supply
+///   rate once supplied, ignoring configured targets
+pub fn target_amounts(
+    strategy: VaultStrategy,
+    total_assets: u64,
+    markets: &[MarketConditions],
+) -> Result<Vec<u64>> {
+    let floors: Vec<u64> = markets.iter().map(MarketConditions::floor).collect();
+    let remaining = total_assets.saturating_sub(floors.iter().sum());
+
+    match strategy {
+        VaultStrategy::Conservative => {
+            let share_cap = apply_bps(total_assets, CONSERVATIVE_MAX_MARKET_SHARE_BPS)?;
+            let mut weights = Vec::with_capacity(markets.len());
+            let mut rooms = Vec::with_capacity(markets.len());
+            for (market, floor) in markets.iter().zip(floors.iter()) {
+                let eligible = market.utilization_bps()? <= CONSERVATIVE_MAX_UTILIZATION_BPS;
+                weights.push(if eligible { market.target_bps as u64 } else { 0 });
+                rooms.push(market.cap.min(share_cap).saturating_sub(*floor));
+            }
+            let extra = water_fill(remaining, &weights, &rooms)?;
+            Ok(floors.iter().zip(extra).map(|(floor, extra)| floor + extra).collect())
+        }
+        VaultStrategy::Balanced => markets
+            .iter()
+            .zip(floors.iter())
+            .map(|(market, floor)| {
+                Ok(apply_bps(total_assets, market.target_bps)?.min(market.cap).max(*floor))
+            })
+            .collect(),
+        VaultStrategy::Aggressive => {
+            let mut targets = floors;
+            let mut remaining = remaining;
+            let step = remaining.div_ceil(AGGRESSIVE_ALLOCATION_STEPS).max(1);
+
+            while remaining > 0 {
+                let chunk = step.min(remaining);
+                let mut best: Option<(usize, u64)> = None;
+                for (index, market) in markets.iter().enumerate() {
+                    let amount = targets[index].saturating_add(chunk);
+                    if amount > market.cap {
+                        continue;
+                    }
+                    let rate = market.supply_rate_at(amount)?;
+                    match best {
+                        Some((_, best_rate)) if best_rate >= rate => {}
+                        _ => best = Some((index, rate)),
+                    }
+                }
+
+                let Some((index, _)) = best else { break };
+                targets[index] += chunk;
+                remaining -= chunk;
+            }
+
+            Ok(targets)
+        }
+    }
+}
+
+/// Spread `amount` over markets in proportion to `weights`, up to each one's `room`
+///
+/// Whatever a full market can't take is spread again over the others.
+fn water_fill(amount: u64, weights: &[u64], rooms: &[u64]) -> Result<Vec<u64>> {
+    let mut filled = vec![0u64; weights.len()];
+    let mut remaining = amount;
+
+    for _ in 0..weights.len() {
+        let total_weight: u64 = (0..weights.len())
+            .filter(|&index| weights[index] > 0 && filled[index] < rooms[index])
+            .map(|index| weights[index])
+            .sum();
+        if total_weight == 0 || remaining == 0 {
+            break;
+        }
+
+        let mut distributed = 0;
+        for index in 0..weights.len() {
+            if weights[index] == 0 || filled[index] >= rooms[index] {
+                continue;
+            }
+            let share = mul_div(remaining, weights[index] as u128, total_weight as u128, false)?;
+            let added = share.min(rooms[index] - filled[index]);
+            filled[index] += added;
+            distributed += added;
+        }
+
+        remaining -= distributed;
+        if distributed == 0 {
+            break;
+        }
+    }
+
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn market(target_bps: u16, cap: u64, total_supplied: u64, total_borrowed: u64) -> MarketConditions {
+        MarketConditions {
+            target_bps,
+            cap,
+            current_amount: 0,
+            available_liquidity: total_supplied - total_borrowed,
+            total_supplied,
+            total_borrowed,
+        }
+    }
+
+    #[test]
+    fn balanced_follows_configured_targets() {
+        let markets = [
+            market(6000, u64::MAX, 1_000_000, 500_000),
+            market(4000, u64::MAX, 1_000_000, 500_000),
+        ];
+        let targets = target_amounts(VaultStrategy::Balanced, 1_000, &markets).unwrap();
+        assert_eq!(targets, vec![600, 400]);
+    }
+
+    #[test]
+    fn balanced_respects_caps() {
+        let markets = [
+            market(6000, 100, 1_000_000, 500_000),
+            market(4000, u64::MAX, 1_000_000, 500_000),
+        ];
+        let targets = target_amounts(VaultStrategy::Balanced, 1_000, &markets).unwrap();
+        assert_eq!(targets, vec![100, 400]);
+    }
+
+    #[test]
+    fn conservative_caps_each_market_share() {
+        let markets = [
+            market(8000, u64::MAX, 1_000_000, 100_000),
+            market(2000, u64::MAX, 1_000_000, 100_000),
+        ];
+        let targets = target_amounts(VaultStrategy::Conservative, 1_000, &markets).unwrap();
+        assert_eq!(targets[0], 400);
+        assert!(targets[1] <= 400);
+    }
+
+    #[test]
+    fn conservative_excludes_highly_utilized_markets() {
+        let markets = [
+            market(5000, u64::MAX, 1_000_000, 950_000),
+            market(5000, u64::MAX, 1_000_000, 100_000),
+        ];
+        let targets = target_amounts(VaultStrategy::Conservative, 1_000, &markets).unwrap();
+        assert_eq!(targets[0], 0);
+        assert_eq!(targets[1], 400);
+    }
+
+    #[test]
+    fn conservative_spreads_excess_over_markets_with_room() {
+        let markets = [
+            market(5000, 100, 1_000_000, 100_000),
+            market(2500, u64::MAX, 1_000_000, 100_000),
+            market(2500, u64::MAX, 1_000_000, 100_000),
+        ];
+        let targets = target_amounts(VaultStrategy::Conservative, 1_000, &markets).unwrap();
+        assert_eq!(targets[0], 100);
+        assert_eq!(targets[1], 400);
+        assert_eq!(targets[2], 400);
+    }
+
+    #[test]
+    fn aggressive_prefers_the_higher_supply_rate() {
+        let markets = [
+            market(5000, u64::MAX, 1_000_000, 200_000),
+            market(5000, u64::MAX, 1_000_000, 900_000),
+        ];
+        let targets = target_amounts(VaultStrategy::Aggressive, 1_000, &markets).unwrap();
+        assert_eq!(targets, vec![0, 1_000]);
+    }
+
+    #[test]
+    fn aggressive_moves_on_when_a_market_is_capped() {
+        let markets = [
+            market(5000, u64::MAX, 1_000_000, 200_000),
+            market(5000, 500, 1_000_000, 900_000),
+        ];
+        let targets = target_amounts(VaultStrategy::Aggressive, 1_000, &markets).unwrap();
+        assert_eq!(targets, vec![500, 500]);
+    }
+
+    #[test]
+    fn targets_never_go_below_what_can_be_withdrawn() {
+        let mut illiquid = market(0, u64::MAX, 1_000_000, 990_000);
+        illiquid.current_amount = 500;
+        illiquid.available_liquidity = 200;
+        let markets = [illiquid, market(10000, u64::MAX, 1_000_000, 100_000)];
+
+        for strategy in [VaultStrategy::Conservative, VaultStrategy::Balanced, VaultStrategy::Aggressive] {
+            let targets = target_amounts(strategy, 1_000, &markets).unwrap();
+            assert!(targets[0] >= 300);
+        }
+    }
+
+    #[test]
+    fn allocation_never_exceeds_total_assets() {
+        let markets = [
+            market(3000, u64::MAX, 1_000_000, 400_000),
+            market(3000, u64::MAX, 1_000_000, 600_000),
+            market(4000, u64::MAX, 1_000_000, 800_000),
+        ];
+        for strategy in [VaultStrategy::Conservative, VaultStrategy::Balanced, VaultStrategy::Aggressive] {
+            let targets = target_amounts(strategy, 12_345, &markets).unwrap();
+            assert!(targets.iter().sum::<u64>() <= 12_345);
+        }
+    }
+}
@@ -6,6 +6,9 @@ pub const MAX_MARKETS: u8 = 50;
 /// Maximum number of borrow positions per user
 pub const MAX_BORROW_POSITIONS: u8 = 10;
 
+/// Maximum number of collateral deposits per obligation
+pub const MAX_OBLIGATION_DEPOSITS: u8 = 8;
+
 /// Default loan-to-value ratio (75% = 7500 basis points)
 pub const DEFAULT_LTV_BPS: u16 = 7500;
 
@@ -22,11 +25,77 @@ pub const MAX_LTV_BPS: u16 = 8000;
 pub const PROTOCOL_FEE_BPS: u16 = 500;
 
 /// Liquidation bonus basis points (5% = 500 bps) - discount for liquidators
+/// Default minimum and maximum of a market's liquidation bonus curve
 pub const LIQUIDATION_BONUS_BPS: u16 = 500;
 
+/// Health factor drop below 1.0 over which the liquidation bonus rises
+/// from the market minimum to the maximum (10% = 1000 bps)
+pub const LIQUIDATION_BONUS_HEALTH_RANGE_BPS: u16 = 1000;
+
+/// Default time for the liquidation bonus to rise from minimum to maximum (1 hour)
+pub const DEFAULT_LIQUIDATION_AUCTION_DURATION: i64 = 3600;
+
+/// Default share of the liquidation bonus sent to the treasury (10% of the bonus)
+pub const DEFAULT_LIQUIDATION_PROTOCOL_FEE_BPS: u16 = 1000;
+
+/// Default time a liquidation auction accepts bids (15 minutes)
+pub const DEFAULT_LARGE_LIQUIDATION_AUCTION_DURATION: i64 = 900;
+
+/// Health factor drop below an auction's starting value that lets anyone cancel it (0.05 = 500 bps)
+pub const AUCTION_CANCEL_HEALTH_DROP_BPS: u16 = 500;
+
+/// Keeper fee on collateral sold by an auto-deleverage (0.5% = 50 bps)
+pub const AUTO_DELEVERAGE_KEEPER_FEE_BPS: u16 = 50;
+
+/// Maximum swap slippage an auto-deleverage can be configured with (5% = 500 bps)
+pub const MAX_AUTO_DELEVERAGE_SLIPPAGE_BPS: u16 = 500;
+
 /// Minimum health factor before liquidation (1.0 = 10000 basis points)
 pub const MIN_HEALTH_FACTOR_BPS: u16 = 10000;
 
+/// E-mode category id meaning the obligation is not in e-mode
+pub const EMODE_NONE: u8 = 0;
+
+/// Maximum LTV for an e-mode category (97% = 9700 basis points)
+pub const MAX_EMODE_LTV_BPS: u16 = 9700;
+
+/// Maximum number of member markets in an e-mode category
+pub const MAX_EMODE_MARKETS: u8 = 10;
+
+/// Maximum number of markets a vault can allocate to
+pub const MAX_VAULT_ALLOCATIONS: u8 = 10;
+
+/// Maximum number of allocators per vault
+pub const MAX_VAULT_ALLOCATORS: u8 = 4;
+
+/// Longest timelock on vault cap increases and market additions (14 days in seconds)
+pub const MAX_VAULT_TIMELOCK: i64 = 14 * 86400;
+
+/// Maximum annual vault management fee (5%)
+pub const MAX_VAULT_MANAGEMENT_FEE_BPS: u16 = 500;
+
+/// Maximum vault performance fee on gains above the high-water mark (50%)
+pub const MAX_VAULT_PERFORMANCE_FEE_BPS: u16 = 5000;
+
+/// Scale of a vault's share price and high-water mark (1e18 = 1 asset per share)
+pub const SHARE_PRICE_SCALE: u128 = 1_000_000_000_000_000_000;
+
+/// Maximum number of allowlisted swap programs
+pub const MAX_SWAP_PROGRAMS: u8 = 4;
+
+/// Decimals used to track debt against isolated collateral debt ceilings
+/// (isolation-borrowable assets are expected to be dollar stablecoins)
+pub const ISOLATION_DEBT_DECIMALS: u8 = 6;
+
+/// Maximum share of an insurance fund that can be slashed per slash period (50%)
+pub const MAX_INSURANCE_SLASH_BPS: u16 = 5000;
+
+/// Period over which insurance fund slashes count against `max_slash_bps` (1 day in seconds)
+pub const INSURANCE_SLASH_PERIOD: i64 = 86_400;
+
+/// Window after an unstake cooldown ends in which the shares can be unstaked (2 days in seconds)
+pub const INSURANCE_UNSTAKE_WINDOW: i64 = 172_800;
+
 /// Interest rate model parameters
 /// Base rate (2% APY = 0.02 / 365 / 24 / 3600 per second)
 pub const BASE_RATE_PER_SECOND: u64 = 634_195_839; // ~2% APY
@@ -49,6 +118,24 @@ pub const SECONDS_PER_YEAR: u64 = 31_536_000;
 /// Oracle price staleness threshold (5 minutes in seconds)
 pub const ORACLE_STALENESS_THRESHOLD: i64 = 300;
 
+/// Oracle prices are normalized to 6 decimals (1_000_000 = $1.00)
+pub const ORACLE_PRICE_SCALE: u64 = 1_000_000;
+
+/// Number of oracle price samples kept per market for the circuit breaker
+pub const PRICE_HISTORY_LEN: usize = 8;
+
+/// Default price move that trips the circuit breaker (20% = 2000 basis points)
+pub const DEFAULT_PRICE_DEVIATION_BPS: u16 = 2000;
+
+/// Default window over which price moves are measured (10 minutes in seconds)
+pub const DEFAULT_PRICE_DEVIATION_WINDOW: i64 = 600;
+
+/// Default time borrows and withdrawals stay blocked after a trip (1 hour in seconds)
+pub const DEFAULT_CIRCUIT_BREAKER_COOLDOWN: i64 = 3600;
+
+/// Default window over which the reserve outflow limit refills (1 day in seconds)
+pub const DEFAULT_OUTFLOW_WINDOW: i64 = 86_400;
+
 /// Minimum borrow amount (0.01 tokens with 6 decimals)
 pub const MIN_BORROW_AMOUNT: u64 = 10_000_000;
 
@@ -66,5 +153,27 @@ pub enum VaultStrategy {
     Aggressive = 2,
 }
 
+impl TryFrom<u8> for VaultStrategy {
+    type Error = anchor_lang::error::Error;
+
+    fn try_from(strategy: u8) -> Result<Self> {
+        match strategy {
+            0 => Ok(VaultStrategy::Conservative),
+            1 => Ok(VaultStrategy::Balanced),
+            2 => Ok(VaultStrategy::Aggressive),
+            _ => Err(crate::errors::LendingError::VaultStrategyNotFound.into()),
+        }
+    }
+}
+
+/// Largest share of a conservative vault's assets in one market (40%)
+pub const CONSERVATIVE_MAX_MARKET_SHARE_BPS: u16 = 4000;
+
+/// Conservative vaults don't supply to markets above this utilization (80%)
+pub const CONSERVATIVE_MAX_UTILIZATION_BPS: u16 = 8000;
+
+/// Chunks an aggressive vault's assets are split into for greedy allocation
+pub const AGGRESSIVE_ALLOCATION_STEPS: u64 = 20;
+
 /// Basis points (10000 = 100%)
 pub const BPS_SCALE: u16 = 10000;
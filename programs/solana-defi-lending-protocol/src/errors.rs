@@ -85,4 +85,142 @@ pub enum LendingError {
 
     #[msg("Liquidation threshold must be greater than LTV")]
     LiquidationThresholdTooLow,
+
+    #[msg("Circuit breaker active - borrows and withdrawals are paused")]
+    CircuitBreakerActive,
+
+    #[msg("Reserve outflow limit exceeded for this window")]
+    OutflowLimitExceeded,
+
+    #[msg("Slippage tolerance exceeded")]
+    SlippageExceeded,
+
+    #[msg("Obligation has reached its maximum number of positions")]
+    ObligationPositionLimit,
+
+    #[msg("Obligation has no position in this market")]
+    ObligationPositionNotFound,
+
+    #[msg("Remaining accounts do not match obligation positions")]
+    InvalidObligationAccounts,
+
+    #[msg("Invalid e-mode category")]
+    InvalidEModeCategory,
+
+    #[msg("E-mode category has reached its maximum number of markets")]
+    EModeCategoryFull,
+
+    #[msg("Market is not a member of the obligation's e-mode category")]
+    EModeMarketNotAllowed,
+
+    #[msg("Isolated collateral cannot be combined with other collateral")]
+    IsolatedCollateralConflict,
+
+    #[msg("Market cannot be borrowed against isolated collateral")]
+    IsolationBorrowNotAllowed,
+
+    #[msg("Isolated collateral debt ceiling exceeded")]
+    IsolationDebtCeilingExceeded,
+
+    #[msg("Obligation still has collateral; debt is not bad debt")]
+    NoBadDebt,
+
+    #[msg("Insurance fund unstake cooldown has not elapsed")]
+    InsuranceCooldownActive,
+
+    #[msg("Insurance fund slash exceeds the maximum")]
+    InsuranceSlashLimitExceeded,
+
+    #[msg("Invalid insurance fund account")]
+    InvalidInsuranceFund,
+
+    #[msg("Obligation has a flash liquidation in progress")]
+    FlashLiquidationPending,
+
+    #[msg("Flash liquidation must be repaid by flash_liquidate_end in the same transaction")]
+    MissingFlashLiquidationEnd,
+
+    #[msg("No flash liquidation in progress")]
+    NoFlashLiquidation,
+
+    #[msg("Obligation has a liquidation auction in progress")]
+    LiquidationAuctionActive,
+
+    #[msg("Liquidation is below the market's auction threshold")]
+    AuctionThresholdNotMet,
+
+    #[msg("Liquidation auction has ended")]
+    AuctionEnded,
+
+    #[msg("Liquidation auction has not ended")]
+    AuctionNotEnded,
+
+    #[msg("Bid must ask for fewer supply tokens than the best bid")]
+    BidNotBetter,
+
+    #[msg("Token account of the current best bidder is required")]
+    MissingBidderAccount,
+
+    #[msg("Swap program is not allowlisted")]
+    InvalidSwapProgram,
+
+    #[msg("Invalid auto-deleverage configuration")]
+    InvalidAutoDeleverageConfig,
+
+    #[msg("Auto-deleverage is not enabled for this obligation")]
+    AutoDeleverageDisabled,
+
+    #[msg("Health factor is above the auto-deleverage trigger")]
+    AutoDeleverageNotTriggered,
+
+    #[msg("Operation did not improve the health factor")]
+    HealthFactorNotImproved,
+
+    #[msg("Operation would lower the health factor")]
+    HealthFactorDecreased,
+
+    #[msg("Target leverage must be above 1x")]
+    InvalidLeverage,
+
+    #[msg("Swap program allowlist is full")]
+    SwapProgramAllowlistFull,
+
+    #[msg("Invalid vault account")]
+    InvalidVaultAccount,
+
+    #[msg("Vault fee exceeds the maximum")]
+    InvalidVaultFee,
+
+    #[msg("Withdrawal request is not the next one in the queue")]
+    InvalidWithdrawalRequest,
+
+    #[msg("Queued withdrawal requests must be filled first")]
+    WithdrawalQueueNotEmpty,
+
+    #[msg("Invalid vault allocator")]
+    InvalidVaultAllocator,
+
+    #[msg("Vault allocator list is full")]
+    VaultAllocatorListFull,
+
+    #[msg("Invalid vault timelock")]
+    InvalidVaultTimelock,
+
+    #[msg("Adding a market or raising a cap requires a timelocked change")]
+    VaultCapIncreaseTimelocked,
+
+    #[msg("Pending change's timelock has not elapsed")]
+    PendingChangeTimelockActive,
+
+    #[msg("Swap program did not take exactly the input amount")]
+    SwapInputMismatch,
+
+    #[msg("Insurance fund unstake window has passed; request again")]
+    InsuranceUnstakeWindowExpired,
+
+    #[msg("Health factor has not fallen enough since the auction started")]
+    AuctionNotCancellable,
+
+    #[msg("Replacing a vault's guardian requires a timelocked change")]
+    VaultGuardianTimelocked,
 }
@@ -0,0 +1,198 @@
+use anchor_lang::prelude::*;
+use crate::constants::*;
+use crate::errors::LendingError;
+use crate::math::*;
+use crate::oracle::get_oracle_price;
+use crate::state::*;
+
+/// Market values of an obligation's positions (ORACLE_PRICE_SCALE units)
+#[derive(Clone, Copy, Default)]
+pub struct ObligationValues {
+    /// Value of all deposited collateral
+    pub collateral_value: u64,
+    /// Collateral value weighted by LTV (maximum debt value)
+    pub borrow_limit: u64,
+    /// Collateral value weighted by liquidation threshold
+    pub liquidation_threshold_value: u64,
+    /// Value of all debt including accrued interest
+    pub borrowed_value: u64,
+    /// Debt value divided by each market's borrow factor
+    pub weighted_borrowed_value: u64,
+}
+
+impl ObligationValues {
+    /// Health factor in basis points (10000 = 1.0)
+    pub fn health_factor(&self) -> Result<u16> {
+        calculate_health_factor(self.liquidation_threshold_value, self.weighted_borrowed_value)
+    }
+
+    /// Whether risk-adjusted debt is within the LTV-based borrow limit
+    pub fn is_within_borrow_limit(&self) -> bool {
+        self.weighted_borrowed_value <= self.borrow_limit
+    }
+
+    /// Take collateral worth `value` out of the totals
+    pub fn remove_collateral(&mut self, value: u64, ltv_bps: u16, liquidation_threshold_bps: u16) -> Result<()> {
+        self.collateral_value = self.collateral_value.saturating_sub(value);
+        self.borrow_limit = self.borrow_limit.saturating_sub(apply_bps(value, ltv_bps)?);
+        self.liquidation_threshold_value = self.liquidation_threshold_value
+            .saturating_sub(apply_bps(value, liquidation_threshold_bps)?);
+        Ok(())
+    }
+
+    /// Take debt worth `value` out of the totals
+    pub fn remove_debt(&mut self, value: u64, borrow_factor_bps: u16) -> Result<()> {
+        self.borrowed_value = self.borrowed_value.saturating_sub(value);
+        self.weighted_borrowed_value = self.weighted_borrowed_value
+            .saturating_sub(calculate_weighted_debt_value(value, borrow_factor_bps)?);
+        Ok(())
+    }
+
+    /// Add collateral worth `value` to the totals
+    pub fn add_collateral(&mut self, value: u64, ltv_bps: u16, liquidation_threshold_bps: u16) -> Result<()> {
+        self.collateral_value = self.collateral_value
+            .checked_add(value)
+            .ok_or(LendingError::MathOverflow)?;
+        self.borrow_limit = self.borrow_limit
+            .checked_add(apply_bps(value, ltv_bps)?)
+            .ok_or(LendingError::MathOverflow)?;
+        self.liquidation_threshold_value = self.liquidation_threshold_value
+            .checked_add(apply_bps(value, liquidation_threshold_bps)?)
+            .ok_or(LendingError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Add debt worth `value` to the totals
+    pub fn add_debt(&mut self, value: u64, borrow_factor_bps: u16) -> Result<()> {
+        self.borrowed_value = self.borrowed_value
+            .checked_add(value)
+            .ok_or(LendingError::MathOverflow)?;
+        self.weighted_borrowed_value = self.weighted_borrowed_value
+            .checked_add(calculate_weighted_debt_value(value, borrow_factor_bps)?)
+            .ok_or(LendingError::MathOverflow)?;
+        Ok(())
+    }
+}
+
+/// LTV and liquidation threshold that apply to collateral in a market
+pub fn collateral_params(
+    market_key: &Pubkey,
+    market: &Market,
+    emode_category: Option<&EModeCategory>,
+) -> (u16, u16) {
+    match emode_category {
+        Some(category) if category.contains(market_key) => {
+            (category.ltv_bps, category.liquidation_threshold_bps)
+        }
+        _ => (market.ltv_bps, market.liquidation_threshold_bps),
+    }
+}
+
+/// Load a market and its oracle price from a `[market, oracle]` account pair
+///
+/// Markets the instruction already holds mutably are taken from `loaded_markets`
+/// so their in-memory updates are used instead of the stale account data.
+fn load_market_and_price(
+    pair: &[AccountInfo],
+    expected_market: &Pubkey,
+    loaded_markets: &[(Pubkey, &Market)],
+    clock: &Clock,
+) -> Result<(Market, u64)> {
+    require!(
+        pair.len() == 2 && pair[0].key() == *expected_market,
+        LendingError::InvalidObligationAccounts
+    );
+
+    let mut market = match loaded_markets.iter().find(|(key, _)| key == expected_market) {
+        Some((_, market)) => (*market).clone(),
+        None => {
+            require!(pair[0].owner == &crate::ID, LendingError::InvalidObligationAccounts);
+            Market::try_deserialize(&mut &pair[0].try_borrow_data()?[..])?
+        }
+    };
+    // Value debt at the current borrow index, not the one last written to the account
+    market.accrue_interest(clock)?;
+    require!(pair[1].key() == market.oracle, LendingError::InvalidOracle);
+
+    let price = get_oracle_price(&pair[1], clock)?;
+
+    Ok((market, price))
+}
+
+/// Value an obligation's deposits and borrows
+///
+/// `remaining_accounts` must hold one `[market, oracle]` pair per deposit,
+/// followed by one pair per borrow, in the obligation's order.
+pub fn calculate_obligation_values(
+    obligation: &Obligation,
+    remaining_accounts: &[AccountInfo],
+    loaded_markets: &[(Pubkey, &Market)],
+    emode_category: Option<&EModeCategory>,
+    clock: &Clock,
+) -> Result<ObligationValues> {
+    require!(
+        remaining_accounts.len() == 2 * (obligation.deposits.len() + obligation.borrows.len()),
+        LendingError::InvalidObligationAccounts
+    );
+
+    let mut pairs = remaining_accounts.chunks(2);
+    let mut values = ObligationValues::default();
+
+    for deposit in obligation.deposits.iter() {
+        let pair = pairs.next().ok_or(LendingError::InvalidObligationAccounts)?;
+        let (market, price) = load_market_and_price(pair, &deposit.market, loaded_markets, clock)?;
+
+        let amount = market.supply_tokens_to_amount(deposit.supply_tokens)?;
+        let value = calculate_market_value(amount, price, market.mint_decimals)?;
+        let (ltv_bps, liquidation_threshold_bps) =
+            collateral_params(&deposit.market, &market, emode_category);
+
+        values.collateral_value = values.collateral_value
+            .checked_add(value)
+            .ok_or(LendingError::MathOverflow)?;
+        values.borrow_limit = values.borrow_limit
+            .checked_add(apply_bps(value, ltv_bps)?)
+            .ok_or(LendingError::MathOverflow)?;
+        values.liquidation_threshold_value = values.liquidation_threshold_value
+            .checked_add(apply_bps(value, liquidation_threshold_bps)?)
+            .ok_or(LendingError::MathOverflow)?;
+    }
+
+    for borrow in obligation.borrows.iter() {
+        let pair = pairs.next().ok_or(LendingError::InvalidObligationAccounts)?;
+        let (market, price) = load_market_and_price(pair, &borrow.market, loaded_markets, clock)?;
+
+        let debt = borrow.calculate_debt(&market)?;
+        let value = calculate_market_value(debt, price, market.mint_decimals)?;
+
+        values.borrowed_value = values.borrowed_value
+            .checked_add(value)
+            .ok_or(LendingError::MathOverflow)?;
+        values.weighted_borrowed_value = values.weighted_borrowed_value
+            .checked_add(calculate_weighted_debt_value(value, market.borrow_factor_bps)?)
+            .ok_or(LendingError::MathOverflow)?;
+    }
+
+    Ok(values)
+}
+
+/// Check the e-mode category account passed to an instruction matches the obligation
+///
+/// The account's seeds already bind it to `obligation.emode_category`; this makes
+/// sure it is not omitted, which would value e-mode collateral at normal parameters.
+pub fn require_emode_category<'a>(
+    obligation: &Obligation,
+    emode_category: Option<&'a EModeCategory>,
+) -> Result<Option<&'a EModeCategory>> {
+    if obligation.emode_category == EMODE_NONE {
+        return Ok(None);
+    }
+
+    let category = emode_category.ok_or(LendingError::InvalidEModeCategory)?;
+    require!(
+        category.id == obligation.emode_category,
+        LendingError::InvalidEModeCategory
+    );
+
+    Ok(Some(category))
+}
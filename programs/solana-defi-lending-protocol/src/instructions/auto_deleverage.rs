@@ -0,0 +1,419 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Burn, Mint, Token, TokenAccount, Transfer};
+use crate::state::*;
+use crate::errors::LendingError;
+use crate::constants::*;
+use crate::math::*;
+use crate::health::{calculate_obligation_values, collateral_params, require_emode_category};
+use crate::instructions::liquidate::apply_liquidation;
+use crate::oracle::get_oracle_price;
+use crate::swap::SwapAdapter;
+
+/// Opt an obligation into auto-deleverage
+///
+/// Once the health factor falls below `health_factor_bps`, any keeper may sell
+/// collateral for enough of the debt asset to repay `repay_bps` of one borrow.
+/// A `health_factor_bps` of 0 turns auto-deleverage off.
+#[derive(Accounts)]
+pub struct ConfigureAutoDeleverage<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"obligation", owner.key().as_ref()],
+        bump = obligation.bump
+    )]
+    pub obligation: Account<'info, Obligation>,
+}
+
+pub fn handler(
+    ctx: Context<ConfigureAutoDeleverage>,
+    health_factor_bps: u16,
+    repay_bps: u16,
+    max_slippage_bps: u16,
+) -> Result<()> {
+    if health_factor_bps != 0 {
+        // Below 1.0 the position is liquidatable anyway
+        require!(
+            health_factor_bps > MIN_HEALTH_FACTOR_BPS,
+            LendingError::InvalidAutoDeleverageConfig
+        );
+        require!(
+            repay_bps > 0 && repay_bps <= BPS_SCALE,
+            LendingError::InvalidAutoDeleverageConfig
+        );
+        require!(
+            max_slippage_bps <= MAX_AUTO_DELEVERAGE_SLIPPAGE_BPS,
+            LendingError::InvalidAutoDeleverageConfig
+        );
+    }
+
+    let obligation = &mut ctx.accounts.obligation;
+    obligation.auto_deleverage_health_factor_bps = health_factor_bps;
+    obligation.auto_deleverage_repay_bps = repay_bps;
+    obligation.auto_deleverage_max_slippage_bps = max_slippage_bps;
+
+    emit!(AutoDeleverageConfigured {
+        obligation: obligation.key(),
+        health_factor_bps,
+        repay_bps,
+        max_slippage_bps,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct AutoDeleverageConfigured {
+    pub obligation: Pubkey,
+    pub health_factor_bps: u16,
+    pub repay_bps: u16,
+    pub max_slippage_bps: u16,
+    pub timestamp: i64,
+}
+
+/// Deleverage an obligation that crossed its auto-deleverage trigger
+///
+/// Permissionless. Collateral worth the configured share of the debt at
+/// oracle prices is swapped into the debt asset through the configured swap
+/// program and repays the borrow; the keeper earns a small fee on top, paid
+/// in collateral. The swap must return at least the oracle value less the
+/// owner's slippage limit, and the health factor must improve. Any output
+/// beyond the outstanding debt is sent to the owner.
+/// Remaining accounts: `[market, oracle]` for each deposit, then for each borrow.
+#[derive(Accounts)]
+pub struct AutoDeleverage<'info> {
+    pub keeper: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"obligation", obligation.owner.as_ref()],
+        bump = obligation.bump
+    )]
+    pub obligation: Account<'info, Obligation>,
+
+    /// Market for the debt being repaid
+    #[account(
+        mut,
+        seeds = [b"market", borrow_market.asset_mint.as_ref()],
+        bump = borrow_market.bump
+    )]
+    pub borrow_market: Account<'info, Market>,
+
+    /// Market for the collateral being sold
+    #[account(
+        mut,
+        seeds = [b"market", collateral_market.asset_mint.as_ref()],
+        bump = collateral_market.bump,
+        constraint = collateral_market.key() != borrow_market.key() @ LendingError::InvalidMarketConfig
+    )]
+    pub collateral_market: Account<'info, Market>,
+
+    /// Borrow market reserve vault (receives the swap output)
+    #[account(
+        mut,
+        constraint = borrow_reserve_vault.key() == borrow_market.reserve_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub borrow_reserve_vault: Account<'info, TokenAccount>,
+
+    /// Collateral market reserve vault (swap input and keeper fee)
+    #[account(
+        mut,
+        constraint = collateral_reserve_vault.key() == collateral_market.reserve_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub collateral_reserve_vault: Account<'info, TokenAccount>,
+
+    /// Collateral market collateral vault (holds the obligation's supply tokens)
+    #[account(
+        mut,
+        constraint = collateral_vault.key() == collateral_market.collateral_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+
+    /// Collateral market supply mint (sold supply tokens are burned)
+    #[account(
+        mut,
+        constraint = collateral_supply_mint.key() == collateral_market.supply_mint @ LendingError::InvalidMarketConfig
+    )]
+    pub collateral_supply_mint: Account<'info, Mint>,
+
+    /// Keeper's collateral token account (receives the keeper fee)
+    #[account(
+        mut,
+        constraint = keeper_collateral_account.owner == keeper.key() @ LendingError::Unauthorized,
+        constraint = keeper_collateral_account.mint == collateral_market.asset_mint @ LendingError::InvalidMarketConfig
+    )]
+    pub keeper_collateral_account: Account<'info, TokenAccount>,
+
+    /// Obligation owner's token account for the debt asset (receives output beyond the debt)
+    #[account(
+        mut,
+        constraint = owner_token_account.owner == obligation.owner @ LendingError::Unauthorized,
+        constraint = owner_token_account.mint == borrow_market.asset_mint @ LendingError::InvalidMarketConfig
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Oracle for borrowed asset price
+    #[account(
+        constraint = borrow_oracle.key() == borrow_market.oracle @ LendingError::InvalidOracle
+    )]
+    pub borrow_oracle: UncheckedAccount<'info>,
+
+    /// CHECK: Oracle for collateral asset price
+    #[account(
+        constraint = collateral_oracle.key() == collateral_market.oracle @ LendingError::InvalidOracle
+    )]
+    pub collateral_oracle: UncheckedAccount<'info>,
+
+    /// E-mode category the obligation opted into, if any
+    #[account(
+        seeds = [b"emode_category", obligation.emode_category.to_le_bytes().as_ref()],
+        bump = emode_category.bump
+    )]
+    pub emode_category: Option<Account<'info, EModeCategory>>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// CHECK: Swap program, must be allowlisted on GlobalConfig
+    #[account(
+        constraint = global_config.is_swap_program_allowed(&swap_program.key()) @ LendingError::InvalidSwapProgram
+    )]
+    pub swap_program: UncheckedAccount<'info>,
+
+    /// CHECK: Swap pool, validated by the swap program
+    #[account(mut)]
+    pub swap_pool: UncheckedAccount<'info>,
+
+    /// CHECK: Pool vault for the collateral asset, validated by the swap program
+    #[account(mut)]
+    pub swap_pool_source_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Pool vault for the debt asset, validated by the swap program
+    #[account(mut)]
+    pub swap_pool_destination_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Swap authority PDA, delegated the swap input for the duration of the swap
+    #[account(
+        seeds = [b"swap_authority", global_config.key().as_ref()],
+        bump
+    )]
+    pub swap_authority: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn deleverage_handler(ctx: Context<AutoDeleverage>) -> Result<()> {
+    let obligation = &mut ctx.accounts.obligation;
+    let borrow_market = &mut ctx.accounts.borrow_market;
+    let collateral_market = &mut ctx.accounts.collateral_market;
+    let clock = Clock::get()?;
+
+    require!(
+        obligation.auto_deleverage_health_factor_bps != 0,
+        LendingError::AutoDeleverageDisabled
+    );
+    obligation.require_not_liquidating()?;
+
+    borrow_market.accrue_interest(&clock)?;
+    collateral_market.accrue_interest(&clock)?;
+
+    // Check the owner's trigger
+    let emode_category = require_emode_category(obligation, ctx.accounts.emode_category.as_deref())?;
+    let mut values = calculate_obligation_values(
+        obligation,
+        ctx.remaining_accounts,
+        &[
+            (borrow_market.key(), borrow_market),
+            (collateral_market.key(), collateral_market),
+        ],
+        emode_category,
+        &clock,
+    )?;
+    let health_factor = values.health_factor()?;
+    require!(
+        health_factor < obligation.auto_deleverage_health_factor_bps,
+        LendingError::AutoDeleverageNotTriggered
+    );
+
+    // Size the swap at oracle prices
+    let debt = obligation
+        .find_borrow(&borrow_market.key())
+        .ok_or(LendingError::BorrowPositionNotFound)?
+        .calculate_debt(borrow_market)?;
+    let repay_target = apply_bps(debt, obligation.auto_deleverage_repay_bps)?;
+    require!(repay_target > 0, LendingError::InvalidAmount);
+
+    let borrow_price = get_oracle_price(&ctx.accounts.borrow_oracle, &clock)?;
+    let collateral_price = get_oracle_price(&ctx.accounts.collateral_oracle, &clock)?;
+
+    let repay_value = calculate_market_value(repay_target, borrow_price, borrow_market.mint_decimals)?;
+    let amount_in = calculate_amount_from_value(repay_value, collateral_price, collateral_market.mint_decimals)?;
+    let min_amount_out = apply_bps(
+        repay_target,
+        BPS_SCALE - obligation.auto_deleverage_max_slippage_bps,
+    )?;
+
+    let keeper_fee = apply_bps(amount_in, AUTO_DELEVERAGE_KEEPER_FEE_BPS)?;
+    let collateral_amount = amount_in
+        .checked_add(keeper_fee)
+        .ok_or(LendingError::MathOverflow)?;
+    let supply_tokens = collateral_market.amount_to_supply_tokens(collateral_amount)?;
+
+    require!(supply_tokens > 0, LendingError::InvalidAmount);
+    require!(
+        supply_tokens <= obligation.deposited_supply_tokens(&collateral_market.key()),
+        LendingError::InsufficientCollateral
+    );
+    require!(
+        collateral_amount <= ctx.accounts.collateral_reserve_vault.amount,
+        LendingError::InsufficientLiquidity
+    );
+
+    let collateral_seeds = &[
+        b"market",
+        collateral_market.asset_mint.as_ref(),
+        &[collateral_market.bump],
+    ];
+    let collateral_signer = &[&collateral_seeds[..]];
+
+    // Burn the sold supply tokens held as collateral
+    let burn_ctx = CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        Burn {
+            mint: ctx.accounts.collateral_supply_mint.to_account_info(),
+            from: ctx.accounts.collateral_vault.to_account_info(),
+            authority: collateral_market.to_account_info(),
+        },
+        collateral_signer,
+    );
+    token::burn(burn_ctx, supply_tokens)?;
+
+    if keeper_fee > 0 {
+        let fee_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.collateral_reserve_vault.to_account_info(),
+                to: ctx.accounts.keeper_collateral_account.to_account_info(),
+                authority: collateral_market.to_account_info(),
+            },
+            collateral_signer,
+        );
+        token::transfer(fee_ctx, keeper_fee)?;
+    }
+
+    // Swap straight from the collateral reserve into the borrow reserve
+    let global_config_key = ctx.accounts.global_config.key();
+    let swap_authority_bump = ctx.bumps.get("swap_authority").copied().unwrap();
+    let swap_authority_seeds: &[&[u8]] = &[
+        b"swap_authority",
+        global_config_key.as_ref(),
+        &[swap_authority_bump],
+    ];
+    let adapter = SwapAdapter {
+        program: &ctx.accounts.swap_program,
+        pool: &ctx.accounts.swap_pool,
+        source_vault: &ctx.accounts.swap_pool_source_vault,
+        destination_vault: &ctx.accounts.swap_pool_destination_vault,
+        authority: &ctx.accounts.swap_authority,
+        authority_seeds: swap_authority_seeds,
+    };
+    let amount_out = adapter.swap(
+        &mut ctx.accounts.collateral_reserve_vault,
+        &mut ctx.accounts.borrow_reserve_vault,
+        &collateral_market.to_account_info(),
+        &ctx.accounts.token_program.to_account_info(),
+        collateral_signer,
+        amount_in,
+        min_amount_out,
+    )?;
+
+    // Return any output beyond the debt to the owner
+    let repay_amount = amount_out.min(debt);
+    let refund = amount_out - repay_amount;
+    if refund > 0 {
+        let borrow_seeds = &[
+            b"market",
+            borrow_market.asset_mint.as_ref(),
+            &[borrow_market.bump],
+        ];
+        let borrow_signer = &[&borrow_seeds[..]];
+        let refund_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.borrow_reserve_vault.to_account_info(),
+                to: ctx.accounts.owner_token_account.to_account_info(),
+                authority: borrow_market.to_account_info(),
+            },
+            borrow_signer,
+        );
+        token::transfer(refund_ctx, refund)?;
+    }
+
+    apply_liquidation(
+        obligation,
+        borrow_market,
+        collateral_market,
+        repay_amount,
+        supply_tokens,
+        &clock,
+    )?;
+    collateral_market.total_supplied = collateral_market.total_supplied
+        .checked_sub(collateral_amount)
+        .ok_or(LendingError::MathOverflow)?;
+    collateral_market.total_supply_tokens = collateral_market.total_supply_tokens
+        .checked_sub(supply_tokens)
+        .ok_or(LendingError::MathOverflow)?;
+
+    // The deleverage has to leave the position healthier
+    let (ltv_bps, liquidation_threshold_bps) =
+        collateral_params(&collateral_market.key(), collateral_market, emode_category);
+    values.remove_collateral(
+        calculate_market_value(collateral_amount, collateral_price, collateral_market.mint_decimals)?,
+        ltv_bps,
+        liquidation_threshold_bps,
+    )?;
+    values.remove_debt(
+        calculate_market_value(repay_amount, borrow_price, borrow_market.mint_decimals)?,
+        borrow_market.borrow_factor_bps,
+    )?;
+    let new_health_factor = values.health_factor()?;
+    require!(
+        new_health_factor > health_factor,
+        LendingError::HealthFactorNotImproved
+    );
+    obligation.update_liquidatable_since(new_health_factor, &clock);
+
+    emit!(AutoDeleveraged {
+        obligation: obligation.key(),
+        borrow_market: borrow_market.key(),
+        collateral_market: collateral_market.key(),
+        keeper: ctx.accounts.keeper.key(),
+        collateral_amount,
+        repay_amount,
+        keeper_fee,
+        health_factor,
+        new_health_factor,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct AutoDeleveraged {
+    pub obligation: Pubkey,
+    pub borrow_market: Pubkey,
+    pub collateral_market: Pubkey,
+    pub keeper: Pubkey,
+    pub collateral_amount: u64,
+    pub repay_amount: u64,
+    pub keeper_fee: u64,
+    pub health_factor: u16,
+    pub new_health_factor: u16,
+    pub timestamp: i64,
+}
@@ -0,0 +1,197 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use crate::state::*;
+use crate::errors::LendingError;
+use crate::instructions::insurance::{require_insurance_fund, slash_insurance_fund};
+
+/// Write off bad debt left on an obligation
+///
+/// Once an obligation has no collateral left, its remaining debt in a market
+/// can never be repaid. Permissionless crank that removes the debt from
+/// `total_borrowed` and covers it, in order, from the market's accumulated
+/// protocol fees, a slash of the asset's insurance fund, the treasury's
+/// insurance buffer, and finally by lowering `total_supplied` (which lowers
+/// the supply-token exchange rate).
+#[derive(Accounts)]
+pub struct SocializeBadDebt<'info> {
+    /// Obligation holding the bad debt
+    #[account(
+        mut,
+        seeds = [b"obligation", obligation.owner.as_ref()],
+        bump = obligation.bump,
+        constraint = obligation.deposits.is_empty() @ LendingError::NoBadDebt
+    )]
+    pub obligation: Account<'info, Obligation>,
+
+    #[account(
+        mut,
+        seeds = [b"market", market.asset_mint.as_ref()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, Market>,
+
+    /// Reserve vault (destination for insurance cover)
+    #[account(
+        mut,
+        constraint = reserve_vault.key() == market.reserve_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub reserve_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// CHECK: Treasury PDA validated by seeds; signs for its token account
+    #[account(
+        seeds = [b"treasury", global_config.key().as_ref()],
+        bump = global_config.treasury_bump
+    )]
+    pub treasury: UncheckedAccount<'info>,
+
+    /// Treasury's token account for the market asset (insurance buffer)
+    #[account(
+        mut,
+        constraint = treasury_token_account.owner == treasury.key() @ LendingError::Unauthorized,
+        constraint = treasury_token_account.mint == market.asset_mint @ LendingError::InvalidMarketConfig
+    )]
+    pub treasury_token_account: Account<'info, TokenAccount>,
+
+    /// Insurance fund for the market asset (required once one exists)
+    #[account(
+        mut,
+        seeds = [b"insurance_fund", market.asset_mint.as_ref()],
+        bump = insurance_fund.bump
+    )]
+    pub insurance_fund: Option<Account<'info, InsuranceFund>>,
+
+    /// Insurance fund vault
+    #[account(mut)]
+    pub insurance_vault: Option<Account<'info, TokenAccount>>,
+
+    /// Isolated collateral market the debt was taken against, if any
+    #[account(
+        mut,
+        constraint = isolated_collateral_market.key() == obligation.isolated_collateral_market @ LendingError::IsolatedCollateralConflict
+    )]
+    pub isolated_collateral_market: Option<Account<'info, Market>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn handler(ctx: Context<SocializeBadDebt>) -> Result<()> {
+    let obligation = &mut ctx.accounts.obligation;
+    let market = &mut ctx.accounts.market;
+    let clock = Clock::get()?;
+
+    market.accrue_interest(&clock)?;
+
+    let bad_debt = obligation
+        .find_borrow(&market.key())
+        .ok_or(LendingError::BorrowPositionNotFound)?
+        .calculate_debt(market)?;
+
+    // Written-off debt no longer counts against an isolated debt ceiling
+    if obligation.is_isolated() {
+        let isolated_market = ctx.accounts.isolated_collateral_market
+            .as_mut()
+            .ok_or(LendingError::IsolatedCollateralConflict)?;
+        isolated_market.remove_isolation_debt(bad_debt, market.mint_decimals)?;
+    }
+
+    // Remove the debt from the obligation and the market
+    obligation.repay(market.key(), market, bad_debt)?;
+    obligation.last_updated = clock.unix_timestamp;
+    market.total_borrowed = market.total_borrowed
+        .checked_sub(bad_debt)
+        .ok_or(LendingError::MathOverflow)?;
+
+    // 1. Protocol fees already held in the reserve
+    let covered_by_protocol_fees = bad_debt.min(market.accumulated_protocol_fees);
+    market.accumulated_protocol_fees -= covered_by_protocol_fees;
+    let mut remaining = bad_debt - covered_by_protocol_fees;
+
+    // 2. Insurance fund stakers, slashed up to what the fund's period cap allows
+    require_insurance_fund(
+        market,
+        ctx.accounts.insurance_fund.as_ref(),
+        ctx.accounts.insurance_vault.as_ref(),
+    )?;
+    let covered_by_insurance_fund = match (ctx.accounts.insurance_fund.as_mut(), &ctx.accounts.insurance_vault) {
+        (Some(fund), Some(vault)) => remaining.min(fund.available_slash(vault.amount, &clock)?),
+        _ => 0,
+    };
+    if covered_by_insurance_fund > 0 {
+        if let (Some(fund), Some(vault)) = (
+            ctx.accounts.insurance_fund.as_mut(),
+            ctx.accounts.insurance_vault.as_ref(),
+        ) {
+            slash_insurance_fund(
+                fund,
+                vault,
+                ctx.accounts.reserve_vault.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+                covered_by_insurance_fund,
+            )?;
+        }
+        remaining -= covered_by_insurance_fund;
+    }
+
+    // 3. Treasury insurance buffer, moved into the reserve
+    let covered_by_treasury = remaining.min(ctx.accounts.treasury_token_account.amount);
+    if covered_by_treasury > 0 {
+        let global_config_key = ctx.accounts.global_config.key();
+        let seeds = &[
+            b"treasury",
+            global_config_key.as_ref(),
+            &[ctx.accounts.global_config.treasury_bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.treasury_token_account.to_account_info(),
+                to: ctx.accounts.reserve_vault.to_account_info(),
+                authority: ctx.accounts.treasury.to_account_info(),
+            },
+            signer,
+        );
+        token::transfer(transfer_ctx, covered_by_treasury)?;
+        remaining -= covered_by_treasury;
+    }
+
+    // 4. Suppliers absorb the rest through a lower exchange rate, down to
+    // nothing; any loss beyond that is reported as unrecovered
+    let socialized = remaining.min(market.total_supplied);
+    let unrecovered = remaining - socialized;
+    market.total_supplied -= socialized;
+
+    emit!(BadDebtSocialized {
+        market: market.key(),
+        obligation: obligation.key(),
+        bad_debt,
+        covered_by_protocol_fees,
+        covered_by_insurance_fund,
+        covered_by_treasury,
+        socialized,
+        unrecovered,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct BadDebtSocialized {
+    pub market: Pubkey,
+    pub obligation: Pubkey,
+    pub bad_debt: u64,
+    pub covered_by_protocol_fees: u64,
+    pub covered_by_insurance_fund: u64,
+    pub covered_by_treasury: u64,
+    pub socialized: u64,
+    pub unrecovered: u64,
+    pub timestamp: i64,
+}
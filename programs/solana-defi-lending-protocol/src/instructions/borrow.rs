@@ -4,16 +4,28 @@ use crate::state::*;
 use crate::errors::LendingError;
 use crate::constants::*;
 use crate::math::*;
+use crate::oracle::get_oracle_price;
+use crate::health::{calculate_obligation_values, require_emode_category};
+use crate::instructions::refresh_market::observe_market_price;
 
 /// Borrow assets from a lending market
 /// 
-/// Users can borrow against their supplied collateral, up to the LTV limit.
+/// Users can borrow against their obligation's collateral, up to the LTV limit.
 /// Health factor must remain above the liquidation threshold.
+/// Remaining accounts: `[market, oracle]` for each deposit, then for each borrow
+/// (including this one).
 #[derive(Accounts)]
 pub struct Borrow<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
 
+    #[account(
+        mut,
+        seeds = [b"obligation", user.key().as_ref()],
+        bump = obligation.bump
+    )]
+    pub obligation: Account<'info, Obligation>,
+
     #[account(
         mut,
         seeds = [b"market", market.asset_mint.as_ref()],
@@ -25,7 +37,7 @@ pub struct Borrow<'info> {
     /// Reserve vault (source of borrowed assets)
     #[account(
         mut,
-        constraint = reserve_vault.mint == market.asset_mint @ LendingError::InvalidMarketConfig
+        constraint = reserve_vault.key() == market.reserve_vault @ LendingError::InvalidMarketConfig
     )]
     pub reserve_vault: Account<'info, TokenAccount>,
 
@@ -38,8 +50,25 @@ pub struct Borrow<'info> {
     pub user_token_account: Account<'info, TokenAccount>,
 
     /// CHECK: Oracle account for price feed
+    #[account(
+        constraint = oracle.key() == market.oracle @ LendingError::InvalidOracle
+    )]
     pub oracle: UncheckedAccount<'info>,
 
+    /// E-mode category the obligation opted into, if any
+    #[account(
+        seeds = [b"emode_category", obligation.emode_category.to_le_bytes().as_ref()],
+        bump = emode_category.bump
+    )]
+    pub emode_category: Option<Account<'info, EModeCategory>>,
+
+    /// Isolated collateral market backing the obligation, if any
+    #[account(
+        mut,
+        constraint = isolated_collateral_market.key() == obligation.isolated_collateral_market @ LendingError::IsolatedCollateralConflict
+    )]
+    pub isolated_collateral_market: Option<Account<'info, Market>>,
+
     pub token_program: Program<'info, Token>,
 }
 
@@ -47,11 +76,26 @@ pub fn handler(ctx: Context<Borrow>, amount: u64) -> Result<()> {
     require!(amount >= MIN_BORROW_AMOUNT, LendingError::InvalidAmount);
 
     let market = &mut ctx.accounts.market;
+    let obligation = &mut ctx.accounts.obligation;
     let clock = Clock::get()?;
 
     // Accrue interest before processing
     market.accrue_interest(&clock)?;
 
+    // Block borrows while the price circuit breaker is tripped;
+    // a new trip returns early so it isn't rolled back with an error
+    let price = get_oracle_price(&ctx.accounts.oracle, &clock)?;
+    if observe_market_price(market, price, &clock)? {
+        return Ok(());
+    }
+    require!(
+        !market.is_circuit_breaker_active(clock.unix_timestamp),
+        LendingError::CircuitBreakerActive
+    );
+
+    // Bound how fast the reserve can be drained
+    market.record_outflow(amount, &clock)?;
+
     // Check available liquidity
     let available_liquidity = ctx.accounts.reserve_vault.amount;
     require!(
@@ -59,9 +103,43 @@ pub fn handler(ctx: Context<Borrow>, amount: u64) -> Result<()> {
         LendingError::InsufficientLiquidity
     );
 
-    // TODO: In a full implementation, calculate user's total collateral across all markets
-    // and total borrowed value to check health factor
-    // For now, we do a simplified check on this market only
+    // E-mode obligations can only borrow from member markets
+    let emode_category = require_emode_category(obligation, ctx.accounts.emode_category.as_deref())?;
+    if let Some(category) = emode_category {
+        require!(
+            category.contains(&market.key()),
+            LendingError::EModeMarketNotAllowed
+        );
+    }
+
+    // Isolated collateral can only back isolation-borrowable markets, up to its debt ceiling
+    if obligation.is_isolated() {
+        require!(market.isolation_borrowable, LendingError::IsolationBorrowNotAllowed);
+        let isolated_market = ctx.accounts.isolated_collateral_market
+            .as_mut()
+            .ok_or(LendingError::IsolatedCollateralConflict)?;
+        isolated_market.add_isolation_debt(amount, market.mint_decimals)?;
+    }
+
+    // The obligation can't change while it is being liquidated
+    obligation.require_not_liquidating()?;
+
+    // Record the debt and check it stays within the obligation's borrow limit
+    obligation.borrow(market.key(), market, amount)?;
+    obligation.last_updated = clock.unix_timestamp;
+
+    let values = calculate_obligation_values(
+        obligation,
+        ctx.remaining_accounts,
+        &[(market.key(), market)],
+        emode_category,
+        &clock,
+    )?;
+    require!(
+        values.is_within_borrow_limit(),
+        LendingError::BorrowWouldCauseLiquidation
+    );
+    obligation.update_liquidatable_since(values.health_factor()?, &clock);
 
     // Calculate new total borrowed
     let new_total_borrowed = market.total_borrowed
@@ -97,13 +175,9 @@ pub fn handler(ctx: Context<Borrow>, amount: u64) -> Result<()> {
     // Update market state
     market.total_borrowed = new_total_borrowed;
 
-    // Create or update borrow position
-    // Note: In a full implementation, you'd use a PDA for the borrow position
-    // For simplicity, we're updating the market's total_borrowed
-    // A real implementation would track individual positions
-
     emit!(Borrowed {
         market: market.key(),
+        obligation: obligation.key(),
         user: ctx.accounts.user.key(),
         amount,
         total_borrowed: market.total_borrowed,
@@ -117,6 +191,7 @@ pub fn handler(ctx: Context<Borrow>, amount: u64) -> Result<()> {
 #[event]
 pub struct Borrowed {
     pub market: Pubkey,
+    pub obligation: Pubkey,
     pub user: Pubkey,
     pub amount: u64,
     pub total_borrowed: u64,
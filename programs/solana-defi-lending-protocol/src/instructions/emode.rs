@@ -0,0 +1,256 @@
+use anchor_lang::prelude::*;
+use crate::state::*;
+use crate::errors::LendingError;
+use crate::constants::*;
+use crate::health::calculate_obligation_values;
+
+/// Create an efficiency mode category
+///
+/// E-mode categories group correlated assets (e.g. stablecoins, SOL and LSTs)
+/// and give them a higher LTV and liquidation threshold than the global limits.
+#[derive(Accounts)]
+#[instruction(id: u8)]
+pub struct CreateEModeCategory<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump,
+        has_one = authority @ LendingError::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = EModeCategory::SIZE,
+        seeds = [b"emode_category", id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub emode_category: Account<'info, EModeCategory>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(
+    ctx: Context<CreateEModeCategory>,
+    id: u8,
+    ltv_bps: u16,
+    liquidation_threshold_bps: u16,
+    liquidation_bonus_bps: u16,
+) -> Result<()> {
+    require!(id != EMODE_NONE, LendingError::InvalidEModeCategory);
+
+    let emode_category = &mut ctx.accounts.emode_category;
+    emode_category.id = id;
+    emode_category.markets = Vec::new();
+    emode_category.bump = ctx.bumps.get("emode_category").copied().unwrap();
+    emode_category.set_params(ltv_bps, liquidation_threshold_bps, liquidation_bonus_bps)?;
+
+    emit!(EModeCategoryUpdated {
+        emode_category: emode_category.key(),
+        id,
+        ltv_bps,
+        liquidation_threshold_bps,
+        liquidation_bonus_bps,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Update an e-mode category's risk parameters
+#[derive(Accounts)]
+pub struct UpdateEModeCategory<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump,
+        has_one = authority @ LendingError::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"emode_category", emode_category.id.to_le_bytes().as_ref()],
+        bump = emode_category.bump
+    )]
+    pub emode_category: Account<'info, EModeCategory>,
+}
+
+pub fn update_handler(
+    ctx: Context<UpdateEModeCategory>,
+    ltv_bps: u16,
+    liquidation_threshold_bps: u16,
+    liquidation_bonus_bps: u16,
+) -> Result<()> {
+    let emode_category = &mut ctx.accounts.emode_category;
+    emode_category.set_params(ltv_bps, liquidation_threshold_bps, liquidation_bonus_bps)?;
+
+    emit!(EModeCategoryUpdated {
+        emode_category: emode_category.key(),
+        id: emode_category.id,
+        ltv_bps,
+        liquidation_threshold_bps,
+        liquidation_bonus_bps,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Add or remove an e-mode category member market
+#[derive(Accounts)]
+pub struct UpdateEModeMarket<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump,
+        has_one = authority @ LendingError::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"emode_category", emode_category.id.to_le_bytes().as_ref()],
+        bump = emode_category.bump
+    )]
+    pub emode_category: Account<'info, EModeCategory>,
+
+    #[account(
+        seeds = [b"market", market.asset_mint.as_ref()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, Market>,
+}
+
+pub fn add_market_handler(ctx: Context<UpdateEModeMarket>) -> Result<()> {
+    let emode_category = &mut ctx.accounts.emode_category;
+    let market = ctx.accounts.market.key();
+
+    require!(!emode_category.contains(&market), LendingError::InvalidEModeCategory);
+    require!(
+        emode_category.markets.len() < MAX_EMODE_MARKETS as usize,
+        LendingError::EModeCategoryFull
+    );
+    emode_category.markets.push(market);
+
+    emit!(EModeMarketUpdated {
+        emode_category: emode_category.key(),
+        market,
+        added: true,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+pub fn remove_market_handler(ctx: Context<UpdateEModeMarket>) -> Result<()> {
+    let emode_category = &mut ctx.accounts.emode_category;
+    let market = ctx.accounts.market.key();
+
+    let index = emode_category.markets
+        .iter()
+        .position(|member| *member == market)
+        .ok_or(LendingError::InvalidEModeCategory)?;
+    emode_category.markets.remove(index);
+
+    emit!(EModeMarketUpdated {
+        emode_category: emode_category.key(),
+        market,
+        added: false,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Opt an obligation into (or out of) an e-mode category
+///
+/// Every existing borrow must be from a member market, and the obligation must
+/// stay within its borrow limit under the new parameters.
+/// Remaining accounts: `[market, oracle]` for each deposit, then for each borrow.
+#[derive(Accounts)]
+pub struct SetObligationEMode<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"obligation", owner.key().as_ref()],
+        bump = obligation.bump
+    )]
+    pub obligation: Account<'info, Obligation>,
+
+    /// Category to enter (omit to leave e-mode)
+    #[account(
+        seeds = [b"emode_category", emode_category.id.to_le_bytes().as_ref()],
+        bump = emode_category.bump
+    )]
+    pub emode_category: Option<Account<'info, EModeCategory>>,
+}
+
+pub fn set_obligation_handler(ctx: Context<SetObligationEMode>) -> Result<()> {
+    let obligation = &mut ctx.accounts.obligation;
+    let emode_category = ctx.accounts.emode_category.as_deref();
+    let clock = Clock::get()?;
+
+    obligation.require_not_liquidating()?;
+
+    if let Some(category) = emode_category {
+        require!(
+            obligation.borrows.iter().all(|borrow| category.contains(&borrow.market)),
+            LendingError::EModeMarketNotAllowed
+        );
+    }
+
+    let values = calculate_obligation_values(
+        obligation,
+        ctx.remaining_accounts,
+        &[],
+        emode_category,
+        &clock,
+    )?;
+    require!(
+        values.is_within_borrow_limit(),
+        LendingError::BorrowWouldCauseLiquidation
+    );
+
+    obligation.emode_category = emode_category.map_or(EMODE_NONE, |category| category.id);
+    obligation.last_updated = clock.unix_timestamp;
+
+    emit!(ObligationEModeSet {
+        obligation: obligation.key(),
+        emode_category: obligation.emode_category,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct EModeCategoryUpdated {
+    pub emode_category: Pubkey,
+    pub id: u8,
+    pub ltv_bps: u16,
+    pub liquidation_threshold_bps: u16,
+    pub liquidation_bonus_bps: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct EModeMarketUpdated {
+    pub emode_category: Pubkey,
+    pub market: Pubkey,
+    pub added: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ObligationEModeSet {
+    pub obligation: Pubkey,
+    pub emode_category: u8,
+    pub timestamp: i64,
+}
@@ -0,0 +1,294 @@
+use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+use anchor_lang::solana_program::sysvar::instructions::{
+    load_current_index_checked,
+    load_instruction_at_checked,
+};
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+use crate::state::*;
+use crate::errors::LendingError;
+use crate::instructions::liquidate::{seize_collateral, settle_liquidation, Liquidated};
+
+/// Position of the obligation in `FlashLiquidateEnd`'s accounts, read through
+/// instruction introspection
+const FLASH_LIQUIDATE_END_OBLIGATION_INDEX: usize = 1;
+
+/// Start a flash liquidation
+///
+/// Seizes collateral before the debt is repaid, so a liquidator without the
+/// borrowed asset can swap the collateral for it within the same transaction.
+/// A `flash_liquidate_end` instruction for the same obligation must follow in
+/// the transaction; it is checked through instruction introspection, and the
+/// whole transaction fails if the debt is not repaid there.
+/// Remaining accounts: `[market, oracle]` for each deposit, then for each borrow.
+#[derive(Accounts)]
+pub struct FlashLiquidateBegin<'info> {
+    #[account(mut)]
+    pub liquidator: Signer<'info>,
+
+    /// Obligation being liquidated
+    #[account(
+        mut,
+        seeds = [b"obligation", obligation.owner.as_ref()],
+        bump = obligation.bump
+    )]
+    pub obligation: Account<'info, Obligation>,
+
+    /// Market for the borrowed asset
+    #[account(
+        mut,
+        seeds = [b"market", borrow_market.asset_mint.as_ref()],
+        bump = borrow_market.bump
+    )]
+    pub borrow_market: Account<'info, Market>,
+
+    /// Market for the collateral asset
+    #[account(
+        mut,
+        seeds = [b"market", collateral_market.asset_mint.as_ref()],
+        bump = collateral_market.bump,
+        constraint = collateral_market.key() != borrow_market.key() @ LendingError::InvalidMarketConfig
+    )]
+    pub collateral_market: Account<'info, Market>,
+
+    /// Collateral market reserve vault (source of seized collateral)
+    #[account(
+        mut,
+        constraint = collateral_reserve_vault.key() == collateral_market.reserve_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub collateral_reserve_vault: Account<'info, TokenAccount>,
+
+    /// Collateral market collateral vault (holds the obligation's supply tokens)
+    #[account(
+        mut,
+        constraint = collateral_vault.key() == collateral_market.collateral_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+
+    /// Collateral market supply mint (seized supply tokens are burned)
+    #[account(
+        mut,
+        constraint = collateral_supply_mint.key() == collateral_market.supply_mint @ LendingError::InvalidMarketConfig
+    )]
+    pub collateral_supply_mint: Account<'info, Mint>,
+
+    /// Liquidator's collateral token account (destination for seized collateral)
+    #[account(
+        mut,
+        constraint = liquidator_collateral_account.owner == liquidator.key() @ LendingError::Unauthorized,
+        constraint = liquidator_collateral_account.mint == collateral_market.asset_mint @ LendingError::InvalidMarketConfig
+    )]
+    pub liquidator_collateral_account: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// Treasury's collateral token account (destination for the protocol's share of the bonus)
+    #[account(
+        mut,
+        constraint = treasury_collateral_account.owner == global_config.treasury @ LendingError::Unauthorized,
+        constraint = treasury_collateral_account.mint == collateral_market.asset_mint @ LendingError::InvalidMarketConfig
+    )]
+    pub treasury_collateral_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Oracle for borrowed asset price
+    #[account(
+        constraint = borrow_oracle.key() == borrow_market.oracle @ LendingError::InvalidOracle
+    )]
+    pub borrow_oracle: UncheckedAccount<'info>,
+
+    /// CHECK: Oracle for collateral asset price
+    #[account(
+        constraint = collateral_oracle.key() == collateral_market.oracle @ LendingError::InvalidOracle
+    )]
+    pub collateral_oracle: UncheckedAccount<'info>,
+
+    /// E-mode category the obligation opted into, if any
+    #[account(
+        seeds = [b"emode_category", obligation.emode_category.to_le_bytes().as_ref()],
+        bump = emode_category.bump
+    )]
+    pub emode_category: Option<Account<'info, EModeCategory>>,
+
+    /// CHECK: Instructions sysvar, validated by address
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn handler(
+    ctx: Context<FlashLiquidateBegin>,
+    repay_amount: u64,
+    min_collateral_amount: u64,
+) -> Result<()> {
+    require_flash_liquidate_end(
+        &ctx.accounts.instructions_sysvar,
+        &ctx.accounts.obligation.key(),
+    )?;
+
+    let clock = Clock::get()?;
+    let amounts = settle_liquidation(
+        &mut ctx.accounts.obligation,
+        &mut ctx.accounts.borrow_market,
+        &mut ctx.accounts.collateral_market,
+        &ctx.accounts.borrow_oracle,
+        &ctx.accounts.collateral_oracle,
+        ctx.accounts.emode_category.as_deref(),
+        ctx.remaining_accounts,
+        repay_amount,
+        &clock,
+    )?;
+
+    // Record the debt flash_liquidate_end has to repay
+    let obligation = &mut ctx.accounts.obligation;
+    obligation.flash_liquidation_repay = amounts.repay_amount;
+    obligation.flash_liquidation_market = ctx.accounts.borrow_market.key();
+
+    let protocol_fee = seize_collateral(
+        &mut ctx.accounts.collateral_market,
+        &ctx.accounts.collateral_reserve_vault,
+        &ctx.accounts.collateral_vault,
+        &ctx.accounts.collateral_supply_mint,
+        &ctx.accounts.liquidator_collateral_account,
+        &ctx.accounts.treasury_collateral_account,
+        &ctx.accounts.token_program,
+        &amounts,
+        min_collateral_amount,
+    )?;
+
+    emit!(Liquidated {
+        borrow_market: ctx.accounts.borrow_market.key(),
+        collateral_market: ctx.accounts.collateral_market.key(),
+        obligation: ctx.accounts.obligation.key(),
+        liquidator: ctx.accounts.liquidator.key(),
+        repay_amount: amounts.repay_amount,
+        collateral_amount: amounts.collateral_amount,
+        protocol_fee,
+        health_factor: amounts.health_factor,
+        liquidation_bonus_bps: amounts.liquidation_bonus_bps,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Check a `flash_liquidate_end` for the obligation follows in the transaction
+fn require_flash_liquidate_end(instructions_sysvar: &AccountInfo, obligation: &Pubkey) -> Result<()> {
+    let current_index = load_current_index_checked(instructions_sysvar)? as usize;
+
+    let mut index = current_index + 1;
+    while let Ok(instruction) = load_instruction_at_checked(index, instructions_sysvar) {
+        let is_end = instruction.program_id == crate::ID
+            && instruction.data.get(..8) == Some(&crate::instruction::FlashLiquidateEnd::DISCRIMINATOR[..])
+            && instruction.accounts.get(FLASH_LIQUIDATE_END_OBLIGATION_INDEX).map(|meta| meta.pubkey) == Some(*obligation);
+        if is_end {
+            return Ok(());
+        }
+        index += 1;
+    }
+
+    err!(LendingError::MissingFlashLiquidationEnd)
+}
+
+/// Repay the debt of a flash liquidation started earlier in the transaction
+#[derive(Accounts)]
+pub struct FlashLiquidateEnd<'info> {
+    pub liquidator: Signer<'info>,
+
+    /// Obligation being liquidated (must stay at `FLASH_LIQUIDATE_END_OBLIGATION_INDEX`)
+    #[account(
+        mut,
+        seeds = [b"obligation", obligation.owner.as_ref()],
+        bump = obligation.bump
+    )]
+    pub obligation: Account<'info, Obligation>,
+
+    /// Market for the borrowed asset
+    #[account(
+        seeds = [b"market", borrow_market.asset_mint.as_ref()],
+        bump = borrow_market.bump,
+        constraint = borrow_market.key() == obligation.flash_liquidation_market @ LendingError::NoFlashLiquidation
+    )]
+    pub borrow_market: Account<'info, Market>,
+
+    /// Liquidator's token account (source of repayment)
+    #[account(
+        mut,
+        constraint = liquidator_token_account.owner == liquidator.key() @ LendingError::Unauthorized,
+        constraint = liquidator_token_account.mint == borrow_market.asset_mint @ LendingError::InvalidMarketConfig
+    )]
+    pub liquidator_token_account: Account<'info, TokenAccount>,
+
+    /// Borrow market reserve vault (destination for repayment)
+    #[account(
+        mut,
+        constraint = borrow_reserve_vault.key() == borrow_market.reserve_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub borrow_reserve_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn end_handler(ctx: Context<FlashLiquidateEnd>) -> Result<()> {
+    let obligation = &mut ctx.accounts.obligation;
+    let repay_amount = obligation.flash_liquidation_repay;
+    require!(repay_amount > 0, LendingError::NoFlashLiquidation);
+
+    // Market and obligation state were settled when the liquidation began
+    let transfer_ctx = CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        Transfer {
+            from: ctx.accounts.liquidator_token_account.to_account_info(),
+            to: ctx.accounts.borrow_reserve_vault.to_account_info(),
+            authority: ctx.accounts.liquidator.to_account_info(),
+        },
+    );
+    token::transfer(transfer_ctx, repay_amount)?;
+
+    obligation.flash_liquidation_repay = 0;
+    obligation.flash_liquidation_market = Pubkey::default();
+
+    emit!(FlashLiquidationRepaid {
+        obligation: obligation.key(),
+        borrow_market: ctx.accounts.borrow_market.key(),
+        liquidator: ctx.accounts.liquidator.key(),
+        repay_amount,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct FlashLiquidationRepaid {
+    pub obligation: Pubkey,
+    pub borrow_market: Pubkey,
+    pub liquidator: Pubkey,
+    pub repay_amount: u64,
+    pub timestamp: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flash_liquidate_end_obligation_index_matches_account_order() {
+        let obligation = Pubkey::new_unique();
+        let accounts = crate::accounts::FlashLiquidateEnd {
+            liquidator: Pubkey::new_unique(),
+            obligation,
+            borrow_market: Pubkey::new_unique(),
+            liquidator_token_account: Pubkey::new_unique(),
+            borrow_reserve_vault: Pubkey::new_unique(),
+            token_program: Pubkey::new_unique(),
+        };
+
+        let metas = accounts.to_account_metas(None);
+        assert_eq!(metas[FLASH_LIQUIDATE_END_OBLIGATION_INDEX].pubkey, obligation);
+    }
+}
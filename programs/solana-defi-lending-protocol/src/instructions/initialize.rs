@@ -1,5 +1,7 @@
 use anchor_lang::prelude::*;
 use crate::state::*;
+use crate::errors::LendingError;
+use crate::constants::MAX_SWAP_PROGRAMS;
 
 /// Initialize the global protocol configuration
 /// 
@@ -55,3 +57,68 @@ pub struct ProtocolInitialized {
     pub treasury: Pubkey,
     pub timestamp: i64,
 }
+
+/// Update protocol-wide settings
+/// 
+/// Only the protocol authority stored in `GlobalConfig` can change them.
+#[derive(Accounts)]
+pub struct UpdateGlobalConfig<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump,
+        has_one = authority @ LendingError::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
+
+/// Allow a swap program for position-management instructions
+pub fn add_swap_program_handler(ctx: Context<UpdateGlobalConfig>, swap_program: Pubkey) -> Result<()> {
+    let global_config = &mut ctx.accounts.global_config;
+
+    require!(
+        !global_config.is_swap_program_allowed(&swap_program),
+        LendingError::InvalidSwapProgram
+    );
+    require!(
+        global_config.swap_programs.len() < MAX_SWAP_PROGRAMS as usize,
+        LendingError::SwapProgramAllowlistFull
+    );
+    global_config.swap_programs.push(swap_program);
+
+    emit!(SwapProgramUpdated {
+        swap_program,
+        allowed: true,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Remove a swap program from the allowlist
+pub fn remove_swap_program_handler(ctx: Context<UpdateGlobalConfig>, swap_program: Pubkey) -> Result<()> {
+    let global_config = &mut ctx.accounts.global_config;
+
+    let index = global_config.swap_programs
+        .iter()
+        .position(|program| *program == swap_program)
+        .ok_or(LendingError::InvalidSwapProgram)?;
+    global_config.swap_programs.remove(index);
+
+    emit!(SwapProgramUpdated {
+        swap_program,
+        allowed: false,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct SwapProgramUpdated {
+    pub swap_program: Pubkey,
+    pub allowed: bool,
+    pub timestamp: i64,
+}
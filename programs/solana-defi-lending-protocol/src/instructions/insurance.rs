@@ -0,0 +1,632 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Burn, Mint, MintTo, Token, TokenAccount, Transfer};
+use crate::state::*;
+use crate::errors::LendingError;
+use crate::math::apply_bps;
+
+/// Create the insurance fund for a market's asset
+///
+/// The share mint must be created beforehand with the fund PDA as mint
+/// authority and no supply; the vault must be owned by the fund PDA.
+#[derive(Accounts)]
+pub struct CreateInsuranceFund<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump,
+        has_one = authority @ LendingError::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"market", market.asset_mint.as_ref()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = InsuranceFund::SIZE,
+        seeds = [b"insurance_fund", market.asset_mint.as_ref()],
+        bump
+    )]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+
+    /// Share token mint
+    #[account(
+        constraint = share_mint.mint_authority == Some(insurance_fund.key()).into() @ LendingError::InvalidInsuranceFund,
+        constraint = share_mint.supply == 0 @ LendingError::InvalidInsuranceFund
+    )]
+    pub share_mint: Account<'info, Mint>,
+
+    /// Vault holding staked assets
+    #[account(
+        constraint = vault.mint == market.asset_mint @ LendingError::InvalidInsuranceFund,
+        constraint = vault.owner == insurance_fund.key() @ LendingError::InvalidInsuranceFund
+    )]
+    pub vault: Account<'info, TokenAccount>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(
+    ctx: Context<CreateInsuranceFund>,
+    cooldown_period: i64,
+    max_slash_bps: u16,
+    fee_share_bps: u16,
+) -> Result<()> {
+    let insurance_fund = &mut ctx.accounts.insurance_fund;
+    let market = &mut ctx.accounts.market;
+    let clock = Clock::get()?;
+
+    insurance_fund.asset_mint = market.asset_mint;
+    insurance_fund.share_mint = ctx.accounts.share_mint.key();
+    insurance_fund.vault = ctx.accounts.vault.key();
+    insurance_fund.total_slashed = 0;
+    insurance_fund.slash_period_start = 0;
+    insurance_fund.slash_period_balance = 0;
+    insurance_fund.slashed_this_period = 0;
+    insurance_fund.created_at = clock.unix_timestamp;
+    insurance_fund.bump = ctx.bumps.get("insurance_fund").copied().unwrap();
+    insurance_fund.set_params(cooldown_period, max_slash_bps, fee_share_bps)?;
+
+    market.insurance_fund = insurance_fund.key();
+
+    emit!(InsuranceFundCreated {
+        insurance_fund: insurance_fund.key(),
+        asset_mint: market.asset_mint,
+        cooldown_period,
+        max_slash_bps,
+        fee_share_bps,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Stake assets in an insurance fund for share tokens
+#[derive(Accounts)]
+pub struct StakeInsurance<'info> {
+    pub staker: Signer<'info>,
+
+    #[account(
+        seeds = [b"insurance_fund", insurance_fund.asset_mint.as_ref()],
+        bump = insurance_fund.bump
+    )]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+
+    #[account(
+        mut,
+        constraint = vault.key() == insurance_fund.vault @ LendingError::InvalidInsuranceFund
+    )]
+    pub vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = share_mint.key() == insurance_fund.share_mint @ LendingError::InvalidInsuranceFund
+    )]
+    pub share_mint: Account<'info, Mint>,
+
+    /// Staker's asset account (source of stake)
+    #[account(
+        mut,
+        constraint = staker_token_account.owner == staker.key() @ LendingError::Unauthorized,
+        constraint = staker_token_account.mint == insurance_fund.asset_mint @ LendingError::InvalidInsuranceFund
+    )]
+    pub staker_token_account: Account<'info, TokenAccount>,
+
+    /// Staker's share account (destination for shares)
+    #[account(
+        mut,
+        constraint = staker_share_account.mint == insurance_fund.share_mint @ LendingError::InvalidInsuranceFund
+    )]
+    pub staker_share_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn stake_handler(ctx: Context<StakeInsurance>, amount: u64) -> Result<()> {
+    require!(amount > 0, LendingError::InvalidAmount);
+
+    let insurance_fund = &ctx.accounts.insurance_fund;
+    let shares = insurance_fund.shares_for_amount(
+        amount,
+        ctx.accounts.vault.amount,
+        ctx.accounts.share_mint.supply,
+    )?;
+    require!(shares > 0, LendingError::InvalidAmount);
+
+    let transfer_ctx = CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        Transfer {
+            from: ctx.accounts.staker_token_account.to_account_info(),
+            to: ctx.accounts.vault.to_account_info(),
+            authority: ctx.accounts.staker.to_account_info(),
+        },
+    );
+    token::transfer(transfer_ctx, amount)?;
+
+    let seeds = &[
+        b"insurance_fund",
+        insurance_fund.asset_mint.as_ref(),
+        &[insurance_fund.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    let mint_ctx = CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        MintTo {
+            mint: ctx.accounts.share_mint.to_account_info(),
+            to: ctx.accounts.staker_share_account.to_account_info(),
+            authority: insurance_fund.to_account_info(),
+        },
+        signer,
+    );
+    token::mint_to(mint_ctx, shares)?;
+
+    emit!(InsuranceStaked {
+        insurance_fund: insurance_fund.key(),
+        staker: ctx.accounts.staker.key(),
+        amount,
+        shares,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Start the unstake cooldown for insurance fund shares
+///
+/// A new request replaces the previous one and restarts the cooldown.
+#[derive(Accounts)]
+pub struct RequestUnstake<'info> {
+    #[account(mut)]
+    pub staker: Signer<'info>,
+
+    #[account(
+        seeds = [b"insurance_fund", insurance_fund.asset_mint.as_ref()],
+        bump = insurance_fund.bump
+    )]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+
+    #[account(
+        init_if_needed,
+        payer = staker,
+        space = StakeCooldown::SIZE,
+        seeds = [b"insurance_cooldown", insurance_fund.key().as_ref(), staker.key().as_ref()],
+        bump
+    )]
+    pub stake_cooldown: Account<'info, StakeCooldown>,
+
+    /// Staker's share account
+    #[account(
+        constraint = staker_share_account.owner == staker.key() @ LendingError::Unauthorized,
+        constraint = staker_share_account.mint == insurance_fund.share_mint @ LendingError::InvalidInsuranceFund
+    )]
+    pub staker_share_account: Account<'info, TokenAccount>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn request_unstake_handler(ctx: Context<RequestUnstake>, shares: u64) -> Result<()> {
+    require!(
+        shares > 0 && shares <= ctx.accounts.staker_share_account.amount,
+        LendingError::InvalidAmount
+    );
+
+    let stake_cooldown = &mut ctx.accounts.stake_cooldown;
+    let clock = Clock::get()?;
+
+    stake_cooldown.owner = ctx.accounts.staker.key();
+    stake_cooldown.insurance_fund = ctx.accounts.insurance_fund.key();
+    stake_cooldown.shares = shares;
+    stake_cooldown.cooldown_end = clock.unix_timestamp
+        .checked_add(ctx.accounts.insurance_fund.cooldown_period)
+        .ok_or(LendingError::MathOverflow)?;
+    stake_cooldown.bump = ctx.bumps.get("stake_cooldown").copied().unwrap();
+
+    emit!(InsuranceUnstakeRequested {
+        insurance_fund: ctx.accounts.insurance_fund.key(),
+        staker: ctx.accounts.staker.key(),
+        shares,
+        cooldown_end: stake_cooldown.cooldown_end,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Burn insurance fund shares for assets after the cooldown
+///
+/// Must happen within the unstake window that follows the cooldown.
+#[derive(Accounts)]
+pub struct Unstake<'info> {
+    pub staker: Signer<'info>,
+
+    #[account(
+        seeds = [b"insurance_fund", insurance_fund.asset_mint.as_ref()],
+        bump = insurance_fund.bump
+    )]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+
+    #[account(
+        mut,
+        seeds = [b"insurance_cooldown", insurance_fund.key().as_ref(), staker.key().as_ref()],
+        bump = stake_cooldown.bump
+    )]
+    pub stake_cooldown: Account<'info, StakeCooldown>,
+
+    #[account(
+        mut,
+        constraint = vault.key() == insurance_fund.vault @ LendingError::InvalidInsuranceFund
+    )]
+    pub vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = share_mint.key() == insurance_fund.share_mint @ LendingError::InvalidInsuranceFund
+    )]
+    pub share_mint: Account<'info, Mint>,
+
+    /// Staker's share account (shares are burned from here)
+    #[account(
+        mut,
+        constraint = staker_share_account.owner == staker.key() @ LendingError::Unauthorized,
+        constraint = staker_share_account.mint == insurance_fund.share_mint @ LendingError::InvalidInsuranceFund
+    )]
+    pub staker_share_account: Account<'info, TokenAccount>,
+
+    /// Staker's asset account (destination for assets)
+    #[account(
+        mut,
+        constraint = staker_token_account.mint == insurance_fund.asset_mint @ LendingError::InvalidInsuranceFund
+    )]
+    pub staker_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn unstake_handler(ctx: Context<Unstake>, shares: u64) -> Result<()> {
+    require!(shares > 0, LendingError::InvalidAmount);
+
+    let insurance_fund = &ctx.accounts.insurance_fund;
+    let stake_cooldown = &mut ctx.accounts.stake_cooldown;
+    let clock = Clock::get()?;
+
+    stake_cooldown.require_unstake_window(clock.unix_timestamp)?;
+    require!(shares <= stake_cooldown.shares, LendingError::InvalidAmount);
+
+    // Shares are valued after any slashing, so stakers bear the losses
+    let amount = insurance_fund.amount_for_shares(
+        shares,
+        ctx.accounts.vault.amount,
+        ctx.accounts.share_mint.supply,
+    )?;
+    stake_cooldown.shares -= shares;
+
+    let burn_ctx = CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        Burn {
+            mint: ctx.accounts.share_mint.to_account_info(),
+            from: ctx.accounts.staker_share_account.to_account_info(),
+            authority: ctx.accounts.staker.to_account_info(),
+        },
+    );
+    token::burn(burn_ctx, shares)?;
+
+    let seeds = &[
+        b"insurance_fund",
+        insurance_fund.asset_mint.as_ref(),
+        &[insurance_fund.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    let transfer_ctx = CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        Transfer {
+            from: ctx.accounts.vault.to_account_info(),
+            to: ctx.accounts.staker_token_account.to_account_info(),
+            authority: insurance_fund.to_account_info(),
+        },
+        signer,
+    );
+    token::transfer(transfer_ctx, amount)?;
+
+    emit!(InsuranceUnstaked {
+        insurance_fund: insurance_fund.key(),
+        staker: ctx.accounts.staker.key(),
+        amount,
+        shares,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Slash an insurance fund to cover a shortfall
+///
+/// Only the protocol authority can slash directly; bad debt socialization
+/// slashes the fund on its own. Slashes within a slash period are capped in
+/// total at `max_slash_bps` of the fund's balance when the period started.
+#[derive(Accounts)]
+pub struct SlashInsuranceFund<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump,
+        has_one = authority @ LendingError::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"insurance_fund", insurance_fund.asset_mint.as_ref()],
+        bump = insurance_fund.bump
+    )]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+
+    #[account(
+        mut,
+        constraint = vault.key() == insurance_fund.vault @ LendingError::InvalidInsuranceFund
+    )]
+    pub vault: Account<'info, TokenAccount>,
+
+    /// Destination for the slashed assets
+    #[account(
+        mut,
+        constraint = destination.mint == insurance_fund.asset_mint @ LendingError::InvalidInsuranceFund
+    )]
+    pub destination: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn slash_handler(ctx: Context<SlashInsuranceFund>, amount: u64) -> Result<()> {
+    require!(amount > 0, LendingError::InvalidAmount);
+    let clock = Clock::get()?;
+    require!(
+        amount <= ctx.accounts.insurance_fund.available_slash(ctx.accounts.vault.amount, &clock)?,
+        LendingError::InsuranceSlashLimitExceeded
+    );
+
+    slash_insurance_fund(
+        &mut ctx.accounts.insurance_fund,
+        &ctx.accounts.vault,
+        ctx.accounts.destination.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        amount,
+    )
+}
+
+/// Move slashed assets out of an insurance fund's vault
+pub(crate) fn slash_insurance_fund<'info>(
+    insurance_fund: &mut Account<'info, InsuranceFund>,
+    vault: &Account<'info, TokenAccount>,
+    destination: AccountInfo<'info>,
+    token_program: AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    let seeds = &[
+        b"insurance_fund",
+        insurance_fund.asset_mint.as_ref(),
+        &[insurance_fund.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    let transfer_ctx = CpiContext::new_with_signer(
+        token_program,
+        Transfer {
+            from: vault.to_account_info(),
+            to: destination.clone(),
+            authority: insurance_fund.to_account_info(),
+        },
+        signer,
+    );
+    token::transfer(transfer_ctx, amount)?;
+
+    insurance_fund.record_slash(amount)?;
+
+    emit!(InsuranceFundSlashed {
+        insurance_fund: insurance_fund.key(),
+        destination: destination.key(),
+        amount,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Collect a market's accumulated protocol interest fees
+///
+/// Permissionless. The insurance fund's `fee_share_bps` cut goes to its
+/// stakers and the rest to the treasury.
+#[derive(Accounts)]
+pub struct CollectProtocolFees<'info> {
+    #[account(
+        mut,
+        seeds = [b"market", market.asset_mint.as_ref()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, Market>,
+
+    /// Reserve vault (source of fees)
+    #[account(
+        mut,
+        constraint = reserve_vault.key() == market.reserve_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub reserve_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// Treasury's token account for the market asset
+    #[account(
+        mut,
+        constraint = treasury_token_account.owner == global_config.treasury @ LendingError::Unauthorized,
+        constraint = treasury_token_account.mint == market.asset_mint @ LendingError::InvalidMarketConfig
+    )]
+    pub treasury_token_account: Account<'info, TokenAccount>,
+
+    /// Insurance fund for the market asset (required once one exists)
+    #[account(
+        seeds = [b"insurance_fund", market.asset_mint.as_ref()],
+        bump = insurance_fund.bump
+    )]
+    pub insurance_fund: Option<Account<'info, InsuranceFund>>,
+
+    /// Insurance fund vault
+    #[account(mut)]
+    pub insurance_vault: Option<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn collect_protocol_fees_handler(ctx: Context<CollectProtocolFees>) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+    let clock = Clock::get()?;
+
+    market.accrue_interest(&clock)?;
+
+    let amount = market.accumulated_protocol_fees.min(ctx.accounts.reserve_vault.amount);
+    require!(amount > 0, LendingError::InvalidAmount);
+    market.accumulated_protocol_fees -= amount;
+
+    require_insurance_fund(
+        market,
+        ctx.accounts.insurance_fund.as_ref(),
+        ctx.accounts.insurance_vault.as_ref(),
+    )?;
+    let to_insurance = match &ctx.accounts.insurance_fund {
+        Some(fund) => apply_bps(amount, fund.fee_share_bps)?,
+        None => 0,
+    };
+    let to_treasury = amount - to_insurance;
+
+    let seeds = &[
+        b"market",
+        market.asset_mint.as_ref(),
+        &[market.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    if let Some(vault) = ctx.accounts.insurance_vault.as_ref().filter(|_| to_insurance > 0) {
+        let insurance_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.reserve_vault.to_account_info(),
+                to: vault.to_account_info(),
+                authority: market.to_account_info(),
+            },
+            signer,
+        );
+        token::transfer(insurance_ctx, to_insurance)?;
+    }
+
+    if to_treasury > 0 {
+        let treasury_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.reserve_vault.to_account_info(),
+                to: ctx.accounts.treasury_token_account.to_account_info(),
+                authority: market.to_account_info(),
+            },
+            signer,
+        );
+        token::transfer(treasury_ctx, to_treasury)?;
+    }
+
+    emit!(ProtocolFeesCollected {
+        market: market.key(),
+        to_insurance,
+        to_treasury,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Check the insurance fund accounts passed to an instruction match the market
+///
+/// Once a market has an insurance fund it must be passed, so callers can't
+/// skip it to route fees or losses elsewhere.
+pub(crate) fn require_insurance_fund(
+    market: &Market,
+    insurance_fund: Option<&Account<InsuranceFund>>,
+    insurance_vault: Option<&Account<TokenAccount>>,
+) -> Result<()> {
+    if market.insurance_fund == Pubkey::default() {
+        require!(
+            insurance_fund.is_none() && insurance_vault.is_none(),
+            LendingError::InvalidInsuranceFund
+        );
+        return Ok(());
+    }
+
+    let fund = insurance_fund.ok_or(LendingError::InvalidInsuranceFund)?;
+    let vault = insurance_vault.ok_or(LendingError::InvalidInsuranceFund)?;
+    require!(fund.key() == market.insurance_fund, LendingError::InvalidInsuranceFund);
+    require!(vault.key() == fund.vault, LendingError::InvalidInsuranceFund);
+
+    Ok(())
+}
+
+#[event]
+pub struct InsuranceFundCreated {
+    pub insurance_fund: Pubkey,
+    pub asset_mint: Pubkey,
+    pub cooldown_period: i64,
+    pub max_slash_bps: u16,
+    pub fee_share_bps: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct InsuranceStaked {
+    pub insurance_fund: Pubkey,
+    pub staker: Pubkey,
+    pub amount: u64,
+    pub shares: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct InsuranceUnstakeRequested {
+    pub insurance_fund: Pubkey,
+    pub staker: Pubkey,
+    pub shares: u64,
+    pub cooldown_end: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct InsuranceUnstaked {
+    pub insurance_fund: Pubkey,
+    pub staker: Pubkey,
+    pub amount: u64,
+    pub shares: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct InsuranceFundSlashed {
+    pub insurance_fund: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProtocolFeesCollected {
+    pub market: Pubkey,
+    pub to_insurance: u64,
+    pub to_treasury: u64,
+    pub timestamp: i64,
+}
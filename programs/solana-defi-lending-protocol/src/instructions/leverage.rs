@@ -0,0 +1,347 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Mint, MintTo, Token, TokenAccount, Transfer};
+use crate::state::*;
+use crate::errors::LendingError;
+use crate::constants::*;
+use crate::math::*;
+use crate::health::{calculate_obligation_values, require_emode_category};
+use crate::oracle::get_oracle_price;
+use crate::swap::SwapAdapter;
+use crate::instructions::refresh_market::observe_market_price;
+
+/// Open a leveraged ("multiply") position in one instruction
+///
+/// Deposits `deposit_amount` of the collateral asset and levers it up to
+/// `target_leverage_bps` of the deposit's value. The borrow reserve fronts the
+/// debt asset like a flash loan, it is swapped into more collateral, all of
+/// which is supplied as collateral, and recording the borrow against it repays
+/// the flash. One round reaches the target, so no manual borrow-swap loop is
+/// needed. The final position must be within the borrow limit.
+/// Remaining accounts: `[market, oracle]` for each deposit, then for each
+/// borrow, as they are once the position is opened.
+#[derive(Accounts)]
+pub struct OpenLeveragedPosition<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"obligation", owner.key().as_ref()],
+        bump = obligation.bump
+    )]
+    pub obligation: Account<'info, Obligation>,
+
+    /// Market the leverage is borrowed from
+    #[account(
+        mut,
+        seeds = [b"market", borrow_market.asset_mint.as_ref()],
+        bump = borrow_market.bump
+    )]
+    pub borrow_market: Account<'info, Market>,
+
+    /// Market for the leveraged collateral
+    #[account(
+        mut,
+        seeds = [b"market", collateral_market.asset_mint.as_ref()],
+        bump = collateral_market.bump,
+        constraint = collateral_market.key() != borrow_market.key() @ LendingError::InvalidMarketConfig
+    )]
+    pub collateral_market: Account<'info, Market>,
+
+    /// Borrow market reserve vault (swap input)
+    #[account(
+        mut,
+        constraint = borrow_reserve_vault.key() == borrow_market.reserve_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub borrow_reserve_vault: Account<'info, TokenAccount>,
+
+    /// Collateral market reserve vault (receives the deposit and the swap output)
+    #[account(
+        mut,
+        constraint = collateral_reserve_vault.key() == collateral_market.reserve_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub collateral_reserve_vault: Account<'info, TokenAccount>,
+
+    /// Collateral market collateral vault (receives the position's supply tokens)
+    #[account(
+        mut,
+        constraint = collateral_vault.key() == collateral_market.collateral_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+
+    /// Collateral market supply mint
+    #[account(
+        mut,
+        constraint = collateral_supply_mint.key() == collateral_market.supply_mint @ LendingError::InvalidMarketConfig
+    )]
+    pub collateral_supply_mint: Account<'info, Mint>,
+
+    /// Owner's collateral token account (source of the deposit)
+    #[account(
+        mut,
+        constraint = owner_collateral_account.owner == owner.key() @ LendingError::Unauthorized,
+        constraint = owner_collateral_account.mint == collateral_market.asset_mint @ LendingError::InvalidMarketConfig
+    )]
+    pub owner_collateral_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Oracle for borrowed asset price
+    #[account(
+        constraint = borrow_oracle.key() == borrow_market.oracle @ LendingError::InvalidOracle
+    )]
+    pub borrow_oracle: UncheckedAccount<'info>,
+
+    /// CHECK: Oracle for collateral asset price
+    #[account(
+        constraint = collateral_oracle.key() == collateral_market.oracle @ LendingError::InvalidOracle
+    )]
+    pub collateral_oracle: UncheckedAccount<'info>,
+
+    /// E-mode category the obligation opted into, if any
+    #[account(
+        seeds = [b"emode_category", obligation.emode_category.to_le_bytes().as_ref()],
+        bump = emode_category.bump
+    )]
+    pub emode_category: Option<Account<'info, EModeCategory>>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// CHECK: Swap program, must be allowlisted on GlobalConfig
+    #[account(
+        constraint = global_config.is_swap_program_allowed(&swap_program.key()) @ LendingError::InvalidSwapProgram
+    )]
+    pub swap_program: UncheckedAccount<'info>,
+
+    /// CHECK: Swap pool, validated by the swap program
+    #[account(mut)]
+    pub swap_pool: UncheckedAccount<'info>,
+
+    /// CHECK: Pool vault for the debt asset, validated by the swap program
+    #[account(mut)]
+    pub swap_pool_source_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Pool vault for the collateral asset, validated by the swap program
+    #[account(mut)]
+    pub swap_pool_destination_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Swap authority PDA, delegated the swap input for the duration of the swap
+    #[account(
+        seeds = [b"swap_authority", global_config.key().as_ref()],
+        bump
+    )]
+    pub swap_authority: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn handler(
+    ctx: Context<OpenLeveragedPosition>,
+    deposit_amount: u64,
+    target_leverage_bps: u32,
+    min_amount_out: u64,
+) -> Result<()> {
+    require!(deposit_amount > 0, LendingError::InvalidAmount);
+    require!(
+        target_leverage_bps > BPS_SCALE as u32,
+        LendingError::InvalidLeverage
+    );
+
+    let obligation = &mut ctx.accounts.obligation;
+    let borrow_market = &mut ctx.accounts.borrow_market;
+    let collateral_market = &mut ctx.accounts.collateral_market;
+    let clock = Clock::get()?;
+
+    // The obligation can't change while it is being liquidated
+    obligation.require_not_liquidating()?;
+
+    borrow_market.accrue_interest(&clock)?;
+    collateral_market.accrue_interest(&clock)?;
+
+    // Block borrows while the price circuit breaker is tripped;
+    // a new trip returns early so it isn't rolled back with an error
+    let borrow_price = get_oracle_price(&ctx.accounts.borrow_oracle, &clock)?;
+    let collateral_price = get_oracle_price(&ctx.accounts.collateral_oracle, &clock)?;
+    if observe_market_price(borrow_market, borrow_price, &clock)? {
+        return Ok(());
+    }
+    require!(
+        !borrow_market.is_circuit_breaker_active(clock.unix_timestamp),
+        LendingError::CircuitBreakerActive
+    );
+
+    // E-mode obligations can only borrow from member markets
+    let emode_category = require_emode_category(obligation, ctx.accounts.emode_category.as_deref())?;
+    if let Some(category) = emode_category {
+        require!(
+            category.contains(&borrow_market.key()),
+            LendingError::EModeMarketNotAllowed
+        );
+    }
+
+    // Borrow the deposit's value times the extra leverage
+    let deposit_value = calculate_market_value(deposit_amount, collateral_price, collateral_market.mint_decimals)?;
+    let borrow_value = (deposit_value as u128)
+        .checked_mul(target_leverage_bps as u128 - BPS_SCALE as u128)
+        .ok_or(LendingError::MathOverflow)?
+        .checked_div(BPS_SCALE as u128)
+        .ok_or(LendingError::MathOverflow)?;
+    let borrow_amount = calculate_amount_from_value(
+        u64::try_from(borrow_value).map_err(|_| LendingError::MathOverflow)?,
+        borrow_price,
+        borrow_market.mint_decimals,
+    )?;
+    require!(borrow_amount >= MIN_BORROW_AMOUNT, LendingError::InvalidAmount);
+
+    // Bound how fast the reserve can be drained
+    borrow_market.record_outflow(borrow_amount, &clock)?;
+    require!(
+        borrow_amount <= ctx.accounts.borrow_reserve_vault.amount,
+        LendingError::InsufficientLiquidity
+    );
+
+    // Take the deposit
+    let deposit_ctx = CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        Transfer {
+            from: ctx.accounts.owner_collateral_account.to_account_info(),
+            to: ctx.accounts.collateral_reserve_vault.to_account_info(),
+            authority: ctx.accounts.owner.to_account_info(),
+        },
+    );
+    token::transfer(deposit_ctx, deposit_amount)?;
+
+    // Flash-borrow from the borrow reserve and swap into the collateral reserve
+    let borrow_seeds = &[
+        b"market",
+        borrow_market.asset_mint.as_ref(),
+        &[borrow_market.bump],
+    ];
+    let borrow_signer = &[&borrow_seeds[..]];
+
+    let global_config_key = ctx.accounts.global_config.key();
+    let swap_authority_bump = ctx.bumps.get("swap_authority").copied().unwrap();
+    let swap_authority_seeds: &[&[u8]] = &[
+        b"swap_authority",
+        global_config_key.as_ref(),
+        &[swap_authority_bump],
+    ];
+    let adapter = SwapAdapter {
+        program: &ctx.accounts.swap_program,
+        pool: &ctx.accounts.swap_pool,
+        source_vault: &ctx.accounts.swap_pool_source_vault,
+        destination_vault: &ctx.accounts.swap_pool_destination_vault,
+        authority: &ctx.accounts.swap_authority,
+        authority_seeds: swap_authority_seeds,
+    };
+    let amount_out = adapter.swap(
+        &mut ctx.accounts.borrow_reserve_vault,
+        &mut ctx.accounts.collateral_reserve_vault,
+        &borrow_market.to_account_info(),
+        &ctx.accounts.token_program.to_account_info(),
+        borrow_signer,
+        borrow_amount,
+        min_amount_out,
+    )?;
+
+    // Supply the deposit and the swap output as collateral
+    let collateral_amount = deposit_amount
+        .checked_add(amount_out)
+        .ok_or(LendingError::MathOverflow)?;
+    let supply_tokens = collateral_market.amount_to_supply_tokens(collateral_amount)?;
+
+    let collateral_seeds = &[
+        b"market",
+        collateral_market.asset_mint.as_ref(),
+        &[collateral_market.bump],
+    ];
+    let collateral_signer = &[&collateral_seeds[..]];
+
+    let mint_ctx = CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        MintTo {
+            mint: ctx.accounts.collateral_supply_mint.to_account_info(),
+            to: ctx.accounts.collateral_vault.to_account_info(),
+            authority: collateral_market.to_account_info(),
+        },
+        collateral_signer,
+    );
+    token::mint_to(mint_ctx, supply_tokens)?;
+
+    collateral_market.record_inflow(collateral_amount, &clock)?;
+    collateral_market.total_supplied = collateral_market.total_supplied
+        .checked_add(collateral_amount)
+        .ok_or(LendingError::MathOverflow)?;
+    collateral_market.total_supply_tokens = collateral_market.total_supply_tokens
+        .checked_add(supply_tokens)
+        .ok_or(LendingError::MathOverflow)?;
+
+    obligation.deposit(collateral_market.key(), collateral_market.isolated_collateral, supply_tokens)?;
+
+    // Isolated collateral can only back isolation-borrowable markets, up to its debt ceiling
+    if obligation.is_isolated() {
+        require!(borrow_market.isolation_borrowable, LendingError::IsolationBorrowNotAllowed);
+        collateral_market.add_isolation_debt(borrow_amount, borrow_market.mint_decimals)?;
+    }
+
+    // Recording the debt repays the flash borrow
+    obligation.borrow(borrow_market.key(), borrow_market, borrow_amount)?;
+    obligation.last_updated = clock.unix_timestamp;
+
+    let new_total_borrowed = borrow_market.total_borrowed
+        .checked_add(borrow_amount)
+        .ok_or(LendingError::MathOverflow)?;
+    let utilization_bps = calculate_utilization_rate(new_total_borrowed, borrow_market.total_supplied)?;
+    require!(
+        utilization_bps <= BPS_SCALE,
+        LendingError::InvalidUtilizationRate
+    );
+    borrow_market.total_borrowed = new_total_borrowed;
+
+    // The leveraged position must stay within the borrow limit
+    let values = calculate_obligation_values(
+        obligation,
+        ctx.remaining_accounts,
+        &[
+            (borrow_market.key(), borrow_market),
+            (collateral_market.key(), collateral_market),
+        ],
+        emode_category,
+        &clock,
+    )?;
+    require!(
+        values.is_within_borrow_limit(),
+        LendingError::BorrowWouldCauseLiquidation
+    );
+    let health_factor = values.health_factor()?;
+    obligation.update_liquidatable_since(health_factor, &clock);
+
+    emit!(LeveragedPositionOpened {
+        obligation: obligation.key(),
+        borrow_market: borrow_market.key(),
+        collateral_market: collateral_market.key(),
+        deposit_amount,
+        borrow_amount,
+        collateral_amount,
+        supply_tokens,
+        health_factor,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct LeveragedPositionOpened {
+    pub obligation: Pubkey,
+    pub borrow_market: Pubkey,
+    pub collateral_market: Pubkey,
+    pub deposit_amount: u64,
+    pub borrow_amount: u64,
+    /// Deposit plus swap output, all supplied as collateral
+    pub collateral_amount: u64,
+    pub supply_tokens: u64,
+    pub health_factor: u16,
+    pub timestamp: i64,
+}
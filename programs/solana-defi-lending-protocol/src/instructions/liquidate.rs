@@ -1,19 +1,33 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use anchor_spl::token::{self, Burn, Mint, Token, TokenAccount, Transfer};
 use crate::state::*;
 use crate::errors::LendingError;
 use crate::constants::*;
 use crate::math::*;
+use crate::health::{calculate_obligation_values, require_emode_category};
+use crate::oracle::get_oracle_price;
 
 /// Liquidate an unhealthy borrow position
-/// 
+///
 /// When a user's health factor drops below the threshold, liquidators can
 /// repay their debt at a discount (liquidation bonus) and seize collateral.
+/// The bonus runs as a Dutch auction between the collateral market's minimum
+/// and maximum, rising as health falls and as time passes. A share of the bonus
+/// set per market goes to the treasury.
+/// Remaining accounts: `[market, oracle]` for each deposit, then for each borrow.
 #[derive(Accounts)]
 pub struct Liquidate<'info> {
     #[account(mut)]
     pub liquidator: Signer<'info>,
 
+    /// Obligation being liquidated
+    #[account(
+        mut,
+        seeds = [b"obligation", obligation.owner.as_ref()],
+        bump = obligation.bump
+    )]
+    pub obligation: Account<'info, Obligation>,
+
     /// Market for the borrowed asset
     #[account(
         mut,
@@ -26,7 +40,8 @@ pub struct Liquidate<'info> {
     #[account(
         mut,
         seeds = [b"market", collateral_market.asset_mint.as_ref()],
-        bump = collateral_market.bump
+        bump = collateral_market.bump,
+        constraint = collateral_market.key() != borrow_market.key() @ LendingError::InvalidMarketConfig
     )]
     pub collateral_market: Account<'info, Market>,
 
@@ -41,17 +56,31 @@ pub struct Liquidate<'info> {
     /// Borrow market reserve vault (destination for repayment)
     #[account(
         mut,
-        constraint = borrow_reserve_vault.mint == borrow_market.asset_mint @ LendingError::InvalidMarketConfig
+        constraint = borrow_reserve_vault.key() == borrow_market.reserve_vault @ LendingError::InvalidMarketConfig
     )]
     pub borrow_reserve_vault: Account<'info, TokenAccount>,
 
     /// Collateral market reserve vault (source of seized collateral)
     #[account(
         mut,
-        constraint = collateral_reserve_vault.mint == collateral_market.asset_mint @ LendingError::InvalidMarketConfig
+        constraint = collateral_reserve_vault.key() == collateral_market.reserve_vault @ LendingError::InvalidMarketConfig
     )]
     pub collateral_reserve_vault: Account<'info, TokenAccount>,
 
+    /// Collateral market collateral vault (holds the obligation's supply tokens)
+    #[account(
+        mut,
+        constraint = collateral_vault.key() == collateral_market.collateral_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+
+    /// Collateral market supply mint (seized supply tokens are burned)
+    #[account(
+        mut,
+        constraint = collateral_supply_mint.key() == collateral_market.supply_mint @ LendingError::InvalidMarketConfig
+    )]
+    pub collateral_supply_mint: Account<'info, Mint>,
+
     /// Liquidator's collateral token account (destination for seized collateral)
     #[account(
         mut,
@@ -60,12 +89,39 @@ pub struct Liquidate<'info> {
     )]
     pub liquidator_collateral_account: Account<'info, TokenAccount>,
 
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// Treasury's collateral token account (destination for the protocol's share of the bonus)
+    #[account(
+        mut,
+        constraint = treasury_collateral_account.owner == global_config.treasury @ LendingError::Unauthorized,
+        constraint = treasury_collateral_account.mint == collateral_market.asset_mint @ LendingError::InvalidMarketConfig
+    )]
+    pub treasury_collateral_account: Account<'info, TokenAccount>,
+
     /// CHECK: Oracle for borrowed asset price
+    #[account(
+        constraint = borrow_oracle.key() == borrow_market.oracle @ LendingError::InvalidOracle
+    )]
     pub borrow_oracle: UncheckedAccount<'info>,
 
     /// CHECK: Oracle for collateral asset price
+    #[account(
+        constraint = collateral_oracle.key() == collateral_market.oracle @ LendingError::InvalidOracle
+    )]
     pub collateral_oracle: UncheckedAccount<'info>,
 
+    /// E-mode category the obligation opted into, if any
+    #[account(
+        seeds = [b"emode_category", obligation.emode_category.to_le_bytes().as_ref()],
+        bump = emode_category.bump
+    )]
+    pub emode_category: Option<Account<'info, EModeCategory>>,
+
     pub token_program: Program<'info, Token>,
 }
 
@@ -74,32 +130,197 @@ pub fn handler(
     repay_amount: u64,
     min_collateral_amount: u64,
 ) -> Result<()> {
-    require!(repay_amount > 0, LendingError::InvalidAmount);
+    let clock = Clock::get()?;
+    let amounts = settle_liquidation(
+        &mut ctx.accounts.obligation,
+        &mut ctx.accounts.borrow_market,
+        &mut ctx.accounts.collateral_market,
+        &ctx.accounts.borrow_oracle,
+        &ctx.accounts.collateral_oracle,
+        ctx.accounts.emode_category.as_deref(),
+        ctx.remaining_accounts,
+        repay_amount,
+        &clock,
+    )?;
 
-    let borrow_market = &mut ctx.accounts.borrow_market;
-    let collateral_market = &mut ctx.accounts.collateral_market;
+    // Transfer repayment from liquidator to reserve
+    let repay_ctx = CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        Transfer {
+            from: ctx.accounts.liquidator_token_account.to_account_info(),
+            to: ctx.accounts.borrow_reserve_vault.to_account_info(),
+            authority: ctx.accounts.liquidator.to_account_info(),
+        },
+    );
+    token::transfer(repay_ctx, amounts.repay_amount)?;
+
+    let protocol_fee = seize_collateral(
+        &mut ctx.accounts.collateral_market,
+        &ctx.accounts.collateral_reserve_vault,
+        &ctx.accounts.collateral_vault,
+        &ctx.accounts.collateral_supply_mint,
+        &ctx.accounts.liquidator_collateral_account,
+        &ctx.accounts.treasury_collateral_account,
+        &ctx.accounts.token_program,
+        &amounts,
+        min_collateral_amount,
+    )?;
+
+    emit!(Liquidated {
+        borrow_market: ctx.accounts.borrow_market.key(),
+        collateral_market: ctx.accounts.collateral_market.key(),
+        obligation: ctx.accounts.obligation.key(),
+        liquidator: ctx.accounts.liquidator.key(),
+        repay_amount: amounts.repay_amount,
+        collateral_amount: amounts.collateral_amount,
+        protocol_fee,
+        health_factor: amounts.health_factor,
+        liquidation_bonus_bps: amounts.liquidation_bonus_bps,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Liquidate an unhealthy borrow position, receiving supply tokens
+///
+/// Same as `liquidate`, but the liquidator receives the collateral market's
+/// yield-bearing supply tokens straight from the collateral vault instead of
+/// the underlying asset, so liquidations still succeed when the collateral
+/// reserve has no liquidity left.
+/// Remaining accounts: `[market, oracle]` for each deposit, then for each borrow.
+#[derive(Accounts)]
+pub struct LiquidateToSupplyTokens<'info> {
+    #[account(mut)]
+    pub liquidator: Signer<'info>,
+
+    /// Obligation being liquidated
+    #[account(
+        mut,
+        seeds = [b"obligation", obligation.owner.as_ref()],
+        bump = obligation.bump
+    )]
+    pub obligation: Account<'info, Obligation>,
+
+    /// Market for the borrowed asset
+    #[account(
+        mut,
+        seeds = [b"market", borrow_market.asset_mint.as_ref()],
+        bump = borrow_market.bump
+    )]
+    pub borrow_market: Account<'info, Market>,
+
+    /// Market for the collateral asset
+    #[account(
+        mut,
+        seeds = [b"market", collateral_market.asset_mint.as_ref()],
+        bump = collateral_market.bump,
+        constraint = collateral_market.key() != borrow_market.key() @ LendingError::InvalidMarketConfig
+    )]
+    pub collateral_market: Account<'info, Market>,
+
+    /// Liquidator's token account (source of repayment)
+    #[account(
+        mut,
+        constraint = liquidator_token_account.owner == liquidator.key() @ LendingError::Unauthorized,
+        constraint = liquidator_token_account.mint == borrow_market.asset_mint @ LendingError::InvalidMarketConfig
+    )]
+    pub liquidator_token_account: Account<'info, TokenAccount>,
+
+    /// Borrow market reserve vault (destination for repayment)
+    #[account(
+        mut,
+        constraint = borrow_reserve_vault.key() == borrow_market.reserve_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub borrow_reserve_vault: Account<'info, TokenAccount>,
+
+    /// Collateral market collateral vault (source of seized supply tokens)
+    #[account(
+        mut,
+        constraint = collateral_vault.key() == collateral_market.collateral_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+
+    /// Liquidator's supply token account (destination for seized supply tokens)
+    #[account(
+        mut,
+        constraint = liquidator_supply_account.owner == liquidator.key() @ LendingError::Unauthorized,
+        constraint = liquidator_supply_account.mint == collateral_market.supply_mint @ LendingError::InvalidMarketConfig
+    )]
+    pub liquidator_supply_account: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// Treasury's supply token account (destination for the protocol's share of the bonus)
+    #[account(
+        mut,
+        constraint = treasury_supply_account.owner == global_config.treasury @ LendingError::Unauthorized,
+        constraint = treasury_supply_account.mint == collateral_market.supply_mint @ LendingError::InvalidMarketConfig
+    )]
+    pub treasury_supply_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Oracle for borrowed asset price
+    #[account(
+        constraint = borrow_oracle.key() == borrow_market.oracle @ LendingError::InvalidOracle
+    )]
+    pub borrow_oracle: UncheckedAccount<'info>,
+
+    /// CHECK: Oracle for collateral asset price
+    #[account(
+        constraint = collateral_oracle.key() == collateral_market.oracle @ LendingError::InvalidOracle
+    )]
+    pub collateral_oracle: UncheckedAccount<'info>,
+
+    /// E-mode category the obligation opted into, if any
+    #[account(
+        seeds = [b"emode_category", obligation.emode_category.to_le_bytes().as_ref()],
+        bump = emode_category.bump
+    )]
+    pub emode_category: Option<Account<'info, EModeCategory>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn liquidate_to_supply_tokens_handler(
+    ctx: Context<LiquidateToSupplyTokens>,
+    repay_amount: u64,
+    min_supply_tokens: u64,
+) -> Result<()> {
     let clock = Clock::get()?;
+    let amounts = settle_liquidation(
+        &mut ctx.accounts.obligation,
+        &mut ctx.accounts.borrow_market,
+        &mut ctx.accounts.collateral_market,
+        &ctx.accounts.borrow_oracle,
+        &ctx.accounts.collateral_oracle,
+        ctx.accounts.emode_category.as_deref(),
+        ctx.remaining_accounts,
+        repay_amount,
+        &clock,
+    )?;
+    let collateral_market = &mut ctx.accounts.collateral_market;
+    let supply_tokens = amounts.supply_tokens;
 
-    // Accrue interest on both markets
-    borrow_market.accrue_interest(&clock)?;
-    collateral_market.accrue_interest(&clock)?;
+    // Part of the bonus goes to the treasury
+    let protocol_fee = calculate_liquidation_protocol_fee(
+        supply_tokens,
+        amounts.liquidation_bonus_bps,
+        collateral_market.liquidation_protocol_fee_bps,
+    )?;
+    let liquidator_supply_tokens = supply_tokens
+        .checked_sub(protocol_fee)
+        .ok_or(LendingError::MathOverflow)?;
 
-    // TODO: In a full implementation, you would:
-    // 1. Fetch user's borrow position
-    // 2. Calculate their health factor using oracle prices
-    // 3. Verify health factor < MIN_HEALTH_FACTOR_BPS
-    // 4. Calculate collateral to seize based on prices and liquidation bonus
-    // 5. Verify min_collateral_amount is met
+    require!(
+        liquidator_supply_tokens >= min_supply_tokens,
+        LendingError::SlippageExceeded
+    );
 
-    // For now, simplified liquidation flow:
     // Transfer repayment from liquidator to reserve
-    let borrow_seeds = &[
-        b"market",
-        borrow_market.asset_mint.as_ref(),
-        &[borrow_market.bump],
-    ];
-    let borrow_signer = &[&borrow_seeds[..]];
-
     let repay_ctx = CpiContext::new(
         ctx.accounts.token_program.to_account_info(),
         Transfer {
@@ -108,21 +329,95 @@ pub fn handler(
             authority: ctx.accounts.liquidator.to_account_info(),
         },
     );
-    token::transfer(repay_ctx, repay_amount)?;
+    token::transfer(repay_ctx, amounts.repay_amount)?;
+
+    let collateral_seeds = &[
+        b"market",
+        collateral_market.asset_mint.as_ref(),
+        &[collateral_market.bump],
+    ];
+    let collateral_signer = &[&collateral_seeds[..]];
 
-    // Calculate collateral to seize (with liquidation bonus)
-    // Simplified: assume 1:1 price ratio for now
-    // In production, use oracle prices
-    let collateral_amount = repay_amount
-        .checked_add(calculate_liquidation_bonus(repay_amount)?)
+    // Move the seized supply tokens out of the collateral vault; the
+    // collateral reserve is untouched so its liquidity doesn't matter
+    let seize_ctx = CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        Transfer {
+            from: ctx.accounts.collateral_vault.to_account_info(),
+            to: ctx.accounts.liquidator_supply_account.to_account_info(),
+            authority: collateral_market.to_account_info(),
+        },
+        collateral_signer,
+    );
+    token::transfer(seize_ctx, liquidator_supply_tokens)?;
+
+    if protocol_fee > 0 {
+        let fee_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.collateral_vault.to_account_info(),
+                to: ctx.accounts.treasury_supply_account.to_account_info(),
+                authority: collateral_market.to_account_info(),
+            },
+            collateral_signer,
+        );
+        token::transfer(fee_ctx, protocol_fee)?;
+    }
+
+    emit!(LiquidatedToSupplyTokens {
+        borrow_market: ctx.accounts.borrow_market.key(),
+        collateral_market: collateral_market.key(),
+        obligation: ctx.accounts.obligation.key(),
+        liquidator: ctx.accounts.liquidator.key(),
+        repay_amount: amounts.repay_amount,
+        supply_tokens,
+        protocol_fee,
+        health_factor: amounts.health_factor,
+        liquidation_bonus_bps: amounts.liquidation_bonus_bps,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Pay out seized collateral in the underlying asset
+///
+/// Burns the seized supply tokens from the collateral vault and sends the
+/// underlying to the liquidator, less the treasury's share of the bonus.
+/// Returns the protocol fee.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn seize_collateral<'info>(
+    collateral_market: &mut Account<'info, Market>,
+    collateral_reserve_vault: &Account<'info, TokenAccount>,
+    collateral_vault: &Account<'info, TokenAccount>,
+    collateral_supply_mint: &Account<'info, Mint>,
+    liquidator_collateral_account: &Account<'info, TokenAccount>,
+    treasury_collateral_account: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    amounts: &LiquidationAmounts,
+    min_collateral_amount: u64,
+) -> Result<u64> {
+    let collateral_amount = amounts.collateral_amount;
+
+    // Part of the bonus goes to the treasury
+    let protocol_fee = calculate_liquidation_protocol_fee(
+        collateral_amount,
+        amounts.liquidation_bonus_bps,
+        collateral_market.liquidation_protocol_fee_bps,
+    )?;
+    let liquidator_amount = collateral_amount
+        .checked_sub(protocol_fee)
         .ok_or(LendingError::MathOverflow)?;
 
     require!(
-        collateral_amount >= min_collateral_amount,
+        liquidator_amount >= min_collateral_amount,
         LendingError::SlippageExceeded
     );
+    require!(
+        collateral_amount <= collateral_reserve_vault.amount,
+        LendingError::InsufficientLiquidity
+    );
 
-    // Transfer collateral from reserve to liquidator
     let collateral_seeds = &[
         b"market",
         collateral_market.asset_mint.as_ref(),
@@ -130,33 +425,232 @@ pub fn handler(
     ];
     let collateral_signer = &[&collateral_seeds[..]];
 
+    // Burn the seized supply tokens held as collateral
+    let burn_ctx = CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        Burn {
+            mint: collateral_supply_mint.to_account_info(),
+            from: collateral_vault.to_account_info(),
+            authority: collateral_market.to_account_info(),
+        },
+        collateral_signer,
+    );
+    token::burn(burn_ctx, amounts.supply_tokens)?;
+
+    // Transfer collateral from reserve to liquidator
+    // Seized collateral is not counted against the outflow limit so that
+    // unhealthy positions can always be closed
     let seize_ctx = CpiContext::new_with_signer(
-        ctx.accounts.token_program.to_account_info(),
+        token_program.to_account_info(),
         Transfer {
-            from: ctx.accounts.collateral_reserve_vault.to_account_info(),
-            to: ctx.accounts.liquidator_collateral_account.to_account_info(),
+            from: collateral_reserve_vault.to_account_info(),
+            to: liquidator_collateral_account.to_account_info(),
             authority: collateral_market.to_account_info(),
         },
         collateral_signer,
     );
-    token::transfer(seize_ctx, collateral_amount)?;
+    token::transfer(seize_ctx, liquidator_amount)?;
 
-    // Update market states
-    borrow_market.total_borrowed = borrow_market.total_borrowed
-        .checked_sub(repay_amount)
-        .ok_or(LendingError::MathOverflow)?;
+    if protocol_fee > 0 {
+        let fee_ctx = CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            Transfer {
+                from: collateral_reserve_vault.to_account_info(),
+                to: treasury_collateral_account.to_account_info(),
+                authority: collateral_market.to_account_info(),
+            },
+            collateral_signer,
+        );
+        token::transfer(fee_ctx, protocol_fee)?;
+    }
+
+    // Update collateral market state
     collateral_market.total_supplied = collateral_market.total_supplied
         .checked_sub(collateral_amount)
         .ok_or(LendingError::MathOverflow)?;
+    collateral_market.total_supply_tokens = collateral_market.total_supply_tokens
+        .checked_sub(amounts.supply_tokens)
+        .ok_or(LendingError::MathOverflow)?;
 
-    emit!(Liquidated {
-        borrow_market: borrow_market.key(),
-        collateral_market: collateral_market.key(),
-        liquidator: ctx.accounts.liquidator.key(),
+    Ok(protocol_fee)
+}
+
+/// Amounts settled by a liquidation
+pub(crate) struct LiquidationAmounts {
+    /// Debt repaid by the liquidator
+    pub repay_amount: u64,
+    /// Underlying value of the seized collateral
+    pub collateral_amount: u64,
+    /// Supply tokens removed from the obligation
+    pub supply_tokens: u64,
+    pub liquidation_bonus_bps: u16,
+    pub health_factor: u16,
+}
+
+/// Check an obligation is liquidatable, size the liquidation and update the
+/// obligation and borrow market; the caller moves the tokens
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn settle_liquidation<'info>(
+    obligation: &mut Account<'info, Obligation>,
+    borrow_market: &mut Account<'info, Market>,
+    collateral_market: &mut Account<'info, Market>,
+    borrow_oracle: &AccountInfo,
+    collateral_oracle: &AccountInfo,
+    emode_category: Option<&EModeCategory>,
+    remaining_accounts: &[AccountInfo],
+    repay_amount: u64,
+    clock: &Clock,
+) -> Result<LiquidationAmounts> {
+    let amounts = calculate_liquidation(
+        obligation,
+        borrow_market,
+        collateral_market,
+        borrow_oracle,
+        collateral_oracle,
+        emode_category,
+        remaining_accounts,
+        repay_amount,
+        clock,
+    )?;
+    apply_liquidation(
+        obligation,
+        borrow_market,
+        collateral_market,
+        amounts.repay_amount,
+        amounts.supply_tokens,
+        clock,
+    )?;
+
+    Ok(amounts)
+}
+
+/// Check an obligation is liquidatable and size the liquidation at oracle
+/// prices, without changing positions
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn calculate_liquidation<'info>(
+    obligation: &mut Account<'info, Obligation>,
+    borrow_market: &mut Account<'info, Market>,
+    collateral_market: &mut Account<'info, Market>,
+    borrow_oracle: &AccountInfo,
+    collateral_oracle: &AccountInfo,
+    emode_category: Option<&EModeCategory>,
+    remaining_accounts: &[AccountInfo],
+    repay_amount: u64,
+    clock: &Clock,
+) -> Result<LiquidationAmounts> {
+    require!(repay_amount > 0, LendingError::InvalidAmount);
+    obligation.require_not_liquidating()?;
+
+    // Accrue interest on both markets
+    borrow_market.accrue_interest(clock)?;
+    collateral_market.accrue_interest(clock)?;
+
+    // Verify the obligation is unhealthy
+    let emode_category = require_emode_category(obligation, emode_category)?;
+    let values = calculate_obligation_values(
+        obligation,
+        remaining_accounts,
+        &[
+            (borrow_market.key(), borrow_market),
+            (collateral_market.key(), collateral_market),
+        ],
+        emode_category,
+        clock,
+    )?;
+    let health_factor = values.health_factor()?;
+    require!(
+        health_factor < MIN_HEALTH_FACTOR_BPS,
+        LendingError::LiquidationNotNeeded
+    );
+    obligation.update_liquidatable_since(health_factor, clock);
+
+    // Repay at most the outstanding debt
+    let debt = obligation
+        .find_borrow(&borrow_market.key())
+        .ok_or(LendingError::BorrowPositionNotFound)?
+        .calculate_debt(borrow_market)?;
+    let mut repay_amount = repay_amount.min(debt);
+
+    // The bonus grows with how unhealthy the position is and how long it has
+    // been liquidatable; e-mode collateral is capped at the category's bonus
+    let mut max_bonus_bps = collateral_market.max_liquidation_bonus_bps;
+    if let Some(category) = emode_category {
+        if category.contains(&collateral_market.key()) {
+            max_bonus_bps = max_bonus_bps.min(category.liquidation_bonus_bps);
+        }
+    }
+    let liquidation_bonus_bps = calculate_liquidation_bonus_bps(
+        health_factor,
+        clock.unix_timestamp - obligation.liquidatable_since,
+        collateral_market.min_liquidation_bonus_bps.min(max_bonus_bps),
+        max_bonus_bps,
+        collateral_market.liquidation_auction_duration,
+    )?;
+
+    // Calculate collateral to seize (with liquidation bonus) at oracle prices
+    let borrow_price = get_oracle_price(borrow_oracle, clock)?;
+    let collateral_price = get_oracle_price(collateral_oracle, clock)?;
+
+    let repay_value = calculate_market_value(repay_amount, borrow_price, borrow_market.mint_decimals)?;
+    let seize_value = repay_value
+        .checked_add(calculate_liquidation_bonus(repay_value, liquidation_bonus_bps)?)
+        .ok_or(LendingError::MathOverflow)?;
+    let mut collateral_amount = calculate_amount_from_value(
+        seize_value,
+        collateral_price,
+        collateral_market.mint_decimals,
+    )?;
+    let mut supply_tokens = collateral_market.amount_to_supply_tokens(collateral_amount)?;
+
+    // If the obligation has less collateral than that, seize all of it
+    // and scale the repayment down to match
+    let deposited = obligation.deposited_supply_tokens(&collateral_market.key());
+    require!(deposited > 0, LendingError::InsufficientCollateral);
+    if supply_tokens > deposited {
+        repay_amount = (repay_amount as u128)
+            .checked_mul(deposited as u128)
+            .ok_or(LendingError::MathOverflow)?
+            .checked_div(supply_tokens as u128)
+            .ok_or(LendingError::MathOverflow)? as u64;
+        supply_tokens = deposited;
+        collateral_amount = collateral_market.supply_tokens_to_amount(deposited)?;
+    }
+    require!(repay_amount > 0, LendingError::InvalidLiquidationAmount);
+
+    Ok(LiquidationAmounts {
         repay_amount,
         collateral_amount,
-        timestamp: clock.unix_timestamp,
-    });
+        supply_tokens,
+        liquidation_bonus_bps,
+        health_factor,
+    })
+}
+
+/// Repay the obligation's debt and remove the seized supply tokens
+pub(crate) fn apply_liquidation(
+    obligation: &mut Obligation,
+    borrow_market: &mut Account<Market>,
+    collateral_market: &mut Account<Market>,
+    repay_amount: u64,
+    supply_tokens: u64,
+    clock: &Clock,
+) -> Result<()> {
+    // An isolated obligation's only collateral is the collateral market,
+    // so its debt ceiling is released there
+    if obligation.isolated_collateral_market == collateral_market.key() {
+        collateral_market.remove_isolation_debt(repay_amount, borrow_market.mint_decimals)?;
+    }
+
+    // Update the obligation
+    obligation.repay(borrow_market.key(), borrow_market, repay_amount)?;
+    obligation.withdraw(collateral_market.key(), supply_tokens)?;
+    obligation.last_updated = clock.unix_timestamp;
+
+    // Update borrow market state
+    borrow_market.record_inflow(repay_amount, clock)?;
+    borrow_market.total_borrowed = borrow_market.total_borrowed
+        .checked_sub(repay_amount)
+        .ok_or(LendingError::MathOverflow)?;
 
     Ok(())
 }
@@ -165,8 +659,26 @@ pub fn handler(
 pub struct Liquidated {
     pub borrow_market: Pubkey,
     pub collateral_market: Pubkey,
+    pub obligation: Pubkey,
     pub liquidator: Pubkey,
     pub repay_amount: u64,
     pub collateral_amount: u64,
+    pub protocol_fee: u64,
+    pub health_factor: u16,
+    pub liquidation_bonus_bps: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LiquidatedToSupplyTokens {
+    pub borrow_market: Pubkey,
+    pub collateral_market: Pubkey,
+    pub obligation: Pubkey,
+    pub liquidator: Pubkey,
+    pub repay_amount: u64,
+    pub supply_tokens: u64,
+    pub protocol_fee: u64,
+    pub health_factor: u16,
+    pub liquidation_bonus_bps: u16,
     pub timestamp: i64,
 }
@@ -0,0 +1,641 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, CloseAccount, Mint, Token, TokenAccount, Transfer};
+use crate::state::*;
+use crate::errors::LendingError;
+use crate::constants::*;
+use crate::math::calculate_liquidation_protocol_fee;
+use crate::health::{calculate_obligation_values, require_emode_category};
+use crate::instructions::liquidate::{apply_liquidation, calculate_liquidation};
+
+/// Start an auction for a large liquidation
+///
+/// Fixes the debt to repay and, at the current liquidation bonus, the most
+/// collateral a bid can ask for. The obligation is locked until the auction
+/// settles, or is cancelled because its health factor kept falling. Only repayments of at least the borrow market's
+/// `large_liquidation_threshold` can be auctioned.
+/// Remaining accounts: `[market, oracle]` for each deposit, then for each borrow.
+#[derive(Accounts)]
+pub struct StartLiquidationAuction<'info> {
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    /// Obligation being liquidated
+    #[account(
+        mut,
+        seeds = [b"obligation", obligation.owner.as_ref()],
+        bump = obligation.bump
+    )]
+    pub obligation: Account<'info, Obligation>,
+
+    /// Market for the borrowed asset
+    #[account(
+        mut,
+        seeds = [b"market", borrow_market.asset_mint.as_ref()],
+        bump = borrow_market.bump
+    )]
+    pub borrow_market: Account<'info, Market>,
+
+    /// Market for the collateral asset
+    #[account(
+        mut,
+        seeds = [b"market", collateral_market.asset_mint.as_ref()],
+        bump = collateral_market.bump,
+        constraint = collateral_market.key() != borrow_market.key() @ LendingError::InvalidMarketConfig
+    )]
+    pub collateral_market: Account<'info, Market>,
+
+    /// Borrowed asset mint (bids are escrowed in it)
+    #[account(
+        constraint = borrow_asset_mint.key() == borrow_market.asset_mint @ LendingError::InvalidMarketConfig
+    )]
+    pub borrow_asset_mint: Account<'info, Mint>,
+
+    /// CHECK: Oracle for borrowed asset price
+    #[account(
+        constraint = borrow_oracle.key() == borrow_market.oracle @ LendingError::InvalidOracle
+    )]
+    pub borrow_oracle: UncheckedAccount<'info>,
+
+    /// CHECK: Oracle for collateral asset price
+    #[account(
+        constraint = collateral_oracle.key() == collateral_market.oracle @ LendingError::InvalidOracle
+    )]
+    pub collateral_oracle: UncheckedAccount<'info>,
+
+    /// E-mode category the obligation opted into, if any
+    #[account(
+        seeds = [b"emode_category", obligation.emode_category.to_le_bytes().as_ref()],
+        bump = emode_category.bump
+    )]
+    pub emode_category: Option<Account<'info, EModeCategory>>,
+
+    #[account(
+        init,
+        payer = creator,
+        space = LiquidationAuction::SIZE,
+        seeds = [b"liquidation_auction", obligation.key().as_ref()],
+        bump
+    )]
+    pub auction: Account<'info, LiquidationAuction>,
+
+    /// Escrow for the best bid's repayment (owned by the auction)
+    #[account(
+        init,
+        payer = creator,
+        seeds = [b"auction_vault", auction.key().as_ref()],
+        bump,
+        token::mint = borrow_asset_mint,
+        token::authority = auction
+    )]
+    pub bid_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(ctx: Context<StartLiquidationAuction>, repay_amount: u64) -> Result<()> {
+    let clock = Clock::get()?;
+    let amounts = calculate_liquidation(
+        &mut ctx.accounts.obligation,
+        &mut ctx.accounts.borrow_market,
+        &mut ctx.accounts.collateral_market,
+        &ctx.accounts.borrow_oracle,
+        &ctx.accounts.collateral_oracle,
+        ctx.accounts.emode_category.as_deref(),
+        ctx.remaining_accounts,
+        repay_amount,
+        &clock,
+    )?;
+
+    // Smaller liquidations go through the fixed-price instructions
+    let borrow_market = &ctx.accounts.borrow_market;
+    require!(
+        borrow_market.large_liquidation_threshold > 0
+            && amounts.repay_amount >= borrow_market.large_liquidation_threshold,
+        LendingError::AuctionThresholdNotMet
+    );
+
+    let auction = &mut ctx.accounts.auction;
+    auction.obligation = ctx.accounts.obligation.key();
+    auction.borrow_market = borrow_market.key();
+    auction.collateral_market = ctx.accounts.collateral_market.key();
+    auction.creator = ctx.accounts.creator.key();
+    auction.repay_amount = amounts.repay_amount;
+    auction.supply_tokens = amounts.supply_tokens;
+    auction.liquidation_bonus_bps = amounts.liquidation_bonus_bps;
+    auction.start_health_factor = amounts.health_factor;
+    auction.best_bidder = Pubkey::default();
+    auction.best_bid = 0;
+    auction.start_time = clock.unix_timestamp;
+    auction.end_time = clock.unix_timestamp
+        .checked_add(borrow_market.large_liquidation_auction_duration)
+        .ok_or(LendingError::MathOverflow)?;
+    auction.bump = ctx.bumps.get("auction").copied().unwrap();
+    auction.vault_bump = ctx.bumps.get("bid_vault").copied().unwrap();
+
+    let obligation = &mut ctx.accounts.obligation;
+    obligation.liquidation_auction = auction.key();
+    obligation.last_updated = clock.unix_timestamp;
+
+    emit!(LiquidationAuctionStarted {
+        auction: auction.key(),
+        obligation: obligation.key(),
+        borrow_market: auction.borrow_market,
+        collateral_market: auction.collateral_market,
+        repay_amount: auction.repay_amount,
+        supply_tokens: auction.supply_tokens,
+        health_factor: amounts.health_factor,
+        end_time: auction.end_time,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct LiquidationAuctionStarted {
+    pub auction: Pubkey,
+    pub obligation: Pubkey,
+    pub borrow_market: Pubkey,
+    pub collateral_market: Pubkey,
+    pub repay_amount: u64,
+    pub supply_tokens: u64,
+    pub health_factor: u16,
+    pub end_time: i64,
+    pub timestamp: i64,
+}
+
+/// Bid on a liquidation auction
+///
+/// The bidder escrows the auction's full repayment and names the collateral
+/// supply tokens they accept for it; a bid must ask for fewer than the current
+/// best. The outbid bidder's escrow is refunded in the same instruction.
+#[derive(Accounts)]
+pub struct BidLiquidationAuction<'info> {
+    pub bidder: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"liquidation_auction", auction.obligation.as_ref()],
+        bump = auction.bump
+    )]
+    pub auction: Account<'info, LiquidationAuction>,
+
+    #[account(
+        mut,
+        seeds = [b"auction_vault", auction.key().as_ref()],
+        bump = auction.vault_bump
+    )]
+    pub bid_vault: Account<'info, TokenAccount>,
+
+    /// Bidder's token account (source of the escrowed repayment)
+    #[account(
+        mut,
+        constraint = bidder_token_account.owner == bidder.key() @ LendingError::Unauthorized,
+        constraint = bidder_token_account.mint == bid_vault.mint @ LendingError::InvalidMarketConfig
+    )]
+    pub bidder_token_account: Account<'info, TokenAccount>,
+
+    /// Outbid bidder's token account (required once the auction has a bid)
+    #[account(
+        mut,
+        constraint = previous_bidder_token_account.owner == auction.best_bidder @ LendingError::Unauthorized,
+        constraint = previous_bidder_token_account.mint == bid_vault.mint @ LendingError::InvalidMarketConfig
+    )]
+    pub previous_bidder_token_account: Option<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn bid_handler(ctx: Context<BidLiquidationAuction>, supply_tokens: u64) -> Result<()> {
+    let auction = &mut ctx.accounts.auction;
+    let clock = Clock::get()?;
+
+    require!(clock.unix_timestamp < auction.end_time, LendingError::AuctionEnded);
+    require!(supply_tokens > 0, LendingError::InvalidAmount);
+
+    let obligation_key = auction.obligation;
+    let auction_seeds = &[
+        b"liquidation_auction",
+        obligation_key.as_ref(),
+        &[auction.bump],
+    ];
+    let auction_signer = &[&auction_seeds[..]];
+
+    if auction.has_bid() {
+        require!(supply_tokens < auction.best_bid, LendingError::BidNotBetter);
+
+        // Refund the outbid bidder
+        let previous_bidder_token_account = ctx.accounts.previous_bidder_token_account
+            .as_ref()
+            .ok_or(LendingError::MissingBidderAccount)?;
+        let refund_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.bid_vault.to_account_info(),
+                to: previous_bidder_token_account.to_account_info(),
+                authority: auction.to_account_info(),
+            },
+            auction_signer,
+        );
+        token::transfer(refund_ctx, auction.repay_amount)?;
+    } else {
+        require!(supply_tokens <= auction.supply_tokens, LendingError::BidNotBetter);
+    }
+
+    // Escrow the new bid's repayment
+    let escrow_ctx = CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        Transfer {
+            from: ctx.accounts.bidder_token_account.to_account_info(),
+            to: ctx.accounts.bid_vault.to_account_info(),
+            authority: ctx.accounts.bidder.to_account_info(),
+        },
+    );
+    token::transfer(escrow_ctx, auction.repay_amount)?;
+
+    auction.best_bidder = ctx.accounts.bidder.key();
+    auction.best_bid = supply_tokens;
+
+    emit!(LiquidationAuctionBid {
+        auction: auction.key(),
+        bidder: auction.best_bidder,
+        supply_tokens,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct LiquidationAuctionBid {
+    pub auction: Pubkey,
+    pub bidder: Pubkey,
+    pub supply_tokens: u64,
+    pub timestamp: i64,
+}
+
+/// Settle a liquidation auction after it ends
+///
+/// The best bid's escrow repays the debt and the bidder receives the supply
+/// tokens they bid for, less the treasury's share of the implied bonus. With no
+/// bids the obligation is simply unlocked. Anyone can settle; the auction's
+/// rent goes back to whoever started it.
+#[derive(Accounts)]
+pub struct SettleLiquidationAuction<'info> {
+    /// CHECK: Account that started the auction, receives the rent
+    #[account(mut, address = auction.creator)]
+    pub creator: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        close = creator,
+        seeds = [b"liquidation_auction", obligation.key().as_ref()],
+        bump = auction.bump,
+        has_one = obligation,
+        has_one = borrow_market,
+        has_one = collateral_market
+    )]
+    pub auction: Account<'info, LiquidationAuction>,
+
+    #[account(
+        mut,
+        seeds = [b"auction_vault", auction.key().as_ref()],
+        bump = auction.vault_bump
+    )]
+    pub bid_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"obligation", obligation.owner.as_ref()],
+        bump = obligation.bump
+    )]
+    pub obligation: Account<'info, Obligation>,
+
+    #[account(
+        mut,
+        seeds = [b"market", borrow_market.asset_mint.as_ref()],
+        bump = borrow_market.bump
+    )]
+    pub borrow_market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        seeds = [b"market", collateral_market.asset_mint.as_ref()],
+        bump = collateral_market.bump
+    )]
+    pub collateral_market: Account<'info, Market>,
+
+    /// Borrow market reserve vault (destination for repayment)
+    #[account(
+        mut,
+        constraint = borrow_reserve_vault.key() == borrow_market.reserve_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub borrow_reserve_vault: Account<'info, TokenAccount>,
+
+    /// Collateral market collateral vault (source of the won supply tokens)
+    #[account(
+        mut,
+        constraint = collateral_vault.key() == collateral_market.collateral_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+
+    /// Winning bidder's supply token account (required if there was a bid)
+    #[account(
+        mut,
+        constraint = winner_supply_account.owner == auction.best_bidder @ LendingError::Unauthorized,
+        constraint = winner_supply_account.mint == collateral_market.supply_mint @ LendingError::InvalidMarketConfig
+    )]
+    pub winner_supply_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// Treasury's supply token account (destination for the protocol's share of the bonus)
+    #[account(
+        mut,
+        constraint = treasury_supply_account.owner == global_config.treasury @ LendingError::Unauthorized,
+        constraint = treasury_supply_account.mint == collateral_market.supply_mint @ LendingError::InvalidMarketConfig
+    )]
+    pub treasury_supply_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn settle_handler(ctx: Context<SettleLiquidationAuction>) -> Result<()> {
+    let auction = &ctx.accounts.auction;
+    let clock = Clock::get()?;
+
+    require!(clock.unix_timestamp >= auction.end_time, LendingError::AuctionNotEnded);
+
+    let obligation_key = auction.obligation;
+    let auction_seeds = &[
+        b"liquidation_auction",
+        obligation_key.as_ref(),
+        &[auction.bump],
+    ];
+    let auction_signer = &[&auction_seeds[..]];
+
+    let mut protocol_fee = 0;
+    if auction.has_bid() {
+        let winner_supply_account = ctx.accounts.winner_supply_account
+            .as_ref()
+            .ok_or(LendingError::MissingBidderAccount)?;
+        let borrow_market = &mut ctx.accounts.borrow_market;
+        let collateral_market = &mut ctx.accounts.collateral_market;
+
+        borrow_market.accrue_interest(&clock)?;
+        collateral_market.accrue_interest(&clock)?;
+
+        apply_liquidation(
+            &mut ctx.accounts.obligation,
+            borrow_market,
+            collateral_market,
+            auction.repay_amount,
+            auction.best_bid,
+            &clock,
+        )?;
+
+        // Release the escrowed repayment to the reserve
+        let repay_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.bid_vault.to_account_info(),
+                to: ctx.accounts.borrow_reserve_vault.to_account_info(),
+                authority: auction.to_account_info(),
+            },
+            auction_signer,
+        );
+        token::transfer(repay_ctx, auction.repay_amount)?;
+
+        // The bid undercuts the starting lot, so it implies a smaller bonus;
+        // the treasury takes its usual share of that
+        let implied_bonus_bps = ((BPS_SCALE as u128 + auction.liquidation_bonus_bps as u128)
+            .checked_mul(auction.best_bid as u128)
+            .ok_or(LendingError::MathOverflow)?
+            .checked_div(auction.supply_tokens as u128)
+            .ok_or(LendingError::MathOverflow)?)
+            .saturating_sub(BPS_SCALE as u128) as u16;
+        protocol_fee = calculate_liquidation_protocol_fee(
+            auction.best_bid,
+            implied_bonus_bps,
+            collateral_market.liquidation_protocol_fee_bps,
+        )?;
+        let winner_supply_tokens = auction.best_bid
+            .checked_sub(protocol_fee)
+            .ok_or(LendingError::MathOverflow)?;
+
+        let collateral_seeds = &[
+            b"market",
+            collateral_market.asset_mint.as_ref(),
+            &[collateral_market.bump],
+        ];
+        let collateral_signer = &[&collateral_seeds[..]];
+
+        let seize_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.collateral_vault.to_account_info(),
+                to: winner_supply_account.to_account_info(),
+                authority: collateral_market.to_account_info(),
+            },
+            collateral_signer,
+        );
+        token::transfer(seize_ctx, winner_supply_tokens)?;
+
+        if protocol_fee > 0 {
+            let fee_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.collateral_vault.to_account_info(),
+                    to: ctx.accounts.treasury_supply_account.to_account_info(),
+                    authority: collateral_market.to_account_info(),
+                },
+                collateral_signer,
+            );
+            token::transfer(fee_ctx, protocol_fee)?;
+        }
+    }
+
+    // The escrow is empty now; return its rent with the auction's
+    let close_ctx = CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        CloseAccount {
+            account: ctx.accounts.bid_vault.to_account_info(),
+            destination: ctx.accounts.creator.to_account_info(),
+            authority: auction.to_account_info(),
+        },
+        auction_signer,
+    );
+    token::close_account(close_ctx)?;
+
+    let obligation = &mut ctx.accounts.obligation;
+    obligation.liquidation_auction = Pubkey::default();
+    obligation.last_updated = clock.unix_timestamp;
+
+    emit!(LiquidationAuctionSettled {
+        auction: auction.key(),
+        obligation: obligation.key(),
+        winner: auction.best_bidder,
+        repay_amount: if auction.has_bid() { auction.repay_amount } else { 0 },
+        supply_tokens: auction.best_bid,
+        protocol_fee,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct LiquidationAuctionSettled {
+    pub auction: Pubkey,
+    pub obligation: Pubkey,
+    /// Default if the auction ended without bids
+    pub winner: Pubkey,
+    pub repay_amount: u64,
+    pub supply_tokens: u64,
+    pub protocol_fee: u64,
+    pub timestamp: i64,
+}
+
+/// Cancel a liquidation auction whose obligation kept getting less healthy
+///
+/// The lot is fixed at start-time prices, so if the collateral keeps falling
+/// nobody bids and the locked obligation drifts into bad debt. Once the health
+/// factor is `AUCTION_CANCEL_HEALTH_DROP_BPS` below its value at the start,
+/// anyone can cancel: the best bid is refunded and the obligation is unlocked
+/// for the fixed-price liquidation instructions.
+/// Remaining accounts: `[market, oracle]` for each deposit, then for each borrow.
+#[derive(Accounts)]
+pub struct CancelLiquidationAuction<'info> {
+    /// CHECK: Account that started the auction, receives the rent
+    #[account(mut, address = auction.creator)]
+    pub creator: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        close = creator,
+        seeds = [b"liquidation_auction", obligation.key().as_ref()],
+        bump = auction.bump,
+        has_one = obligation
+    )]
+    pub auction: Account<'info, LiquidationAuction>,
+
+    #[account(
+        mut,
+        seeds = [b"auction_vault", auction.key().as_ref()],
+        bump = auction.vault_bump
+    )]
+    pub bid_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"obligation", obligation.owner.as_ref()],
+        bump = obligation.bump
+    )]
+    pub obligation: Account<'info, Obligation>,
+
+    /// E-mode category the obligation opted into, if any
+    #[account(
+        seeds = [b"emode_category", obligation.emode_category.to_le_bytes().as_ref()],
+        bump = emode_category.bump
+    )]
+    pub emode_category: Option<Account<'info, EModeCategory>>,
+
+    /// Best bidder's token account (required if there was a bid)
+    #[account(
+        mut,
+        constraint = best_bidder_token_account.owner == auction.best_bidder @ LendingError::Unauthorized,
+        constraint = best_bidder_token_account.mint == bid_vault.mint @ LendingError::InvalidMarketConfig
+    )]
+    pub best_bidder_token_account: Option<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn cancel_handler(ctx: Context<CancelLiquidationAuction>) -> Result<()> {
+    let auction = &ctx.accounts.auction;
+    let clock = Clock::get()?;
+
+    // After the end the auction is settled instead
+    require!(clock.unix_timestamp < auction.end_time, LendingError::AuctionEnded);
+
+    let emode_category = require_emode_category(
+        &ctx.accounts.obligation,
+        ctx.accounts.emode_category.as_deref(),
+    )?;
+    let values = calculate_obligation_values(
+        &ctx.accounts.obligation,
+        ctx.remaining_accounts,
+        &[],
+        emode_category,
+        &clock,
+    )?;
+    let health_factor = values.health_factor()?;
+    require!(
+        (health_factor as u32) + (AUCTION_CANCEL_HEALTH_DROP_BPS as u32)
+            <= auction.start_health_factor as u32,
+        LendingError::AuctionNotCancellable
+    );
+
+    let obligation_key = auction.obligation;
+    let auction_seeds = &[
+        b"liquidation_auction",
+        obligation_key.as_ref(),
+        &[auction.bump],
+    ];
+    let auction_signer = &[&auction_seeds[..]];
+
+    if auction.has_bid() {
+        let best_bidder_token_account = ctx.accounts.best_bidder_token_account
+            .as_ref()
+            .ok_or(LendingError::MissingBidderAccount)?;
+        let refund_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.bid_vault.to_account_info(),
+                to: best_bidder_token_account.to_account_info(),
+                authority: auction.to_account_info(),
+            },
+            auction_signer,
+        );
+        token::transfer(refund_ctx, auction.repay_amount)?;
+    }
+
+    let close_ctx = CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        CloseAccount {
+            account: ctx.accounts.bid_vault.to_account_info(),
+            destination: ctx.accounts.creator.to_account_info(),
+            authority: auction.to_account_info(),
+        },
+        auction_signer,
+    );
+    token::close_account(close_ctx)?;
+
+    let obligation = &mut ctx.accounts.obligation;
+    obligation.liquidation_auction = Pubkey::default();
+    obligation.last_updated = clock.unix_timestamp;
+
+    emit!(LiquidationAuctionCancelled {
+        auction: auction.key(),
+        obligation: obligation.key(),
+        start_health_factor: auction.start_health_factor,
+        health_factor,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct LiquidationAuctionCancelled {
+    pub auction: Pubkey,
+    pub obligation: Pubkey,
+    pub start_health_factor: u16,
+    pub health_factor: u16,
+    pub timestamp: i64,
+}
@@ -3,6 +3,7 @@ use anchor_spl::token::{self, Mint, Token, TokenAccount};
 use crate::state::*;
 use crate::errors::LendingError;
 use crate::constants::*;
+use crate::math::calculate_liquidation_bonus;
 
 /// Create a new lending market
 /// 
@@ -31,6 +32,13 @@ pub struct CreateMarket<'info> {
     )]
     pub reserve_vault: Account<'info, TokenAccount>,
 
+    /// Collateral vault (holds supply tokens pledged to obligations)
+    #[account(
+        constraint = collateral_vault.mint == supply_mint.key() @ LendingError::InvalidMarketConfig,
+        constraint = collateral_vault.owner == market.key() @ LendingError::InvalidMarketConfig
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+
     /// CHECK: Oracle account (Pyth or other price feed)
     pub oracle: UncheckedAccount<'info>,
 
@@ -66,8 +74,10 @@ pub fn handler(
     market.initialize(
         market_id,
         ctx.accounts.asset_mint.key(),
+        ctx.accounts.asset_mint.decimals,
         ctx.accounts.supply_mint.key(),
         ctx.accounts.reserve_vault.key(),
+        ctx.accounts.collateral_vault.key(),
         ctx.accounts.oracle.key(),
         ltv_bps,
         liquidation_threshold_bps,
@@ -103,3 +113,296 @@ pub struct MarketCreated {
     pub creator: Pubkey,
     pub timestamp: i64,
 }
+
+/// Update risk parameters of an existing market
+/// 
+/// Only the protocol authority stored in `GlobalConfig` can change market parameters.
+#[derive(Accounts)]
+pub struct UpdateMarketConfig<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump,
+        has_one = authority @ LendingError::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"market", market.asset_mint.as_ref()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, Market>,
+}
+
+/// Configure the price-deviation circuit breaker
+/// 
+/// If the oracle price moves more than `price_deviation_bps` within
+/// `price_deviation_window` seconds, borrows and withdrawals are blocked for
+/// `circuit_breaker_cooldown` seconds. A deviation of 0 disables the breaker.
+pub fn set_circuit_breaker_handler(
+    ctx: Context<UpdateMarketConfig>,
+    price_deviation_bps: u16,
+    price_deviation_window: i64,
+    circuit_breaker_cooldown: i64,
+) -> Result<()> {
+    require!(
+        price_deviation_bps <= BPS_SCALE,
+        LendingError::InvalidMarketConfig
+    );
+    require!(price_deviation_window > 0, LendingError::InvalidMarketConfig);
+    require!(circuit_breaker_cooldown >= 0, LendingError::InvalidMarketConfig);
+
+    let market = &mut ctx.accounts.market;
+    market.price_deviation_bps = price_deviation_bps;
+    market.price_deviation_window = price_deviation_window;
+    market.circuit_breaker_cooldown = circuit_breaker_cooldown;
+
+    emit!(CircuitBreakerConfigUpdated {
+        market: market.key(),
+        price_deviation_bps,
+        price_deviation_window,
+        circuit_breaker_cooldown,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct CircuitBreakerConfigUpdated {
+    pub market: Pubkey,
+    pub price_deviation_bps: u16,
+    pub price_deviation_window: i64,
+    pub circuit_breaker_cooldown: i64,
+    pub timestamp: i64,
+}
+
+/// Configure the reserve outflow limit
+/// 
+/// Net outflows (borrows and withdrawals minus supplies and repayments) are
+/// tracked in a token bucket that refills over `outflow_window` seconds.
+/// An `outflow_limit` of 0 disables the limit.
+pub fn set_outflow_limit_handler(
+    ctx: Context<UpdateMarketConfig>,
+    outflow_limit: u64,
+    outflow_window: i64,
+) -> Result<()> {
+    require!(outflow_window > 0, LendingError::InvalidMarketConfig);
+
+    let market = &mut ctx.accounts.market;
+    let clock = Clock::get()?;
+
+    // Settle the bucket under the old parameters before switching
+    market.record_inflow(0, &clock)?;
+    market.outflow_limit = outflow_limit;
+    market.outflow_window = outflow_window;
+
+    emit!(OutflowLimitUpdated {
+        market: market.key(),
+        outflow_limit,
+        outflow_window,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct OutflowLimitUpdated {
+    pub market: Pubkey,
+    pub outflow_limit: u64,
+    pub outflow_window: i64,
+    pub timestamp: i64,
+}
+
+/// Configure isolated collateral mode
+/// 
+/// Isolated collateral must be the only collateral in an obligation, which can
+/// then only borrow from isolation-borrowable markets, and total debt backed by
+/// it is capped at `isolation_debt_ceiling` (ISOLATION_DEBT_DECIMALS).
+pub fn set_isolation_config_handler(
+    ctx: Context<UpdateMarketConfig>,
+    isolated_collateral: bool,
+    isolation_borrowable: bool,
+    isolation_debt_ceiling: u64,
+) -> Result<()> {
+    // An isolated asset can't be borrowed against itself
+    require!(
+        !(isolated_collateral && isolation_borrowable),
+        LendingError::InvalidMarketConfig
+    );
+
+    let market = &mut ctx.accounts.market;
+    market.isolated_collateral = isolated_collateral;
+    market.isolation_borrowable = isolation_borrowable;
+    market.isolation_debt_ceiling = isolation_debt_ceiling;
+
+    emit!(IsolationConfigUpdated {
+        market: market.key(),
+        isolated_collateral,
+        isolation_borrowable,
+        isolation_debt_ceiling,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct IsolationConfigUpdated {
+    pub market: Pubkey,
+    pub isolated_collateral: bool,
+    pub isolation_borrowable: bool,
+    pub isolation_debt_ceiling: u64,
+    pub timestamp: i64,
+}
+
+/// Configure the market's borrow factor
+/// 
+/// Debt in this market is divided by `borrow_factor_bps` when computing an
+/// obligation's borrow limit and health factor, so volatile assets use up more
+/// borrowing power than stablecoins. 10000 (1.0) leaves debt unweighted.
+pub fn set_borrow_factor_handler(
+    ctx: Context<UpdateMarketConfig>,
+    borrow_factor_bps: u16,
+) -> Result<()> {
+    require!(
+        borrow_factor_bps > 0 && borrow_factor_bps <= BPS_SCALE,
+        LendingError::InvalidMarketConfig
+    );
+
+    let market = &mut ctx.accounts.market;
+    market.borrow_factor_bps = borrow_factor_bps;
+
+    emit!(BorrowFactorUpdated {
+        market: market.key(),
+        borrow_factor_bps,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct BorrowFactorUpdated {
+    pub market: Pubkey,
+    pub borrow_factor_bps: u16,
+    pub timestamp: i64,
+}
+
+/// Configure the Dutch-auction liquidation bonus
+/// 
+/// The bonus rises linearly from `min_liquidation_bonus_bps` to
+/// `max_liquidation_bonus_bps` as the health factor falls below 1.0, or as
+/// `liquidation_auction_duration` seconds pass since the position became
+/// liquidatable. Seized collateral at the maximum bonus must stay covered by
+/// the liquidation threshold.
+pub fn set_liquidation_bonus_handler(
+    ctx: Context<UpdateMarketConfig>,
+    min_liquidation_bonus_bps: u16,
+    max_liquidation_bonus_bps: u16,
+    liquidation_auction_duration: i64,
+) -> Result<()> {
+    require!(
+        min_liquidation_bonus_bps <= max_liquidation_bonus_bps,
+        LendingError::InvalidMarketConfig
+    );
+    require!(liquidation_auction_duration > 0, LendingError::InvalidMarketConfig);
+
+    let market = &mut ctx.accounts.market;
+    let max_seized = calculate_liquidation_bonus(market.liquidation_threshold_bps as u64, max_liquidation_bonus_bps)?
+        .checked_add(market.liquidation_threshold_bps as u64)
+        .ok_or(LendingError::MathOverflow)?;
+    require!(max_seized <= BPS_SCALE as u64, LendingError::InvalidMarketConfig);
+
+    market.min_liquidation_bonus_bps = min_liquidation_bonus_bps;
+    market.max_liquidation_bonus_bps = max_liquidation_bonus_bps;
+    market.liquidation_auction_duration = liquidation_auction_duration;
+
+    emit!(LiquidationBonusUpdated {
+        market: market.key(),
+        min_liquidation_bonus_bps,
+        max_liquidation_bonus_bps,
+        liquidation_auction_duration,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct LiquidationBonusUpdated {
+    pub market: Pubkey,
+    pub min_liquidation_bonus_bps: u16,
+    pub max_liquidation_bonus_bps: u16,
+    pub liquidation_auction_duration: i64,
+    pub timestamp: i64,
+}
+
+/// Configure the treasury's share of the liquidation bonus
+/// 
+/// `liquidation_protocol_fee_bps` is a fraction of the bonus, not of the seized
+/// collateral; 10000 sends the whole bonus to the treasury.
+pub fn set_liquidation_protocol_fee_handler(
+    ctx: Context<UpdateMarketConfig>,
+    liquidation_protocol_fee_bps: u16,
+) -> Result<()> {
+    require!(
+        liquidation_protocol_fee_bps <= BPS_SCALE,
+        LendingError::InvalidMarketConfig
+    );
+
+    let market = &mut ctx.accounts.market;
+    market.liquidation_protocol_fee_bps = liquidation_protocol_fee_bps;
+
+    emit!(LiquidationProtocolFeeUpdated {
+        market: market.key(),
+        liquidation_protocol_fee_bps,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct LiquidationProtocolFeeUpdated {
+    pub market: Pubkey,
+    pub liquidation_protocol_fee_bps: u16,
+    pub timestamp: i64,
+}
+
+/// Configure auction liquidations
+/// 
+/// Liquidations repaying at least `large_liquidation_threshold` of the
+/// borrowed asset may be auctioned for `large_liquidation_auction_duration` seconds
+/// instead of sold at a fixed price. A threshold of 0 disables auctions.
+pub fn set_auction_liquidation_handler(
+    ctx: Context<UpdateMarketConfig>,
+    large_liquidation_threshold: u64,
+    large_liquidation_auction_duration: i64,
+) -> Result<()> {
+    require!(large_liquidation_auction_duration > 0, LendingError::InvalidMarketConfig);
+
+    let market = &mut ctx.accounts.market;
+    market.large_liquidation_threshold = large_liquidation_threshold;
+    market.large_liquidation_auction_duration = large_liquidation_auction_duration;
+
+    emit!(AuctionLiquidationUpdated {
+        market: market.key(),
+        large_liquidation_threshold,
+        large_liquidation_auction_duration,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct AuctionLiquidationUpdated {
+    pub market: Pubkey,
+    pub large_liquidation_threshold: u64,
+    pub large_liquidation_auction_duration: i64,
+    pub timestamp: i64,
+}
@@ -6,6 +6,20 @@ pub mod repay;
 pub mod withdraw;
 pub mod liquidate;
 pub mod vault;
+pub mod refresh_market;
+pub mod obligation;
+pub mod emode;
+pub mod refresh_obligation;
+pub mod bad_debt;
+pub mod insurance;
+pub mod flash_liquidate;
+pub mod liquidation_auction;
+pub mod auto_deleverage;
+pub mod repay_with_collateral;
+pub mod leverage;
+pub mod swap_position;
+pub mod withdrawal_queue;
+pub mod vault_timelock;
 
 pub use initialize::*;
 pub use market::*;
@@ -15,3 +29,17 @@ pub use repay::*;
 pub use withdraw::*;
 pub use liquidate::*;
 pub use vault::*;
+pub use refresh_market::*;
+pub use obligation::*;
+pub use emode::*;
+pub use refresh_obligation::*;
+pub use bad_debt::*;
+pub use insurance::*;
+pub use flash_liquidate::*;
+pub use liquidation_auction::*;
+pub use auto_deleverage::*;
+pub use repay_with_collateral::*;
+pub use leverage::*;
+pub use swap_position::*;
+pub use withdrawal_queue::*;
+pub use vault_timelock::*;
@@ -0,0 +1,264 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use crate::state::*;
+use crate::errors::LendingError;
+use crate::health::{calculate_obligation_values, require_emode_category};
+use crate::oracle::get_oracle_price;
+use crate::instructions::refresh_market::observe_market_price;
+
+/// Create a user's obligation
+///
+/// An obligation tracks the supply tokens a user has pledged as collateral
+/// and the debt they owe across all markets.
+#[derive(Accounts)]
+pub struct InitObligation<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = Obligation::SIZE,
+        seeds = [b"obligation", owner.key().as_ref()],
+        bump
+    )]
+    pub obligation: Account<'info, Obligation>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(ctx: Context<InitObligation>) -> Result<()> {
+    let obligation = &mut ctx.accounts.obligation;
+    let clock = Clock::get()?;
+    let bump = ctx.bumps.get("obligation").copied().unwrap();
+
+    obligation.initialize(ctx.accounts.owner.key(), bump, &clock);
+
+    emit!(ObligationCreated {
+        obligation: obligation.key(),
+        owner: ctx.accounts.owner.key(),
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Deposit supply tokens into an obligation as collateral
+///
+/// The supply tokens are held in the market's collateral vault and keep
+/// earning supply interest while pledged. Isolated collateral can't be
+/// combined with any other collateral.
+#[derive(Accounts)]
+pub struct DepositCollateral<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"obligation", owner.key().as_ref()],
+        bump = obligation.bump
+    )]
+    pub obligation: Account<'info, Obligation>,
+
+    #[account(
+        seeds = [b"market", market.asset_mint.as_ref()],
+        bump = market.bump,
+        constraint = !market.paused @ LendingError::MarketPaused
+    )]
+    pub market: Account<'info, Market>,
+
+    /// User's supply token account (source of collateral)
+    #[account(
+        mut,
+        constraint = user_supply_account.owner == owner.key() @ LendingError::Unauthorized,
+        constraint = user_supply_account.mint == market.supply_mint @ LendingError::InvalidMarketConfig
+    )]
+    pub user_supply_account: Account<'info, TokenAccount>,
+
+    /// Market collateral vault (destination for collateral)
+    #[account(
+        mut,
+        constraint = collateral_vault.key() == market.collateral_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn deposit_collateral_handler(ctx: Context<DepositCollateral>, supply_tokens: u64) -> Result<()> {
+    require!(supply_tokens > 0, LendingError::InvalidAmount);
+
+    let obligation = &mut ctx.accounts.obligation;
+    let clock = Clock::get()?;
+
+    let transfer_ctx = CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        Transfer {
+            from: ctx.accounts.user_supply_account.to_account_info(),
+            to: ctx.accounts.collateral_vault.to_account_info(),
+            authority: ctx.accounts.owner.to_account_info(),
+        },
+    );
+    token::transfer(transfer_ctx, supply_tokens)?;
+
+    obligation.deposit(
+        ctx.accounts.market.key(),
+        ctx.accounts.market.isolated_collateral,
+        supply_tokens,
+    )?;
+    obligation.last_updated = clock.unix_timestamp;
+
+    emit!(CollateralDeposited {
+        obligation: obligation.key(),
+        market: ctx.accounts.market.key(),
+        supply_tokens,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Withdraw supply tokens from an obligation's collateral
+///
+/// Debt must stay within the obligation's borrow limit afterwards.
+/// Remaining accounts: `[market, oracle]` for each deposit, then for each borrow.
+#[derive(Accounts)]
+pub struct WithdrawCollateral<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"obligation", owner.key().as_ref()],
+        bump = obligation.bump
+    )]
+    pub obligation: Account<'info, Obligation>,
+
+    #[account(
+        mut,
+        seeds = [b"market", market.asset_mint.as_ref()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, Market>,
+
+    /// Market collateral vault (source of collateral)
+    #[account(
+        mut,
+        constraint = collateral_vault.key() == market.collateral_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+
+    /// User's supply token account (destination for collateral)
+    #[account(
+        mut,
+        constraint = user_supply_account.mint == market.supply_mint @ LendingError::InvalidMarketConfig
+    )]
+    pub user_supply_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Oracle account validated against the market
+    #[account(
+        constraint = oracle.key() == market.oracle @ LendingError::InvalidOracle
+    )]
+    pub oracle: UncheckedAccount<'info>,
+
+    /// E-mode category the obligation opted into, if any
+    #[account(
+        seeds = [b"emode_category", obligation.emode_category.to_le_bytes().as_ref()],
+        bump = emode_category.bump
+    )]
+    pub emode_category: Option<Account<'info, EModeCategory>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn withdraw_collateral_handler(ctx: Context<WithdrawCollateral>, supply_tokens: u64) -> Result<()> {
+    require!(supply_tokens > 0, LendingError::InvalidAmount);
+
+    let obligation = &mut ctx.accounts.obligation;
+    let market = &mut ctx.accounts.market;
+    let clock = Clock::get()?;
+
+    market.accrue_interest(&clock)?;
+
+    // Block collateral withdrawals while the price circuit breaker is tripped;
+    // a new trip returns early so it isn't rolled back with an error
+    let price = get_oracle_price(&ctx.accounts.oracle, &clock)?;
+    if observe_market_price(market, price, &clock)? {
+        return Ok(());
+    }
+    require!(
+        !market.is_circuit_breaker_active(clock.unix_timestamp),
+        LendingError::CircuitBreakerActive
+    );
+
+    // The obligation can't change while it is being liquidated
+    obligation.require_not_liquidating()?;
+
+    obligation.withdraw(market.key(), supply_tokens)?;
+
+    let emode_category = require_emode_category(obligation, ctx.accounts.emode_category.as_deref())?;
+    let values = calculate_obligation_values(
+        obligation,
+        ctx.remaining_accounts,
+        &[(market.key(), market)],
+        emode_category,
+        &clock,
+    )?;
+    require!(
+        values.is_within_borrow_limit(),
+        LendingError::WithdrawWouldCauseLiquidation
+    );
+    obligation.update_liquidatable_since(values.health_factor()?, &clock);
+
+    let seeds = &[
+        b"market",
+        market.asset_mint.as_ref(),
+        &[market.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    let transfer_ctx = CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        Transfer {
+            from: ctx.accounts.collateral_vault.to_account_info(),
+            to: ctx.accounts.user_supply_account.to_account_info(),
+            authority: market.to_account_info(),
+        },
+        signer,
+    );
+    token::transfer(transfer_ctx, supply_tokens)?;
+
+    obligation.last_updated = clock.unix_timestamp;
+
+    emit!(CollateralWithdrawn {
+        obligation: obligation.key(),
+        market: market.key(),
+        supply_tokens,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct ObligationCreated {
+    pub obligation: Pubkey,
+    pub owner: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CollateralDeposited {
+    pub obligation: Pubkey,
+    pub market: Pubkey,
+    pub supply_tokens: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CollateralWithdrawn {
+    pub obligation: Pubkey,
+    pub market: Pubkey,
+    pub supply_tokens: u64,
+    pub timestamp: i64,
+}
@@ -0,0 +1,63 @@
+use anchor_lang::prelude::*;
+use crate::state::*;
+use crate::errors::LendingError;
+use crate::oracle::get_oracle_price;
+
+/// Refresh a market's interest and oracle price
+///
+/// Permissionless crank. Borrow and withdraw check the circuit breaker too;
+/// keepers call this to record a trip without borrowing or withdrawing.
+#[derive(Accounts)]
+pub struct RefreshMarket<'info> {
+    #[account(
+        mut,
+        seeds = [b"market", market.asset_mint.as_ref()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, Market>,
+
+    /// CHECK: Oracle account validated against the market
+    #[account(
+        constraint = oracle.key() == market.oracle @ LendingError::InvalidOracle
+    )]
+    pub oracle: UncheckedAccount<'info>,
+}
+
+pub fn handler(ctx: Context<RefreshMarket>) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+    let clock = Clock::get()?;
+
+    market.accrue_interest(&clock)?;
+
+    let price = get_oracle_price(&ctx.accounts.oracle, &clock)?;
+    observe_market_price(market, price, &clock)?;
+
+    Ok(())
+}
+
+/// Record an oracle price on a market, reporting a circuit breaker trip
+///
+/// Returns true if this observation tripped the breaker. Instructions that
+/// check the breaker return early without an error when it trips, so the trip
+/// and its cooldown are not rolled back with the transaction.
+pub fn observe_market_price(market: &mut Account<Market>, price: u64, clock: &Clock) -> Result<bool> {
+    let tripped = market.observe_price(price, clock)?;
+    if tripped {
+        emit!(CircuitBreakerTripped {
+            market: market.key(),
+            price,
+            paused_until: market.circuit_breaker_until,
+            timestamp: clock.unix_timestamp,
+        });
+    }
+
+    Ok(tripped)
+}
+
+#[event]
+pub struct CircuitBreakerTripped {
+    pub market: Pubkey,
+    pub price: u64,
+    pub paused_until: i64,
+    pub timestamp: i64,
+}
@@ -0,0 +1,59 @@
+use anchor_lang::prelude::*;
+use crate::state::*;
+use crate::health::{calculate_obligation_values, require_emode_category};
+
+/// Refresh whether an obligation is liquidatable
+///
+/// Permissionless crank. Starts the liquidation auction clock when the
+/// obligation first becomes unhealthy, so the liquidation bonus can rise
+/// over time, and resets it once the obligation is healthy again.
+/// Remaining accounts: `[market, oracle]` for each deposit, then for each borrow.
+#[derive(Accounts)]
+pub struct RefreshObligation<'info> {
+    #[account(
+        mut,
+        seeds = [b"obligation", obligation.owner.as_ref()],
+        bump = obligation.bump
+    )]
+    pub obligation: Account<'info, Obligation>,
+
+    /// E-mode category the obligation opted into, if any
+    #[account(
+        seeds = [b"emode_category", obligation.emode_category.to_le_bytes().as_ref()],
+        bump = emode_category.bump
+    )]
+    pub emode_category: Option<Account<'info, EModeCategory>>,
+}
+
+pub fn handler(ctx: Context<RefreshObligation>) -> Result<()> {
+    let obligation = &mut ctx.accounts.obligation;
+    let clock = Clock::get()?;
+
+    let emode_category = require_emode_category(obligation, ctx.accounts.emode_category.as_deref())?;
+    let values = calculate_obligation_values(
+        obligation,
+        ctx.remaining_accounts,
+        &[],
+        emode_category,
+        &clock,
+    )?;
+    let health_factor = values.health_factor()?;
+    obligation.update_liquidatable_since(health_factor, &clock);
+
+    emit!(ObligationRefreshed {
+        obligation: obligation.key(),
+        health_factor,
+        liquidatable_since: obligation.liquidatable_since,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct ObligationRefreshed {
+    pub obligation: Pubkey,
+    pub health_factor: u16,
+    pub liquidatable_since: i64,
+    pub timestamp: i64,
+}
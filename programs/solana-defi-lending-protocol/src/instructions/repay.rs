@@ -7,11 +7,19 @@ use crate::constants::*;
 /// Repay borrowed assets
 /// 
 /// Users repay their borrows, reducing their debt and freeing up collateral.
+/// Anyone can repay on behalf of an obligation.
 #[derive(Accounts)]
 pub struct Repay<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
 
+    #[account(
+        mut,
+        seeds = [b"obligation", obligation.owner.as_ref()],
+        bump = obligation.bump
+    )]
+    pub obligation: Account<'info, Obligation>,
+
     #[account(
         mut,
         seeds = [b"market", market.asset_mint.as_ref()],
@@ -30,10 +38,17 @@ pub struct Repay<'info> {
     /// Reserve vault (destination for repaid assets)
     #[account(
         mut,
-        constraint = reserve_vault.mint == market.asset_mint @ LendingError::InvalidMarketConfig
+        constraint = reserve_vault.key() == market.reserve_vault @ LendingError::InvalidMarketConfig
     )]
     pub reserve_vault: Account<'info, TokenAccount>,
 
+    /// Isolated collateral market backing the obligation, if any
+    #[account(
+        mut,
+        constraint = isolated_collateral_market.key() == obligation.isolated_collateral_market @ LendingError::IsolatedCollateralConflict
+    )]
+    pub isolated_collateral_market: Option<Account<'info, Market>>,
+
     pub token_program: Program<'info, Token>,
 }
 
@@ -41,11 +56,29 @@ pub fn handler(ctx: Context<Repay>, amount: u64) -> Result<()> {
     require!(amount > 0, LendingError::InvalidAmount);
 
     let market = &mut ctx.accounts.market;
+    let obligation = &mut ctx.accounts.obligation;
     let clock = Clock::get()?;
 
+    // An auction has fixed the debt its winner repays
+    obligation.require_not_liquidating()?;
+
     // Accrue interest before processing
     market.accrue_interest(&clock)?;
 
+    // Repay at most the outstanding debt (checking isolation first, since
+    // repaying the last of it can end isolated mode)
+    let isolated = obligation.is_isolated();
+    let amount = obligation.repay(market.key(), market, amount)?;
+    obligation.last_updated = clock.unix_timestamp;
+
+    // Release the repaid debt from the isolated collateral's debt ceiling
+    if isolated {
+        let isolated_market = ctx.accounts.isolated_collateral_market
+            .as_mut()
+            .ok_or(LendingError::IsolatedCollateralConflict)?;
+        isolated_market.remove_isolation_debt(amount, market.mint_decimals)?;
+    }
+
     // Transfer assets from user to reserve vault
     let transfer_ctx = CpiContext::new(
         ctx.accounts.token_program.to_account_info(),
@@ -56,16 +89,16 @@ pub fn handler(ctx: Context<Repay>, amount: u64) -> Result<()> {
         },
     );
     token::transfer(transfer_ctx, amount)?;
+    market.record_inflow(amount, &clock)?;
 
     // Update market state
-    // Note: In a full implementation, you'd update the specific borrow position
-    // For now, we're simplifying by updating total_borrowed
     market.total_borrowed = market.total_borrowed
         .checked_sub(amount)
         .ok_or(LendingError::MathOverflow)?;
 
     emit!(Repaid {
         market: market.key(),
+        obligation: obligation.key(),
         user: ctx.accounts.user.key(),
         amount,
         total_borrowed: market.total_borrowed,
@@ -78,6 +111,7 @@ pub fn handler(ctx: Context<Repay>, amount: u64) -> Result<()> {
 #[event]
 pub struct Repaid {
     pub market: Pubkey,
+    pub obligation: Pubkey,
     pub user: Pubkey,
     pub amount: u64,
     pub total_borrowed: u64,
@@ -0,0 +1,324 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Burn, Mint, Token, TokenAccount, Transfer};
+use crate::state::*;
+use crate::errors::LendingError;
+use crate::math::calculate_market_value;
+use crate::health::{calculate_obligation_values, collateral_params, require_emode_category};
+use crate::instructions::liquidate::apply_liquidation;
+use crate::oracle::get_oracle_price;
+use crate::swap::SwapAdapter;
+use crate::instructions::refresh_market::observe_market_price;
+
+/// Repay a borrow by selling collateral
+///
+/// Withdraws `supply_tokens` of collateral, swaps the underlying into the debt
+/// asset through the configured swap program and repays the borrow. Output
+/// beyond the outstanding debt is sent to the owner. The health factor must
+/// not drop.
+/// Remaining accounts: `[market, oracle]` for each deposit, then for each borrow.
+#[derive(Accounts)]
+pub struct RepayWithCollateral<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"obligation", owner.key().as_ref()],
+        bump = obligation.bump
+    )]
+    pub obligation: Account<'info, Obligation>,
+
+    /// Market for the debt being repaid
+    #[account(
+        mut,
+        seeds = [b"market", borrow_market.asset_mint.as_ref()],
+        bump = borrow_market.bump
+    )]
+    pub borrow_market: Account<'info, Market>,
+
+    /// Market for the collateral being sold
+    #[account(
+        mut,
+        seeds = [b"market", collateral_market.asset_mint.as_ref()],
+        bump = collateral_market.bump,
+        constraint = collateral_market.key() != borrow_market.key() @ LendingError::InvalidMarketConfig
+    )]
+    pub collateral_market: Account<'info, Market>,
+
+    /// Borrow market reserve vault (receives the swap output)
+    #[account(
+        mut,
+        constraint = borrow_reserve_vault.key() == borrow_market.reserve_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub borrow_reserve_vault: Account<'info, TokenAccount>,
+
+    /// Collateral market reserve vault (swap input)
+    #[account(
+        mut,
+        constraint = collateral_reserve_vault.key() == collateral_market.reserve_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub collateral_reserve_vault: Account<'info, TokenAccount>,
+
+    /// Collateral market collateral vault (holds the obligation's supply tokens)
+    #[account(
+        mut,
+        constraint = collateral_vault.key() == collateral_market.collateral_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+
+    /// Collateral market supply mint (sold supply tokens are burned)
+    #[account(
+        mut,
+        constraint = collateral_supply_mint.key() == collateral_market.supply_mint @ LendingError::InvalidMarketConfig
+    )]
+    pub collateral_supply_mint: Account<'info, Mint>,
+
+    /// Owner's token account for the debt asset (receives output beyond the debt)
+    #[account(
+        mut,
+        constraint = owner_token_account.owner == owner.key() @ LendingError::Unauthorized,
+        constraint = owner_token_account.mint == borrow_market.asset_mint @ LendingError::InvalidMarketConfig
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Oracle for borrowed asset price
+    #[account(
+        constraint = borrow_oracle.key() == borrow_market.oracle @ LendingError::InvalidOracle
+    )]
+    pub borrow_oracle: UncheckedAccount<'info>,
+
+    /// CHECK: Oracle for collateral asset price
+    #[account(
+        constraint = collateral_oracle.key() == collateral_market.oracle @ LendingError::InvalidOracle
+    )]
+    pub collateral_oracle: UncheckedAccount<'info>,
+
+    /// E-mode category the obligation opted into, if any
+    #[account(
+        seeds = [b"emode_category", obligation.emode_category.to_le_bytes().as_ref()],
+        bump = emode_category.bump
+    )]
+    pub emode_category: Option<Account<'info, EModeCategory>>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// CHECK: Swap program, must be allowlisted on GlobalConfig
+    #[account(
+        constraint = global_config.is_swap_program_allowed(&swap_program.key()) @ LendingError::InvalidSwapProgram
+    )]
+    pub swap_program: UncheckedAccount<'info>,
+
+    /// CHECK: Swap pool, validated by the swap program
+    #[account(mut)]
+    pub swap_pool: UncheckedAccount<'info>,
+
+    /// CHECK: Pool vault for the collateral asset, validated by the swap program
+    #[account(mut)]
+    pub swap_pool_source_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Pool vault for the debt asset, validated by the swap program
+    #[account(mut)]
+    pub swap_pool_destination_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Swap authority PDA, delegated the swap input for the duration of the swap
+    #[account(
+        seeds = [b"swap_authority", global_config.key().as_ref()],
+        bump
+    )]
+    pub swap_authority: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn handler(
+    ctx: Context<RepayWithCollateral>,
+    supply_tokens: u64,
+    min_amount_out: u64,
+) -> Result<()> {
+    require!(supply_tokens > 0, LendingError::InvalidAmount);
+
+    let obligation = &mut ctx.accounts.obligation;
+    let borrow_market = &mut ctx.accounts.borrow_market;
+    let collateral_market = &mut ctx.accounts.collateral_market;
+    let clock = Clock::get()?;
+
+    // The obligation can't change while it is being liquidated
+    obligation.require_not_liquidating()?;
+
+    borrow_market.accrue_interest(&clock)?;
+    collateral_market.accrue_interest(&clock)?;
+
+    // Block collateral withdrawals while the price circuit breaker is tripped;
+    // a new trip returns early so it isn't rolled back with an error
+    let borrow_price = get_oracle_price(&ctx.accounts.borrow_oracle, &clock)?;
+    let collateral_price = get_oracle_price(&ctx.accounts.collateral_oracle, &clock)?;
+    if observe_market_price(collateral_market, collateral_price, &clock)? {
+        return Ok(());
+    }
+    require!(
+        !collateral_market.is_circuit_breaker_active(clock.unix_timestamp),
+        LendingError::CircuitBreakerActive
+    );
+
+    let emode_category = require_emode_category(obligation, ctx.accounts.emode_category.as_deref())?;
+    let mut values = calculate_obligation_values(
+        obligation,
+        ctx.remaining_accounts,
+        &[
+            (borrow_market.key(), borrow_market),
+            (collateral_market.key(), collateral_market),
+        ],
+        emode_category,
+        &clock,
+    )?;
+    let health_factor = values.health_factor()?;
+
+    let debt = obligation
+        .find_borrow(&borrow_market.key())
+        .ok_or(LendingError::BorrowPositionNotFound)?
+        .calculate_debt(borrow_market)?;
+
+    let collateral_amount = collateral_market.supply_tokens_to_amount(supply_tokens)?;
+    require!(
+        supply_tokens <= obligation.deposited_supply_tokens(&collateral_market.key()),
+        LendingError::InsufficientCollateral
+    );
+    require!(
+        collateral_amount <= ctx.accounts.collateral_reserve_vault.amount,
+        LendingError::InsufficientLiquidity
+    );
+
+    // Bound how fast the collateral reserve can be drained
+    collateral_market.record_outflow(collateral_amount, &clock)?;
+
+    let collateral_seeds = &[
+        b"market",
+        collateral_market.asset_mint.as_ref(),
+        &[collateral_market.bump],
+    ];
+    let collateral_signer = &[&collateral_seeds[..]];
+
+    // Burn the sold supply tokens held as collateral
+    let burn_ctx = CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        Burn {
+            mint: ctx.accounts.collateral_supply_mint.to_account_info(),
+            from: ctx.accounts.collateral_vault.to_account_info(),
+            authority: collateral_market.to_account_info(),
+        },
+        collateral_signer,
+    );
+    token::burn(burn_ctx, supply_tokens)?;
+
+    // Swap straight from the collateral reserve into the borrow reserve
+    let global_config_key = ctx.accounts.global_config.key();
+    let swap_authority_bump = ctx.bumps.get("swap_authority").copied().unwrap();
+    let swap_authority_seeds: &[&[u8]] = &[
+        b"swap_authority",
+        global_config_key.as_ref(),
+        &[swap_authority_bump],
+    ];
+    let adapter = SwapAdapter {
+        program: &ctx.accounts.swap_program,
+        pool: &ctx.accounts.swap_pool,
+        source_vault: &ctx.accounts.swap_pool_source_vault,
+        destination_vault: &ctx.accounts.swap_pool_destination_vault,
+        authority: &ctx.accounts.swap_authority,
+        authority_seeds: swap_authority_seeds,
+    };
+    let amount_out = adapter.swap(
+        &mut ctx.accounts.collateral_reserve_vault,
+        &mut ctx.accounts.borrow_reserve_vault,
+        &collateral_market.to_account_info(),
+        &ctx.accounts.token_program.to_account_info(),
+        collateral_signer,
+        collateral_amount,
+        min_amount_out,
+    )?;
+
+    // Return any output beyond the debt to the owner
+    let repay_amount = amount_out.min(debt);
+    let refund = amount_out - repay_amount;
+    if refund > 0 {
+        let borrow_seeds = &[
+            b"market",
+            borrow_market.asset_mint.as_ref(),
+            &[borrow_market.bump],
+        ];
+        let borrow_signer = &[&borrow_seeds[..]];
+        let refund_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.borrow_reserve_vault.to_account_info(),
+                to: ctx.accounts.owner_token_account.to_account_info(),
+                authority: borrow_market.to_account_info(),
+            },
+            borrow_signer,
+        );
+        token::transfer(refund_ctx, refund)?;
+    }
+
+    apply_liquidation(
+        obligation,
+        borrow_market,
+        collateral_market,
+        repay_amount,
+        supply_tokens,
+        &clock,
+    )?;
+    collateral_market.total_supplied = collateral_market.total_supplied
+        .checked_sub(collateral_amount)
+        .ok_or(LendingError::MathOverflow)?;
+    collateral_market.total_supply_tokens = collateral_market.total_supply_tokens
+        .checked_sub(supply_tokens)
+        .ok_or(LendingError::MathOverflow)?;
+
+    // A bad swap could cost more collateral than the debt it repays
+    let (ltv_bps, liquidation_threshold_bps) =
+        collateral_params(&collateral_market.key(), collateral_market, emode_category);
+    values.remove_collateral(
+        calculate_market_value(collateral_amount, collateral_price, collateral_market.mint_decimals)?,
+        ltv_bps,
+        liquidation_threshold_bps,
+    )?;
+    values.remove_debt(
+        calculate_market_value(repay_amount, borrow_price, borrow_market.mint_decimals)?,
+        borrow_market.borrow_factor_bps,
+    )?;
+    let new_health_factor = values.health_factor()?;
+    require!(
+        new_health_factor >= health_factor,
+        LendingError::HealthFactorDecreased
+    );
+    obligation.update_liquidatable_since(new_health_factor, &clock);
+
+    emit!(RepaidWithCollateral {
+        obligation: obligation.key(),
+        borrow_market: borrow_market.key(),
+        collateral_market: collateral_market.key(),
+        supply_tokens,
+        collateral_amount,
+        repay_amount,
+        refund,
+        health_factor: new_health_factor,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct RepaidWithCollateral {
+    pub obligation: Pubkey,
+    pub borrow_market: Pubkey,
+    pub collateral_market: Pubkey,
+    pub supply_tokens: u64,
+    pub collateral_amount: u64,
+    pub repay_amount: u64,
+    pub refund: u64,
+    pub health_factor: u16,
+    pub timestamp: i64,
+}
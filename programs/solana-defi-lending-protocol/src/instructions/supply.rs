@@ -33,7 +33,7 @@ pub struct Supply<'info> {
     /// Reserve vault (destination for supplied assets)
     #[account(
         mut,
-        constraint = reserve_vault.mint == market.asset_mint @ LendingError::InvalidMarketConfig
+        constraint = reserve_vault.key() == market.reserve_vault @ LendingError::InvalidMarketConfig
     )]
     pub reserve_vault: Account<'info, TokenAccount>,
 
@@ -73,6 +73,7 @@ pub fn handler(ctx: Context<Supply>, amount: u64) -> Result<()> {
         },
     );
     token::transfer(transfer_ctx, amount)?;
+    market.record_inflow(amount, &clock)?;
 
     // Calculate exchange rate
     let exchange_rate = if market.total_supply_tokens == 0 {
@@ -0,0 +1,653 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Burn, Mint, MintTo, Token, TokenAccount, Transfer};
+use crate::state::*;
+use crate::errors::LendingError;
+use crate::constants::*;
+use crate::math::*;
+use crate::health::{calculate_obligation_values, collateral_params, require_emode_category};
+use crate::oracle::get_oracle_price;
+use crate::swap::SwapAdapter;
+use crate::instructions::refresh_market::observe_market_price;
+
+/// Rotate collateral from one market into another
+///
+/// Withdraws `supply_tokens` of source collateral, swaps the underlying into the
+/// destination asset through the configured swap program and deposits the
+/// output as collateral, all in one instruction. The position must stay within
+/// the borrow limit.
+/// Remaining accounts: `[market, oracle]` for each deposit, then for each borrow,
+/// as they are before the swap.
+#[derive(Accounts)]
+pub struct SwapCollateral<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"obligation", owner.key().as_ref()],
+        bump = obligation.bump
+    )]
+    pub obligation: Account<'info, Obligation>,
+
+    /// Market for the collateral being sold
+    #[account(
+        mut,
+        seeds = [b"market", source_market.asset_mint.as_ref()],
+        bump = source_market.bump
+    )]
+    pub source_market: Account<'info, Market>,
+
+    /// Market for the collateral being bought
+    #[account(
+        mut,
+        seeds = [b"market", destination_market.asset_mint.as_ref()],
+        bump = destination_market.bump,
+        constraint = !destination_market.paused @ LendingError::MarketPaused,
+        constraint = destination_market.key() != source_market.key() @ LendingError::InvalidMarketConfig
+    )]
+    pub destination_market: Account<'info, Market>,
+
+    /// Source market reserve vault (swap input)
+    #[account(
+        mut,
+        constraint = source_reserve_vault.key() == source_market.reserve_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub source_reserve_vault: Account<'info, TokenAccount>,
+
+    /// Destination market reserve vault (receives the swap output)
+    #[account(
+        mut,
+        constraint = destination_reserve_vault.key() == destination_market.reserve_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub destination_reserve_vault: Account<'info, TokenAccount>,
+
+    /// Source market collateral vault (holds the obligation's sold supply tokens)
+    #[account(
+        mut,
+        constraint = source_collateral_vault.key() == source_market.collateral_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub source_collateral_vault: Account<'info, TokenAccount>,
+
+    /// Destination market collateral vault (receives the new supply tokens)
+    #[account(
+        mut,
+        constraint = destination_collateral_vault.key() == destination_market.collateral_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub destination_collateral_vault: Account<'info, TokenAccount>,
+
+    /// Source market supply mint (sold supply tokens are burned)
+    #[account(
+        mut,
+        constraint = source_supply_mint.key() == source_market.supply_mint @ LendingError::InvalidMarketConfig
+    )]
+    pub source_supply_mint: Account<'info, Mint>,
+
+    /// Destination market supply mint
+    #[account(
+        mut,
+        constraint = destination_supply_mint.key() == destination_market.supply_mint @ LendingError::InvalidMarketConfig
+    )]
+    pub destination_supply_mint: Account<'info, Mint>,
+
+    /// CHECK: Oracle for the sold collateral price
+    #[account(
+        constraint = source_oracle.key() == source_market.oracle @ LendingError::InvalidOracle
+    )]
+    pub source_oracle: UncheckedAccount<'info>,
+
+    /// CHECK: Oracle for the bought collateral price
+    #[account(
+        constraint = destination_oracle.key() == destination_market.oracle @ LendingError::InvalidOracle
+    )]
+    pub destination_oracle: UncheckedAccount<'info>,
+
+    /// E-mode category the obligation opted into, if any
+    #[account(
+        seeds = [b"emode_category", obligation.emode_category.to_le_bytes().as_ref()],
+        bump = emode_category.bump
+    )]
+    pub emode_category: Option<Account<'info, EModeCategory>>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// CHECK: Swap program, must be allowlisted on GlobalConfig
+    #[account(
+        constraint = global_config.is_swap_program_allowed(&swap_program.key()) @ LendingError::InvalidSwapProgram
+    )]
+    pub swap_program: UncheckedAccount<'info>,
+
+    /// CHECK: Swap pool, validated by the swap program
+    #[account(mut)]
+    pub swap_pool: UncheckedAccount<'info>,
+
+    /// CHECK: Pool vault for the sold asset, validated by the swap program
+    #[account(mut)]
+    pub swap_pool_source_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Pool vault for the bought asset, validated by the swap program
+    #[account(mut)]
+    pub swap_pool_destination_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Swap authority PDA, delegated the swap input for the duration of the swap
+    #[account(
+        seeds = [b"swap_authority", global_config.key().as_ref()],
+        bump
+    )]
+    pub swap_authority: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn handler(
+    ctx: Context<SwapCollateral>,
+    supply_tokens: u64,
+    min_amount_out: u64,
+) -> Result<()> {
+    require!(supply_tokens > 0, LendingError::InvalidAmount);
+
+    let obligation = &mut ctx.accounts.obligation;
+    let source_market = &mut ctx.accounts.source_market;
+    let destination_market = &mut ctx.accounts.destination_market;
+    let clock = Clock::get()?;
+
+    // The obligation can't change while it is being liquidated
+    obligation.require_not_liquidating()?;
+
+    // Isolation debt ceilings are tracked on the collateral market, so isolated
+    // collateral can only be rotated once the obligation is debt-free
+    require!(
+        obligation.borrows.is_empty()
+            || (!obligation.is_isolated() && !destination_market.isolated_collateral),
+        LendingError::IsolatedCollateralConflict
+    );
+
+    source_market.accrue_interest(&clock)?;
+    destination_market.accrue_interest(&clock)?;
+
+    // Block collateral withdrawals while the price circuit breaker is tripped;
+    // a new trip returns early so it isn't rolled back with an error
+    let source_price = get_oracle_price(&ctx.accounts.source_oracle, &clock)?;
+    let destination_price = get_oracle_price(&ctx.accounts.destination_oracle, &clock)?;
+    if observe_market_price(source_market, source_price, &clock)? {
+        return Ok(());
+    }
+    require!(
+        !source_market.is_circuit_breaker_active(clock.unix_timestamp),
+        LendingError::CircuitBreakerActive
+    );
+
+    let emode_category = require_emode_category(obligation, ctx.accounts.emode_category.as_deref())?;
+    let mut values = calculate_obligation_values(
+        obligation,
+        ctx.remaining_accounts,
+        &[
+            (source_market.key(), source_market),
+            (destination_market.key(), destination_market),
+        ],
+        emode_category,
+        &clock,
+    )?;
+
+    let source_amount = source_market.supply_tokens_to_amount(supply_tokens)?;
+    require!(
+        supply_tokens <= obligation.deposited_supply_tokens(&source_market.key()),
+        LendingError::InsufficientCollateral
+    );
+    require!(
+        source_amount <= ctx.accounts.source_reserve_vault.amount,
+        LendingError::InsufficientLiquidity
+    );
+
+    // Bound how fast the source reserve can be drained
+    source_market.record_outflow(source_amount, &clock)?;
+
+    let source_seeds = &[
+        b"market",
+        source_market.asset_mint.as_ref(),
+        &[source_market.bump],
+    ];
+    let source_signer = &[&source_seeds[..]];
+
+    // Burn the sold supply tokens held as collateral
+    let burn_ctx = CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        Burn {
+            mint: ctx.accounts.source_supply_mint.to_account_info(),
+            from: ctx.accounts.source_collateral_vault.to_account_info(),
+            authority: source_market.to_account_info(),
+        },
+        source_signer,
+    );
+    token::burn(burn_ctx, supply_tokens)?;
+
+    // Swap straight from the source reserve into the destination reserve
+    let global_config_key = ctx.accounts.global_config.key();
+    let swap_authority_bump = ctx.bumps.get("swap_authority").copied().unwrap();
+    let swap_authority_seeds: &[&[u8]] = &[
+        b"swap_authority",
+        global_config_key.as_ref(),
+        &[swap_authority_bump],
+    ];
+    let adapter = SwapAdapter {
+        program: &ctx.accounts.swap_program,
+        pool: &ctx.accounts.swap_pool,
+        source_vault: &ctx.accounts.swap_pool_source_vault,
+        destination_vault: &ctx.accounts.swap_pool_destination_vault,
+        authority: &ctx.accounts.swap_authority,
+        authority_seeds: swap_authority_seeds,
+    };
+    let amount_out = adapter.swap(
+        &mut ctx.accounts.source_reserve_vault,
+        &mut ctx.accounts.destination_reserve_vault,
+        &source_market.to_account_info(),
+        &ctx.accounts.token_program.to_account_info(),
+        source_signer,
+        source_amount,
+        min_amount_out,
+    )?;
+
+    // Supply the output as collateral
+    let destination_supply_tokens = destination_market.amount_to_supply_tokens(amount_out)?;
+
+    let destination_seeds = &[
+        b"market",
+        destination_market.asset_mint.as_ref(),
+        &[destination_market.bump],
+    ];
+    let destination_signer = &[&destination_seeds[..]];
+
+    let mint_ctx = CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        MintTo {
+            mint: ctx.accounts.destination_supply_mint.to_account_info(),
+            to: ctx.accounts.destination_collateral_vault.to_account_info(),
+            authority: destination_market.to_account_info(),
+        },
+        destination_signer,
+    );
+    token::mint_to(mint_ctx, destination_supply_tokens)?;
+
+    source_market.total_supplied = source_market.total_supplied
+        .checked_sub(source_amount)
+        .ok_or(LendingError::MathOverflow)?;
+    source_market.total_supply_tokens = source_market.total_supply_tokens
+        .checked_sub(supply_tokens)
+        .ok_or(LendingError::MathOverflow)?;
+
+    destination_market.record_inflow(amount_out, &clock)?;
+    destination_market.total_supplied = destination_market.total_supplied
+        .checked_add(amount_out)
+        .ok_or(LendingError::MathOverflow)?;
+    destination_market.total_supply_tokens = destination_market.total_supply_tokens
+        .checked_add(destination_supply_tokens)
+        .ok_or(LendingError::MathOverflow)?;
+
+    // Withdraw first so a fully rotated deposit frees its slot
+    obligation.withdraw(source_market.key(), supply_tokens)?;
+    obligation.deposit(
+        destination_market.key(),
+        destination_market.isolated_collateral,
+        destination_supply_tokens,
+    )?;
+    obligation.last_updated = clock.unix_timestamp;
+
+    // The rotated position must stay within the borrow limit
+    let (source_ltv_bps, source_liquidation_threshold_bps) =
+        collateral_params(&source_market.key(), source_market, emode_category);
+    values.remove_collateral(
+        calculate_market_value(source_amount, source_price, source_market.mint_decimals)?,
+        source_ltv_bps,
+        source_liquidation_threshold_bps,
+    )?;
+    let (destination_ltv_bps, destination_liquidation_threshold_bps) =
+        collateral_params(&destination_market.key(), destination_market, emode_category);
+    values.add_collateral(
+        calculate_market_value(amount_out, destination_price, destination_market.mint_decimals)?,
+        destination_ltv_bps,
+        destination_liquidation_threshold_bps,
+    )?;
+    require!(
+        values.is_within_borrow_limit(),
+        LendingError::WithdrawWouldCauseLiquidation
+    );
+    let health_factor = values.health_factor()?;
+    obligation.update_liquidatable_since(health_factor, &clock);
+
+    emit!(CollateralSwapped {
+        obligation: obligation.key(),
+        source_market: source_market.key(),
+        destination_market: destination_market.key(),
+        supply_tokens,
+        source_amount,
+        destination_amount: amount_out,
+        destination_supply_tokens,
+        health_factor,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Refinance a borrow into another debt asset
+///
+/// Borrows `borrow_amount` from the new debt market, swaps it into the old debt
+/// asset through the configured swap program and repays the old borrow, all in
+/// one instruction. Output beyond the old debt is sent to the owner. The
+/// position must stay within the borrow limit.
+/// Remaining accounts: `[market, oracle]` for each deposit, then for each borrow,
+/// as they are before the swap.
+#[derive(Accounts)]
+pub struct SwapDebt<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"obligation", owner.key().as_ref()],
+        bump = obligation.bump
+    )]
+    pub obligation: Account<'info, Obligation>,
+
+    /// Market for the debt being repaid
+    #[account(
+        mut,
+        seeds = [b"market", repay_market.asset_mint.as_ref()],
+        bump = repay_market.bump
+    )]
+    pub repay_market: Account<'info, Market>,
+
+    /// Market for the new debt
+    #[account(
+        mut,
+        seeds = [b"market", borrow_market.asset_mint.as_ref()],
+        bump = borrow_market.bump,
+        constraint = !borrow_market.paused @ LendingError::MarketPaused,
+        constraint = borrow_market.key() != repay_market.key() @ LendingError::InvalidMarketConfig
+    )]
+    pub borrow_market: Account<'info, Market>,
+
+    /// Repay market reserve vault (receives the swap output)
+    #[account(
+        mut,
+        constraint = repay_reserve_vault.key() == repay_market.reserve_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub repay_reserve_vault: Account<'info, TokenAccount>,
+
+    /// Borrow market reserve vault (swap input)
+    #[account(
+        mut,
+        constraint = borrow_reserve_vault.key() == borrow_market.reserve_vault @ LendingError::InvalidMarketConfig
+    )]
+    pub borrow_reserve_vault: Account<'info, TokenAccount>,
+
+    /// Owner's token account for the repaid asset (receives output beyond the debt)
+    #[account(
+        mut,
+        constraint = owner_token_account.owner == owner.key() @ LendingError::Unauthorized,
+        constraint = owner_token_account.mint == repay_market.asset_mint @ LendingError::InvalidMarketConfig
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Oracle for the repaid asset price
+    #[account(
+        constraint = repay_oracle.key() == repay_market.oracle @ LendingError::InvalidOracle
+    )]
+    pub repay_oracle: UncheckedAccount<'info>,
+
+    /// CHECK: Oracle for the borrowed asset price
+    #[account(
+        constraint = borrow_oracle.key() == borrow_market.oracle @ LendingError::InvalidOracle
+    )]
+    pub borrow_oracle: UncheckedAccount<'info>,
+
+    /// E-mode category the obligation opted into, if any
+    #[account(
+        seeds = [b"emode_category", obligation.emode_category.to_le_bytes().as_ref()],
+        bump = emode_category.bump
+    )]
+    pub emode_category: Option<Account<'info, EModeCategory>>,
+
+    /// Isolated collateral market backing the obligation, if any
+    #[account(
+        mut,
+        constraint = isolated_collateral_market.key() == obligation.isolated_collateral_market @ LendingError::IsolatedCollateralConflict
+    )]
+    pub isolated_collateral_market: Option<Account<'info, Market>>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// CHECK: Swap program, must be allowlisted on GlobalConfig
+    #[account(
+        constraint = global_config.is_swap_program_allowed(&swap_program.key()) @ LendingError::InvalidSwapProgram
+    )]
+    pub swap_program: UncheckedAccount<'info>,
+
+    /// CHECK: Swap pool, validated by the swap program
+    #[account(mut)]
+    pub swap_pool: UncheckedAccount<'info>,
+
+    /// CHECK: Pool vault for the new debt asset, validated by the swap program
+    #[account(mut)]
+    pub swap_pool_source_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Pool vault for the repaid asset, validated by the swap program
+    #[account(mut)]
+    pub swap_pool_destination_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Swap authority PDA, delegated the swap input for the duration of the swap
+    #[account(
+        seeds = [b"swap_authority", global_config.key().as_ref()],
+        bump
+    )]
+    pub swap_authority: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn swap_debt_handler(
+    ctx: Context<SwapDebt>,
+    borrow_amount: u64,
+    min_amount_out: u64,
+) -> Result<()> {
+    require!(borrow_amount >= MIN_BORROW_AMOUNT, LendingError::InvalidAmount);
+
+    let obligation = &mut ctx.accounts.obligation;
+    let repay_market = &mut ctx.accounts.repay_market;
+    let borrow_market = &mut ctx.accounts.borrow_market;
+    let clock = Clock::get()?;
+
+    // The obligation can't change while it is being liquidated
+    obligation.require_not_liquidating()?;
+
+    repay_market.accrue_interest(&clock)?;
+    borrow_market.accrue_interest(&clock)?;
+
+    // Block borrows while the price circuit breaker is tripped;
+    // a new trip returns early so it isn't rolled back with an error
+    let repay_price = get_oracle_price(&ctx.accounts.repay_oracle, &clock)?;
+    let borrow_price = get_oracle_price(&ctx.accounts.borrow_oracle, &clock)?;
+    if observe_market_price(borrow_market, borrow_price, &clock)? {
+        return Ok(());
+    }
+    require!(
+        !borrow_market.is_circuit_breaker_active(clock.unix_timestamp),
+        LendingError::CircuitBreakerActive
+    );
+
+    // E-mode obligations can only borrow from member markets
+    let emode_category = require_emode_category(obligation, ctx.accounts.emode_category.as_deref())?;
+    if let Some(category) = emode_category {
+        require!(
+            category.contains(&borrow_market.key()),
+            LendingError::EModeMarketNotAllowed
+        );
+    }
+
+    let mut values = calculate_obligation_values(
+        obligation,
+        ctx.remaining_accounts,
+        &[
+            (repay_market.key(), repay_market),
+            (borrow_market.key(), borrow_market),
+        ],
+        emode_category,
+        &clock,
+    )?;
+
+    let debt = obligation
+        .find_borrow(&repay_market.key())
+        .ok_or(LendingError::BorrowPositionNotFound)?
+        .calculate_debt(repay_market)?;
+
+    // Bound how fast the reserve can be drained
+    borrow_market.record_outflow(borrow_amount, &clock)?;
+    require!(
+        borrow_amount <= ctx.accounts.borrow_reserve_vault.amount,
+        LendingError::InsufficientLiquidity
+    );
+
+    // Swap straight from the borrow reserve into the repay reserve
+    let borrow_seeds = &[
+        b"market",
+        borrow_market.asset_mint.as_ref(),
+        &[borrow_market.bump],
+    ];
+    let borrow_signer = &[&borrow_seeds[..]];
+
+    let global_config_key = ctx.accounts.global_config.key();
+    let swap_authority_bump = ctx.bumps.get("swap_authority").copied().unwrap();
+    let swap_authority_seeds: &[&[u8]] = &[
+        b"swap_authority",
+        global_config_key.as_ref(),
+        &[swap_authority_bump],
+    ];
+    let adapter = SwapAdapter {
+        program: &ctx.accounts.swap_program,
+        pool: &ctx.accounts.swap_pool,
+        source_vault: &ctx.accounts.swap_pool_source_vault,
+        destination_vault: &ctx.accounts.swap_pool_destination_vault,
+        authority: &ctx.accounts.swap_authority,
+        authority_seeds: swap_authority_seeds,
+    };
+    let amount_out = adapter.swap(
+        &mut ctx.accounts.borrow_reserve_vault,
+        &mut ctx.accounts.repay_reserve_vault,
+        &borrow_market.to_account_info(),
+        &ctx.accounts.token_program.to_account_info(),
+        borrow_signer,
+        borrow_amount,
+        min_amount_out,
+    )?;
+
+    // Return any output beyond the debt to the owner
+    let repay_amount = amount_out.min(debt);
+    let refund = amount_out - repay_amount;
+    if refund > 0 {
+        let repay_seeds = &[
+            b"market",
+            repay_market.asset_mint.as_ref(),
+            &[repay_market.bump],
+        ];
+        let repay_signer = &[&repay_seeds[..]];
+        let refund_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.repay_reserve_vault.to_account_info(),
+                to: ctx.accounts.owner_token_account.to_account_info(),
+                authority: repay_market.to_account_info(),
+            },
+            repay_signer,
+        );
+        token::transfer(refund_ctx, refund)?;
+    }
+
+    // Move the debt across the isolated collateral's debt ceiling
+    if obligation.is_isolated() {
+        require!(borrow_market.isolation_borrowable, LendingError::IsolationBorrowNotAllowed);
+        let isolated_market = ctx.accounts.isolated_collateral_market
+            .as_mut()
+            .ok_or(LendingError::IsolatedCollateralConflict)?;
+        isolated_market.remove_isolation_debt(repay_amount, repay_market.mint_decimals)?;
+        isolated_market.add_isolation_debt(borrow_amount, borrow_market.mint_decimals)?;
+    }
+
+    // Repay first so a fully refinanced borrow frees its slot
+    obligation.repay(repay_market.key(), repay_market, repay_amount)?;
+    obligation.borrow(borrow_market.key(), borrow_market, borrow_amount)?;
+    obligation.last_updated = clock.unix_timestamp;
+
+    repay_market.record_inflow(repay_amount, &clock)?;
+    repay_market.total_borrowed = repay_market.total_borrowed
+        .checked_sub(repay_amount)
+        .ok_or(LendingError::MathOverflow)?;
+
+    let new_total_borrowed = borrow_market.total_borrowed
+        .checked_add(borrow_amount)
+        .ok_or(LendingError::MathOverflow)?;
+    let utilization_bps = calculate_utilization_rate(new_total_borrowed, borrow_market.total_supplied)?;
+    require!(
+        utilization_bps <= BPS_SCALE,
+        LendingError::InvalidUtilizationRate
+    );
+    borrow_market.total_borrowed = new_total_borrowed;
+
+    // The refinanced position must stay within the borrow limit
+    values.remove_debt(
+        calculate_market_value(repay_amount, repay_price, repay_market.mint_decimals)?,
+        repay_market.borrow_factor_bps,
+    )?;
+    values.add_debt(
+        calculate_market_value(borrow_amount, borrow_price, borrow_market.mint_decimals)?,
+        borrow_market.borrow_factor_bps,
+    )?;
+    require!(
+        values.is_within_borrow_limit(),
+        LendingError::BorrowWouldCauseLiquidation
+    );
+    let health_factor = values.health_factor()?;
+    obligation.update_liquidatable_since(health_factor, &clock);
+
+    emit!(DebtSwapped {
+        obligation: obligation.key(),
+        repay_market: repay_market.key(),
+        borrow_market: borrow_market.key(),
+        borrow_amount,
+        repay_amount,
+        refund,
+        health_factor,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct CollateralSwapped {
+    pub obligation: Pubkey,
+    pub source_market: Pubkey,
+    pub destination_market: Pubkey,
+    pub supply_tokens: u64,
+    pub source_amount: u64,
+    pub destination_amount: u64,
+    pub destination_supply_tokens: u64,
+    pub health_factor: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DebtSwapped {
+    pub obligation: Pubkey,
+    pub repay_market: Pubkey,
+    pub borrow_market: Pubkey,
+    pub borrow_amount: u64,
+    pub repay_amount: u64,
+    pub refund: u64,
+    pub health_factor: u16,
+    pub timestamp: i64,
+}
@@ -1,13 +1,27 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::invoke_signed;
+use anchor_spl::token::{self, Burn, Mint, MintTo, Token, TokenAccount, Transfer};
 use crate::state::*;
 use crate::errors::LendingError;
 use crate::constants::*;
+use crate::math::*;
+use crate::allocation::{self, MarketConditions};
+use crate::instructions::withdrawal_queue::fill_withdrawal_requests;
+use crate::program::SolanaDefiLendingProtocol;
 
 /// Create a vault for automated yield strategies
 /// 
 /// Vaults allow users to deposit assets that are automatically allocated
 /// across multiple markets based on a strategy (conservative, balanced, aggressive).
+/// The share mint must be created beforehand with the vault PDA as mint
+/// authority, the asset's decimals and no supply; the asset vault must be owned
+/// by the vault PDA. Management and performance fees are paid in shares to the
+/// fee recipient share account. `index` lets one owner run several vaults; the
+/// owner starts out as curator. `timelock` delays cap increases and market
+/// additions.
 #[derive(Accounts)]
+#[instruction(index: u16)]
 pub struct CreateVault<'info> {
     #[account(mut)]
     pub owner: Signer<'info>,
@@ -15,24 +29,67 @@ pub struct CreateVault<'info> {
     #[account(
         init,
         payer = owner,
-        space = Vault::BASE_SIZE + 100, // Space for allocations
-        seeds = [b"vault", owner.key().as_ref()],
+        space = Vault::space(0), // Grows with set_allocations
+        seeds = [b"vault", owner.key().as_ref(), index.to_le_bytes().as_ref()],
         bump
     )]
     pub vault: Account<'info, Vault>,
 
+    pub asset_mint: Account<'info, Mint>,
+
+    /// Token account holding the vault's idle assets
+    #[account(
+        constraint = asset_vault.mint == asset_mint.key() @ LendingError::InvalidVaultAccount,
+        constraint = asset_vault.owner == vault.key() @ LendingError::InvalidVaultAccount
+    )]
+    pub asset_vault: Account<'info, TokenAccount>,
+
+    /// Share token mint
+    #[account(
+        constraint = share_mint.mint_authority == Some(vault.key()).into() @ LendingError::InvalidVaultAccount,
+        constraint = share_mint.decimals == asset_mint.decimals @ LendingError::InvalidVaultAccount,
+        constraint = share_mint.supply == 0 @ LendingError::InvalidVaultAccount
+    )]
+    pub share_mint: Account<'info, Mint>,
+
+    /// Share account receiving fee shares
+    #[account(
+        constraint = fee_recipient.mint == share_mint.key() @ LendingError::InvalidVaultAccount
+    )]
+    pub fee_recipient: Account<'info, TokenAccount>,
+
+    /// Share account holding shares locked in withdrawal requests
+    #[account(
+        constraint = withdrawal_escrow.mint == share_mint.key() @ LendingError::InvalidVaultAccount,
+        constraint = withdrawal_escrow.owner == vault.key() @ LendingError::InvalidVaultAccount
+    )]
+    pub withdrawal_escrow: Account<'info, TokenAccount>,
+
     pub system_program: Program<'info, System>,
 }
 
 pub fn handler(
     ctx: Context<CreateVault>,
+    index: u16,
     strategy: u8,
     rebalance_threshold_bps: u16,
+    management_fee_bps: u16,
+    performance_fee_bps: u16,
+    timelock: i64,
 ) -> Result<()> {
     require!(
         strategy <= VaultStrategy::Aggressive as u8,
         LendingError::InvalidVaultAllocation
     );
+    require!(
+        management_fee_bps <= MAX_VAULT_MANAGEMENT_FEE_BPS
+            && performance_fee_bps <= MAX_VAULT_PERFORMANCE_FEE_BPS,
+        LendingError::InvalidVaultFee
+    );
+    require!(
+        (0..=MAX_VAULT_TIMELOCK).contains(&timelock),
+        LendingError::InvalidVaultTimelock
+    );
 
     let vault = &mut ctx.accounts.vault;
     let clock = Clock::get()?;
@@ -40,8 +97,17 @@ pub fn handler(
 
     vault.initialize(
         ctx.accounts.owner.key(),
+        index,
+        timelock,
         strategy,
+        ctx.accounts.asset_mint.key(),
+        ctx.accounts.asset_vault.key(),
+        ctx.accounts.share_mint.key(),
         rebalance_threshold_bps,
+        management_fee_bps,
+        performance_fee_bps,
+        ctx.accounts.fee_recipient.key(),
+        ctx.accounts.withdrawal_escrow.key(),
         bump,
         &clock,
     );
@@ -49,73 +115,1078 @@ pub fn handler(
     emit!(VaultCreated {
         vault: vault.key(),
         owner: ctx.accounts.owner.key(),
+        index,
+        asset_mint: ctx.accounts.asset_mint.key(),
         strategy,
+        management_fee_bps,
+        performance_fee_bps,
+        timelock,
         timestamp: clock.unix_timestamp,
     });
 
     Ok(())
 }
 
-/// Rebalance vault allocations across markets
-/// 
-/// This instruction rebalances the vault's asset allocation based on
-/// current market conditions and the vault's strategy.
+/// Deposit assets into a vault for shares
+///
+/// Used by both `vault_deposit` (exact assets in) and `vault_mint` (exact
+/// shares out). The vault is valued at current market exchange rates first.
+/// Remaining accounts: the market account of each allocation, in order.
 #[derive(Accounts)]
-pub struct RebalanceVault<'info> {
+pub struct VaultDeposit<'info> {
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.owner.as_ref(), vault.index.to_le_bytes().as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        constraint = asset_vault.key() == vault.asset_vault @ LendingError::InvalidVaultAccount
+    )]
+    pub asset_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = share_mint.key() == vault.share_mint @ LendingError::InvalidVaultAccount
+    )]
+    pub share_mint: Account<'info, Mint>,
+
+    /// Share account receiving fee shares
+    #[account(
+        mut,
+        constraint = fee_recipient.key() == vault.fee_recipient @ LendingError::InvalidVaultAccount
+    )]
+    pub fee_recipient: Account<'info, TokenAccount>,
+
+    /// User's asset account (source of the deposit)
+    #[account(
+        mut,
+        constraint = user_token_account.owner == user.key() @ LendingError::Unauthorized,
+        constraint = user_token_account.mint == vault.asset_mint @ LendingError::InvalidVaultAccount
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    /// Share account receiving the minted shares
+    #[account(
+        mut,
+        constraint = receiver_share_account.mint == vault.share_mint @ LendingError::InvalidVaultAccount
+    )]
+    pub receiver_share_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+/// Deposit exactly `assets`, minting shares rounded down
+pub fn vault_deposit_handler(ctx: Context<VaultDeposit>, assets: u64) -> Result<()> {
+    require!(assets > 0, LendingError::InvalidAmount);
+
+    let accounts = &mut *ctx.accounts;
+    refresh_total_assets(&mut accounts.vault, accounts.asset_vault.amount, ctx.remaining_accounts)?;
+    collect_fees(
+        &mut accounts.vault,
+        &mut accounts.share_mint,
+        &accounts.fee_recipient,
+        &accounts.token_program,
+    )?;
+
+    let shares = ctx.accounts.vault.preview_deposit(assets, ctx.accounts.share_mint.supply)?;
+    require!(shares > 0, LendingError::InvalidAmount);
+
+    deposit(ctx, assets, shares)
+}
+
+/// Mint exactly `shares`, taking assets rounded up
+pub fn vault_mint_handler(ctx: Context<VaultDeposit>, shares: u64) -> Result<()> {
+    require!(shares > 0, LendingError::InvalidAmount);
+
+    let accounts = &mut *ctx.accounts;
+    refresh_total_assets(&mut accounts.vault, accounts.asset_vault.amount, ctx.remaining_accounts)?;
+    collect_fees(
+        &mut accounts.vault,
+        &mut accounts.share_mint,
+        &accounts.fee_recipient,
+        &accounts.token_program,
+    )?;
+
+    let assets = ctx.accounts.vault.preview_mint(shares, ctx.accounts.share_mint.supply)?;
+
+    deposit(ctx, assets, shares)
+}
+
+fn deposit(ctx: Context<VaultDeposit>, assets: u64, shares: u64) -> Result<()> {
+    let vault = &mut ctx.accounts.vault;
+    let clock = Clock::get()?;
+
+    let transfer_ctx = CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        Transfer {
+            from: ctx.accounts.user_token_account.to_account_info(),
+            to: ctx.accounts.asset_vault.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        },
+    );
+    token::transfer(transfer_ctx, assets)?;
+
+    let vault_index = vault.index.to_le_bytes();
+    let seeds = &[
+        b"vault",
+        vault.owner.as_ref(),
+        vault_index.as_ref(),
+        &[vault.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    let mint_ctx = CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        MintTo {
+            mint: ctx.accounts.share_mint.to_account_info(),
+            to: ctx.accounts.receiver_share_account.to_account_info(),
+            authority: vault.to_account_info(),
+        },
+        signer,
+    );
+    token::mint_to(mint_ctx, shares)?;
+
+    vault.total_assets = vault.total_assets
+        .checked_add(assets)
+        .ok_or(LendingError::MathOverflow)?;
+
+    emit!(VaultDeposited {
+        vault: vault.key(),
+        user: ctx.accounts.user.key(),
+        receiver: ctx.accounts.receiver_share_account.key(),
+        assets,
+        shares,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Withdraw assets from a vault by burning shares
+///
+/// Used by both `vault_withdraw` (exact assets out) and `vault_redeem` (exact
+/// shares in). Only the vault's idle assets can be withdrawn, less what queued
+/// withdrawal requests are owed. The vault is valued at current market
+/// exchange rates first.
+/// Remaining accounts: the market account of each allocation, in order.
+#[derive(Accounts)]
+pub struct VaultWithdraw<'info> {
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.owner.as_ref(), vault.index.to_le_bytes().as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        constraint = asset_vault.key() == vault.asset_vault @ LendingError::InvalidVaultAccount
+    )]
+    pub asset_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = share_mint.key() == vault.share_mint @ LendingError::InvalidVaultAccount
+    )]
+    pub share_mint: Account<'info, Mint>,
+
+    /// Share account receiving fee shares
+    #[account(
+        mut,
+        constraint = fee_recipient.key() == vault.fee_recipient @ LendingError::InvalidVaultAccount
+    )]
+    pub fee_recipient: Account<'info, TokenAccount>,
+
+    /// User's share account (shares are burned from it)
+    #[account(
+        mut,
+        constraint = user_share_account.owner == user.key() @ LendingError::Unauthorized,
+        constraint = user_share_account.mint == vault.share_mint @ LendingError::InvalidVaultAccount
+    )]
+    pub user_share_account: Account<'info, TokenAccount>,
+
+    /// Asset account receiving the withdrawal
+    #[account(
+        mut,
+        constraint = receiver_token_account.mint == vault.asset_mint @ LendingError::InvalidVaultAccount
+    )]
+    pub receiver_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+/// Withdraw exactly `assets`, burning shares rounded up
+pub fn vault_withdraw_handler(ctx: Context<VaultWithdraw>, assets: u64) -> Result<()> {
+    require!(assets > 0, LendingError::InvalidAmount);
+
+    let accounts = &mut *ctx.accounts;
+    refresh_total_assets(&mut accounts.vault, accounts.asset_vault.amount, ctx.remaining_accounts)?;
+    collect_fees(
+        &mut accounts.vault,
+        &mut accounts.share_mint,
+        &accounts.fee_recipient,
+        &accounts.token_program,
+    )?;
+
+    let shares = ctx.accounts.vault.preview_withdraw(assets, ctx.accounts.share_mint.supply)?;
+
+    withdraw(ctx, assets, shares)
+}
+
+/// Redeem exactly `shares`, returning assets rounded down
+pub fn vault_redeem_handler(ctx: Context<VaultWithdraw>, shares: u64) -> Result<()> {
+    require!(shares > 0, LendingError::InvalidAmount);
+
+    let accounts = &mut *ctx.accounts;
+    refresh_total_assets(&mut accounts.vault, accounts.asset_vault.amount, ctx.remaining_accounts)?;
+    collect_fees(
+        &mut accounts.vault,
+        &mut accounts.share_mint,
+        &accounts.fee_recipient,
+        &accounts.token_program,
+    )?;
+
+    let assets = ctx.accounts.vault.preview_redeem(shares, ctx.accounts.share_mint.supply)?;
+    require!(assets > 0, LendingError::InvalidAmount);
+
+    withdraw(ctx, assets, shares)
+}
+
+fn withdraw(ctx: Context<VaultWithdraw>, assets: u64, shares: u64) -> Result<()> {
+    let vault = &mut ctx.accounts.vault;
+    let clock = Clock::get()?;
+
+    // Idle assets owed to queued withdrawals stay behind to fill them
+    let queued_assets = vault.preview_redeem(vault.queued_shares, ctx.accounts.share_mint.supply)?;
+    require!(
+        assets <= ctx.accounts.asset_vault.amount.saturating_sub(queued_assets),
+        LendingError::InsufficientLiquidity
+    );
+
+    let burn_ctx = CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        Burn {
+            mint: ctx.accounts.share_mint.to_account_info(),
+            from: ctx.accounts.user_share_account.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        },
+    );
+    token::burn(burn_ctx, shares)?;
+
+    let vault_index = vault.index.to_le_bytes();
+    let seeds = &[
+        b"vault",
+        vault.owner.as_ref(),
+        vault_index.as_ref(),
+        &[vault.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    let transfer_ctx = CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        Transfer {
+            from: ctx.accounts.asset_vault.to_account_info(),
+            to: ctx.accounts.receiver_token_account.to_account_info(),
+            authority: vault.to_account_info(),
+        },
+        signer,
+    );
+    token::transfer(transfer_ctx, assets)?;
+
+    vault.total_assets = vault.total_assets
+        .checked_sub(assets)
+        .ok_or(LendingError::MathOverflow)?;
+
+    emit!(VaultWithdrawn {
+        vault: vault.key(),
+        user: ctx.accounts.user.key(),
+        receiver: ctx.accounts.receiver_token_account.key(),
+        assets,
+        shares,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Value the vault's idle assets plus its market positions at current exchange rates
+///
+/// `market_accounts` hold the market account of each allocation, in order.
+/// Positions are valued with interest accrued up to now, so share conversions
+/// never use a valuation left over from the last rebalance.
+pub(crate) fn refresh_total_assets(
+    vault: &mut Vault,
+    idle_assets: u64,
+    market_accounts: &[AccountInfo],
+) -> Result<()> {
+    require!(
+        market_accounts.len() == vault.allocations.len(),
+        LendingError::InvalidVaultAccount
+    );
+
+    let clock = Clock::get()?;
+    let mut total_assets = idle_assets;
+    for (allocation, market_info) in vault.allocations.iter().zip(market_accounts) {
+        require!(
+            market_info.key() == allocation.market && market_info.owner == &crate::ID,
+            LendingError::InvalidVaultAccount
+        );
+        let mut market = Market::try_deserialize(&mut &market_info.try_borrow_data()?[..])?;
+        market.accrue_interest(&clock)?;
+
+        total_assets = total_assets
+            .checked_add(market.supply_tokens_to_amount(allocation.current_supply_tokens)?)
+            .ok_or(LendingError::MathOverflow)?;
+    }
+    vault.total_assets = total_assets;
+
+    Ok(())
+}
+
+/// Accrue the vault's fees and mint the fee shares to its fee recipient
+///
+/// Runs before every share conversion so deposits and withdrawals happen at
+/// the post-fee share price.
+pub(crate) fn collect_fees<'info>(
+    vault: &mut Account<'info, Vault>,
+    share_mint: &mut Account<'info, Mint>,
+    fee_recipient: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let fees = vault.accrue_fees(share_mint.supply, &clock)?;
+    if fees.shares == 0 {
+        return Ok(());
+    }
+
+    let vault_index = vault.index.to_le_bytes();
+    let seeds = &[
+        b"vault",
+        vault.owner.as_ref(),
+        vault_index.as_ref(),
+        &[vault.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    let mint_ctx = CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        MintTo {
+            mint: share_mint.to_account_info(),
+            to: fee_recipient.to_account_info(),
+            authority: vault.to_account_info(),
+        },
+        signer,
+    );
+    token::mint_to(mint_ctx, fees.shares)?;
+    share_mint.reload()?;
+
+    emit!(VaultFeesCollected {
+        vault: vault.key(),
+        fee_recipient: fee_recipient.key(),
+        management_fee: fees.management_fee,
+        performance_fee: fees.performance_fee,
+        shares: fees.shares,
+        high_water_mark: vault.high_water_mark,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Target allocation to one market, as passed to `set_allocations`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct AllocationConfig {
+    pub market: Pubkey,
+    pub target_bps: u16,
+    pub cap: u64,
+}
+
+/// Replace a vault's allowed markets, targets and caps (curator only)
+///
+/// Targets must sum to 100%, and markets the vault still holds supply tokens in
+/// can't be dropped. Caps can only be lowered and markets only removed here;
+/// raising a cap or adding a market goes through `submit_vault_cap`. The vault
+/// account is resized to fit the new list.
+/// Remaining accounts: the market account of each allocation, in order.
+#[derive(Accounts)]
+#[instruction(allocations: Vec<AllocationConfig>)]
+pub struct SetAllocations<'info> {
     #[account(mut)]
+    pub curator: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.owner.as_ref(), vault.index.to_le_bytes().as_ref()],
+        bump = vault.bump,
+        constraint = vault.is_curator(&curator.key()) @ LendingError::Unauthorized,
+        realloc = Vault::space(allocations.len()),
+        realloc::payer = curator,
+        realloc::zero = false
+    )]
+    pub vault: Account<'info, Vault>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn set_allocations_handler(
+    ctx: Context<SetAllocations>,
+    allocations: Vec<AllocationConfig>,
+) -> Result<()> {
+    let vault = &mut ctx.accounts.vault;
+
+    require!(
+        !allocations.is_empty() && allocations.len() <= MAX_VAULT_ALLOCATIONS as usize,
+        LendingError::InvalidVaultAllocation
+    );
+    require!(
+        ctx.remaining_accounts.len() == allocations.len(),
+        LendingError::InvalidVaultAccount
+    );
+
+    let mut total_target_bps: u32 = 0;
+    let mut new_allocations = Vec::with_capacity(allocations.len());
+
+    for (config, market_info) in allocations.iter().zip(ctx.remaining_accounts.iter()) {
+        // Each market must be one of this program's markets for the vault's asset
+        require!(
+            market_info.key() == config.market && market_info.owner == &crate::ID,
+            LendingError::InvalidVaultAccount
+        );
+        let market = Market::try_deserialize(&mut &market_info.try_borrow_data()?[..])?;
+        require!(
+            market.asset_mint == vault.asset_mint,
+            LendingError::InvalidVaultAllocation
+        );
+        require!(
+            new_allocations.iter().all(|allocation: &Allocation| allocation.market != config.market),
+            LendingError::InvalidVaultAllocation
+        );
+
+        total_target_bps += config.target_bps as u32;
+
+        // Only timelocked changes can add markets or raise caps
+        let existing = vault
+            .find_allocation(&config.market)
+            .ok_or(LendingError::VaultCapIncreaseTimelocked)?;
+        require!(config.cap <= existing.cap, LendingError::VaultCapIncreaseTimelocked);
+
+        // Existing positions carry over
+        let current_supply_tokens = existing.current_supply_tokens;
+
+        new_allocations.push(Allocation {
+            market: config.market,
+            target_bps: config.target_bps,
+            cap: config.cap,
+            current_supply_tokens,
+        });
+    }
+
+    require!(
+        total_target_bps == BPS_SCALE as u32,
+        LendingError::InvalidVaultAllocation
+    );
+
+    // Markets still holding the vault's funds can't be dropped
+    require!(
+        vault.allocations.iter().all(|allocation| {
+            allocation.current_supply_tokens == 0
+                || allocations.iter().any(|config| config.market == allocation.market)
+        }),
+        LendingError::InvalidVaultAllocation
+    );
+
+    vault.allocations = new_allocations;
+
+    emit!(VaultAllocationsUpdated {
+        vault: vault.key(),
+        allocations: allocations.len() as u8,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Appoint a vault's curator, guardian and allocators (owner only)
+#[derive(Accounts)]
+pub struct UpdateVaultRoles<'info> {
     pub owner: Signer<'info>,
 
     #[account(
         mut,
-        seeds = [b"vault", owner.key().as_ref()],
+        seeds = [b"vault", vault.owner.as_ref(), vault.index.to_le_bytes().as_ref()],
         bump = vault.bump,
-        constraint = vault.owner == owner.key() @ LendingError::Unauthorized
+        has_one = owner @ LendingError::Unauthorized
     )]
     pub vault: Account<'info, Vault>,
+}
+
+/// Set the curator and guardian (default pubkey = no guardian)
+///
+/// The first guardian applies immediately. Once there is one it can only be
+/// replaced through `submit_vault_guardian`, so it can veto its own removal.
+pub fn set_vault_roles_handler(
+    ctx: Context<UpdateVaultRoles>,
+    curator: Pubkey,
+    guardian: Pubkey,
+) -> Result<()> {
+    let vault = &mut ctx.accounts.vault;
+
+    require!(
+        vault.guardian == Pubkey::default() || guardian == vault.guardian,
+        LendingError::VaultGuardianTimelocked
+    );
+
+    vault.curator = curator;
+    vault.guardian = guardian;
+
+    emit!(VaultRolesUpdated {
+        vault: vault.key(),
+        curator,
+        guardian,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Allow an account to rebalance the vault
+pub fn add_vault_allocator_handler(ctx: Context<UpdateVaultRoles>, allocator: Pubkey) -> Result<()> {
+    let vault = &mut ctx.accounts.vault;
+
+    require!(
+        !vault.allocators.contains(&allocator),
+        LendingError::InvalidVaultAllocator
+    );
+    require!(
+        vault.allocators.len() < MAX_VAULT_ALLOCATORS as usize,
+        LendingError::VaultAllocatorListFull
+    );
+    vault.allocators.push(allocator);
+
+    emit!(VaultAllocatorUpdated {
+        vault: vault.key(),
+        allocator,
+        allowed: true,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Revoke an account's allocator role
+pub fn remove_vault_allocator_handler(ctx: Context<UpdateVaultRoles>, allocator: Pubkey) -> Result<()> {
+    let vault = &mut ctx.accounts.vault;
+
+    let index = vault.allocators
+        .iter()
+        .position(|key| *key == allocator)
+        .ok_or(LendingError::InvalidVaultAllocator)?;
+    vault.allocators.remove(index);
+
+    emit!(VaultAllocatorUpdated {
+        vault: vault.key(),
+        allocator,
+        allowed: false,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Read-only accounts for the vault preview helpers
+///
+/// The previews return their result as instruction return data, so clients can
+/// simulate them (`.view()`) without sending a transaction. They value the
+/// vault at current exchange rates and account for fees that would be
+/// collected first.
+/// Remaining accounts: the market account of each allocation, in order.
+#[derive(Accounts)]
+pub struct PreviewVault<'info> {
+    #[account(
+        seeds = [b"vault", vault.owner.as_ref(), vault.index.to_le_bytes().as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        constraint = asset_vault.key() == vault.asset_vault @ LendingError::InvalidVaultAccount
+    )]
+    pub asset_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        constraint = share_mint.key() == vault.share_mint @ LendingError::InvalidVaultAccount
+    )]
+    pub share_mint: Account<'info, Mint>,
+}
+
+/// The vault and share supply as they'd be once pending fees are collected
+fn vault_after_fees(ctx: &Context<PreviewVault>) -> Result<(Vault, u64)> {
+    let mut vault = (*ctx.accounts.vault).clone();
+    refresh_total_assets(&mut vault, ctx.accounts.asset_vault.amount, ctx.remaining_accounts)?;
+    let fees = vault.accrue_fees(ctx.accounts.share_mint.supply, &Clock::get()?)?;
+    let share_supply = ctx.accounts.share_mint.supply
+        .checked_add(fees.shares)
+        .ok_or(LendingError::MathOverflow)?;
+    Ok((vault, share_supply))
+}
+
+pub fn preview_deposit_handler(ctx: Context<PreviewVault>, assets: u64) -> Result<u64> {
+    let (vault, share_supply) = vault_after_fees(&ctx)?;
+    vault.preview_deposit(assets, share_supply)
+}
+
+pub fn preview_mint_handler(ctx: Context<PreviewVault>, shares: u64) -> Result<u64> {
+    let (vault, share_supply) = vault_after_fees(&ctx)?;
+    vault.preview_mint(shares, share_supply)
+}
+
+pub fn preview_withdraw_handler(ctx: Context<PreviewVault>, assets: u64) -> Result<u64> {
+    let (vault, share_supply) = vault_after_fees(&ctx)?;
+    vault.preview_withdraw(assets, share_supply)
+}
+
+pub fn preview_redeem_handler(ctx: Context<PreviewVault>, shares: u64) -> Result<u64> {
+    let (vault, share_supply) = vault_after_fees(&ctx)?;
+    vault.preview_redeem(shares, share_supply)
+}
+
+/// Rebalance vault allocations across markets (allocators only)
+/// 
+/// Values the vault's position in each allocated market and compares it with
+/// the target the vault's strategy computes, keeping assets owed to queued
+/// withdrawals idle. If any market has drifted from its target by more than
+/// `rebalance_threshold_bps` of the vault's assets, or withdrawals are queued,
+/// funds are withdrawn from overweight markets, queued withdrawals are filled,
+/// and the rest is supplied to underweight ones through this program's own
+/// `withdraw` and `supply`.
+/// Remaining accounts: `[market, reserve_vault, supply_mint, vault_supply_account,
+/// oracle]` for each allocation, in order, followed by `[withdrawal_request,
+/// receiver_token_account, request_owner]` for queued requests from the head of
+/// the queue. All of them must be writable except the oracles.
+#[derive(Accounts)]
+pub struct RebalanceVault<'info> {
+    pub allocator: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.owner.as_ref(), vault.index.to_le_bytes().as_ref()],
+        bump = vault.bump,
+        constraint = vault.is_allocator(&allocator.key()) @ LendingError::Unauthorized
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        constraint = asset_vault.key() == vault.asset_vault @ LendingError::InvalidVaultAccount
+    )]
+    pub asset_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = share_mint.key() == vault.share_mint @ LendingError::InvalidVaultAccount
+    )]
+    pub share_mint: Account<'info, Mint>,
 
-    /// CHECK: Multiple markets can be passed for rebalancing
-    /// In a full implementation, you'd use remaining_accounts
-    pub markets: Vec<AccountInfo<'info>>,
+    /// Share account receiving fee shares
+    #[account(
+        mut,
+        constraint = fee_recipient.key() == vault.fee_recipient @ LendingError::InvalidVaultAccount
+    )]
+    pub fee_recipient: Account<'info, TokenAccount>,
+
+    /// Vault-owned share account holding shares locked in withdrawal requests
+    #[account(
+        mut,
+        constraint = withdrawal_escrow.key() == vault.withdrawal_escrow @ LendingError::InvalidVaultAccount
+    )]
+    pub withdrawal_escrow: Account<'info, TokenAccount>,
+
+    pub lending_program: Program<'info, SolanaDefiLendingProtocol>,
+    pub token_program: Program<'info, Token>,
 }
 
-pub fn handler(ctx: Context<RebalanceVault>) -> Result<()> {
+/// Accounts per allocation in a rebalance's remaining accounts
+const REBALANCE_ACCOUNTS_PER_MARKET: usize = 5;
+
+/// Discriminator of this program's `supply` instruction
+const SUPPLY_DISCRIMINATOR: [u8; 8] = [81, 67, 116, 61, 250, 209, 5, 198];
+
+/// Discriminator of this program's `withdraw` instruction
+const WITHDRAW_DISCRIMINATOR: [u8; 8] = [183, 18, 70, 156, 148, 109, 161, 34];
+
+/// Where a vault's allocation to a market stands before a rebalance
+struct MarketPosition {
+    market: Market,
+    conditions: MarketConditions,
+}
+
+pub fn rebalance_handler<'info>(
+    ctx: Context<'_, '_, 'info, 'info, RebalanceVault<'info>>,
+) -> Result<()> {
     let vault = &mut ctx.accounts.vault;
     let clock = Clock::get()?;
 
-    // TODO: In a full implementation, this would:
-    // 1. Fetch current market conditions (interest rates, utilization, etc.)
-    // 2. Calculate optimal allocation based on vault strategy
-    // 3. Compare with current allocation
-    // 4. If drift exceeds rebalance_threshold_bps, execute rebalance
-    // 5. Transfer assets between markets via supply/withdraw
+    require!(
+        ctx.remaining_accounts.len() >= vault.allocations.len() * REBALANCE_ACCOUNTS_PER_MARKET,
+        LendingError::InvalidVaultAccount
+    );
+    let (market_accounts, request_accounts) = ctx.remaining_accounts
+        .split_at(vault.allocations.len() * REBALANCE_ACCOUNTS_PER_MARKET);
+
+    let mut positions = Vec::with_capacity(vault.allocations.len());
+    for (allocation, accounts) in vault.allocations
+        .iter()
+        .zip(market_accounts.chunks(REBALANCE_ACCOUNTS_PER_MARKET))
+    {
+        require!(
+            accounts[0].key() == allocation.market && accounts[0].owner == &crate::ID,
+            LendingError::InvalidVaultAccount
+        );
+        let mut market = Market::try_deserialize(&mut &accounts[0].try_borrow_data()?[..])?;
+
+        // The reserve's balance caps what can be withdrawn, so it must be the real one
+        require!(
+            accounts[1].key() == market.reserve_vault,
+            LendingError::InvalidVaultAccount
+        );
+
+        let vault_supply_account = TokenAccount::try_deserialize(&mut &accounts[3].try_borrow_data()?[..])?;
+        require!(
+            vault_supply_account.owner == vault.key() && vault_supply_account.mint == market.supply_mint,
+            LendingError::InvalidVaultAccount
+        );
+
+        // Value the position with interest accrued up to now
+        market.accrue_interest(&clock)?;
+        let conditions = MarketConditions {
+            target_bps: allocation.target_bps,
+            cap: allocation.cap,
+            current_amount: market.supply_tokens_to_amount(allocation.current_supply_tokens)?,
+            available_liquidity: market.total_supplied
+                .saturating_sub(market.total_borrowed)
+                .min(token_balance(&accounts[1])?),
+            total_supplied: market.total_supplied,
+            total_borrowed: market.total_borrowed,
+        };
+
+        positions.push(MarketPosition { market, conditions });
+    }
+
+    // Idle assets plus everything supplied to markets
+    let total_assets = positions
+        .iter()
+        .try_fold(ctx.accounts.asset_vault.amount, |total, position| {
+            total.checked_add(position.conditions.current_amount)
+        })
+        .ok_or(LendingError::MathOverflow)?;
+    vault.total_assets = total_assets;
+    require!(total_assets > 0, LendingError::VaultRebalanceNotNeeded);
+
+    // Interest earned since the last rebalance is now in total assets
+    collect_fees(
+        vault,
+        &mut ctx.accounts.share_mint,
+        &ctx.accounts.fee_recipient,
+        &ctx.accounts.token_program,
+    )?;
+
+    // Assets owed to queued withdrawals stay idle to fill them
+    let queued_assets = vault.preview_redeem(vault.queued_shares, ctx.accounts.share_mint.supply)?;
+
+    let conditions: Vec<MarketConditions> = positions.iter().map(|position| position.conditions).collect();
+    let target_amounts = allocation::target_amounts(
+        VaultStrategy::try_from(vault.strategy)?,
+        total_assets.saturating_sub(queued_assets),
+        &conditions,
+    )?;
+
+    let mut max_drift_bps: u16 = 0;
+    for (position, target_amount) in conditions.iter().zip(target_amounts.iter()) {
+        let drift_bps = mul_div(
+            position.current_amount.abs_diff(*target_amount),
+            BPS_SCALE as u128,
+            total_assets as u128,
+            false,
+        )?;
+        max_drift_bps = max_drift_bps.max(drift_bps.min(BPS_SCALE as u64) as u16);
+    }
+    require!(
+        max_drift_bps > vault.rebalance_threshold_bps || vault.queued_shares > 0,
+        LendingError::VaultRebalanceNotNeeded
+    );
+
+    let vault_info = vault.to_account_info();
+    let vault_owner = vault.owner;
+    let vault_index = vault.index.to_le_bytes();
+    let seeds = &[
+        b"vault",
+        vault_owner.as_ref(),
+        vault_index.as_ref(),
+        &[vault.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    // Pull funds out of overweight markets first
+    let mut withdrawn = vec![0u64; positions.len()];
+    for (index, accounts) in market_accounts.chunks(REBALANCE_ACCOUNTS_PER_MARKET).enumerate() {
+        let position = &positions[index];
+        let allocation = &mut vault.allocations[index];
+        if position.conditions.current_amount <= target_amounts[index] {
+            continue;
+        }
+
+        let excess = (position.conditions.current_amount - target_amounts[index])
+            .min(position.conditions.available_liquidity);
+        let supply_tokens = position.market
+            .amount_to_supply_tokens(excess)?
+            .min(allocation.current_supply_tokens);
+        if supply_tokens == 0 {
+            continue;
+        }
+
+        let balance_before = ctx.accounts.asset_vault.amount;
+        invoke_market_instruction(
+            &ctx.accounts.lending_program.to_account_info(),
+            WITHDRAW_DISCRIMINATOR,
+            supply_tokens,
+            &[
+                (&vault_info, true),
+                (&accounts[0], true),
+                (&accounts[3], true),
+                (&accounts[2], true),
+                (&accounts[1], true),
+                (&ctx.accounts.asset_vault.to_account_info(), true),
+                (&accounts[4], false),
+                (&ctx.accounts.token_program.to_account_info(), false),
+            ],
+            signer,
+        )?;
+        ctx.accounts.asset_vault.reload()?;
+
+        allocation.current_supply_tokens -= supply_tokens;
+        withdrawn[index] = ctx.accounts.asset_vault.amount
+            .checked_sub(balance_before)
+            .ok_or(LendingError::MathOverflow)?;
+    }
+
+    // Fill queued withdrawals before putting idle assets back to work
+    fill_withdrawal_requests(
+        vault,
+        &mut ctx.accounts.asset_vault,
+        &mut ctx.accounts.share_mint,
+        &ctx.accounts.withdrawal_escrow,
+        &ctx.accounts.token_program,
+        request_accounts,
+    )?;
+
+    // Then supply idle assets to underweight markets
+    let mut supplied = vec![0u64; positions.len()];
+    for (index, accounts) in market_accounts.chunks(REBALANCE_ACCOUNTS_PER_MARKET).enumerate() {
+        let current_amount = positions[index].conditions.current_amount;
+        if current_amount >= target_amounts[index] {
+            continue;
+        }
+
+        let amount = (target_amounts[index] - current_amount)
+            .min(ctx.accounts.asset_vault.amount);
+        if amount < MIN_SUPPLY_AMOUNT {
+            continue;
+        }
+
+        let supply_tokens_before = token_balance(&accounts[3])?;
+        invoke_market_instruction(
+            &ctx.accounts.lending_program.to_account_info(),
+            SUPPLY_DISCRIMINATOR,
+            amount,
+            &[
+                (&vault_info, true),
+                (&accounts[0], true),
+                (&ctx.accounts.asset_vault.to_account_info(), true),
+                (&accounts[1], true),
+                (&accounts[2], true),
+                (&accounts[3], true),
+                (&ctx.accounts.token_program.to_account_info(), false),
+            ],
+            signer,
+        )?;
+        ctx.accounts.asset_vault.reload()?;
+
+        let minted = token_balance(&accounts[3])?
+            .checked_sub(supply_tokens_before)
+            .ok_or(LendingError::MathOverflow)?;
+        let allocation = &mut vault.allocations[index];
+        allocation.current_supply_tokens = allocation.current_supply_tokens
+            .checked_add(minted)
+            .ok_or(LendingError::MathOverflow)?;
+        supplied[index] = amount;
+    }
 
-    // For now, just update last_rebalance timestamp
     vault.last_rebalance = clock.unix_timestamp;
 
+    for (index, position) in positions.iter().enumerate() {
+        if withdrawn[index] == 0 && supplied[index] == 0 {
+            continue;
+        }
+        emit!(VaultMarketRebalanced {
+            vault: vault.key(),
+            market: vault.allocations[index].market,
+            utilization_bps: position.conditions.utilization_bps()?,
+            supply_rate: position.conditions.supply_rate()?,
+            withdrawn: withdrawn[index],
+            supplied: supplied[index],
+            timestamp: clock.unix_timestamp,
+        });
+    }
+
     emit!(VaultRebalanced {
         vault: vault.key(),
-        owner: ctx.accounts.owner.key(),
+        allocator: ctx.accounts.allocator.key(),
+        total_assets,
+        max_drift_bps,
         timestamp: clock.unix_timestamp,
     });
 
     Ok(())
 }
 
-pub fn rebalance_handler(ctx: Context<RebalanceVault>) -> Result<()> {
-    handler(ctx)
+/// Call this program's `supply` or `withdraw` with the vault PDA as the user
+///
+/// `accounts` are `(account, writable)` in the instruction's account order,
+/// with the vault first as the signing user.
+fn invoke_market_instruction<'info>(
+    program: &AccountInfo<'info>,
+    discriminator: [u8; 8],
+    amount: u64,
+    accounts: &[(&AccountInfo<'info>, bool)],
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    let mut data = discriminator.to_vec();
+    data.extend_from_slice(&amount.to_le_bytes());
+
+    let metas = accounts
+        .iter()
+        .enumerate()
+        .map(|(index, (account, writable))| {
+            let is_signer = index == 0;
+            if *writable {
+                AccountMeta::new(account.key(), is_signer)
+            } else {
+                AccountMeta::new_readonly(account.key(), is_signer)
+            }
+        })
+        .collect();
+    let mut infos: Vec<AccountInfo> = accounts.iter().map(|(account, _)| (*account).clone()).collect();
+    infos.push(program.clone());
+
+    invoke_signed(
+        &Instruction {
+            program_id: program.key(),
+            accounts: metas,
+            data,
+        },
+        &infos,
+        signer_seeds,
+    )?;
+
+    Ok(())
+}
+
+/// Balance of an SPL token account passed as an unchecked account
+fn token_balance(account: &AccountInfo) -> Result<u64> {
+    Ok(TokenAccount::try_deserialize(&mut &account.try_borrow_data()?[..])?.amount)
 }
 
 #[event]
 pub struct VaultCreated {
     pub vault: Pubkey,
     pub owner: Pubkey,
+    pub index: u16,
+    pub asset_mint: Pubkey,
     pub strategy: u8,
+    pub management_fee_bps: u16,
+    pub performance_fee_bps: u16,
+    pub timelock: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultDeposited {
+    pub vault: Pubkey,
+    pub user: Pubkey,
+    pub receiver: Pubkey,
+    pub assets: u64,
+    pub shares: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultWithdrawn {
+    pub vault: Pubkey,
+    pub user: Pubkey,
+    pub receiver: Pubkey,
+    pub assets: u64,
+    pub shares: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultFeesCollected {
+    pub vault: Pubkey,
+    pub fee_recipient: Pubkey,
+    pub management_fee: u64,
+    pub performance_fee: u64,
+    pub shares: u64,
+    pub high_water_mark: u128,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultAllocationsUpdated {
+    pub vault: Pubkey,
+    pub allocations: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultRolesUpdated {
+    pub vault: Pubkey,
+    pub curator: Pubkey,
+    pub guardian: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultAllocatorUpdated {
+    pub vault: Pubkey,
+    pub allocator: Pubkey,
+    pub allowed: bool,
     pub timestamp: i64,
 }
 
 #[event]
 pub struct VaultRebalanced {
     pub vault: Pubkey,
-    pub owner: Pubkey,
+    pub allocator: Pubkey,
+    pub total_assets: u64,
+    /// Largest drift from target that triggered the rebalance
+    pub max_drift_bps: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultMarketRebalanced {
+    pub vault: Pubkey,
+    pub market: Pubkey,
+    pub utilization_bps: u16,
+    pub supply_rate: u64,
+    pub withdrawn: u64,
+    pub supplied: u64,
     pub timestamp: i64,
 }
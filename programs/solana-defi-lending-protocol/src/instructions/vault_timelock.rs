@@ -0,0 +1,378 @@
+use anchor_lang::prelude::*;
+use crate::state::*;
+use crate::errors::LendingError;
+use crate::constants::*;
+
+/// Propose raising a market's cap or adding a market to a vault (curator only)
+///
+/// The change is recorded as pending and can be accepted once the vault's
+/// timelock has passed. Lowering caps and removing markets don't need this;
+/// they apply immediately through `set_allocations`.
+#[derive(Accounts)]
+pub struct SubmitVaultCap<'info> {
+    #[account(mut)]
+    pub curator: Signer<'info>,
+
+    #[account(
+        seeds = [b"vault", vault.owner.as_ref(), vault.index.to_le_bytes().as_ref()],
+        bump = vault.bump,
+        constraint = vault.is_curator(&curator.key()) @ LendingError::Unauthorized
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        constraint = market.asset_mint == vault.asset_mint @ LendingError::InvalidVaultAllocation
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        init,
+        payer = curator,
+        space = PendingVaultCap::SIZE,
+        seeds = [b"pending_vault_cap", vault.key().as_ref(), market.key().as_ref()],
+        bump
+    )]
+    pub pending_cap: Account<'info, PendingVaultCap>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn submit_cap_handler(ctx: Context<SubmitVaultCap>, cap: u64) -> Result<()> {
+    let vault = &ctx.accounts.vault;
+    let pending_cap = &mut ctx.accounts.pending_cap;
+    let clock = Clock::get()?;
+
+    let current_cap = match vault.find_allocation(&ctx.accounts.market.key()) {
+        Some(allocation) => allocation.cap,
+        None => {
+            require!(
+                vault.allocations.len() < MAX_VAULT_ALLOCATIONS as usize,
+                LendingError::InvalidVaultAllocation
+            );
+            0
+        }
+    };
+    require!(cap > current_cap, LendingError::InvalidVaultAllocation);
+
+    pending_cap.vault = vault.key();
+    pending_cap.market = ctx.accounts.market.key();
+    pending_cap.cap = cap;
+    pending_cap.valid_at = clock.unix_timestamp
+        .checked_add(vault.timelock)
+        .ok_or(LendingError::MathOverflow)?;
+    pending_cap.submitter = ctx.accounts.curator.key();
+    pending_cap.bump = ctx.bumps.get("pending_cap").copied().unwrap();
+
+    emit!(VaultCapSubmitted {
+        vault: vault.key(),
+        market: pending_cap.market,
+        current_cap,
+        cap,
+        valid_at: pending_cap.valid_at,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Apply a pending cap change after the timelock (curator only)
+///
+/// A market the vault doesn't allocate to yet is added with a 0% target; the
+/// curator sets its target with `set_allocations`. The vault account grows to
+/// fit it.
+#[derive(Accounts)]
+pub struct AcceptVaultCap<'info> {
+    #[account(mut)]
+    pub curator: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.owner.as_ref(), vault.index.to_le_bytes().as_ref()],
+        bump = vault.bump,
+        constraint = vault.is_curator(&curator.key()) @ LendingError::Unauthorized,
+        realloc = Vault::space(
+            vault.allocations.len() + usize::from(vault.find_allocation(&pending_cap.market).is_none())
+        ),
+        realloc::payer = curator,
+        realloc::zero = false
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        close = submitter,
+        seeds = [b"pending_vault_cap", vault.key().as_ref(), pending_cap.market.as_ref()],
+        bump = pending_cap.bump
+    )]
+    pub pending_cap: Account<'info, PendingVaultCap>,
+
+    /// CHECK: Account that submitted the change, receives the rent
+    #[account(mut, address = pending_cap.submitter)]
+    pub submitter: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn accept_cap_handler(ctx: Context<AcceptVaultCap>) -> Result<()> {
+    let vault = &mut ctx.accounts.vault;
+    let pending_cap = &ctx.accounts.pending_cap;
+    let clock = Clock::get()?;
+
+    require!(
+        clock.unix_timestamp >= pending_cap.valid_at,
+        LendingError::PendingChangeTimelockActive
+    );
+
+    match vault.allocations.iter_mut().find(|allocation| allocation.market == pending_cap.market) {
+        Some(allocation) => allocation.cap = pending_cap.cap,
+        None => {
+            require!(
+                vault.allocations.len() < MAX_VAULT_ALLOCATIONS as usize,
+                LendingError::InvalidVaultAllocation
+            );
+            vault.allocations.push(Allocation {
+                market: pending_cap.market,
+                target_bps: 0,
+                cap: pending_cap.cap,
+                current_supply_tokens: 0,
+            });
+        }
+    }
+
+    emit!(VaultCapAccepted {
+        vault: vault.key(),
+        market: pending_cap.market,
+        cap: pending_cap.cap,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Drop a pending cap change (curator or guardian)
+#[derive(Accounts)]
+pub struct RevokeVaultCap<'info> {
+    #[account(
+        constraint = vault.is_curator(&authority.key()) || vault.is_guardian(&authority.key())
+            @ LendingError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"vault", vault.owner.as_ref(), vault.index.to_le_bytes().as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        close = submitter,
+        seeds = [b"pending_vault_cap", vault.key().as_ref(), pending_cap.market.as_ref()],
+        bump = pending_cap.bump
+    )]
+    pub pending_cap: Account<'info, PendingVaultCap>,
+
+    /// CHECK: Account that submitted the change, receives the rent
+    #[account(mut, address = pending_cap.submitter)]
+    pub submitter: UncheckedAccount<'info>,
+}
+
+pub fn revoke_cap_handler(ctx: Context<RevokeVaultCap>) -> Result<()> {
+    emit!(VaultCapRevoked {
+        vault: ctx.accounts.vault.key(),
+        market: ctx.accounts.pending_cap.market,
+        cap: ctx.accounts.pending_cap.cap,
+        revoked_by: ctx.accounts.authority.key(),
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Propose replacing a vault's guardian (owner only)
+///
+/// Like cap increases, the change waits out the vault's timelock, during which
+/// the current guardian can veto it. A vault without a guardian appoints one
+/// directly with `set_vault_roles`.
+#[derive(Accounts)]
+pub struct SubmitVaultGuardian<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"vault", vault.owner.as_ref(), vault.index.to_le_bytes().as_ref()],
+        bump = vault.bump,
+        has_one = owner @ LendingError::Unauthorized
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = PendingVaultGuardian::SIZE,
+        seeds = [b"pending_vault_guardian", vault.key().as_ref()],
+        bump
+    )]
+    pub pending_guardian: Account<'info, PendingVaultGuardian>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn submit_guardian_handler(ctx: Context<SubmitVaultGuardian>, guardian: Pubkey) -> Result<()> {
+    let vault = &ctx.accounts.vault;
+    let pending_guardian = &mut ctx.accounts.pending_guardian;
+    let clock = Clock::get()?;
+
+    pending_guardian.vault = vault.key();
+    pending_guardian.guardian = guardian;
+    pending_guardian.valid_at = clock.unix_timestamp
+        .checked_add(vault.timelock)
+        .ok_or(LendingError::MathOverflow)?;
+    pending_guardian.bump = ctx.bumps.get("pending_guardian").copied().unwrap();
+
+    emit!(VaultGuardianSubmitted {
+        vault: vault.key(),
+        current_guardian: vault.guardian,
+        guardian,
+        valid_at: pending_guardian.valid_at,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Apply a pending guardian change after the timelock (owner only)
+#[derive(Accounts)]
+pub struct AcceptVaultGuardian<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.owner.as_ref(), vault.index.to_le_bytes().as_ref()],
+        bump = vault.bump,
+        has_one = owner @ LendingError::Unauthorized
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"pending_vault_guardian", vault.key().as_ref()],
+        bump = pending_guardian.bump
+    )]
+    pub pending_guardian: Account<'info, PendingVaultGuardian>,
+}
+
+pub fn accept_guardian_handler(ctx: Context<AcceptVaultGuardian>) -> Result<()> {
+    let vault = &mut ctx.accounts.vault;
+    let pending_guardian = &ctx.accounts.pending_guardian;
+    let clock = Clock::get()?;
+
+    require!(
+        clock.unix_timestamp >= pending_guardian.valid_at,
+        LendingError::PendingChangeTimelockActive
+    );
+
+    vault.guardian = pending_guardian.guardian;
+
+    emit!(VaultGuardianAccepted {
+        vault: vault.key(),
+        guardian: vault.guardian,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Drop a pending guardian change (owner or guardian)
+#[derive(Accounts)]
+pub struct RevokeVaultGuardian<'info> {
+    #[account(
+        constraint = authority.key() == vault.owner || vault.is_guardian(&authority.key())
+            @ LendingError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"vault", vault.owner.as_ref(), vault.index.to_le_bytes().as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"pending_vault_guardian", vault.key().as_ref()],
+        bump = pending_guardian.bump
+    )]
+    pub pending_guardian: Account<'info, PendingVaultGuardian>,
+
+    /// CHECK: Vault owner, submitted the change and receives the rent
+    #[account(mut, address = vault.owner)]
+    pub owner: UncheckedAccount<'info>,
+}
+
+pub fn revoke_guardian_handler(ctx: Context<RevokeVaultGuardian>) -> Result<()> {
+    emit!(VaultGuardianRevoked {
+        vault: ctx.accounts.vault.key(),
+        guardian: ctx.accounts.pending_guardian.guardian,
+        revoked_by: ctx.accounts.authority.key(),
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct VaultCapSubmitted {
+    pub vault: Pubkey,
+    pub market: Pubkey,
+    /// Cap before the change (0 = market not yet allocated to)
+    pub current_cap: u64,
+    pub cap: u64,
+    pub valid_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultCapAccepted {
+    pub vault: Pubkey,
+    pub market: Pubkey,
+    pub cap: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultCapRevoked {
+    pub vault: Pubkey,
+    pub market: Pubkey,
+    pub cap: u64,
+    pub revoked_by: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultGuardianSubmitted {
+    pub vault: Pubkey,
+    pub current_guardian: Pubkey,
+    /// Default if the guardian is being removed
+    pub guardian: Pubkey,
+    pub valid_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultGuardianAccepted {
+    pub vault: Pubkey,
+    pub guardian: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultGuardianRevoked {
+    pub vault: Pubkey,
+    pub guardian: Pubkey,
+    pub revoked_by: Pubkey,
+    pub timestamp: i64,
+}
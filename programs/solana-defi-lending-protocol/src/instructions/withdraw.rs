@@ -4,6 +4,8 @@ use crate::state::*;
 use crate::errors::LendingError;
 use crate::constants::*;
 use crate::math::*;
+use crate::oracle::get_oracle_price;
+use crate::instructions::refresh_market::observe_market_price;
 
 /// Withdraw supplied assets from a lending market
 /// 
@@ -39,7 +41,7 @@ pub struct Withdraw<'info> {
     /// Reserve vault (source of assets)
     #[account(
         mut,
-        constraint = reserve_vault.mint == market.asset_mint @ LendingError::InvalidMarketConfig
+        constraint = reserve_vault.key() == market.reserve_vault @ LendingError::InvalidMarketConfig
     )]
     pub reserve_vault: Account<'info, TokenAccount>,
 
@@ -51,6 +53,12 @@ pub struct Withdraw<'info> {
     )]
     pub user_token_account: Account<'info, TokenAccount>,
 
+    /// CHECK: Oracle account for price feed
+    #[account(
+        constraint = oracle.key() == market.oracle @ LendingError::InvalidOracle
+    )]
+    pub oracle: UncheckedAccount<'info>,
+
     pub token_program: Program<'info, Token>,
 }
 
@@ -63,6 +71,17 @@ pub fn handler(ctx: Context<Withdraw>, supply_tokens: u64) -> Result<()> {
     // Accrue interest before processing
     market.accrue_interest(&clock)?;
 
+    // Block withdrawals while the price circuit breaker is tripped;
+    // a new trip returns early so it isn't rolled back with an error
+    let price = get_oracle_price(&ctx.accounts.oracle, &clock)?;
+    if observe_market_price(market, price, &clock)? {
+        return Ok(());
+    }
+    require!(
+        !market.is_circuit_breaker_active(clock.unix_timestamp),
+        LendingError::CircuitBreakerActive
+    );
+
     // Calculate exchange rate
     let exchange_rate = calculate_exchange_rate(market.total_supplied, market.total_supply_tokens)?;
 
@@ -79,6 +98,9 @@ pub fn handler(ctx: Context<Withdraw>, supply_tokens: u64) -> Result<()> {
         LendingError::InsufficientLiquidity
     );
 
+    // Bound how fast the reserve can be drained
+    market.record_outflow(withdraw_amount, &clock)?;
+
     // TODO: Check health factor - ensure withdrawal doesn't cause liquidation
     // For now, simplified check
 
@@ -0,0 +1,458 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Burn, Mint, Token, TokenAccount, Transfer};
+use crate::state::*;
+use crate::errors::LendingError;
+use crate::instructions::vault::{collect_fees, refresh_total_assets};
+
+/// Queue a vault withdrawal when the vault's markets are too illiquid to exit
+///
+/// The shares are moved into the vault's withdrawal escrow and the request
+/// joins the back of the queue. Requests are filled first-in-first-out by
+/// `process_withdrawals` or `rebalance_vault` as liquidity comes back.
+#[derive(Accounts)]
+pub struct RequestWithdrawal<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.owner.as_ref(), vault.index.to_le_bytes().as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = WithdrawalRequest::SIZE,
+        seeds = [
+            b"withdrawal_request",
+            vault.key().as_ref(),
+            vault.next_withdrawal_request.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub withdrawal_request: Account<'info, WithdrawalRequest>,
+
+    /// Owner's share account (shares are locked from here)
+    #[account(
+        mut,
+        constraint = owner_share_account.owner == owner.key() @ LendingError::Unauthorized,
+        constraint = owner_share_account.mint == vault.share_mint @ LendingError::InvalidVaultAccount
+    )]
+    pub owner_share_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = withdrawal_escrow.key() == vault.withdrawal_escrow @ LendingError::InvalidVaultAccount
+    )]
+    pub withdrawal_escrow: Account<'info, TokenAccount>,
+
+    /// Asset account receiving the withdrawal once filled
+    #[account(
+        constraint = receiver_token_account.mint == vault.asset_mint @ LendingError::InvalidVaultAccount
+    )]
+    pub receiver_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn request_withdrawal_handler(ctx: Context<RequestWithdrawal>, shares: u64) -> Result<()> {
+    require!(shares > 0, LendingError::InvalidAmount);
+
+    let vault = &mut ctx.accounts.vault;
+    let withdrawal_request = &mut ctx.accounts.withdrawal_request;
+    let clock = Clock::get()?;
+
+    let transfer_ctx = CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        Transfer {
+            from: ctx.accounts.owner_share_account.to_account_info(),
+            to: ctx.accounts.withdrawal_escrow.to_account_info(),
+            authority: ctx.accounts.owner.to_account_info(),
+        },
+    );
+    token::transfer(transfer_ctx, shares)?;
+
+    withdrawal_request.vault = vault.key();
+    withdrawal_request.owner = ctx.accounts.owner.key();
+    withdrawal_request.receiver = ctx.accounts.receiver_token_account.key();
+    withdrawal_request.id = vault.next_withdrawal_request;
+    withdrawal_request.shares = shares;
+    withdrawal_request.created_at = clock.unix_timestamp;
+    withdrawal_request.bump = ctx.bumps.get("withdrawal_request").copied().unwrap();
+
+    vault.next_withdrawal_request = vault.next_withdrawal_request
+        .checked_add(1)
+        .ok_or(LendingError::MathOverflow)?;
+    vault.queued_shares = vault.queued_shares
+        .checked_add(shares)
+        .ok_or(LendingError::MathOverflow)?;
+
+    emit!(WithdrawalRequested {
+        vault: vault.key(),
+        withdrawal_request: withdrawal_request.key(),
+        owner: ctx.accounts.owner.key(),
+        id: withdrawal_request.id,
+        shares,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Cancel a withdrawal request and return its shares to the owner
+///
+/// Works for requests still in the queue and for requests the crank skipped
+/// because their receiver couldn't be paid. A request cancelled from the
+/// middle of the queue keeps its slot, empty, until the crank reaches and
+/// closes it; otherwise it is closed straight away.
+#[derive(Accounts)]
+pub struct CancelWithdrawal<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.owner.as_ref(), vault.index.to_le_bytes().as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"withdrawal_request",
+            vault.key().as_ref(),
+            withdrawal_request.id.to_le_bytes().as_ref()
+        ],
+        bump = withdrawal_request.bump,
+        has_one = owner @ LendingError::Unauthorized,
+        constraint = withdrawal_request.shares > 0 @ LendingError::InvalidWithdrawalRequest
+    )]
+    pub withdrawal_request: Account<'info, WithdrawalRequest>,
+
+    /// Share account receiving the returned shares
+    #[account(
+        mut,
+        constraint = owner_share_account.mint == vault.share_mint @ LendingError::InvalidVaultAccount
+    )]
+    pub owner_share_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = withdrawal_escrow.key() == vault.withdrawal_escrow @ LendingError::InvalidVaultAccount
+    )]
+    pub withdrawal_escrow: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn cancel_withdrawal_handler(ctx: Context<CancelWithdrawal>) -> Result<()> {
+    let vault = &mut ctx.accounts.vault;
+    let withdrawal_request = &mut ctx.accounts.withdrawal_request;
+    let shares = withdrawal_request.shares;
+
+    let vault_owner = vault.owner;
+    let vault_index = vault.index.to_le_bytes();
+    let seeds = &[
+        b"vault",
+        vault_owner.as_ref(),
+        vault_index.as_ref(),
+        &[vault.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    let transfer_ctx = CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        Transfer {
+            from: ctx.accounts.withdrawal_escrow.to_account_info(),
+            to: ctx.accounts.owner_share_account.to_account_info(),
+            authority: vault.to_account_info(),
+        },
+        signer,
+    );
+    token::transfer(transfer_ctx, shares)?;
+
+    // Skipped requests already left the queue and its share count
+    let queued = withdrawal_request.id >= vault.next_withdrawal_to_fill;
+    if queued {
+        vault.queued_shares = vault.queued_shares
+            .checked_sub(shares)
+            .ok_or(LendingError::MathOverflow)?;
+    }
+    withdrawal_request.shares = 0;
+
+    emit!(WithdrawalCancelled {
+        vault: vault.key(),
+        withdrawal_request: withdrawal_request.key(),
+        owner: withdrawal_request.owner,
+        shares,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    // A request behind the head keeps its slot so the queue stays consecutive
+    if withdrawal_request.id == vault.next_withdrawal_to_fill {
+        vault.next_withdrawal_to_fill += 1;
+    } else if queued {
+        return Ok(());
+    }
+    withdrawal_request.close(ctx.accounts.owner.to_account_info())
+}
+
+/// Fill queued withdrawal requests from the vault's idle assets
+///
+/// Permissionless crank. Requests are paid at the vault's current valuation.
+/// Remaining accounts: the market account of each allocation, in order,
+/// followed by `[withdrawal_request, receiver_token_account, request_owner]`
+/// for consecutive requests starting at the head of the queue (all writable).
+#[derive(Accounts)]
+pub struct ProcessWithdrawals<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.owner.as_ref(), vault.index.to_le_bytes().as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        constraint = asset_vault.key() == vault.asset_vault @ LendingError::InvalidVaultAccount
+    )]
+    pub asset_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = share_mint.key() == vault.share_mint @ LendingError::InvalidVaultAccount
+    )]
+    pub share_mint: Account<'info, Mint>,
+
+    /// Share account receiving fee shares
+    #[account(
+        mut,
+        constraint = fee_recipient.key() == vault.fee_recipient @ LendingError::InvalidVaultAccount
+    )]
+    pub fee_recipient: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = withdrawal_escrow.key() == vault.withdrawal_escrow @ LendingError::InvalidVaultAccount
+    )]
+    pub withdrawal_escrow: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn process_withdrawals_handler<'info>(
+    ctx: Context<'_, '_, 'info, 'info, ProcessWithdrawals<'info>>,
+) -> Result<()> {
+    let accounts = &mut *ctx.accounts;
+    require!(
+        ctx.remaining_accounts.len() >= accounts.vault.allocations.len(),
+        LendingError::InvalidVaultAccount
+    );
+    let (market_accounts, request_accounts) = ctx.remaining_accounts
+        .split_at(accounts.vault.allocations.len());
+
+    refresh_total_assets(&mut accounts.vault, accounts.asset_vault.amount, market_accounts)?;
+    collect_fees(
+        &mut accounts.vault,
+        &mut accounts.share_mint,
+        &accounts.fee_recipient,
+        &accounts.token_program,
+    )?;
+
+    fill_withdrawal_requests(
+        &mut accounts.vault,
+        &mut accounts.asset_vault,
+        &mut accounts.share_mint,
+        &accounts.withdrawal_escrow,
+        &accounts.token_program,
+        request_accounts,
+    )?;
+
+    Ok(())
+}
+
+/// Accounts per withdrawal request in remaining accounts
+pub(crate) const WITHDRAWAL_ACCOUNTS_PER_REQUEST: usize = 3;
+
+/// Fill queued withdrawal requests in order from the vault's idle assets
+///
+/// `request_accounts` hold `[withdrawal_request, receiver_token_account,
+/// request_owner]` per request, starting at the head of the queue. Each request
+/// is paid at the current share price. One that can't be filled in full is
+/// filled partially and ends the pass; filled and cancelled requests are closed
+/// with the rent returned to their owner. A request whose receiver is closed,
+/// frozen or not an account for the vault's asset is skipped so it can't stall
+/// the queue; its owner gets the shares back with `cancel_withdrawal`.
+pub(crate) fn fill_withdrawal_requests<'info>(
+    vault: &mut Account<'info, Vault>,
+    asset_vault: &mut Account<'info, TokenAccount>,
+    share_mint: &mut Account<'info, Mint>,
+    withdrawal_escrow: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    request_accounts: &'info [AccountInfo<'info>],
+) -> Result<()> {
+    let requests = request_accounts.chunks_exact(WITHDRAWAL_ACCOUNTS_PER_REQUEST);
+    require!(requests.remainder().is_empty(), LendingError::InvalidVaultAccount);
+
+    let clock = Clock::get()?;
+    let vault_owner = vault.owner;
+    let vault_index = vault.index.to_le_bytes();
+    let seeds = &[
+        b"vault",
+        vault_owner.as_ref(),
+        vault_index.as_ref(),
+        &[vault.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    for accounts in requests {
+        let mut request = Account::<WithdrawalRequest>::try_from(&accounts[0])?;
+        require!(
+            request.vault == vault.key()
+                && request.id == vault.next_withdrawal_to_fill
+                && accounts[1].key() == request.receiver
+                && accounts[2].key() == request.owner,
+            LendingError::InvalidWithdrawalRequest
+        );
+
+        // Cancelled from the middle of the queue; its shares are already returned
+        if request.shares == 0 {
+            request.close(accounts[2].clone())?;
+            vault.next_withdrawal_to_fill += 1;
+            continue;
+        }
+
+        if !can_receive(&accounts[1], &vault.asset_mint) {
+            vault.queued_shares = vault.queued_shares
+                .checked_sub(request.shares)
+                .ok_or(LendingError::MathOverflow)?;
+            vault.next_withdrawal_to_fill += 1;
+
+            emit!(WithdrawalSkipped {
+                vault: vault.key(),
+                withdrawal_request: request.key(),
+                owner: request.owner,
+                receiver: request.receiver,
+                shares: request.shares,
+                timestamp: clock.unix_timestamp,
+            });
+            continue;
+        }
+
+        // As many of the request's shares as idle assets can pay for
+        let shares = request.shares.min(vault.convert_to_shares(asset_vault.amount, share_mint.supply, false)?);
+        let assets = vault.preview_redeem(shares, share_mint.supply)?;
+        if assets == 0 {
+            break;
+        }
+
+        let burn_ctx = CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            Burn {
+                mint: share_mint.to_account_info(),
+                from: withdrawal_escrow.to_account_info(),
+                authority: vault.to_account_info(),
+            },
+            signer,
+        );
+        token::burn(burn_ctx, shares)?;
+
+        let transfer_ctx = CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            Transfer {
+                from: asset_vault.to_account_info(),
+                to: accounts[1].clone(),
+                authority: vault.to_account_info(),
+            },
+            signer,
+        );
+        token::transfer(transfer_ctx, assets)?;
+        share_mint.reload()?;
+        asset_vault.reload()?;
+
+        vault.total_assets = vault.total_assets
+            .checked_sub(assets)
+            .ok_or(LendingError::MathOverflow)?;
+        vault.queued_shares = vault.queued_shares
+            .checked_sub(shares)
+            .ok_or(LendingError::MathOverflow)?;
+        request.shares -= shares;
+
+        emit!(WithdrawalFilled {
+            vault: vault.key(),
+            withdrawal_request: request.key(),
+            owner: request.owner,
+            shares,
+            assets,
+            remaining_shares: request.shares,
+            timestamp: clock.unix_timestamp,
+        });
+
+        if request.shares > 0 {
+            request.exit(&crate::ID)?;
+            break;
+        }
+
+        request.close(accounts[2].clone())?;
+        vault.next_withdrawal_to_fill += 1;
+    }
+
+    Ok(())
+}
+
+/// Whether `account` is an open, unfrozen token account for `mint`
+fn can_receive(account: &AccountInfo, mint: &Pubkey) -> bool {
+    if account.owner != &token::ID {
+        return false;
+    }
+    match account.try_borrow_data() {
+        Ok(data) => TokenAccount::try_deserialize(&mut &data[..])
+            .map(|token_account| token_account.mint == *mint && !token_account.is_frozen())
+            .unwrap_or(false),
+        Err(_) => false,
+    }
+}
+
+#[event]
+pub struct WithdrawalRequested {
+    pub vault: Pubkey,
+    pub withdrawal_request: Pubkey,
+    pub owner: Pubkey,
+    pub id: u64,
+    pub shares: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct WithdrawalFilled {
+    pub vault: Pubkey,
+    pub withdrawal_request: Pubkey,
+    pub owner: Pubkey,
+    pub shares: u64,
+    pub assets: u64,
+    pub remaining_shares: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct WithdrawalSkipped {
+    pub vault: Pubkey,
+    pub withdrawal_request: Pubkey,
+    pub owner: Pubkey,
+    pub receiver: Pubkey,
+    pub shares: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct WithdrawalCancelled {
+    pub vault: Pubkey,
+    pub withdrawal_request: Pubkey,
+    pub owner: Pubkey,
+    pub shares: u64,
+    pub timestamp: i64,
+}
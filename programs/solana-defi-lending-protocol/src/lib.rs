@@ -3,6 +3,10 @@ use anchor_lang::prelude::*;
 pub mod constants;
 pub mod errors;
 pub mod math;
+pub mod oracle;
+pub mod health;
+pub mod allocation;
+pub mod swap;
 pub mod state;
 pub mod instructions;
 
@@ -19,6 +23,16 @@ pub mod solana_defi_lending_protocol {
         instructions::initialize::handler(ctx)
     }
 
+    /// Allowlist a swap program for in-protocol swaps (authority only)
+    pub fn add_swap_program(ctx: Context<UpdateGlobalConfig>, swap_program: Pubkey) -> Result<()> {
+        instructions::initialize::add_swap_program_handler(ctx, swap_program)
+    }
+
+    /// Remove a swap program from the allowlist (authority only)
+    pub fn remove_swap_program(ctx: Context<UpdateGlobalConfig>, swap_program: Pubkey) -> Result<()> {
+        instructions::initialize::remove_swap_program_handler(ctx, swap_program)
+    }
+
     /// Create a new lending market
     pub fn create_market(
         ctx: Context<CreateMarket>,
@@ -43,6 +57,43 @@ pub mod solana_defi_lending_protocol {
         instructions::repay::handler(ctx, amount)
     }
 
+    /// Repay a borrow by swapping collateral into the debt asset
+    pub fn repay_with_collateral(
+        ctx: Context<RepayWithCollateral>,
+        supply_tokens: u64,
+        min_amount_out: u64,
+    ) -> Result<()> {
+        instructions::repay_with_collateral::handler(ctx, supply_tokens, min_amount_out)
+    }
+
+    /// Deposit collateral and lever it up to a target in one flash-borrow round
+    pub fn open_leveraged_position(
+        ctx: Context<OpenLeveragedPosition>,
+        deposit_amount: u64,
+        target_leverage_bps: u32,
+        min_amount_out: u64,
+    ) -> Result<()> {
+        instructions::leverage::handler(ctx, deposit_amount, target_leverage_bps, min_amount_out)
+    }
+
+    /// Rotate collateral into another market through a swap
+    pub fn swap_collateral(
+        ctx: Context<SwapCollateral>,
+        supply_tokens: u64,
+        min_amount_out: u64,
+    ) -> Result<()> {
+        instructions::swap_position::handler(ctx, supply_tokens, min_amount_out)
+    }
+
+    /// Refinance a borrow into another debt asset through a swap
+    pub fn swap_debt(
+        ctx: Context<SwapDebt>,
+        borrow_amount: u64,
+        min_amount_out: u64,
+    ) -> Result<()> {
+        instructions::swap_position::swap_debt_handler(ctx, borrow_amount, min_amount_out)
+    }
+
     /// Withdraw supplied assets
     pub fn withdraw(ctx: Context<Withdraw>, supply_tokens: u64) -> Result<()> {
         instructions::withdraw::handler(ctx, supply_tokens)
@@ -57,19 +108,389 @@ pub mod solana_defi_lending_protocol {
         instructions::liquidate::handler(ctx, repay_amount, min_collateral_amount)
     }
 
+    /// Liquidate an unhealthy position, receiving collateral supply tokens
+    pub fn liquidate_to_supply_tokens(
+        ctx: Context<LiquidateToSupplyTokens>,
+        repay_amount: u64,
+        min_supply_tokens: u64,
+    ) -> Result<()> {
+        instructions::liquidate::liquidate_to_supply_tokens_handler(ctx, repay_amount, min_supply_tokens)
+    }
+
+    /// Seize collateral before repaying; flash_liquidate_end must follow in the transaction
+    pub fn flash_liquidate_begin(
+        ctx: Context<FlashLiquidateBegin>,
+        repay_amount: u64,
+        min_collateral_amount: u64,
+    ) -> Result<()> {
+        instructions::flash_liquidate::handler(ctx, repay_amount, min_collateral_amount)
+    }
+
+    /// Repay the debt of a flash liquidation
+    pub fn flash_liquidate_end(ctx: Context<FlashLiquidateEnd>) -> Result<()> {
+        instructions::flash_liquidate::end_handler(ctx)
+    }
+
+    /// Start an auction for a large liquidation's collateral
+    pub fn start_liquidation_auction(
+        ctx: Context<StartLiquidationAuction>,
+        repay_amount: u64,
+    ) -> Result<()> {
+        instructions::liquidation_auction::handler(ctx, repay_amount)
+    }
+
+    /// Bid the fewest collateral supply tokens accepted for repaying an auction's debt
+    pub fn bid_liquidation_auction(ctx: Context<BidLiquidationAuction>, supply_tokens: u64) -> Result<()> {
+        instructions::liquidation_auction::bid_handler(ctx, supply_tokens)
+    }
+
+    /// Settle an ended liquidation auction (permissionless)
+    pub fn settle_liquidation_auction(ctx: Context<SettleLiquidationAuction>) -> Result<()> {
+        instructions::liquidation_auction::settle_handler(ctx)
+    }
+
+    /// Cancel a liquidation auction once the health factor has fallen well below its start (permissionless)
+    pub fn cancel_liquidation_auction(ctx: Context<CancelLiquidationAuction>) -> Result<()> {
+        instructions::liquidation_auction::cancel_handler(ctx)
+    }
+
+    /// Configure when keepers may deleverage an obligation (owner only)
+    pub fn configure_auto_deleverage(
+        ctx: Context<ConfigureAutoDeleverage>,
+        health_factor_bps: u16,
+        repay_bps: u16,
+        max_slippage_bps: u16,
+    ) -> Result<()> {
+        instructions::auto_deleverage::handler(ctx, health_factor_bps, repay_bps, max_slippage_bps)
+    }
+
+    /// Partially deleverage an obligation past its trigger (permissionless keeper)
+    pub fn auto_deleverage(ctx: Context<AutoDeleverage>) -> Result<()> {
+        instructions::auto_deleverage::deleverage_handler(ctx)
+    }
+
+    /// Write off debt left on an obligation with no collateral
+    pub fn socialize_bad_debt(ctx: Context<SocializeBadDebt>) -> Result<()> {
+        instructions::bad_debt::handler(ctx)
+    }
+
+    /// Create the insurance fund for a market's asset (authority only)
+    pub fn create_insurance_fund(
+        ctx: Context<CreateInsuranceFund>,
+        cooldown_period: i64,
+        max_slash_bps: u16,
+        fee_share_bps: u16,
+    ) -> Result<()> {
+        instructions::insurance::handler(ctx, cooldown_period, max_slash_bps, fee_share_bps)
+    }
+
+    /// Stake assets in an insurance fund for share tokens
+    pub fn stake_insurance(ctx: Context<StakeInsurance>, amount: u64) -> Result<()> {
+        instructions::insurance::stake_handler(ctx, amount)
+    }
+
+    /// Start the unstake cooldown for insurance fund shares
+    pub fn request_unstake(ctx: Context<RequestUnstake>, shares: u64) -> Result<()> {
+        instructions::insurance::request_unstake_handler(ctx, shares)
+    }
+
+    /// Burn insurance fund shares for assets within the window after the cooldown
+    pub fn unstake(ctx: Context<Unstake>, shares: u64) -> Result<()> {
+        instructions::insurance::unstake_handler(ctx, shares)
+    }
+
+    /// Slash an insurance fund to cover a shortfall (authority only)
+    pub fn slash_insurance_fund(ctx: Context<SlashInsuranceFund>, amount: u64) -> Result<()> {
+        instructions::insurance::slash_handler(ctx, amount)
+    }
+
+    /// Split a market's protocol fees between its insurance fund and the treasury
+    pub fn collect_protocol_fees(ctx: Context<CollectProtocolFees>) -> Result<()> {
+        instructions::insurance::collect_protocol_fees_handler(ctx)
+    }
+
     /// Create a vault for automated yield strategies
     pub fn create_vault(
         ctx: Context<CreateVault>,
+        index: u16,
         strategy: u8,
         rebalance_threshold_bps: u16,
+        management_fee_bps: u16,
+        performance_fee_bps: u16,
+        timelock: i64,
+    ) -> Result<()> {
+        instructions::vault::handler(
+            ctx,
+            index,
+            strategy,
+            rebalance_threshold_bps,
+            management_fee_bps,
+            performance_fee_bps,
+            timelock,
+        )
+    }
+
+    /// Set a vault's target allocations across markets
+    pub fn set_allocations(
+        ctx: Context<SetAllocations>,
+        allocations: Vec<AllocationConfig>,
+    ) -> Result<()> {
+        instructions::vault::set_allocations_handler(ctx, allocations)
+    }
+
+    /// Propose raising a vault's cap on a market or adding the market (timelocked)
+    pub fn submit_vault_cap(ctx: Context<SubmitVaultCap>, cap: u64) -> Result<()> {
+        instructions::vault_timelock::submit_cap_handler(ctx, cap)
+    }
+
+    /// Apply a pending vault cap change once its timelock has passed
+    pub fn accept_vault_cap(ctx: Context<AcceptVaultCap>) -> Result<()> {
+        instructions::vault_timelock::accept_cap_handler(ctx)
+    }
+
+    /// Veto a pending vault cap change
+    pub fn revoke_vault_cap(ctx: Context<RevokeVaultCap>) -> Result<()> {
+        instructions::vault_timelock::revoke_cap_handler(ctx)
+    }
+
+    /// Propose replacing a vault's guardian (timelocked)
+    pub fn submit_vault_guardian(ctx: Context<SubmitVaultGuardian>, guardian: Pubkey) -> Result<()> {
+        instructions::vault_timelock::submit_guardian_handler(ctx, guardian)
+    }
+
+    /// Apply a pending vault guardian change once its timelock has passed
+    pub fn accept_vault_guardian(ctx: Context<AcceptVaultGuardian>) -> Result<()> {
+        instructions::vault_timelock::accept_guardian_handler(ctx)
+    }
+
+    /// Veto a pending vault guardian change
+    pub fn revoke_vault_guardian(ctx: Context<RevokeVaultGuardian>) -> Result<()> {
+        instructions::vault_timelock::revoke_guardian_handler(ctx)
+    }
+
+    /// Appoint a vault's curator, and its guardian if it has none
+    pub fn set_vault_roles(
+        ctx: Context<UpdateVaultRoles>,
+        curator: Pubkey,
+        guardian: Pubkey,
     ) -> Result<()> {
-        instructions::vault::handler(ctx, strategy, rebalance_threshold_bps)
+        instructions::vault::set_vault_roles_handler(ctx, curator, guardian)
+    }
+
+    /// Allow an account to rebalance a vault
+    pub fn add_vault_allocator(ctx: Context<UpdateVaultRoles>, allocator: Pubkey) -> Result<()> {
+        instructions::vault::add_vault_allocator_handler(ctx, allocator)
+    }
+
+    /// Revoke a vault allocator
+    pub fn remove_vault_allocator(ctx: Context<UpdateVaultRoles>, allocator: Pubkey) -> Result<()> {
+        instructions::vault::remove_vault_allocator_handler(ctx, allocator)
+    }
+
+    /// Deposit exact assets into a vault for shares
+    pub fn vault_deposit(ctx: Context<VaultDeposit>, assets: u64) -> Result<()> {
+        instructions::vault::vault_deposit_handler(ctx, assets)
+    }
+
+    /// Mint exact vault shares for assets
+    pub fn vault_mint(ctx: Context<VaultDeposit>, shares: u64) -> Result<()> {
+        instructions::vault::vault_mint_handler(ctx, shares)
+    }
+
+    /// Withdraw exact assets from a vault by burning shares
+    pub fn vault_withdraw(ctx: Context<VaultWithdraw>, assets: u64) -> Result<()> {
+        instructions::vault::vault_withdraw_handler(ctx, assets)
+    }
+
+    /// Redeem exact vault shares for assets
+    pub fn vault_redeem(ctx: Context<VaultWithdraw>, shares: u64) -> Result<()> {
+        instructions::vault::vault_redeem_handler(ctx, shares)
+    }
+
+    /// Shares a vault deposit of `assets` would mint
+    pub fn preview_vault_deposit(ctx: Context<PreviewVault>, assets: u64) -> Result<u64> {
+        instructions::vault::preview_deposit_handler(ctx, assets)
+    }
+
+    /// Assets a vault mint of `shares` would take
+    pub fn preview_vault_mint(ctx: Context<PreviewVault>, shares: u64) -> Result<u64> {
+        instructions::vault::preview_mint_handler(ctx, shares)
+    }
+
+    /// Shares a vault withdrawal of `assets` would burn
+    pub fn preview_vault_withdraw(ctx: Context<PreviewVault>, assets: u64) -> Result<u64> {
+        instructions::vault::preview_withdraw_handler(ctx, assets)
+    }
+
+    /// Assets a vault redemption of `shares` would return
+    pub fn preview_vault_redeem(ctx: Context<PreviewVault>, shares: u64) -> Result<u64> {
+        instructions::vault::preview_redeem_handler(ctx, shares)
     }
 
     /// Rebalance vault allocations
-    pub fn rebalance_vault(ctx: Context<RebalanceVault>) -> Result<()> {
+    pub fn rebalance_vault<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RebalanceVault<'info>>,
+    ) -> Result<()> {
         instructions::vault::rebalance_handler(ctx)
     }
+
+    /// Lock vault shares in a withdrawal request at the back of the queue
+    pub fn request_withdrawal(ctx: Context<RequestWithdrawal>, shares: u64) -> Result<()> {
+        instructions::withdrawal_queue::request_withdrawal_handler(ctx, shares)
+    }
+
+    /// Return a queued or skipped withdrawal request's shares to its owner
+    pub fn cancel_withdrawal(ctx: Context<CancelWithdrawal>) -> Result<()> {
+        instructions::withdrawal_queue::cancel_withdrawal_handler(ctx)
+    }
+
+    /// Fill queued vault withdrawals from idle assets (permissionless)
+    pub fn process_withdrawals<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ProcessWithdrawals<'info>>,
+    ) -> Result<()> {
+        instructions::withdrawal_queue::process_withdrawals_handler(ctx)
+    }
+
+    /// Refresh a market's interest and oracle price (permissionless)
+    pub fn refresh_market(ctx: Context<RefreshMarket>) -> Result<()> {
+        instructions::refresh_market::handler(ctx)
+    }
+
+    /// Configure a market's price-deviation circuit breaker
+    pub fn set_circuit_breaker_config(
+        ctx: Context<UpdateMarketConfig>,
+        price_deviation_bps: u16,
+        price_deviation_window: i64,
+        circuit_breaker_cooldown: i64,
+    ) -> Result<()> {
+        instructions::market::set_circuit_breaker_handler(
+            ctx,
+            price_deviation_bps,
+            price_deviation_window,
+            circuit_breaker_cooldown,
+        )
+    }
+
+    /// Configure a market's reserve outflow limit
+    pub fn set_outflow_limit(
+        ctx: Context<UpdateMarketConfig>,
+        outflow_limit: u64,
+        outflow_window: i64,
+    ) -> Result<()> {
+        instructions::market::set_outflow_limit_handler(ctx, outflow_limit, outflow_window)
+    }
+
+    /// Configure isolated collateral mode and its debt ceiling (authority only)
+    pub fn set_isolation_config(
+        ctx: Context<UpdateMarketConfig>,
+        isolated_collateral: bool,
+        isolation_borrowable: bool,
+        isolation_debt_ceiling: u64,
+    ) -> Result<()> {
+        instructions::market::set_isolation_config_handler(
+            ctx,
+            isolated_collateral,
+            isolation_borrowable,
+            isolation_debt_ceiling,
+        )
+    }
+
+    /// Set the market's borrow factor (risk weight on debt, authority only)
+    pub fn set_borrow_factor(ctx: Context<UpdateMarketConfig>, borrow_factor_bps: u16) -> Result<()> {
+        instructions::market::set_borrow_factor_handler(ctx, borrow_factor_bps)
+    }
+
+    /// Configure the market's Dutch-auction liquidation bonus (authority only)
+    pub fn set_liquidation_bonus(
+        ctx: Context<UpdateMarketConfig>,
+        min_liquidation_bonus_bps: u16,
+        max_liquidation_bonus_bps: u16,
+        liquidation_auction_duration: i64,
+    ) -> Result<()> {
+        instructions::market::set_liquidation_bonus_handler(
+            ctx,
+            min_liquidation_bonus_bps,
+            max_liquidation_bonus_bps,
+            liquidation_auction_duration,
+        )
+    }
+
+    /// Set the treasury's share of the liquidation bonus (authority only)
+    pub fn set_liquidation_protocol_fee(
+        ctx: Context<UpdateMarketConfig>,
+        liquidation_protocol_fee_bps: u16,
+    ) -> Result<()> {
+        instructions::market::set_liquidation_protocol_fee_handler(ctx, liquidation_protocol_fee_bps)
+    }
+
+    /// Set the size threshold and bidding time for auction liquidations
+    pub fn set_auction_liquidation(
+        ctx: Context<UpdateMarketConfig>,
+        large_liquidation_threshold: u64,
+        large_liquidation_auction_duration: i64,
+    ) -> Result<()> {
+        instructions::market::set_auction_liquidation_handler(
+            ctx,
+            large_liquidation_threshold,
+            large_liquidation_auction_duration,
+        )
+    }
+
+    /// Record whether an obligation is liquidatable (permissionless crank)
+    pub fn refresh_obligation(ctx: Context<RefreshObligation>) -> Result<()> {
+        instructions::refresh_obligation::handler(ctx)
+    }
+
+    /// Create a user's obligation (collateral and debt across markets)
+    pub fn init_obligation(ctx: Context<InitObligation>) -> Result<()> {
+        instructions::obligation::handler(ctx)
+    }
+
+    /// Deposit supply tokens into an obligation as collateral
+    pub fn deposit_collateral(ctx: Context<DepositCollateral>, supply_tokens: u64) -> Result<()> {
+        instructions::obligation::deposit_collateral_handler(ctx, supply_tokens)
+    }
+
+    /// Withdraw supply tokens from an obligation's collateral
+    pub fn withdraw_collateral(ctx: Context<WithdrawCollateral>, supply_tokens: u64) -> Result<()> {
+        instructions::obligation::withdraw_collateral_handler(ctx, supply_tokens)
+    }
+
+    /// Create an e-mode category for correlated assets
+    pub fn create_emode_category(
+        ctx: Context<CreateEModeCategory>,
+        id: u8,
+        ltv_bps: u16,
+        liquidation_threshold_bps: u16,
+        liquidation_bonus_bps: u16,
+    ) -> Result<()> {
+        instructions::emode::handler(ctx, id, ltv_bps, liquidation_threshold_bps, liquidation_bonus_bps)
+    }
+
+    /// Update an e-mode category's risk parameters
+    pub fn update_emode_category(
+        ctx: Context<UpdateEModeCategory>,
+        ltv_bps: u16,
+        liquidation_threshold_bps: u16,
+        liquidation_bonus_bps: u16,
+    ) -> Result<()> {
+        instructions::emode::update_handler(ctx, ltv_bps, liquidation_threshold_bps, liquidation_bonus_bps)
+    }
+
+    /// Add a market to an e-mode category
+    pub fn add_emode_market(ctx: Context<UpdateEModeMarket>) -> Result<()> {
+        instructions::emode::add_market_handler(ctx)
+    }
+
+    /// Remove a market from an e-mode category
+    pub fn remove_emode_market(ctx: Context<UpdateEModeMarket>) -> Result<()> {
+        instructions::emode::remove_market_handler(ctx)
+    }
+
+    /// Opt an obligation into an e-mode category (omit the category to leave e-mode)
+    pub fn set_obligation_emode(ctx: Context<SetObligationEMode>) -> Result<()> {
+        instructions::emode::set_obligation_handler(ctx)
+    }
 }
 }
 
@@ -78,3 +499,4 @@ pub use state::*;
 pub use errors::*;
 pub use constants::*;
 pub use math::*;
+pub use oracle::*;
@@ -31,7 +31,7 @@ pub fn calculate_borrow_rate(utilization_bps: u16) -> Result<u64> {
 
     let rate = if util <= optimal_util {
         // Below optimal: linear increase
-        let rate_per_second = BASE_RATE_PER_SECOND
+        BASE_RATE_PER_SECOND
             .checked_add(
                 SLOPE_1_PER_SECOND
                     .checked_mul(util)
@@ -39,8 +39,7 @@ pub fn calculate_borrow_rate(utilization_bps: u16) -> Result<u64> {
                     .checked_div(optimal_util)
                     .ok_or(LendingError::MathOverflow)?,
             )
-            .ok_or(LendingError::MathOverflow)?;
-        rate_per_second
+            .ok_or(LendingError::MathOverflow)?
     } else {
         // Above optimal: steeper increase
         let excess_util = util
@@ -103,11 +102,9 @@ pub fn calculate_accrued_interest(
     }
 
     // Simplified compound interest: principal * (1 + rate * time)
-    // Using fixed-point math with INTEREST_SCALE
+    // Rates are already scaled by INTEREST_SCALE, so the factor is too
     let interest_factor = (rate_per_second as u128)
         .checked_mul(seconds_elapsed as u128)
-        .ok_or(LendingError::MathOverflow)?
-        .checked_div(INTEREST_SCALE)
         .ok_or(LendingError::MathOverflow)?;
 
     let new_amount = (principal as u128)
@@ -119,32 +116,39 @@ pub fn calculate_accrued_interest(
     Ok(new_amount as u64)
 }
 
+/// Calculate risk-adjusted debt value
+/// weighted_debt_value = debt_value / borrow_factor
+/// A borrow factor below 1.0 makes volatile debt count for more than its market value
+pub fn calculate_weighted_debt_value(debt_value: u64, borrow_factor_bps: u16) -> Result<u64> {
+    let weighted = (debt_value as u128)
+        .checked_mul(BPS_SCALE as u128)
+        .ok_or(LendingError::MathOverflow)?
+        .checked_div(borrow_factor_bps as u128)
+        .ok_or(LendingError::MathOverflow)?;
+
+    u64::try_from(weighted).map_err(|_| error!(LendingError::MathOverflow))
+}
+
 /// Calculate health factor
-/// Health Factor = (collateral_value * liquidation_threshold) / total_borrowed_value
-/// Returns basis points (10000 = 1.0, healthy)
+/// Health Factor = sum(collateral_value_i * liquidation_threshold_i) / sum(debt_value_j / borrow_factor_j)
+/// Both sums are computed by the caller across all of an obligation's positions
+/// Returns basis points (10000 = 1.0, healthy), capped at u16::MAX
 pub fn calculate_health_factor(
-    collateral_value: u64,
-    liquidation_threshold_bps: u16,
-    borrowed_value: u64,
+    weighted_collateral_value: u64,
+    weighted_borrowed_value: u64,
 ) -> Result<u16> {
-    if borrowed_value == 0 {
+    if weighted_borrowed_value == 0 {
         // No borrows = infinite health factor, return max
         return Ok(u16::MAX);
     }
 
-    let adjusted_collateral = (collateral_value as u128)
-        .checked_mul(liquidation_threshold_bps as u128)
-        .ok_or(LendingError::MathOverflow)?
-        .checked_div(BPS_SCALE as u128)
-        .ok_or(LendingError::MathOverflow)?;
-
-    let health_factor = (adjusted_collateral as u128)
+    let health_factor = (weighted_collateral_value as u128)
         .checked_mul(BPS_SCALE as u128)
         .ok_or(LendingError::MathOverflow)?
-        .checked_div(borrowed_value as u128)
+        .checked_div(weighted_borrowed_value as u128)
         .ok_or(LendingError::MathOverflow)?;
 
-    Ok(health_factor as u16)
+    Ok(health_factor.min(u16::MAX as u128) as u16)
 }
 
 /// Calculate maximum borrow amount given collateral
@@ -169,9 +173,9 @@ pub fn calculate_max_borrow(
 
 /// Calculate liquidation bonus amount
 /// bonus = amount * liquidation_bonus_bps / BPS_SCALE
-pub fn calculate_liquidation_bonus(amount: u64) -> Result<u64> {
+pub fn calculate_liquidation_bonus(amount: u64, liquidation_bonus_bps: u16) -> Result<u64> {
     let bonus = (amount as u128)
-        .checked_mul(LIQUIDATION_BONUS_BPS as u128)
+        .checked_mul(liquidation_bonus_bps as u128)
         .ok_or(LendingError::MathOverflow)?
         .checked_div(BPS_SCALE as u128)
         .ok_or(LendingError::MathOverflow)?;
@@ -179,6 +183,84 @@ pub fn calculate_liquidation_bonus(amount: u64) -> Result<u64> {
     Ok(bonus as u64)
 }
 
+/// Calculate the Dutch-auction liquidation bonus
+/// The bonus rises linearly from `min_bonus_bps` to `max_bonus_bps` both as the
+/// health factor falls from 1.0 to 1.0 - LIQUIDATION_BONUS_HEALTH_RANGE_BPS, and
+/// as `seconds_liquidatable` grows to `auction_duration`; the larger of the two applies
+pub fn calculate_liquidation_bonus_bps(
+    health_factor: u16,
+    seconds_liquidatable: i64,
+    min_bonus_bps: u16,
+    max_bonus_bps: u16,
+    auction_duration: i64,
+) -> Result<u16> {
+    if max_bonus_bps <= min_bonus_bps {
+        return Ok(max_bonus_bps);
+    }
+
+    let health_shortfall = MIN_HEALTH_FACTOR_BPS.saturating_sub(health_factor);
+    let health_bonus = interpolate_bonus_bps(
+        min_bonus_bps,
+        max_bonus_bps,
+        health_shortfall as u64,
+        LIQUIDATION_BONUS_HEALTH_RANGE_BPS as u64,
+    )?;
+
+    let time_bonus = if auction_duration > 0 {
+        interpolate_bonus_bps(
+            min_bonus_bps,
+            max_bonus_bps,
+            seconds_liquidatable.max(0) as u64,
+            auction_duration as u64,
+        )?
+    } else {
+        max_bonus_bps
+    };
+
+    Ok(health_bonus.max(time_bonus))
+}
+
+/// Linear interpolation between two bonuses, clamped at `max_bonus_bps`
+/// bonus = min + (max - min) * progress / range
+fn interpolate_bonus_bps(
+    min_bonus_bps: u16,
+    max_bonus_bps: u16,
+    progress: u64,
+    range: u64,
+) -> Result<u16> {
+    let progress = progress.min(range);
+    let increase = ((max_bonus_bps - min_bonus_bps) as u128)
+        .checked_mul(progress as u128)
+        .ok_or(LendingError::MathOverflow)?
+        .checked_div(range as u128)
+        .ok_or(LendingError::MathOverflow)?;
+
+    Ok(min_bonus_bps + increase as u16)
+}
+
+/// Calculate the protocol's cut of seized collateral
+/// bonus_amount = seized_amount * bonus / (1 + bonus)
+/// protocol_fee = bonus_amount * protocol_fee_bps / BPS_SCALE
+pub fn calculate_liquidation_protocol_fee(
+    seized_amount: u64,
+    liquidation_bonus_bps: u16,
+    protocol_fee_bps: u16,
+) -> Result<u64> {
+    let bonus_amount = (seized_amount as u128)
+        .checked_mul(liquidation_bonus_bps as u128)
+        .ok_or(LendingError::MathOverflow)?
+        .checked_div(BPS_SCALE as u128 + liquidation_bonus_bps as u128)
+        .ok_or(LendingError::MathOverflow)?;
+
+    let protocol_fee = bonus_amount
+        .checked_mul(protocol_fee_bps as u128)
+        .ok_or(LendingError::MathOverflow)?
+        .checked_div(BPS_SCALE as u128)
+        .ok_or(LendingError::MathOverflow)?;
+
+    Ok(protocol_fee as u64)
+}
+
 /// Calculate exchange rate for yield-bearing tokens
 /// exchange_rate = total_supplied / total_supply_tokens
 pub fn calculate_exchange_rate(
@@ -198,3 +280,249 @@ pub fn calculate_exchange_rate(
 
     Ok(rate)
 }
+
+/// Calculate how far a price has moved from a reference price
+/// deviation = |price - reference_price| / reference_price
+/// Returns basis points, saturating at u16::MAX
+pub fn calculate_price_deviation_bps(reference_price: u64, price: u64) -> Result<u16> {
+    if reference_price == 0 {
+        return Ok(0);
+    }
+
+    let deviation = (reference_price.abs_diff(price) as u128)
+        .checked_mul(BPS_SCALE as u128)
+        .ok_or(LendingError::MathOverflow)?
+        .checked_div(reference_price as u128)
+        .ok_or(LendingError::MathOverflow)?;
+
+    Ok(deviation.min(u16::MAX as u128) as u16)
+}
+
+/// Calculate the outflow token bucket level after it drains over time
+/// The bucket empties at `limit / window` tokens per second, so the full
+/// limit becomes available again one window after the last outflow
+pub fn calculate_outflow_bucket(
+    level: u64,
+    limit: u64,
+    seconds_elapsed: u64,
+    window: u64,
+) -> Result<u64> {
+    if window == 0 {
+        return Ok(0);
+    }
+
+    let drained = (limit as u128)
+        .checked_mul(seconds_elapsed as u128)
+        .ok_or(LendingError::MathOverflow)?
+        .checked_div(window as u128)
+        .ok_or(LendingError::MathOverflow)?;
+
+    Ok((level as u128).saturating_sub(drained) as u64)
+}
+
+/// Calculate the market value of a token amount
+/// value = amount * price / 10^decimals
+/// Returns value in ORACLE_PRICE_SCALE units
+pub fn calculate_market_value(amount: u64, price: u64, decimals: u8) -> Result<u64> {
+    let value = (amount as u128)
+        .checked_mul(price as u128)
+        .ok_or(LendingError::MathOverflow)?
+        .checked_div(10u128.pow(decimals as u32))
+        .ok_or(LendingError::MathOverflow)?;
+
+    u64::try_from(value).map_err(|_| error!(LendingError::MathOverflow))
+}
+
+/// Calculate the token amount worth a given market value
+/// amount = value * 10^decimals / price
+pub fn calculate_amount_from_value(value: u64, price: u64, decimals: u8) -> Result<u64> {
+    let amount = (value as u128)
+        .checked_mul(10u128.pow(decimals as u32))
+        .ok_or(LendingError::MathOverflow)?
+        .checked_div(price as u128)
+        .ok_or(LendingError::MathOverflow)?;
+
+    u64::try_from(amount).map_err(|_| error!(LendingError::MathOverflow))
+}
+
+/// Apply a basis-point weight to a value
+/// weighted = value * weight_bps / BPS_SCALE
+pub fn apply_bps(value: u64, weight_bps: u16) -> Result<u64> {
+    let weighted = (value as u128)
+        .checked_mul(weight_bps as u128)
+        .ok_or(LendingError::MathOverflow)?
+        .checked_div(BPS_SCALE as u128)
+        .ok_or(LendingError::MathOverflow)?;
+
+    u64::try_from(weighted).map_err(|_| error!(LendingError::MathOverflow))
+}
+
+/// Scale a value by a ratio, rounding down or up
+/// result = value * numerator / denominator
+pub fn mul_div(value: u64, numerator: u128, denominator: u128, round_up: bool) -> Result<u64> {
+    require!(denominator > 0, LendingError::MathOverflow);
+
+    let product = (value as u128)
+        .checked_mul(numerator)
+        .ok_or(LendingError::MathOverflow)?;
+    let mut result = product / denominator;
+    if round_up && product % denominator != 0 {
+        result += 1;
+    }
+
+    u64::try_from(result).map_err(|_| error!(LendingError::MathOverflow))
+}
+
+/// Convert a token amount between decimal precisions
+pub fn normalize_decimals(amount: u64, from_decimals: u8, to_decimals: u8) -> Result<u64> {
+    let normalized = if from_decimals >= to_decimals {
+        (amount as u128)
+            .checked_div(10u128.pow((from_decimals - to_decimals) as u32))
+            .ok_or(LendingError::MathOverflow)?
+    } else {
+        (amount as u128)
+            .checked_mul(10u128.pow((to_decimals - from_decimals) as u32))
+            .ok_or(LendingError::MathOverflow)?
+    };
+
+    u64::try_from(normalized).map_err(|_| error!(LendingError::MathOverflow))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIN_BONUS_BPS: u16 = 500;
+    const MAX_BONUS_BPS: u16 = 1500;
+    const AUCTION_DURATION: i64 = 600;
+
+    fn bonus_bps(health_factor: u16, seconds_liquidatable: i64) -> u16 {
+        calculate_liquidation_bonus_bps(
+            health_factor,
+            seconds_liquidatable,
+            MIN_BONUS_BPS,
+            MAX_BONUS_BPS,
+            AUCTION_DURATION,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn liquidation_bonus_starts_at_the_minimum() {
+        assert_eq!(bonus_bps(MIN_HEALTH_FACTOR_BPS, 0), MIN_BONUS_BPS);
+        assert_eq!(bonus_bps(MIN_HEALTH_FACTOR_BPS - 1, 0), MIN_BONUS_BPS + 1);
+    }
+
+    #[test]
+    fn liquidation_bonus_is_halfway_at_the_midpoint_of_the_ramp() {
+        assert_eq!(bonus_bps(MIN_HEALTH_FACTOR_BPS, AUCTION_DURATION / 2), 1000);
+        let health_midpoint = MIN_HEALTH_FACTOR_BPS - LIQUIDATION_BONUS_HEALTH_RANGE_BPS / 2;
+        assert_eq!(bonus_bps(health_midpoint, 0), 1000);
+    }
+
+    #[test]
+    fn liquidation_bonus_is_clamped_past_the_ramp() {
+        assert_eq!(bonus_bps(MIN_HEALTH_FACTOR_BPS, AUCTION_DURATION), MAX_BONUS_BPS);
+        assert_eq!(bonus_bps(MIN_HEALTH_FACTOR_BPS, AUCTION_DURATION * 10), MAX_BONUS_BPS);
+        assert_eq!(bonus_bps(MIN_HEALTH_FACTOR_BPS - LIQUIDATION_BONUS_HEALTH_RANGE_BPS, 0), MAX_BONUS_BPS);
+        assert_eq!(bonus_bps(0, 0), MAX_BONUS_BPS);
+    }
+
+    #[test]
+    fn liquidation_bonus_uses_the_larger_of_health_and_time() {
+        // 25% along the health ramp, 75% along the time ramp
+        assert_eq!(bonus_bps(MIN_HEALTH_FACTOR_BPS - 250, 450), 1250);
+        // 75% along the health ramp, 25% along the time ramp
+        assert_eq!(bonus_bps(MIN_HEALTH_FACTOR_BPS - 750, 150), 1250);
+    }
+
+    #[test]
+    fn liquidation_bonus_ignores_time_before_the_obligation_was_liquidatable() {
+        assert_eq!(bonus_bps(MIN_HEALTH_FACTOR_BPS, -100), MIN_BONUS_BPS);
+    }
+
+    #[test]
+    fn liquidation_bonus_without_a_ramp_is_the_maximum() {
+        assert_eq!(
+            calculate_liquidation_bonus_bps(MIN_HEALTH_FACTOR_BPS, 0, MIN_BONUS_BPS, MAX_BONUS_BPS, 0).unwrap(),
+            MAX_BONUS_BPS
+        );
+        assert_eq!(
+            calculate_liquidation_bonus_bps(MIN_HEALTH_FACTOR_BPS, 0, MAX_BONUS_BPS, MAX_BONUS_BPS, AUCTION_DURATION).unwrap(),
+            MAX_BONUS_BPS
+        );
+    }
+
+    #[test]
+    fn accrued_interest_matches_the_annual_rate() {
+        // The base rate is just under 2% a year
+        let amount = calculate_accrued_interest(1_000_000_000, BASE_RATE_PER_SECOND, SECONDS_PER_YEAR).unwrap();
+        assert_eq!(amount, 1_019_999_999);
+    }
+
+    #[test]
+    fn accrued_interest_is_zero_with_no_time_elapsed() {
+        assert_eq!(calculate_accrued_interest(1_000_000_000, BASE_RATE_PER_SECOND, 0).unwrap(), 1_000_000_000);
+    }
+
+    #[test]
+    fn liquidation_protocol_fee_is_a_share_of_the_bonus_only() {
+        // 10_500 seized at a 5% bonus is 10_000 repaid plus a 500 bonus
+        assert_eq!(calculate_liquidation_protocol_fee(10_500, 500, 1000).unwrap(), 50);
+        assert_eq!(calculate_liquidation_protocol_fee(10_500, 500, 10000).unwrap(), 500);
+    }
+
+    #[test]
+    fn liquidation_protocol_fee_rounds_down() {
+        // Bonus part of 1_000 seized at 5% is 47.6, and 10% of 47 is 4.7
+        assert_eq!(calculate_liquidation_protocol_fee(1_000, 500, 1000).unwrap(), 4);
+    }
+
+    #[test]
+    fn liquidation_protocol_fee_is_zero_without_a_bonus_or_fee_share() {
+        assert_eq!(calculate_liquidation_protocol_fee(10_000, 0, 1000).unwrap(), 0);
+        assert_eq!(calculate_liquidation_protocol_fee(10_500, 500, 0).unwrap(), 0);
+    }
+
+    #[test]
+    fn price_deviation_is_measured_against_the_reference_price() {
+        assert_eq!(calculate_price_deviation_bps(1_000_000, 1_200_000).unwrap(), 2000);
+        assert_eq!(calculate_price_deviation_bps(1_000_000, 800_000).unwrap(), 2000);
+        // A rise back to the reference is a larger move relative to the lower price
+        assert_eq!(calculate_price_deviation_bps(800_000, 1_000_000).unwrap(), 2500);
+        assert_eq!(calculate_price_deviation_bps(1_000_000, 1_000_000).unwrap(), 0);
+    }
+
+    #[test]
+    fn price_deviation_saturates_and_ignores_a_missing_reference() {
+        assert_eq!(calculate_price_deviation_bps(1, u64::MAX).unwrap(), u16::MAX);
+        assert_eq!(calculate_price_deviation_bps(0, 1_000_000).unwrap(), 0);
+    }
+
+    #[test]
+    fn weighted_debt_value_grows_as_the_borrow_factor_falls() {
+        assert_eq!(calculate_weighted_debt_value(1_000, 10000).unwrap(), 1_000);
+        assert_eq!(calculate_weighted_debt_value(1_000, 8000).unwrap(), 1_250);
+        assert_eq!(calculate_weighted_debt_value(1_000, 5000).unwrap(), 2_000);
+    }
+
+    #[test]
+    fn health_factor_is_weighted_collateral_over_weighted_debt() {
+        assert_eq!(calculate_health_factor(800, 600).unwrap(), 13333);
+        // The same debt at an 80% borrow factor counts as 750
+        let weighted_debt = calculate_weighted_debt_value(600, 8000).unwrap();
+        assert_eq!(calculate_health_factor(800, weighted_debt).unwrap(), 10666);
+    }
+
+    #[test]
+    fn health_factor_without_debt_is_the_maximum() {
+        assert_eq!(calculate_health_factor(0, 0).unwrap(), u16::MAX);
+        assert_eq!(calculate_health_factor(1_000, 0).unwrap(), u16::MAX);
+    }
+
+    #[test]
+    fn health_factor_is_capped_at_the_maximum() {
+        assert_eq!(calculate_health_factor(7, 1).unwrap(), u16::MAX);
+        assert_eq!(calculate_health_factor(u64::MAX, 1).unwrap(), u16::MAX);
+    }
+}
@@ -0,0 +1,87 @@
+use anchor_lang::prelude::*;
+use crate::constants::*;
+use crate::errors::LendingError;
+
+/// Pyth price account magic number
+const PYTH_MAGIC: u32 = 0xa1b2c3d4;
+
+/// Pyth account type for price accounts
+const PYTH_PRICE_ACCOUNT_TYPE: u32 = 3;
+
+/// Pyth aggregate price status: trading
+const PYTH_STATUS_TRADING: u32 = 1;
+
+/// Byte offsets into a Pyth v2 price account
+const MAGIC_OFFSET: usize = 0;
+const ATYPE_OFFSET: usize = 8;
+const EXPO_OFFSET: usize = 20;
+const TIMESTAMP_OFFSET: usize = 96;
+const AGG_PRICE_OFFSET: usize = 208;
+const AGG_STATUS_OFFSET: usize = 224;
+const MIN_PRICE_ACCOUNT_LEN: usize = 240;
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_i32(data: &[u8], offset: usize) -> i32 {
+    i32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_i64(data: &[u8], offset: usize) -> i64 {
+    i64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+/// Read the aggregate price from a Pyth price account
+/// Returns the price normalized to ORACLE_PRICE_SCALE (6 decimals)
+pub fn get_oracle_price(oracle: &AccountInfo, clock: &Clock) -> Result<u64> {
+    let data = oracle.try_borrow_data()?;
+
+    require!(data.len() >= MIN_PRICE_ACCOUNT_LEN, LendingError::InvalidOracle);
+    require!(read_u32(&data, MAGIC_OFFSET) == PYTH_MAGIC, LendingError::InvalidOracle);
+    require!(
+        read_u32(&data, ATYPE_OFFSET) == PYTH_PRICE_ACCOUNT_TYPE,
+        LendingError::InvalidOracle
+    );
+    require!(
+        read_u32(&data, AGG_STATUS_OFFSET) == PYTH_STATUS_TRADING,
+        LendingError::InvalidOracle
+    );
+
+    // Reject prices older than the staleness threshold
+    let publish_time = read_i64(&data, TIMESTAMP_OFFSET);
+    let age = clock
+        .unix_timestamp
+        .checked_sub(publish_time)
+        .ok_or(LendingError::MathOverflow)?;
+    require!(age <= ORACLE_STALENESS_THRESHOLD, LendingError::StaleOraclePrice);
+
+    let price = read_i64(&data, AGG_PRICE_OFFSET);
+    require!(price > 0, LendingError::InvalidOracle);
+
+    normalize_price(price as u64, read_i32(&data, EXPO_OFFSET))
+}
+
+/// Convert a price with the given exponent to ORACLE_PRICE_SCALE
+fn normalize_price(price: u64, expo: i32) -> Result<u64> {
+    let target_expo = -(ORACLE_PRICE_SCALE.ilog10() as i32);
+
+    let normalized = if expo >= target_expo {
+        let factor = 10u128
+            .checked_pow((expo - target_expo) as u32)
+            .ok_or(LendingError::MathOverflow)?;
+        (price as u128)
+            .checked_mul(factor)
+            .ok_or(LendingError::MathOverflow)?
+    } else {
+        let factor = 10u128
+            .checked_pow((target_expo - expo) as u32)
+            .ok_or(LendingError::MathOverflow)?;
+        (price as u128)
+            .checked_div(factor)
+            .ok_or(LendingError::MathOverflow)?
+    };
+
+    require!(normalized > 0, LendingError::InvalidOracle);
+    u64::try_from(normalized).map_err(|_| error!(LendingError::MathOverflow))
+}
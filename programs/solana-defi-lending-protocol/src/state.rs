@@ -17,6 +17,8 @@ pub struct GlobalConfig {
     pub market_count: u8,
     /// Bump seed for treasury PDA
     pub treasury_bump: u8,
+    /// Swap programs allowed for in-protocol swaps (empty = swaps disabled)
+    pub swap_programs: Vec<Pubkey>,
     /// Reserved for future upgrades
     pub _reserved: [u8; 32],
 }
@@ -28,6 +30,7 @@ impl GlobalConfig {
         2 +  // protocol_fee_bps
         1 +  // market_count
         1 +  // treasury_bump
+        4 + 32 * MAX_SWAP_PROGRAMS as usize + // swap_programs
         32;  // _reserved
 
     pub fn initialize(&mut self, authority: Pubkey, treasury: Pubkey, treasury_bump: u8) {
@@ -36,6 +39,12 @@ impl GlobalConfig {
         self.protocol_fee_bps = PROTOCOL_FEE_BPS;
         self.market_count = 0;
         self.treasury_bump = treasury_bump;
+        self.swap_programs = Vec::new();
+    }
+
+    /// Whether a swap program is on the allowlist
+    pub fn is_swap_program_allowed(&self, program: &Pubkey) -> bool {
+        self.swap_programs.contains(program)
     }
 }
 
@@ -46,16 +55,32 @@ pub struct Market {
     pub market_id: u8,
     /// Asset mint address
     pub asset_mint: Pubkey,
+    /// Asset mint decimals (used to value positions)
+    pub mint_decimals: u8,
     /// Yield-bearing token mint (represents supply shares)
     pub supply_mint: Pubkey,
     /// Reserve vault (holds supplied assets)
     pub reserve_vault: Pubkey,
+    /// Collateral vault (holds supply tokens deposited into obligations)
+    pub collateral_vault: Pubkey,
     /// Oracle account (Pyth or other price feed)
     pub oracle: Pubkey,
     /// Loan-to-value ratio in basis points (e.g., 7500 = 75%)
     pub ltv_bps: u16,
     /// Liquidation threshold in basis points (e.g., 8500 = 85%)
     pub liquidation_threshold_bps: u16,
+    /// Borrow factor in basis points (10000 = 1.0); debt value is divided by it
+    pub borrow_factor_bps: u16,
+    /// Liquidation bonus for barely-unhealthy positions
+    pub min_liquidation_bonus_bps: u16,
+    /// Liquidation bonus for deeply unhealthy or long-liquidatable positions
+    pub max_liquidation_bonus_bps: u16,
+    /// Seconds for the liquidation bonus to rise from minimum to maximum
+    pub liquidation_auction_duration: i64,
+    /// Share of the liquidation bonus sent to the treasury (basis points of the bonus)
+    pub liquidation_protocol_fee_bps: u16,
+    /// Interest fees owed to the protocol, held in the reserve
+    pub accumulated_protocol_fees: u64,
     /// Total amount supplied (with accrued interest)
     pub total_supplied: u64,
     /// Total amount borrowed (with accrued interest)
@@ -76,17 +101,75 @@ pub struct Market {
     pub created_at: i64,
     /// Bump seed for market PDA
     pub bump: u8,
+    /// Last observed oracle price (normalized to ORACLE_PRICE_SCALE)
+    pub last_price: u64,
+    /// Recent oracle price samples (ring buffer) used by the circuit breaker
+    pub price_history: [PriceSample; PRICE_HISTORY_LEN],
+    /// Next slot to write in price_history
+    pub price_history_index: u8,
+    /// Price move within the window that trips the circuit breaker (bps, 0 = disabled)
+    pub price_deviation_bps: u16,
+    /// Window in seconds over which price moves are measured
+    pub price_deviation_window: i64,
+    /// Seconds borrows and withdrawals stay blocked after the breaker trips
+    pub circuit_breaker_cooldown: i64,
+    /// Borrows and withdrawals are blocked until this timestamp
+    pub circuit_breaker_until: i64,
+    /// Max net outflow from the reserve per window (token units, 0 = disabled)
+    pub outflow_limit: u64,
+    /// Window in seconds over which the outflow limit refills
+    pub outflow_window: i64,
+    /// Net outflow currently counted against the limit (drains over the window)
+    pub outflow_bucket: u64,
+    /// Last time the outflow bucket was updated
+    pub outflow_last_update: i64,
+    /// Collateral in this market must be the only collateral in an obligation
+    pub isolated_collateral: bool,
+    /// Obligations with isolated collateral may borrow from this market
+    pub isolation_borrowable: bool,
+    /// Max debt backed by this isolated collateral (ISOLATION_DEBT_DECIMALS)
+    pub isolation_debt_ceiling: u64,
+    /// Debt currently backed by this isolated collateral (ISOLATION_DEBT_DECIMALS)
+    pub isolation_total_debt: u64,
+    /// Insurance fund for this asset (default = none)
+    pub insurance_fund: Pubkey,
+    /// Repayments at least this large may be liquidated by auction (token units, 0 = disabled)
+    pub large_liquidation_threshold: u64,
+    /// Seconds a liquidation auction accepts bids
+    pub large_liquidation_auction_duration: i64,
+}
+
+/// Oracle price observation
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct PriceSample {
+    /// Price normalized to ORACLE_PRICE_SCALE
+    pub price: u64,
+    /// Timestamp of the observation
+    pub timestamp: i64,
+}
+
+impl PriceSample {
+    pub const SIZE: usize = 8 + // price
+        8;   // timestamp
 }
 
 impl Market {
     pub const SIZE: usize = 8 + // discriminator
         1 +  // market_id
         32 + // asset_mint
+        1 +  // mint_decimals
         32 + // supply_mint
         32 + // reserve_vault
+        32 + // collateral_vault
         32 + // oracle
         2 +  // ltv_bps
         2 +  // liquidation_threshold_bps
+        2 +  // borrow_factor_bps
+        2 +  // min_liquidation_bonus_bps
+        2 +  // max_liquidation_bonus_bps
+        8 +  // liquidation_auction_duration
+        2 +  // liquidation_protocol_fee_bps
+        8 +  // accumulated_protocol_fees
         8 +  // total_supplied
         8 +  // total_borrowed
         8 +  // total_supply_tokens
@@ -96,14 +179,34 @@ impl Market {
         1 +  // paused
         32 + // creator
         8 +  // created_at
-        1;   // bump
+        1 +  // bump
+        8 +  // last_price
+        PriceSample::SIZE * PRICE_HISTORY_LEN + // price_history
+        1 +  // price_history_index
+        2 +  // price_deviation_bps
+        8 +  // price_deviation_window
+        8 +  // circuit_breaker_cooldown
+        8 +  // circuit_breaker_until
+        8 +  // outflow_limit
+        8 +  // outflow_window
+        8 +  // outflow_bucket
+        8 +  // outflow_last_update
+        1 +  // isolated_collateral
+        1 +  // isolation_borrowable
+        8 +  // isolation_debt_ceiling
+        8 +  // isolation_total_debt
+        32 + // insurance_fund
+        8 +  // large_liquidation_threshold
+        8;   // large_liquidation_auction_duration
 
     pub fn initialize(
         &mut self,
         market_id: u8,
         asset_mint: Pubkey,
+        mint_decimals: u8,
         supply_mint: Pubkey,
         reserve_vault: Pubkey,
+        collateral_vault: Pubkey,
         oracle: Pubkey,
         ltv_bps: u16,
         liquidation_threshold_bps: u16,
@@ -126,11 +229,19 @@ impl Market {
 
         self.market_id = market_id;
         self.asset_mint = asset_mint;
+        self.mint_decimals = mint_decimals;
         self.supply_mint = supply_mint;
         self.reserve_vault = reserve_vault;
+        self.collateral_vault = collateral_vault;
         self.oracle = oracle;
         self.ltv_bps = ltv_bps;
         self.liquidation_threshold_bps = liquidation_threshold_bps;
+        self.borrow_factor_bps = BPS_SCALE;
+        self.min_liquidation_bonus_bps = LIQUIDATION_BONUS_BPS;
+        self.max_liquidation_bonus_bps = LIQUIDATION_BONUS_BPS;
+        self.liquidation_auction_duration = DEFAULT_LIQUIDATION_AUCTION_DURATION;
+        self.liquidation_protocol_fee_bps = DEFAULT_LIQUIDATION_PROTOCOL_FEE_BPS;
+        self.accumulated_protocol_fees = 0;
         self.total_supplied = 0;
         self.total_borrowed = 0;
         self.total_supply_tokens = 0;
@@ -141,10 +252,174 @@ impl Market {
         self.creator = creator;
         self.created_at = clock.unix_timestamp;
         self.bump = bump;
+        self.last_price = 0;
+        self.price_history = [PriceSample::default(); PRICE_HISTORY_LEN];
+        self.price_history_index = 0;
+        self.price_deviation_bps = DEFAULT_PRICE_DEVIATION_BPS;
+        self.price_deviation_window = DEFAULT_PRICE_DEVIATION_WINDOW;
+        self.circuit_breaker_cooldown = DEFAULT_CIRCUIT_BREAKER_COOLDOWN;
+        self.circuit_breaker_until = 0;
+        self.outflow_limit = 0;
+        self.outflow_window = DEFAULT_OUTFLOW_WINDOW;
+        self.outflow_bucket = 0;
+        self.outflow_last_update = clock.unix_timestamp;
+        self.isolated_collateral = false;
+        self.isolation_borrowable = false;
+        self.isolation_debt_ceiling = 0;
+        self.isolation_total_debt = 0;
+        self.insurance_fund = Pubkey::default();
+        self.large_liquidation_threshold = 0;
+        self.large_liquidation_auction_duration = DEFAULT_LARGE_LIQUIDATION_AUCTION_DURATION;
+
+        Ok(())
+    }
+
+    /// Count new debt against this isolated collateral's debt ceiling
+    pub fn add_isolation_debt(&mut self, amount: u64, decimals: u8) -> Result<()> {
+        let debt = normalize_decimals(amount, decimals, ISOLATION_DEBT_DECIMALS)?;
+        let new_total = self.isolation_total_debt
+            .checked_add(debt)
+            .ok_or(crate::errors::LendingError::MathOverflow)?;
+        require!(
+            new_total <= self.isolation_debt_ceiling,
+            crate::errors::LendingError::IsolationDebtCeilingExceeded
+        );
+        self.isolation_total_debt = new_total;
+
+        Ok(())
+    }
+
+    /// Release repaid debt from this isolated collateral's debt ceiling
+    pub fn remove_isolation_debt(&mut self, amount: u64, decimals: u8) -> Result<()> {
+        let debt = normalize_decimals(amount, decimals, ISOLATION_DEBT_DECIMALS)?;
+        self.isolation_total_debt = self.isolation_total_debt.saturating_sub(debt);
+
+        Ok(())
+    }
+
+    /// Drain the outflow bucket for the time elapsed since the last update
+    fn refresh_outflow_bucket(&mut self, clock: &Clock) -> Result<()> {
+        let seconds_elapsed = clock
+            .unix_timestamp
+            .saturating_sub(self.outflow_last_update)
+            .max(0) as u64;
+
+        self.outflow_bucket = calculate_outflow_bucket(
+            self.outflow_bucket,
+            self.outflow_limit,
+            seconds_elapsed,
+            self.outflow_window as u64,
+        )?;
+        self.outflow_last_update = clock.unix_timestamp;
+
+        Ok(())
+    }
+
+    /// Count tokens leaving the reserve against the outflow limit
+    /// Fails if the net outflow in the current window would exceed the limit
+    pub fn record_outflow(&mut self, amount: u64, clock: &Clock) -> Result<()> {
+        self.refresh_outflow_bucket(clock)?;
+
+        let new_bucket = self.outflow_bucket
+            .checked_add(amount)
+            .ok_or(crate::errors::LendingError::MathOverflow)?;
+
+        if self.outflow_limit > 0 {
+            require!(
+                new_bucket <= self.outflow_limit,
+                crate::errors::LendingError::OutflowLimitExceeded
+            );
+        }
+
+        self.outflow_bucket = new_bucket;
 
         Ok(())
     }
 
+    /// Credit tokens entering the reserve against the outflow bucket
+    pub fn record_inflow(&mut self, amount: u64, clock: &Clock) -> Result<()> {
+        self.refresh_outflow_bucket(clock)?;
+        self.outflow_bucket = self.outflow_bucket.saturating_sub(amount);
+
+        Ok(())
+    }
+
+    /// Convert supply tokens to the underlying amount at the current exchange rate
+    pub fn supply_tokens_to_amount(&self, supply_tokens: u64) -> Result<u64> {
+        let exchange_rate = calculate_exchange_rate(self.total_supplied, self.total_supply_tokens)?;
+
+        let amount = (supply_tokens as u128)
+            .checked_mul(exchange_rate)
+            .ok_or(crate::errors::LendingError::MathOverflow)?
+            .checked_div(INTEREST_SCALE)
+            .ok_or(crate::errors::LendingError::MathOverflow)?;
+
+        Ok(amount as u64)
+    }
+
+    /// Convert an underlying amount to supply tokens at the current exchange rate
+    pub fn amount_to_supply_tokens(&self, amount: u64) -> Result<u64> {
+        let exchange_rate = calculate_exchange_rate(self.total_supplied, self.total_supply_tokens)?;
+
+        let supply_tokens = (amount as u128)
+            .checked_mul(INTEREST_SCALE)
+            .ok_or(crate::errors::LendingError::MathOverflow)?
+            .checked_div(exchange_rate)
+            .ok_or(crate::errors::LendingError::MathOverflow)?;
+
+        Ok(supply_tokens as u64)
+    }
+
+    /// Whether the price circuit breaker is currently blocking borrows and withdrawals
+    pub fn is_circuit_breaker_active(&self, now: i64) -> bool {
+        now < self.circuit_breaker_until
+    }
+
+    /// Record an oracle price and trip the circuit breaker if the price moved
+    /// more than `price_deviation_bps` against any sample inside the window.
+    /// Returns true if this observation tripped the breaker.
+    pub fn observe_price(&mut self, price: u64, clock: &Clock) -> Result<bool> {
+        let now = clock.unix_timestamp;
+        let mut tripped = false;
+
+        if self.price_deviation_bps > 0 && !self.is_circuit_breaker_active(now) {
+            let window_start = now.saturating_sub(self.price_deviation_window);
+
+            for sample in self.price_history.iter() {
+                if sample.price == 0 || sample.timestamp < window_start {
+                    continue;
+                }
+                if calculate_price_deviation_bps(sample.price, price)? > self.price_deviation_bps {
+                    tripped = true;
+                    break;
+                }
+            }
+
+            if tripped {
+                self.circuit_breaker_until = now
+                    .checked_add(self.circuit_breaker_cooldown)
+                    .ok_or(crate::errors::LendingError::MathOverflow)?;
+            }
+        }
+
+        // Space samples out so the history always spans the whole window,
+        // no matter how many transactions land in the same second
+        let latest_index = (self.price_history_index as usize + PRICE_HISTORY_LEN - 1) % PRICE_HISTORY_LEN;
+        let min_spacing = self.price_deviation_window / PRICE_HISTORY_LEN as i64;
+        let latest = self.price_history[latest_index];
+        if latest.price == 0 || now.saturating_sub(latest.timestamp) >= min_spacing {
+            self.price_history[self.price_history_index as usize] = PriceSample {
+                price,
+                timestamp: now,
+            };
+            self.price_history_index = ((self.price_history_index as usize + 1) % PRICE_HISTORY_LEN) as u8;
+        }
+
+        self.last_price = price;
+
+        Ok(tripped)
+    }
+
     /// Accrue interest and update reserves
     pub fn accrue_interest(&mut self, clock: &Clock) -> Result<()> {
         if self.total_supplied == 0 && self.total_borrowed == 0 {
@@ -171,42 +446,42 @@ impl Market {
         let supply_rate_per_second = calculate_supply_rate(borrow_rate_per_second, utilization_bps)?;
 
         // Update cumulative rates
-        self.cumulative_borrow_rate = (self.cumulative_borrow_rate as u128)
+        self.cumulative_borrow_rate = self.cumulative_borrow_rate
             .checked_mul(
-                (INTEREST_SCALE + (borrow_rate_per_second as u128)
+                INTEREST_SCALE + (borrow_rate_per_second as u128)
                     .checked_mul(seconds_elapsed as u128)
-                    .ok_or(crate::errors::LendingError::MathOverflow)?
-                    .checked_div(INTEREST_SCALE)
-                    .ok_or(crate::errors::LendingError::MathOverflow)?) as u128,
+                    .ok_or(crate::errors::LendingError::MathOverflow)?,
             )
             .ok_or(crate::errors::LendingError::MathOverflow)?
             .checked_div(INTEREST_SCALE)
             .ok_or(crate::errors::LendingError::MathOverflow)?;
 
-        self.cumulative_supply_rate = (self.cumulative_supply_rate as u128)
+        self.cumulative_supply_rate = self.cumulative_supply_rate
             .checked_mul(
-                (INTEREST_SCALE + (supply_rate_per_second as u128)
+                INTEREST_SCALE + (supply_rate_per_second as u128)
                     .checked_mul(seconds_elapsed as u128)
-                    .ok_or(crate::errors::LendingError::MathOverflow)?
-                    .checked_div(INTEREST_SCALE)
-                    .ok_or(crate::errors::LendingError::MathOverflow)?) as u128,
+                    .ok_or(crate::errors::LendingError::MathOverflow)?,
             )
             .ok_or(crate::errors::LendingError::MathOverflow)?
             .checked_div(INTEREST_SCALE)
             .ok_or(crate::errors::LendingError::MathOverflow)?;
 
-        // Update total borrowed and supplied with accrued interest
-        self.total_borrowed = (self.total_borrowed as u128)
-            .checked_mul(self.cumulative_borrow_rate)
-            .ok_or(crate::errors::LendingError::MathOverflow)?
-            .checked_div(INTEREST_SCALE)
-            .ok_or(crate::errors::LendingError::MathOverflow)? as u64;
+        // Update total borrowed and supplied with this period's interest;
+        // the protocol's share is set aside rather than paid to suppliers
+        let interest = calculate_accrued_interest(self.total_borrowed, borrow_rate_per_second, seconds_elapsed)?
+            .checked_sub(self.total_borrowed)
+            .ok_or(crate::errors::LendingError::MathOverflow)?;
+        let protocol_fee = apply_bps(interest, PROTOCOL_FEE_BPS)?;
 
-        self.total_supplied = (self.total_supplied as u128)
-            .checked_mul(self.cumulative_supply_rate)
-            .ok_or(crate::errors::LendingError::MathOverflow)?
-            .checked_div(INTEREST_SCALE)
-            .ok_or(crate::errors::LendingError::MathOverflow)? as u64;
+        self.total_borrowed = self.total_borrowed
+            .checked_add(interest)
+            .ok_or(crate::errors::LendingError::MathOverflow)?;
+        self.total_supplied = self.total_supplied
+            .checked_add(interest - protocol_fee)
+            .ok_or(crate::errors::LendingError::MathOverflow)?;
+        self.accumulated_protocol_fees = self.accumulated_protocol_fees
+            .checked_add(protocol_fee)
+            .ok_or(crate::errors::LendingError::MathOverflow)?;
 
         self.last_accrual_timestamp = clock.unix_timestamp;
 
@@ -261,54 +536,1210 @@ impl BorrowPosition {
     }
 }
 
+/// User's collateral deposits and borrows across markets
+#[account]
+pub struct Obligation {
+    /// Obligation owner
+    pub owner: Pubkey,
+    /// Supply tokens deposited as collateral
+    pub deposits: Vec<ObligationDeposit>,
+    /// Outstanding borrows
+    pub borrows: Vec<ObligationBorrow>,
+    /// E-mode category the obligation opted into (EMODE_NONE = not in e-mode)
+    pub emode_category: u8,
+    /// Isolated collateral market backing this obligation (default = not isolated)
+    pub isolated_collateral_market: Pubkey,
+    /// When the obligation was first seen liquidatable (0 = healthy)
+    pub liquidatable_since: i64,
+    /// Debt a flash liquidation in this transaction still has to repay
+    pub flash_liquidation_repay: u64,
+    /// Borrow market the pending flash liquidation repays
+    pub flash_liquidation_market: Pubkey,
+    /// Liquidation auction holding some of the collateral (default = none)
+    pub liquidation_auction: Pubkey,
+    /// Keepers may deleverage below this health factor (bps, 0 = disabled)
+    pub auto_deleverage_health_factor_bps: u16,
+    /// Share of the debt repaid per deleverage in basis points
+    pub auto_deleverage_repay_bps: u16,
+    /// Max loss against oracle prices on the deleverage swap in basis points
+    pub auto_deleverage_max_slippage_bps: u16,
+    /// Timestamp when obligation was created
+    pub created_at: i64,
+    /// Last update timestamp
+    pub last_updated: i64,
+    /// Bump seed
+    pub bump: u8,
+}
+
+/// Collateral deposited in one market
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct ObligationDeposit {
+    /// Market the collateral belongs to
+    pub market: Pubkey,
+    /// Supply tokens held in the market's collateral vault
+    pub supply_tokens: u64,
+}
+
+impl ObligationDeposit {
+    pub const SIZE: usize = 32 + // market
+        8;   // supply_tokens
+}
+
+/// Debt owed to one market
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct ObligationBorrow {
+    /// Market the debt is owed to
+    pub market: Pubkey,
+    /// Amount borrowed (principal plus interest up to the snapshot)
+    pub borrowed_amount: u64,
+    /// Market cumulative borrow rate when borrowed_amount was last updated
+    pub cumulative_borrow_rate_snapshot: u128,
+}
+
+impl ObligationBorrow {
+    pub const SIZE: usize = 32 + // market
+        8 +  // borrowed_amount
+        16;  // cumulative_borrow_rate_snapshot
+
+    /// Calculate current debt including accrued interest
+    pub fn calculate_debt(&self, market: &Market) -> Result<u64> {
+        let debt = (self.borrowed_amount as u128)
+            .checked_mul(market.cumulative_borrow_rate)
+            .ok_or(crate::errors::LendingError::MathOverflow)?
+            .checked_div(self.cumulative_borrow_rate_snapshot)
+            .ok_or(crate::errors::LendingError::MathOverflow)?;
+
+        Ok(debt as u64)
+    }
+}
+
+impl Obligation {
+    pub const SIZE: usize = 8 + // discriminator
+        32 + // owner
+        4 + ObligationDeposit::SIZE * MAX_OBLIGATION_DEPOSITS as usize + // deposits
+        4 + ObligationBorrow::SIZE * MAX_BORROW_POSITIONS as usize + // borrows
+        1 +  // emode_category
+        32 + // isolated_collateral_market
+        8 +  // liquidatable_since
+        8 +  // flash_liquidation_repay
+        32 + // flash_liquidation_market
+        32 + // liquidation_auction
+        2 +  // auto_deleverage_health_factor_bps
+        2 +  // auto_deleverage_repay_bps
+        2 +  // auto_deleverage_max_slippage_bps
+        8 +  // created_at
+        8 +  // last_updated
+        1;   // bump
+
+    pub fn initialize(&mut self, owner: Pubkey, bump: u8, clock: &Clock) {
+        self.owner = owner;
+        self.deposits = Vec::new();
+        self.borrows = Vec::new();
+        self.emode_category = EMODE_NONE;
+        self.isolated_collateral_market = Pubkey::default();
+        self.liquidatable_since = 0;
+        self.flash_liquidation_repay = 0;
+        self.flash_liquidation_market = Pubkey::default();
+        self.liquidation_auction = Pubkey::default();
+        self.auto_deleverage_health_factor_bps = 0;
+        self.auto_deleverage_repay_bps = 0;
+        self.auto_deleverage_max_slippage_bps = 0;
+        self.created_at = clock.unix_timestamp;
+        self.last_updated = clock.unix_timestamp;
+        self.bump = bump;
+    }
+
+    /// Start or reset the liquidation auction clock from a fresh health factor
+    pub fn update_liquidatable_since(&mut self, health_factor: u16, clock: &Clock) {
+        if health_factor >= MIN_HEALTH_FACTOR_BPS {
+            self.liquidatable_since = 0;
+        } else if self.liquidatable_since == 0 {
+            self.liquidatable_since = clock.unix_timestamp;
+        }
+    }
+
+    /// Fail while a flash liquidation or liquidation auction is in progress
+    pub fn require_not_liquidating(&self) -> Result<()> {
+        require!(
+            self.flash_liquidation_repay == 0,
+            crate::errors::LendingError::FlashLiquidationPending
+        );
+        require!(
+            self.liquidation_auction == Pubkey::default(),
+            crate::errors::LendingError::LiquidationAuctionActive
+        );
+
+        Ok(())
+    }
+
+    /// Whether the obligation is backed by isolated collateral
+    pub fn is_isolated(&self) -> bool {
+        self.isolated_collateral_market != Pubkey::default()
+    }
+
+    /// Supply tokens deposited in a market (0 if none)
+    pub fn deposited_supply_tokens(&self, market: &Pubkey) -> u64 {
+        self.deposits
+            .iter()
+            .find(|deposit| deposit.market == *market)
+            .map_or(0, |deposit| deposit.supply_tokens)
+    }
+
+    /// Find the borrow owed to a market
+    pub fn find_borrow(&self, market: &Pubkey) -> Option<&ObligationBorrow> {
+        self.borrows.iter().find(|borrow| borrow.market == *market)
+    }
+
+    /// Add supply tokens to the collateral deposited in a market
+    /// Isolated collateral must be the obligation's only collateral
+    pub fn deposit(&mut self, market: Pubkey, isolated: bool, supply_tokens: u64) -> Result<()> {
+        if self.is_isolated() {
+            require!(
+                self.isolated_collateral_market == market,
+                crate::errors::LendingError::IsolatedCollateralConflict
+            );
+        } else if isolated {
+            require!(
+                self.deposits.is_empty(),
+                crate::errors::LendingError::IsolatedCollateralConflict
+            );
+            self.isolated_collateral_market = market;
+        }
+
+        if let Some(deposit) = self.deposits.iter_mut().find(|deposit| deposit.market == market) {
+            deposit.supply_tokens = deposit.supply_tokens
+                .checked_add(supply_tokens)
+                .ok_or(crate::errors::LendingError::MathOverflow)?;
+            return Ok(());
+        }
+
+        require!(
+            self.deposits.len() < MAX_OBLIGATION_DEPOSITS as usize,
+            crate::errors::LendingError::ObligationPositionLimit
+        );
+        self.deposits.push(ObligationDeposit {
+            market,
+            supply_tokens,
+        });
+
+        Ok(())
+    }
+
+    /// Remove supply tokens from the collateral deposited in a market
+    pub fn withdraw(&mut self, market: Pubkey, supply_tokens: u64) -> Result<()> {
+        let index = self.deposits
+            .iter()
+            .position(|deposit| deposit.market == market)
+            .ok_or(crate::errors::LendingError::ObligationPositionNotFound)?;
+
+        let deposit = &mut self.deposits[index];
+        deposit.supply_tokens = deposit.supply_tokens
+            .checked_sub(supply_tokens)
+            .ok_or(crate::errors::LendingError::InsufficientCollateral)?;
+
+        if deposit.supply_tokens == 0 {
+            self.deposits.remove(index);
+            self.release_isolation();
+        }
+
+        Ok(())
+    }
+
+    /// Add debt owed to a market, compounding any existing debt to the current rate
+    pub fn borrow(&mut self, market_key: Pubkey, market: &Market, amount: u64) -> Result<()> {
+        if let Some(borrow) = self.borrows.iter_mut().find(|borrow| borrow.market == market_key) {
+            borrow.borrowed_amount = borrow
+                .calculate_debt(market)?
+                .checked_add(amount)
+                .ok_or(crate::errors::LendingError::MathOverflow)?;
+            borrow.cumulative_borrow_rate_snapshot = market.cumulative_borrow_rate;
+            return Ok(());
+        }
+
+        require!(
+            self.borrows.len() < MAX_BORROW_POSITIONS as usize,
+            crate::errors::LendingError::ObligationPositionLimit
+        );
+        self.borrows.push(ObligationBorrow {
+            market: market_key,
+            borrowed_amount: amount,
+            cumulative_borrow_rate_snapshot: market.cumulative_borrow_rate,
+        });
+
+        Ok(())
+    }
+
+    /// Reduce debt owed to a market
+    /// Returns the amount actually repaid (capped at the outstanding debt)
+    pub fn repay(&mut self, market_key: Pubkey, market: &Market, amount: u64) -> Result<u64> {
+        let index = self.borrows
+            .iter()
+            .position(|borrow| borrow.market == market_key)
+            .ok_or(crate::errors::LendingError::BorrowPositionNotFound)?;
+
+        let borrow = &mut self.borrows[index];
+        let debt = borrow.calculate_debt(market)?;
+        let repaid = amount.min(debt);
+
+        borrow.borrowed_amount = debt - repaid;
+        borrow.cumulative_borrow_rate_snapshot = market.cumulative_borrow_rate;
+
+        if borrow.borrowed_amount == 0 {
+            self.borrows.remove(index);
+            self.release_isolation();
+        }
+
+        Ok(repaid)
+    }
+
+    /// Leave isolated mode once both the isolated collateral and all debt are gone
+    ///
+    /// Debt taken against isolated collateral keeps the obligation isolated (and
+    /// counted against the debt ceiling) even after a liquidation seizes the
+    /// last of that collateral.
+    fn release_isolation(&mut self) {
+        if self.borrows.is_empty()
+            && self.deposited_supply_tokens(&self.isolated_collateral_market) == 0
+        {
+            self.isolated_collateral_market = Pubkey::default();
+        }
+    }
+}
+
+/// Efficiency mode category for correlated assets
+/// 
+/// Obligations opted into a category use its LTV and liquidation threshold for
+/// collateral in member markets, and can only borrow from member markets.
+#[account]
+pub struct EModeCategory {
+    /// Category identifier (never EMODE_NONE)
+    pub id: u8,
+    /// Loan-to-value ratio in basis points for member collateral
+    pub ltv_bps: u16,
+    /// Liquidation threshold in basis points for member collateral
+    pub liquidation_threshold_bps: u16,
+    /// Liquidation bonus in basis points when seizing member collateral
+    pub liquidation_bonus_bps: u16,
+    /// Member markets
+    pub markets: Vec<Pubkey>,
+    /// Bump seed
+    pub bump: u8,
+}
+
+impl EModeCategory {
+    pub const SIZE: usize = 8 + // discriminator
+        1 +  // id
+        2 +  // ltv_bps
+        2 +  // liquidation_threshold_bps
+        2 +  // liquidation_bonus_bps
+        4 + 32 * MAX_EMODE_MARKETS as usize + // markets
+        1;   // bump
+
+    /// Validate and set the category's risk parameters
+    pub fn set_params(
+        &mut self,
+        ltv_bps: u16,
+        liquidation_threshold_bps: u16,
+        liquidation_bonus_bps: u16,
+    ) -> Result<()> {
+        require!(
+            liquidation_threshold_bps > ltv_bps,
+            crate::errors::LendingError::LiquidationThresholdTooLow
+        );
+        require!(
+            ltv_bps <= MAX_EMODE_LTV_BPS,
+            crate::errors::LendingError::InvalidLtvRatio
+        );
+        // Seizing threshold-weighted collateral plus the bonus must not exceed the collateral
+        let max_seized = calculate_liquidation_bonus(liquidation_threshold_bps as u64, liquidation_bonus_bps)?
+            .checked_add(liquidation_threshold_bps as u64)
+            .ok_or(crate::errors::LendingError::MathOverflow)?;
+        require!(
+            max_seized <= BPS_SCALE as u64,
+            crate::errors::LendingError::InvalidLiquidationThreshold
+        );
+
+        self.ltv_bps = ltv_bps;
+        self.liquidation_threshold_bps = liquidation_threshold_bps;
+        self.liquidation_bonus_bps = liquidation_bonus_bps;
+
+        Ok(())
+    }
+
+    pub fn contains(&self, market: &Pubkey) -> bool {
+        self.markets.contains(market)
+    }
+}
+
+/// Insurance fund (safety module) for one asset
+/// 
+/// Stakers deposit the asset for share tokens and earn a cut of protocol fees.
+/// In return the fund can be slashed to cover bad debt and other shortfalls.
+#[account]
+pub struct InsuranceFund {
+    /// Insured asset mint
+    pub asset_mint: Pubkey,
+    /// Share token mint (mint authority is this account)
+    pub share_mint: Pubkey,
+    /// Token account holding staked assets (owned by this account)
+    pub vault: Pubkey,
+    /// Seconds stakers must wait between requesting and completing an unstake
+    pub cooldown_period: i64,
+    /// Maximum share of the fund slashed per slash period in basis points
+    pub max_slash_bps: u16,
+    /// Share of collected protocol fees paid to stakers in basis points
+    pub fee_share_bps: u16,
+    /// Total amount slashed (lifetime)
+    pub total_slashed: u64,
+    /// Start of the current slash period
+    pub slash_period_start: i64,
+    /// Vault balance when the current slash period started
+    pub slash_period_balance: u64,
+    /// Amount slashed in the current slash period
+    pub slashed_this_period: u64,
+    /// Timestamp when fund was created
+    pub created_at: i64,
+    /// Bump seed
+    pub bump: u8,
+}
+
+impl InsuranceFund {
+    pub const SIZE: usize = 8 + // discriminator
+        32 + // asset_mint
+        32 + // share_mint
+        32 + // vault
+        8 +  // cooldown_period
+        2 +  // max_slash_bps
+        2 +  // fee_share_bps
+        8 +  // total_slashed
+        8 +  // slash_period_start
+        8 +  // slash_period_balance
+        8 +  // slashed_this_period
+        8 +  // created_at
+        1;   // bump
+
+    pub fn set_params(&mut self, cooldown_period: i64, max_slash_bps: u16, fee_share_bps: u16) -> Result<()> {
+        require!(cooldown_period >= 0, crate::errors::LendingError::InvalidInsuranceFund);
+        require!(
+            max_slash_bps <= MAX_INSURANCE_SLASH_BPS,
+            crate::errors::LendingError::InvalidInsuranceFund
+        );
+        require!(fee_share_bps <= BPS_SCALE, crate::errors::LendingError::InvalidInsuranceFund);
+
+        self.cooldown_period = cooldown_period;
+        self.max_slash_bps = max_slash_bps;
+        self.fee_share_bps = fee_share_bps;
+
+        Ok(())
+    }
+
+    /// Shares minted for a deposit (rounds down)
+    ///
+    /// One virtual share and one virtual asset keep a donation to the vault
+    /// from inflating the share price against the next staker.
+    pub fn shares_for_amount(&self, amount: u64, vault_amount: u64, share_supply: u64) -> Result<u64> {
+        mul_div(amount, share_supply as u128 + 1, vault_amount as u128 + 1, false)
+    }
+
+    /// Assets returned for burning shares (rounds down)
+    pub fn amount_for_shares(&self, shares: u64, vault_amount: u64, share_supply: u64) -> Result<u64> {
+        mul_div(shares, vault_amount as u128 + 1, share_supply as u128 + 1, false)
+    }
+
+    /// Amount that can still be slashed in the current slash period
+    ///
+    /// The cap is `max_slash_bps` of the vault balance when the period started,
+    /// so repeated slashes can't drain the fund within one period.
+    pub fn available_slash(&mut self, vault_amount: u64, clock: &Clock) -> Result<u64> {
+        let period_end = self.slash_period_start
+            .checked_add(INSURANCE_SLASH_PERIOD)
+            .ok_or(crate::errors::LendingError::MathOverflow)?;
+        if clock.unix_timestamp >= period_end {
+            self.slash_period_start = clock.unix_timestamp;
+            self.slash_period_balance = vault_amount;
+            self.slashed_this_period = 0;
+        }
+
+        Ok(apply_bps(self.slash_period_balance, self.max_slash_bps)?
+            .saturating_sub(self.slashed_this_period))
+    }
+
+    /// Count a slash against the current period and the lifetime total
+    pub fn record_slash(&mut self, amount: u64) -> Result<()> {
+        self.slashed_this_period = self.slashed_this_period
+            .checked_add(amount)
+            .ok_or(crate::errors::LendingError::MathOverflow)?;
+        self.total_slashed = self.total_slashed
+            .checked_add(amount)
+            .ok_or(crate::errors::LendingError::MathOverflow)?;
+        Ok(())
+    }
+}
+
+/// Pending unstake request for an insurance fund staker
+#[account]
+pub struct StakeCooldown {
+    /// Staker wallet
+    pub owner: Pubkey,
+    /// Insurance fund
+    pub insurance_fund: Pubkey,
+    /// Shares that can be unstaked once the cooldown ends
+    pub shares: u64,
+    /// Timestamp when the shares can be unstaked (until `INSURANCE_UNSTAKE_WINDOW` later)
+    pub cooldown_end: i64,
+    /// Bump seed
+    pub bump: u8,
+}
+
+impl StakeCooldown {
+    pub const SIZE: usize = 8 + // discriminator
+        32 + // owner
+        32 + // insurance_fund
+        8 +  // shares
+        8 +  // cooldown_end
+        1;   // bump
+
+    /// Check the shares can be unstaked now
+    ///
+    /// Unstaking opens when the cooldown ends and closes `INSURANCE_UNSTAKE_WINDOW`
+    /// later, so a finished cooldown can't be held open to exit just ahead of a slash.
+    pub fn require_unstake_window(&self, now: i64) -> Result<()> {
+        require!(
+            now >= self.cooldown_end,
+            crate::errors::LendingError::InsuranceCooldownActive
+        );
+        let window_end = self.cooldown_end
+            .checked_add(INSURANCE_UNSTAKE_WINDOW)
+            .ok_or(crate::errors::LendingError::MathOverflow)?;
+        require!(
+            now <= window_end,
+            crate::errors::LendingError::InsuranceUnstakeWindowExpired
+        );
+        Ok(())
+    }
+}
+
+/// Auction of a large liquidation's collateral
+/// 
+/// The debt to repay is fixed when the auction starts; bidders compete on how
+/// few collateral supply tokens they accept for repaying it. Only the best bid
+/// is escrowed, so an outbid bidder is refunded straight away.
+#[account]
+pub struct LiquidationAuction {
+    /// Obligation being liquidated
+    pub obligation: Pubkey,
+    /// Market the debt is repaid to
+    pub borrow_market: Pubkey,
+    /// Market the collateral comes from
+    pub collateral_market: Pubkey,
+    /// Account that started the auction (receives the rent back)
+    pub creator: Pubkey,
+    /// Debt repaid by the winning bid
+    pub repay_amount: u64,
+    /// Collateral supply tokens taken from the obligation (most a bid can ask for)
+    pub supply_tokens: u64,
+    /// Liquidation bonus that `supply_tokens` includes
+    pub liquidation_bonus_bps: u16,
+    /// Obligation's health factor when the auction started
+    pub start_health_factor: u16,
+    /// Current best bidder (default = no bids)
+    pub best_bidder: Pubkey,
+    /// Supply tokens the best bidder accepts
+    pub best_bid: u64,
+    /// Timestamp when the auction started
+    pub start_time: i64,
+    /// Timestamp after which the auction can be settled
+    pub end_time: i64,
+    /// Bump seed
+    pub bump: u8,
+    /// Bump seed of the bid escrow token account
+    pub vault_bump: u8,
+}
+
+impl LiquidationAuction {
+    pub const SIZE: usize = 8 + // discriminator
+        32 + // obligation
+        32 + // borrow_market
+        32 + // collateral_market
+        32 + // creator
+        8 +  // repay_amount
+        8 +  // supply_tokens
+        2 +  // liquidation_bonus_bps
+        2 +  // start_health_factor
+        32 + // best_bidder
+        8 +  // best_bid
+        8 +  // start_time
+        8 +  // end_time
+        1 +  // bump
+        1;   // vault_bump
+
+    /// Whether anyone has bid yet
+    pub fn has_bid(&self) -> bool {
+        self.best_bidder != Pubkey::default()
+    }
+}
+
 /// Vault account for automated yield strategies
+///
+/// MetaMorpho-style roles: the owner appoints the others, the curator sets
+/// allowed markets and caps, allocators move funds within those caps and the
+/// guardian can veto pending changes. The owner holds every role except
+/// guardian once one is appointed.
 #[account]
 pub struct Vault {
-    /// Vault owner/manager
+    /// Vault owner, appoints the other roles
     pub owner: Pubkey,
+    /// Distinguishes the owner's vaults (part of the PDA seeds)
+    pub index: u16,
+    /// Sets allowed markets and caps
+    pub curator: Pubkey,
+    /// Can veto pending changes (default = none)
+    pub guardian: Pubkey,
+    /// Accounts allowed to rebalance (up to MAX_VAULT_ALLOCATORS)
+    pub allocators: Vec<Pubkey>,
+    /// Delay (seconds) before a cap increase or market addition can be accepted
+    pub timelock: i64,
     /// Strategy type
     pub strategy: u8, // VaultStrategy enum as u8
+    /// Mint of the asset the vault accepts
+    pub asset_mint: Pubkey,
+    /// Vault-owned token account holding idle assets
+    pub asset_vault: Pubkey,
+    /// Share token mint (mint authority is the vault PDA)
+    pub share_mint: Pubkey,
     /// Total assets under management
     pub total_assets: u64,
-    /// Market allocations (market pubkey -> allocation percentage in basis points)
-    /// Stored as serialized Vec<(Pubkey, u16)>
-    pub allocations: Vec<u8>, // Serialized allocations
+    /// Market allocations (up to MAX_VAULT_ALLOCATIONS)
+    pub allocations: Vec<Allocation>,
     /// Last rebalance timestamp
     pub last_rebalance: i64,
     /// Rebalance threshold (basis points) - triggers rebalance when drift exceeds
     pub rebalance_threshold_bps: u16,
+    /// Annual management fee on total assets (basis points), accrued per second
+    pub management_fee_bps: u16,
+    /// Fee on share price gains above the high-water mark (basis points)
+    pub performance_fee_bps: u16,
+    /// Share token account receiving fee shares
+    pub fee_recipient: Pubkey,
+    /// Highest share price fees have been charged at (SHARE_PRICE_SCALE)
+    pub high_water_mark: u128,
+    /// Last time fees were accrued
+    pub last_fee_accrual: i64,
+    /// Vault-owned share account holding shares locked in withdrawal requests
+    pub withdrawal_escrow: Pubkey,
+    /// Id of the next withdrawal request to be created
+    pub next_withdrawal_request: u64,
+    /// Id of the oldest unfilled withdrawal request (head of the queue)
+    pub next_withdrawal_to_fill: u64,
+    /// Shares locked in unfilled withdrawal requests
+    pub queued_shares: u64,
     /// Created timestamp
     pub created_at: i64,
     /// Bump seed
     pub bump: u8,
 }
 
+/// A vault's allocation to one market
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct Allocation {
+    /// Market the vault supplies to
+    pub market: Pubkey,
+    /// Target share of the vault's assets in basis points
+    pub target_bps: u16,
+    /// Maximum assets the vault may supply to the market
+    pub cap: u64,
+    /// Market supply tokens held by the vault
+    pub current_supply_tokens: u64,
+}
+
+impl Allocation {
+    pub const SIZE: usize = 32 + // market
+        2 +  // target_bps
+        8 +  // cap
+        8;   // current_supply_tokens
+}
+
 impl Vault {
     pub const BASE_SIZE: usize = 8 + // discriminator
         32 + // owner
+        2 +  // index
+        32 + // curator
+        32 + // guardian
+        4 + 32 * MAX_VAULT_ALLOCATORS as usize + // allocators
+        8 +  // timelock
         1 +  // strategy
+        32 + // asset_mint
+        32 + // asset_vault
+        32 + // share_mint
         8 +  // total_assets
-        4 +  // allocations vec length
+        4 +  // allocations vec length (entries sized by Vault::space)
         8 +  // last_rebalance
         2 +  // rebalance_threshold_bps
+        2 +  // management_fee_bps
+        2 +  // performance_fee_bps
+        32 + // fee_recipient
+        16 + // high_water_mark
+        8 +  // last_fee_accrual
+        32 + // withdrawal_escrow
+        8 +  // next_withdrawal_request
+        8 +  // next_withdrawal_to_fill
+        8 +  // queued_shares
         8 +  // created_at
         1;   // bump
 
+    #[allow(clippy::too_many_arguments)]
     pub fn initialize(
         &mut self,
         owner: Pubkey,
+        index: u16,
+        timelock: i64,
         strategy: u8,
+        asset_mint: Pubkey,
+        asset_vault: Pubkey,
+        share_mint: Pubkey,
         rebalance_threshold_bps: u16,
+        management_fee_bps: u16,
+        performance_fee_bps: u16,
+        fee_recipient: Pubkey,
+        withdrawal_escrow: Pubkey,
         bump: u8,
         clock: &Clock,
     ) {
         self.owner = owner;
+        self.index = index;
+        self.curator = owner;
+        self.guardian = Pubkey::default();
+        self.allocators = Vec::new();
+        self.timelock = timelock;
         self.strategy = strategy;
+        self.asset_mint = asset_mint;
+        self.asset_vault = asset_vault;
+        self.share_mint = share_mint;
         self.total_assets = 0;
         self.allocations = Vec::new();
         self.last_rebalance = clock.unix_timestamp;
         self.rebalance_threshold_bps = rebalance_threshold_bps;
+        self.management_fee_bps = management_fee_bps;
+        self.performance_fee_bps = performance_fee_bps;
+        self.fee_recipient = fee_recipient;
+        self.high_water_mark = SHARE_PRICE_SCALE;
+        self.last_fee_accrual = clock.unix_timestamp;
+        self.withdrawal_escrow = withdrawal_escrow;
+        self.next_withdrawal_request = 0;
+        self.next_withdrawal_to_fill = 0;
+        self.queued_shares = 0;
         self.created_at = clock.unix_timestamp;
         self.bump = bump;
     }
+
+    /// Whether `key` may set allowed markets and caps
+    pub fn is_curator(&self, key: &Pubkey) -> bool {
+        *key == self.owner || *key == self.curator
+    }
+
+    /// Whether `key` may move funds between markets
+    pub fn is_allocator(&self, key: &Pubkey) -> bool {
+        self.is_curator(key) || self.allocators.contains(key)
+    }
+
+    /// Whether `key` may veto pending changes (the owner only while there is no guardian)
+    pub fn is_guardian(&self, key: &Pubkey) -> bool {
+        if self.guardian == Pubkey::default() {
+            *key == self.owner
+        } else {
+            *key == self.guardian
+        }
+    }
+
+    /// Account size holding `allocations` entries
+    pub fn space(allocations: usize) -> usize {
+        Self::BASE_SIZE + allocations * Allocation::SIZE
+    }
+
+    /// Find the allocation to a market
+    pub fn find_allocation(&self, market: &Pubkey) -> Option<&Allocation> {
+        self.allocations.iter().find(|allocation| allocation.market == *market)
+    }
+
+    /// Shares worth `assets` (ERC-4626 `convertToShares`)
+    ///
+    /// One virtual share and one virtual asset keep the first depositor from
+    /// inflating the share price against later depositors.
+    pub fn convert_to_shares(&self, assets: u64, share_supply: u64, round_up: bool) -> Result<u64> {
+        mul_div(
+            assets,
+            share_supply as u128 + 1,
+            self.total_assets as u128 + 1,
+            round_up,
+        )
+    }
+
+    /// Assets worth `shares` (ERC-4626 `convertToAssets`)
+    pub fn convert_to_assets(&self, shares: u64, share_supply: u64, round_up: bool) -> Result<u64> {
+        mul_div(
+            shares,
+            self.total_assets as u128 + 1,
+            share_supply as u128 + 1,
+            round_up,
+        )
+    }
+
+    /// Shares minted for depositing `assets` (rounds down)
+    pub fn preview_deposit(&self, assets: u64, share_supply: u64) -> Result<u64> {
+        self.convert_to_shares(assets, share_supply, false)
+    }
+
+    /// Assets needed to mint `shares` (rounds up)
+    pub fn preview_mint(&self, shares: u64, share_supply: u64) -> Result<u64> {
+        self.convert_to_assets(shares, share_supply, true)
+    }
+
+    /// Shares burned to withdraw `assets` (rounds up)
+    pub fn preview_withdraw(&self, assets: u64, share_supply: u64) -> Result<u64> {
+        self.convert_to_shares(assets, share_supply, true)
+    }
+
+    /// Assets returned for redeeming `shares` (rounds down)
+    pub fn preview_redeem(&self, shares: u64, share_supply: u64) -> Result<u64> {
+        self.convert_to_assets(shares, share_supply, false)
+    }
+
+    /// Assets per share (SHARE_PRICE_SCALE), with the same virtual share and asset
+    pub fn share_price(&self, share_supply: u64) -> u128 {
+        (self.total_assets as u128 + 1) * SHARE_PRICE_SCALE / (share_supply as u128 + 1)
+    }
+
+    /// Accrue management and performance fees since the last accrual
+    ///
+    /// The management fee accrues per second on total assets. The performance
+    /// fee is charged only on the share price's gain above the high-water mark,
+    /// which then moves up to the price after fees. Fees are paid in new shares
+    /// worth the fee at the post-fee price; the caller mints them to the fee
+    /// recipient.
+    pub fn accrue_fees(&mut self, share_supply: u64, clock: &Clock) -> Result<VaultFees> {
+        let elapsed = clock.unix_timestamp.saturating_sub(self.last_fee_accrual).max(0) as u128;
+        self.last_fee_accrual = clock.unix_timestamp;
+
+        if share_supply == 0 || self.total_assets == 0 {
+            return Ok(VaultFees::default());
+        }
+
+        let management_fee = (self.total_assets as u128 * self.management_fee_bps as u128 * elapsed
+            / (BPS_SCALE as u128 * SECONDS_PER_YEAR as u128)) as u64;
+
+        let price = self.share_price(share_supply);
+        let performance_fee = if price > self.high_water_mark {
+            let gain = (price - self.high_water_mark) * (share_supply as u128 + 1) / SHARE_PRICE_SCALE;
+            (gain * self.performance_fee_bps as u128 / BPS_SCALE as u128) as u64
+        } else {
+            0
+        };
+
+        let fee_assets = management_fee
+            .checked_add(performance_fee)
+            .ok_or(crate::errors::LendingError::MathOverflow)?
+            .min(self.total_assets);
+        if fee_assets == 0 {
+            return Ok(VaultFees::default());
+        }
+
+        // Shares whose value after minting equals the fee
+        let shares = mul_div(
+            fee_assets,
+            share_supply as u128 + 1,
+            (self.total_assets - fee_assets) as u128 + 1,
+            false,
+        )?;
+
+        let price_after_fees = self.share_price(
+            share_supply
+                .checked_add(shares)
+                .ok_or(crate::errors::LendingError::MathOverflow)?,
+        );
+        self.high_water_mark = self.high_water_mark.max(price_after_fees);
+
+        Ok(VaultFees {
+            management_fee,
+            performance_fee,
+            shares,
+        })
+    }
+}
+
+/// Fees accrued by a vault, paid in shares
+#[derive(Clone, Copy, Default)]
+pub struct VaultFees {
+    /// Management fee in assets
+    pub management_fee: u64,
+    /// Performance fee in assets
+    pub performance_fee: u64,
+    /// Shares to mint to the fee recipient
+    pub shares: u64,
+}
+
+/// Cap increase or market addition waiting out a vault's timelock
+///
+/// Lowering a cap or removing a market applies immediately through
+/// `set_allocations`; raising a cap or adding a market goes through this record
+/// so depositors can exit, or the guardian can veto, before it takes effect.
+#[account]
+pub struct PendingVaultCap {
+    /// Vault the change applies to
+    pub vault: Pubkey,
+    /// Market whose cap is raised (or which is added)
+    pub market: Pubkey,
+    /// New cap
+    pub cap: u64,
+    /// Timestamp from which the change can be accepted
+    pub valid_at: i64,
+    /// Account that submitted the change (receives the rent back)
+    pub submitter: Pubkey,
+    /// Bump seed
+    pub bump: u8,
+}
+
+impl PendingVaultCap {
+    pub const SIZE: usize = 8 + // discriminator
+        32 + // vault
+        32 + // market
+        8 +  // cap
+        8 +  // valid_at
+        32 + // submitter
+        1;   // bump
+}
+
+/// Pending replacement of a vault's guardian
+///
+/// Created by `submit_vault_guardian` and applied by `accept_vault_guardian`
+/// once the vault's timelock has passed, unless the guardian revokes it first.
+#[account]
+pub struct PendingVaultGuardian {
+    /// Vault the change applies to
+    pub vault: Pubkey,
+    /// Proposed guardian (default = none)
+    pub guardian: Pubkey,
+    /// Timestamp from which the change can be accepted
+    pub valid_at: i64,
+    /// Bump seed
+    pub bump: u8,
+}
+
+impl PendingVaultGuardian {
+    pub const SIZE: usize = 8 + // discriminator
+        32 + // vault
+        32 + // guardian
+        8 +  // valid_at
+        1;   // bump
+}
+
+/// Queued vault withdrawal
+///
+/// The shares are locked in the vault's withdrawal escrow until the request is
+/// filled, in order, at the share price at that time.
+#[account]
+pub struct WithdrawalRequest {
+    /// Vault the shares belong to
+    pub vault: Pubkey,
+    /// Account that queued the withdrawal (receives the rent back)
+    pub owner: Pubkey,
+    /// Asset account receiving the withdrawal
+    pub receiver: Pubkey,
+    /// Position in the vault's queue
+    pub id: u64,
+    /// Locked shares not yet filled
+    pub shares: u64,
+    /// Created timestamp
+    pub created_at: i64,
+    /// Bump seed
+    pub bump: u8,
+}
+
+impl WithdrawalRequest {
+    pub const SIZE: usize = 8 + // discriminator
+        32 + // vault
+        32 + // owner
+        32 + // receiver
+        8 +  // id
+        8 +  // shares
+        8 +  // created_at
+        1;   // bump
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn market(total_supplied: u64, total_borrowed: u64) -> Market {
+        let mut market = Market::deserialize(&mut &[0u8; Market::SIZE - 8][..]).unwrap();
+        market.total_supplied = total_supplied;
+        market.total_borrowed = total_borrowed;
+        market.cumulative_borrow_rate = INTEREST_SCALE;
+        market.cumulative_supply_rate = INTEREST_SCALE;
+        market
+    }
+
+    fn clock(unix_timestamp: i64) -> Clock {
+        Clock {
+            unix_timestamp,
+            ..Clock::default()
+        }
+    }
+
+    #[test]
+    fn accrual_sets_aside_the_protocol_share_of_interest() {
+        let mut market = market(1_000_000_000, 500_000_000);
+        market.accrue_interest(&clock(SECONDS_PER_YEAR as i64)).unwrap();
+
+        let interest = market.total_borrowed - 500_000_000;
+        assert!(interest > 0);
+        assert_eq!(market.accumulated_protocol_fees, apply_bps(interest, PROTOCOL_FEE_BPS).unwrap());
+        assert_eq!(
+            market.total_supplied - 1_000_000_000,
+            interest - market.accumulated_protocol_fees
+        );
+    }
+
+    #[test]
+    fn accrual_adds_to_previously_accumulated_fees() {
+        let mut market = market(1_000_000_000, 800_000_000);
+        market.accrue_interest(&clock(3_600)).unwrap();
+        let first_fees = market.accumulated_protocol_fees;
+        market.accrue_interest(&clock(7_200)).unwrap();
+
+        assert!(first_fees > 0);
+        assert!(market.accumulated_protocol_fees > first_fees);
+    }
+
+    #[test]
+    fn accrual_without_borrows_charges_no_fees() {
+        let mut market = market(1_000_000_000, 0);
+        market.accrue_interest(&clock(SECONDS_PER_YEAR as i64)).unwrap();
+
+        assert_eq!(market.total_supplied, 1_000_000_000);
+        assert_eq!(market.accumulated_protocol_fees, 0);
+    }
+
+    #[test]
+    fn accrual_twice_in_the_same_second_is_a_no_op() {
+        let mut market = market(1_000_000_000, 500_000_000);
+        market.accrue_interest(&clock(3_600)).unwrap();
+        let (supplied, borrowed, fees) = (market.total_supplied, market.total_borrowed, market.accumulated_protocol_fees);
+        market.accrue_interest(&clock(3_600)).unwrap();
+
+        assert_eq!(market.total_supplied, supplied);
+        assert_eq!(market.total_borrowed, borrowed);
+        assert_eq!(market.accumulated_protocol_fees, fees);
+    }
+
+    fn circuit_breaker_market() -> Market {
+        let mut market = market(0, 0);
+        market.price_deviation_bps = 2000;
+        market.price_deviation_window = 600;
+        market.circuit_breaker_cooldown = 3_600;
+        market
+    }
+
+    #[test]
+    fn price_move_beyond_the_deviation_trips_the_breaker() {
+        let mut market = circuit_breaker_market();
+        assert!(!market.observe_price(1_000_000, &clock(1_000)).unwrap());
+        assert!(market.observe_price(1_300_000, &clock(1_100)).unwrap());
+
+        assert_eq!(market.circuit_breaker_until, 1_100 + 3_600);
+        assert!(market.is_circuit_breaker_active(1_100 + 3_599));
+        assert_eq!(market.last_price, 1_300_000);
+    }
+
+    #[test]
+    fn price_move_within_the_deviation_or_outside_the_window_does_not_trip() {
+        let mut market = circuit_breaker_market();
+        market.observe_price(1_000_000, &clock(1_000)).unwrap();
+        assert!(!market.observe_price(1_150_000, &clock(1_100)).unwrap());
+
+        // Both samples have aged out of the 10 minute window
+        assert!(!market.observe_price(1_500_000, &clock(1_100 + 601)).unwrap());
+        assert!(!market.is_circuit_breaker_active(1_100 + 601));
+    }
+
+    #[test]
+    fn breaker_expires_after_the_cooldown_without_being_extended() {
+        let mut market = circuit_breaker_market();
+        market.observe_price(1_000_000, &clock(1_000)).unwrap();
+        market.observe_price(1_300_000, &clock(1_100)).unwrap();
+
+        // Further moves while tripped don't push the end back
+        assert!(!market.observe_price(500_000, &clock(2_000)).unwrap());
+        assert_eq!(market.circuit_breaker_until, 1_100 + 3_600);
+        assert!(!market.is_circuit_breaker_active(1_100 + 3_600));
+    }
+
+    #[test]
+    fn price_samples_are_spaced_across_the_window() {
+        // 600 second window over 8 samples: at most one every 75 seconds
+        let mut market = circuit_breaker_market();
+        market.observe_price(1_000_000, &clock(1_000)).unwrap();
+        market.observe_price(1_010_000, &clock(1_074)).unwrap();
+        assert_eq!(market.price_history_index, 1);
+        assert_eq!(market.last_price, 1_010_000);
+
+        market.observe_price(1_020_000, &clock(1_075)).unwrap();
+        assert_eq!(market.price_history_index, 2);
+        assert_eq!(market.price_history[1].price, 1_020_000);
+        assert_eq!(market.price_history[1].timestamp, 1_075);
+    }
+
+    fn insurance_fund(max_slash_bps: u16) -> InsuranceFund {
+        let mut fund = InsuranceFund::deserialize(&mut &[0u8; InsuranceFund::SIZE - 8][..]).unwrap();
+        fund.max_slash_bps = max_slash_bps;
+        fund
+    }
+
+    #[test]
+    fn repeated_slashes_are_capped_per_period() {
+        let mut fund = insurance_fund(3_000);
+        let now = 1_700_000_000;
+
+        // 30% of the 1000 held when the period starts, however it is split up
+        assert_eq!(fund.available_slash(1_000, &clock(now)).unwrap(), 300);
+        fund.record_slash(200).unwrap();
+        assert_eq!(fund.available_slash(800, &clock(now + 60)).unwrap(), 100);
+        fund.record_slash(100).unwrap();
+        assert_eq!(fund.available_slash(700, &clock(now + 120)).unwrap(), 0);
+
+        // A new period caps against the balance at its start
+        assert_eq!(fund.available_slash(700, &clock(now + INSURANCE_SLASH_PERIOD)).unwrap(), 210);
+        assert_eq!(fund.total_slashed, 300);
+    }
+
+    #[test]
+    fn donating_to_the_insurance_vault_costs_the_attacker_more_than_it_takes() {
+        let fund = insurance_fund(3_000);
+
+        // An attacker stakes 1 unit and donates 1_000_000 to the vault
+        let attacker_shares = fund.shares_for_amount(1, 0, 0).unwrap();
+        assert_eq!(attacker_shares, 1);
+        let victim_shares = fund.shares_for_amount(1_000_000, 1_000_001, attacker_shares).unwrap();
+        assert!(victim_shares > 0);
+
+        // The virtual share keeps part of the donation out of the attacker's reach
+        let attacker_assets = fund
+            .amount_for_shares(attacker_shares, 2_000_001, attacker_shares + victim_shares)
+            .unwrap();
+        assert!(attacker_assets < 1_000_001);
+    }
+
+    fn stake_cooldown(cooldown_end: i64) -> StakeCooldown {
+        let mut cooldown = StakeCooldown::deserialize(&mut &[0u8; StakeCooldown::SIZE - 8][..]).unwrap();
+        cooldown.cooldown_end = cooldown_end;
+        cooldown
+    }
+
+    #[test]
+    fn unstaking_is_rejected_before_the_cooldown_ends() {
+        let cooldown = stake_cooldown(1_700_000_000);
+        assert_eq!(
+            cooldown.require_unstake_window(1_700_000_000 - 1).unwrap_err(),
+            crate::errors::LendingError::InsuranceCooldownActive.into()
+        );
+    }
+
+    #[test]
+    fn unstaking_is_allowed_within_the_window() {
+        let cooldown = stake_cooldown(1_700_000_000);
+        assert!(cooldown.require_unstake_window(1_700_000_000).is_ok());
+        assert!(cooldown.require_unstake_window(1_700_000_000 + INSURANCE_UNSTAKE_WINDOW).is_ok());
+    }
+
+    #[test]
+    fn unstaking_is_rejected_once_the_window_has_passed() {
+        // A cooldown finished long ago can't be used to exit just ahead of a slash
+        let cooldown = stake_cooldown(1_700_000_000);
+        assert_eq!(
+            cooldown.require_unstake_window(1_700_000_000 + INSURANCE_UNSTAKE_WINDOW + 1).unwrap_err(),
+            crate::errors::LendingError::InsuranceUnstakeWindowExpired.into()
+        );
+    }
+
+    fn vault(owner: Pubkey, guardian: Pubkey) -> Vault {
+        let mut vault = Vault::deserialize(&mut &[0u8; Vault::BASE_SIZE - 8][..]).unwrap();
+        vault.owner = owner;
+        vault.guardian = guardian;
+        vault
+    }
+
+    #[test]
+    fn owner_is_guardian_only_until_one_is_appointed() {
+        let owner = Pubkey::new_unique();
+        let guardian = Pubkey::new_unique();
+
+        assert!(vault(owner, Pubkey::default()).is_guardian(&owner));
+        assert!(!vault(owner, Pubkey::default()).is_guardian(&Pubkey::default()));
+
+        let vault = vault(owner, guardian);
+        assert!(vault.is_guardian(&guardian));
+        assert!(!vault.is_guardian(&owner));
+    }
+
+    fn fee_vault(total_assets: u64, management_fee_bps: u16, performance_fee_bps: u16) -> Vault {
+        let mut vault = vault(Pubkey::new_unique(), Pubkey::default());
+        vault.total_assets = total_assets;
+        vault.management_fee_bps = management_fee_bps;
+        vault.performance_fee_bps = performance_fee_bps;
+        vault.high_water_mark = SHARE_PRICE_SCALE;
+        vault
+    }
+
+    #[test]
+    fn management_fee_accrues_per_second() {
+        // 2% a year on 1_000 assets
+        let mut vault = fee_vault(1_000_000_000, 200, 0);
+        let fees = vault.accrue_fees(1_000_000_000, &clock(SECONDS_PER_YEAR as i64 / 2)).unwrap();
+        assert_eq!(fees.management_fee, 10_000_000);
+        assert_eq!(fees.performance_fee, 0);
+        assert_eq!(vault.last_fee_accrual, SECONDS_PER_YEAR as i64 / 2);
+
+        // Nothing more accrues within the same second
+        let fees = vault.accrue_fees(1_000_000_000, &clock(SECONDS_PER_YEAR as i64 / 2)).unwrap();
+        assert_eq!(fees.shares, 0);
+    }
+
+    #[test]
+    fn no_performance_fee_below_the_high_water_mark() {
+        let mut vault = fee_vault(900_000_000, 0, 2000);
+        let fees = vault.accrue_fees(1_000_000_000, &clock(0)).unwrap();
+        assert_eq!(fees.performance_fee, 0);
+        assert_eq!(fees.shares, 0);
+        assert_eq!(vault.high_water_mark, SHARE_PRICE_SCALE);
+    }
+
+    #[test]
+    fn high_water_mark_moves_to_the_price_after_fees() {
+        // A 10% gain with a 20% performance fee
+        let mut vault = fee_vault(1_100_000_000, 0, 2000);
+        let price_before_fees = vault.share_price(1_000_000_000);
+        let fees = vault.accrue_fees(1_000_000_000, &clock(0)).unwrap();
+        // The virtual share and asset round the 100 gain down by a unit
+        assert_eq!(fees.performance_fee, 19_999_999);
+
+        let price_after_fees = vault.share_price(1_000_000_000 + fees.shares);
+        assert_eq!(vault.high_water_mark, price_after_fees);
+        assert!(price_after_fees < price_before_fees);
+
+        // The same price again is not charged twice
+        let fees = vault.accrue_fees(1_000_000_000 + fees.shares, &clock(0)).unwrap();
+        assert_eq!(fees.performance_fee, 0);
+    }
+
+    #[test]
+    fn fee_shares_are_worth_the_fee() {
+        let mut vault = fee_vault(1_100_000_000, 200, 2000);
+        let fees = vault.accrue_fees(1_000_000_000, &clock(SECONDS_PER_YEAR as i64)).unwrap();
+        let fee_assets = fees.management_fee + fees.performance_fee;
+        assert_eq!(fee_assets, 41_999_999);
+        // Minting the fee shares at the post-fee price costs exactly the fee;
+        // redeeming them rounds down in the depositors' favour
+        let share_supply = 1_000_000_000 + fees.shares;
+        assert_eq!(vault.preview_mint(fees.shares, share_supply).unwrap(), fee_assets);
+        assert_eq!(vault.preview_redeem(fees.shares, share_supply).unwrap(), fee_assets - 1);
+    }
 }
@@ -0,0 +1,124 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::invoke_signed;
+use anchor_spl::token::{self, Approve, Revoke, TokenAccount};
+use crate::errors::LendingError;
+
+/// Discriminator of the swap program's `swap(amount_in, min_amount_out)` instruction
+/// (first 8 bytes of `sha256("global:swap")`)
+const SWAP_DISCRIMINATOR: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
+
+/// Adapter for CPIs into an allowlisted swap program
+///
+/// Every swap program on the `GlobalConfig` allowlist must implement
+/// `swap(amount_in, min_amount_out)` over the accounts `[pool, authority, source,
+/// destination, pool_source_vault, pool_destination_vault, token_program]`.
+/// Pool accounts are validated by the swap program itself.
+///
+/// The swap program never sees the market PDA as a signer: the market delegates
+/// exactly `amount_in` of the source vault to the swap authority PDA, which signs
+/// the swap instead, and the delegation is revoked afterwards.
+pub struct SwapAdapter<'a, 'info> {
+    /// Swap program (must be allowlisted on `GlobalConfig`)
+    pub program: &'a AccountInfo<'info>,
+    /// Pool state
+    pub pool: &'a AccountInfo<'info>,
+    /// Pool vault receiving the input token
+    pub source_vault: &'a AccountInfo<'info>,
+    /// Pool vault paying out the output token
+    pub destination_vault: &'a AccountInfo<'info>,
+    /// Swap authority PDA (`[swap_authority, global_config]`)
+    pub authority: &'a AccountInfo<'info>,
+    /// Signer seeds of the swap authority
+    pub authority_seeds: &'a [&'a [u8]],
+}
+
+impl<'info> SwapAdapter<'_, 'info> {
+    /// Swap `amount_in` from `source` into `destination`
+    ///
+    /// The output is measured from `destination`'s balance rather than trusted
+    /// from the swap program, and must be at least `min_amount_out`. `source`
+    /// must have dropped by exactly `amount_in`. `source_authority` (the market
+    /// owning `source`) only signs the approval and revocation.
+    #[allow(clippy::too_many_arguments)]
+    pub fn swap(
+        &self,
+        source: &mut Account<'info, TokenAccount>,
+        destination: &mut Account<'info, TokenAccount>,
+        source_authority: &AccountInfo<'info>,
+        token_program: &AccountInfo<'info>,
+        signer_seeds: &[&[&[u8]]],
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> Result<u64> {
+        let source_balance_before = source.amount;
+        let balance_before = destination.amount;
+
+        let approve_ctx = CpiContext::new_with_signer(
+            token_program.clone(),
+            Approve {
+                to: source.to_account_info(),
+                delegate: self.authority.clone(),
+                authority: source_authority.clone(),
+            },
+            signer_seeds,
+        );
+        token::approve(approve_ctx, amount_in)?;
+
+        let mut data = SWAP_DISCRIMINATOR.to_vec();
+        data.extend_from_slice(&amount_in.to_le_bytes());
+        data.extend_from_slice(&min_amount_out.to_le_bytes());
+
+        let instruction = Instruction {
+            program_id: self.program.key(),
+            accounts: vec![
+                AccountMeta::new(self.pool.key(), false),
+                AccountMeta::new_readonly(self.authority.key(), true),
+                AccountMeta::new(source.key(), false),
+                AccountMeta::new(destination.key(), false),
+                AccountMeta::new(self.source_vault.key(), false),
+                AccountMeta::new(self.destination_vault.key(), false),
+                AccountMeta::new_readonly(token_program.key(), false),
+            ],
+            data,
+        };
+        invoke_signed(
+            &instruction,
+            &[
+                self.pool.clone(),
+                self.authority.clone(),
+                source.to_account_info(),
+                destination.to_account_info(),
+                self.source_vault.clone(),
+                self.destination_vault.clone(),
+                token_program.clone(),
+                self.program.clone(),
+            ],
+            &[self.authority_seeds],
+        )?;
+
+        let revoke_ctx = CpiContext::new_with_signer(
+            token_program.clone(),
+            Revoke {
+                source: source.to_account_info(),
+                authority: source_authority.clone(),
+            },
+            signer_seeds,
+        );
+        token::revoke(revoke_ctx)?;
+
+        source.reload()?;
+        let amount_spent = source_balance_before
+            .checked_sub(source.amount)
+            .ok_or(LendingError::SwapInputMismatch)?;
+        require!(amount_spent == amount_in, LendingError::SwapInputMismatch);
+
+        destination.reload()?;
+        let amount_out = destination.amount
+            .checked_sub(balance_before)
+            .ok_or(LendingError::MathOverflow)?;
+        require!(amount_out >= min_amount_out, LendingError::SlippageExceeded);
+
+        Ok(amount_out)
+    }
+}
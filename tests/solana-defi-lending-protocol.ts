@@ -1,11 +1,14 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import { SolanaDefiLendingProtocol } from "../target/types/solana_defi_lending_protocol";
+import { MockAmm } from "../target/types/mock_amm";
+import { MockOracle } from "../target/types/mock_oracle";
 import {
   PublicKey,
   Keypair,
   SystemProgram,
   SYSVAR_RENT_PUBKEY,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
   LAMPORTS_PER_SOL,
 } from "@solana/web3.js";
 import {
@@ -15,6 +18,9 @@ import {
   createAccount,
   mintTo,
   getMint,
+  getAccount,
+  getOrCreateAssociatedTokenAccount,
+  closeAccount,
 } from "@solana/spl-token";
 import { expect } from "chai";
 
@@ -24,11 +30,14 @@ describe("solana-defi-lending-protocol", () => {
   anchor.setProvider(provider);
 
   const program = anchor.workspace.SolanaDefiLendingProtocol as Program<SolanaDefiLendingProtocol>;
+  const mockAmm = anchor.workspace.MockAmm as Program<MockAmm>;
+  const mockOracle = anchor.workspace.MockOracle as Program<MockOracle>;
   const authority = provider.wallet;
   const payer = Keypair.generate();
 
   let globalConfig: PublicKey;
   let treasury: PublicKey;
+  let swapAuthority: PublicKey;
   let globalConfigBump: number;
   let treasuryBump: number;
 
@@ -50,8 +59,258 @@ describe("solana-defi-lending-protocol", () => {
       [Buffer.from("treasury"), globalConfig.toBuffer()],
       program.programId
     );
+
+    [swapAuthority] = PublicKey.findProgramAddressSync(
+      [Buffer.from("swap_authority"), globalConfig.toBuffer()],
+      program.programId
+    );
   });
 
+  // Price feed size the lending program's oracle reader requires
+  const PRICE_ACCOUNT_LEN = 240;
+
+  interface TestMarket {
+    assetMint: PublicKey;
+    supplyMint: PublicKey;
+    reserveVault: PublicKey;
+    collateralVault: PublicKey;
+    oracle: PublicKey;
+    market: PublicKey;
+  }
+
+  async function newUser(): Promise<Keypair> {
+    const user = Keypair.generate();
+    const sig = await provider.connection.requestAirdrop(user.publicKey, LAMPORTS_PER_SOL);
+    await provider.connection.confirmTransaction(sig);
+    return user;
+  }
+
+  async function tokenAccount(mint: PublicKey, owner: PublicKey): Promise<PublicKey> {
+    return (await getOrCreateAssociatedTokenAccount(provider.connection, payer, mint, owner, true)).address;
+  }
+
+  async function balance(account: PublicKey): Promise<number> {
+    return Number((await getAccount(provider.connection, account)).amount);
+  }
+
+  // Publish a price to a mock oracle feed in the Pyth layout
+  async function setPrice(oracle: PublicKey, price: number) {
+    await mockOracle.methods
+      .setPrice(new anchor.BN(Math.round(price * 1e6)), -6)
+      .accounts({ priceAccount: oracle })
+      .rpc();
+  }
+
+  async function createPriceFeed(price: number): Promise<PublicKey> {
+    const feed = Keypair.generate();
+    const tx = new anchor.web3.Transaction().add(
+      SystemProgram.createAccount({
+        fromPubkey: authority.publicKey,
+        newAccountPubkey: feed.publicKey,
+        lamports: await provider.connection.getMinimumBalanceForRentExemption(PRICE_ACCOUNT_LEN),
+        space: PRICE_ACCOUNT_LEN,
+        programId: mockOracle.programId,
+      })
+    );
+    await provider.sendAndConfirm(tx, [feed]);
+    await setPrice(feed.publicKey, price);
+    return feed.publicKey;
+  }
+
+  // Create a market whose supply mint and vaults belong to the market PDA
+  async function createTestMarket(
+    price: number,
+    ltvBps = 7500,
+    liquidationThresholdBps = 8000
+  ): Promise<TestMarket> {
+    const assetMint = await createMint(provider.connection, payer, payer.publicKey, null, 6);
+    const [market] = PublicKey.findProgramAddressSync(
+      [Buffer.from("market"), assetMint.toBuffer()],
+      program.programId
+    );
+    const supplyMint = await createMint(provider.connection, payer, market, null, 6);
+    const reserveVault = await createAccount(provider.connection, payer, assetMint, market, Keypair.generate());
+    const collateralVault = await createAccount(provider.connection, payer, supplyMint, market, Keypair.generate());
+    const oracle = await createPriceFeed(price);
+
+    await program.methods
+      .createMarket(ltvBps, liquidationThresholdBps)
+      .accounts({
+        creator: payer.publicKey,
+        globalConfig,
+        assetMint,
+        supplyMint,
+        reserveVault,
+        collateralVault,
+        oracle,
+        market,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([payer])
+      .rpc();
+
+    return { assetMint, supplyMint, reserveVault, collateralVault, oracle, market };
+  }
+
+  // Mint `amount` of the market's asset to `user` and supply it; returns the user's supply token account
+  async function supplyTo(m: TestMarket, user: Keypair, amount: number): Promise<PublicKey> {
+    const userTokenAccount = await tokenAccount(m.assetMint, user.publicKey);
+    const userSupplyAccount = await tokenAccount(m.supplyMint, user.publicKey);
+    await mintTo(provider.connection, payer, m.assetMint, userTokenAccount, payer, amount);
+
+    await program.methods
+      .supply(new anchor.BN(amount))
+      .accounts({
+        user: user.publicKey,
+        market: m.market,
+        userTokenAccount,
+        reserveVault: m.reserveVault,
+        supplyMint: m.supplyMint,
+        userSupplyAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([user])
+      .rpc();
+
+    return userSupplyAccount;
+  }
+
+  // `[market, oracle]` pairs in the order the health check expects
+  function positionAccounts(markets: TestMarket[]) {
+    return markets.flatMap((m) => [
+      { pubkey: m.market, isSigner: false, isWritable: false },
+      { pubkey: m.oracle, isSigner: false, isWritable: false },
+    ]);
+  }
+
+  // Open an obligation for `owner` backed by `supplyTokens` of `collateral`
+  async function openObligation(owner: Keypair, collateral: TestMarket, supplyTokens: number): Promise<PublicKey> {
+    const [obligation] = PublicKey.findProgramAddressSync(
+      [Buffer.from("obligation"), owner.publicKey.toBuffer()],
+      program.programId
+    );
+    const userSupplyAccount = await supplyTo(collateral, owner, supplyTokens);
+
+    await program.methods
+      .initObligation()
+      .accounts({
+        owner: owner.publicKey,
+        obligation,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([owner])
+      .rpc();
+
+    await program.methods
+      .depositCollateral(new anchor.BN(supplyTokens))
+      .accounts({
+        owner: owner.publicKey,
+        obligation,
+        market: collateral.market,
+        userSupplyAccount,
+        collateralVault: collateral.collateralVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([owner])
+      .rpc();
+
+    return obligation;
+  }
+
+  async function borrowFrom(
+    m: TestMarket,
+    owner: Keypair,
+    obligation: PublicKey,
+    amount: number,
+    positions: TestMarket[]
+  ) {
+    await program.methods
+      .borrow(new anchor.BN(amount))
+      .accounts({
+        user: owner.publicKey,
+        obligation,
+        market: m.market,
+        reserveVault: m.reserveVault,
+        userTokenAccount: await tokenAccount(m.assetMint, owner.publicKey),
+        oracle: m.oracle,
+        emodeCategory: null,
+        isolatedCollateralMarket: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .remainingAccounts(positionAccounts(positions))
+      .signers([owner])
+      .rpc();
+  }
+
+  async function debtOf(obligation: PublicKey, m: TestMarket): Promise<number> {
+    const account = await program.account.obligation.fetch(obligation);
+    const position = account.borrows.find((b) => b.market.equals(m.market));
+    return position ? position.borrowedAmount.toNumber() : 0;
+  }
+
+  async function collateralOf(obligation: PublicKey, m: TestMarket): Promise<number> {
+    const account = await program.account.obligation.fetch(obligation);
+    const position = account.deposits.find((d) => d.market.equals(m.market));
+    return position ? position.supplyTokens.toNumber() : 0;
+  }
+
+  interface TestPool {
+    pool: PublicKey;
+    vaultA: PublicKey;
+    vaultB: PublicKey;
+  }
+
+  // Create a mock AMM pool between two test assets, seeded 1:1 with `liquidity` on each side
+  async function createTestPool(mintA: PublicKey, mintB: PublicKey, liquidity: number): Promise<TestPool> {
+    const [pool] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pool"), mintA.toBuffer(), mintB.toBuffer()],
+      mockAmm.programId
+    );
+    const [vaultA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), pool.toBuffer(), mintA.toBuffer()],
+      mockAmm.programId
+    );
+    const [vaultB] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), pool.toBuffer(), mintB.toBuffer()],
+      mockAmm.programId
+    );
+
+    await mockAmm.methods
+      .initializePool()
+      .accounts({
+        payer: payer.publicKey,
+        mintA,
+        mintB,
+        pool,
+        vaultA,
+        vaultB,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([payer])
+      .rpc();
+
+    const providerA = await tokenAccount(mintA, payer.publicKey);
+    const providerB = await tokenAccount(mintB, payer.publicKey);
+    await mintTo(provider.connection, payer, mintA, providerA, payer, liquidity);
+    await mintTo(provider.connection, payer, mintB, providerB, payer, liquidity);
+    await mockAmm.methods
+      .addLiquidity(new anchor.BN(liquidity), new anchor.BN(liquidity))
+      .accounts({
+        provider: payer.publicKey,
+        pool,
+        vaultA,
+        vaultB,
+        providerA,
+        providerB,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([payer])
+      .rpc();
+
+    return { pool, vaultA, vaultB };
+  }
+
   it("Initializes global config", async () => {
     try {
       const tx = await program.methods
@@ -76,11 +335,640 @@ describe("solana-defi-lending-protocol", () => {
     }
   });
 
+  it("Allowlists and removes a swap program", async () => {
+    const swapProgram = Keypair.generate().publicKey;
+    await program.methods
+      .addSwapProgram(swapProgram)
+      .accounts({
+        authority: authority.publicKey,
+        globalConfig,
+      })
+      .rpc();
+
+    let config = await program.account.globalConfig.fetch(globalConfig);
+    expect(config.swapPrograms.map((key) => key.toString())).to.include(swapProgram.toString());
+
+    await program.methods
+      .removeSwapProgram(swapProgram)
+      .accounts({
+        authority: authority.publicKey,
+        globalConfig,
+      })
+      .rpc();
+
+    config = await program.account.globalConfig.fetch(globalConfig);
+    expect(config.swapPrograms.map((key) => key.toString())).to.not.include(swapProgram.toString());
+  });
+
+  it("Rejects a swap program that is already allowlisted", async () => {
+    const swapProgram = Keypair.generate().publicKey;
+    await program.methods
+      .addSwapProgram(swapProgram)
+      .accounts({
+        authority: authority.publicKey,
+        globalConfig,
+      })
+      .rpc();
+
+    try {
+      await program.methods
+        .addSwapProgram(swapProgram)
+        .accounts({
+          authority: authority.publicKey,
+          globalConfig,
+        })
+        .rpc();
+      expect.fail("Expected InvalidSwapProgram");
+    } catch (err) {
+      expect(err.toString()).to.include("InvalidSwapProgram");
+    }
+
+    await program.methods
+      .removeSwapProgram(swapProgram)
+      .accounts({
+        authority: authority.publicKey,
+        globalConfig,
+      })
+      .rpc();
+  });
+
+  describe("Mock AMM swaps", () => {
+    let mintA: PublicKey;
+    let mintB: PublicKey;
+    let pool: PublicKey;
+    let vaultA: PublicKey;
+    let vaultB: PublicKey;
+    let traderA: PublicKey;
+    let traderB: PublicKey;
+
+    before(async () => {
+      mintA = await createMint(provider.connection, payer, payer.publicKey, null, 6);
+      mintB = await createMint(provider.connection, payer, payer.publicKey, null, 6);
+
+      [pool] = PublicKey.findProgramAddressSync(
+        [Buffer.from("pool"), mintA.toBuffer(), mintB.toBuffer()],
+        mockAmm.programId
+      );
+      [vaultA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), pool.toBuffer(), mintA.toBuffer()],
+        mockAmm.programId
+      );
+      [vaultB] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), pool.toBuffer(), mintB.toBuffer()],
+        mockAmm.programId
+      );
+
+      traderA = await createAccount(provider.connection, payer, mintA, payer.publicKey);
+      traderB = await createAccount(provider.connection, payer, mintB, payer.publicKey);
+      await mintTo(provider.connection, payer, mintA, traderA, payer, 2_000_000 * 1e6);
+      await mintTo(provider.connection, payer, mintB, traderB, payer, 1_000_000 * 1e6);
+    });
+
+    it("Allowlists the mock AMM", async () => {
+      await program.methods
+        .addSwapProgram(mockAmm.programId)
+        .accounts({
+          authority: authority.publicKey,
+          globalConfig,
+        })
+        .rpc();
+
+      const config = await program.account.globalConfig.fetch(globalConfig);
+      expect(config.swapPrograms.map((key) => key.toString())).to.include(mockAmm.programId.toString());
+    });
+
+    it("Creates a pool and adds liquidity", async () => {
+      await mockAmm.methods
+        .initializePool()
+        .accounts({
+          payer: payer.publicKey,
+          mintA,
+          mintB,
+          pool,
+          vaultA,
+          vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([payer])
+        .rpc();
+
+      await mockAmm.methods
+        .addLiquidity(new anchor.BN(1_000_000 * 1e6), new anchor.BN(1_000_000 * 1e6))
+        .accounts({
+          provider: payer.publicKey,
+          pool,
+          vaultA,
+          vaultB,
+          providerA: traderA,
+          providerB: traderB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([payer])
+        .rpc();
+
+      expect(Number((await getAccount(provider.connection, vaultA)).amount)).to.equal(1_000_000 * 1e6);
+      expect(Number((await getAccount(provider.connection, vaultB)).amount)).to.equal(1_000_000 * 1e6);
+    });
+
+    it("Swaps through the lending program's swap account layout", async () => {
+      const amountIn = 1_000 * 1e6;
+      const before = Number((await getAccount(provider.connection, traderB)).amount);
+
+      await mockAmm.methods
+        .swap(new anchor.BN(amountIn), new anchor.BN(0))
+        .accounts({
+          pool,
+          authority: payer.publicKey,
+          source: traderA,
+          destination: traderB,
+          poolSourceVault: vaultA,
+          poolDestinationVault: vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([payer])
+        .rpc();
+
+      // 0.3% fee plus price impact on a 1:1 pool
+      const amountOut = Number((await getAccount(provider.connection, traderB)).amount) - before;
+      expect(amountOut).to.be.greaterThan(amountIn * 0.99);
+      expect(amountOut).to.be.lessThan(amountIn * 0.997);
+    });
+
+    it("Rejects a swap below the minimum output", async () => {
+      try {
+        await mockAmm.methods
+          .swap(new anchor.BN(1_000 * 1e6), new anchor.BN(1_000 * 1e6))
+          .accounts({
+            pool,
+            authority: payer.publicKey,
+            source: traderA,
+            destination: traderB,
+            poolSourceVault: vaultA,
+            poolDestinationVault: vaultB,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([payer])
+          .rpc();
+        expect.fail("Expected SlippageExceeded");
+      } catch (err) {
+        expect(err.toString()).to.include("SlippageExceeded");
+      }
+    });
+  });
+
+  describe("Vault deposits and withdrawals", () => {
+    let assetMint: PublicKey;
+    let shareMint: PublicKey;
+    let assetVault: PublicKey;
+    let vault: PublicKey;
+    let userTokenAccount: PublicKey;
+    let userShareAccount: PublicKey;
+    let feeRecipient: PublicKey;
+    let withdrawalEscrow: PublicKey;
+
+    before(async () => {
+      [vault] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), payer.publicKey.toBuffer(), new anchor.BN(0).toArrayLike(Buffer, "le", 2)],
+        program.programId
+      );
+
+      assetMint = await createMint(provider.connection, payer, payer.publicKey, null, 6);
+      shareMint = await createMint(provider.connection, payer, vault, null, 6);
+      assetVault = await createAccount(
+        provider.connection,
+        payer,
+        assetMint,
+        vault,
+        Keypair.generate()
+      );
+
+      userTokenAccount = await createAccount(provider.connection, payer, assetMint, payer.publicKey);
+      userShareAccount = await createAccount(provider.connection, payer, shareMint, payer.publicKey);
+      feeRecipient = await createAccount(
+        provider.connection,
+        payer,
+        shareMint,
+        payer.publicKey,
+        Keypair.generate()
+      );
+      withdrawalEscrow = await createAccount(
+        provider.connection,
+        payer,
+        shareMint,
+        vault,
+        Keypair.generate()
+      );
+      await mintTo(provider.connection, payer, assetMint, userTokenAccount, payer, 1_000 * 1e6);
+    });
+
+    it("Rejects vault fees above the maximum", async () => {
+      try {
+        await program.methods
+          .createVault(0, 0, 500, 100, 6000, new anchor.BN(0))
+          .accounts({
+            owner: payer.publicKey,
+            vault,
+            assetMint,
+            assetVault,
+            shareMint,
+            feeRecipient,
+            withdrawalEscrow,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([payer])
+          .rpc();
+        expect.fail("Expected InvalidVaultFee");
+      } catch (err) {
+        expect(err.toString()).to.include("InvalidVaultFee");
+      }
+    });
+
+    it("Creates a vault with a share mint", async () => {
+      await program.methods
+        .createVault(0, 0, 500, 100, 1000, new anchor.BN(0))
+        .accounts({
+          owner: payer.publicKey,
+          vault,
+          assetMint,
+          assetVault,
+          shareMint,
+          feeRecipient,
+          withdrawalEscrow,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([payer])
+        .rpc();
+
+      const vaultAccount = await program.account.vault.fetch(vault);
+      expect(vaultAccount.assetMint.toString()).to.equal(assetMint.toString());
+      expect(vaultAccount.shareMint.toString()).to.equal(shareMint.toString());
+      expect(vaultAccount.totalAssets.toNumber()).to.equal(0);
+      expect(vaultAccount.managementFeeBps).to.equal(100);
+      expect(vaultAccount.performanceFeeBps).to.equal(1000);
+      expect(vaultAccount.feeRecipient.toString()).to.equal(feeRecipient.toString());
+      expect(vaultAccount.index).to.equal(0);
+      expect(vaultAccount.curator.toString()).to.equal(payer.publicKey.toString());
+    });
+
+    it("Appoints vault roles and rejects rebalances from non-allocators", async () => {
+      const allocator = Keypair.generate();
+      const guardian = Keypair.generate();
+
+      await program.methods
+        .setVaultRoles(payer.publicKey, guardian.publicKey)
+        .accounts({ owner: payer.publicKey, vault })
+        .signers([payer])
+        .rpc();
+      await program.methods
+        .addVaultAllocator(allocator.publicKey)
+        .accounts({ owner: payer.publicKey, vault })
+        .signers([payer])
+        .rpc();
+
+      let vaultAccount = await program.account.vault.fetch(vault);
+      expect(vaultAccount.guardian.toString()).to.equal(guardian.publicKey.toString());
+      expect(vaultAccount.allocators.map((a) => a.toString())).to.include(allocator.publicKey.toString());
+
+      await program.methods
+        .removeVaultAllocator(allocator.publicKey)
+        .accounts({ owner: payer.publicKey, vault })
+        .signers([payer])
+        .rpc();
+      vaultAccount = await program.account.vault.fetch(vault);
+      expect(vaultAccount.allocators).to.have.length(0);
+
+      try {
+        await program.methods
+          .rebalanceVault()
+          .accounts({
+            allocator: allocator.publicKey,
+            vault,
+            assetVault,
+            shareMint,
+            feeRecipient,
+            withdrawalEscrow,
+            lendingProgram: program.programId,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([allocator])
+          .rpc();
+        expect.fail("Expected Unauthorized");
+      } catch (err) {
+        expect(err.toString()).to.include("Unauthorized");
+      }
+    });
+
+    it("Deposits into the vault for shares", async () => {
+      const assets = new anchor.BN(100 * 1e6);
+      const preview = await program.methods
+        .previewVaultDeposit(assets)
+        .accounts({ vault, assetVault, shareMint })
+        .view();
+
+      await program.methods
+        .vaultDeposit(assets)
+        .accounts({
+          user: payer.publicKey,
+          vault,
+          assetVault,
+          shareMint,
+          feeRecipient,
+          userTokenAccount,
+          receiverShareAccount: userShareAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([payer])
+        .rpc();
+
+      const shares = Number((await getAccount(provider.connection, userShareAccount)).amount);
+      expect(shares).to.equal(preview.toNumber());
+      const vaultAccount = await program.account.vault.fetch(vault);
+      expect(vaultAccount.totalAssets.toNumber()).to.equal(100 * 1e6);
+    });
+
+    it("Redeems shares for assets", async () => {
+      const shares = new anchor.BN(40 * 1e6);
+      const preview = await program.methods
+        .previewVaultRedeem(shares)
+        .accounts({ vault, assetVault, shareMint })
+        .view();
+      const before = Number((await getAccount(provider.connection, userTokenAccount)).amount);
+
+      await program.methods
+        .vaultRedeem(shares)
+        .accounts({
+          user: payer.publicKey,
+          vault,
+          assetVault,
+          shareMint,
+          feeRecipient,
+          userShareAccount,
+          receiverTokenAccount: userTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([payer])
+        .rpc();
+
+      const received = Number((await getAccount(provider.connection, userTokenAccount)).amount) - before;
+      expect(received).to.equal(preview.toNumber());
+    });
+
+    it("Queues a withdrawal request and fills it with the crank", async () => {
+      const shares = new anchor.BN(10 * 1e6);
+      const [withdrawalRequest] = PublicKey.findProgramAddressSync(
+        [Buffer.from("withdrawal_request"), vault.toBuffer(), new anchor.BN(0).toArrayLike(Buffer, "le", 8)],
+        program.programId
+      );
+
+      await program.methods
+        .requestWithdrawal(shares)
+        .accounts({
+          owner: payer.publicKey,
+          vault,
+          withdrawalRequest,
+          ownerShareAccount: userShareAccount,
+          withdrawalEscrow,
+          receiverTokenAccount: userTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([payer])
+        .rpc();
+
+      let vaultAccount = await program.account.vault.fetch(vault);
+      expect(vaultAccount.queuedShares.toNumber()).to.equal(10 * 1e6);
+      const escrowed = Number((await getAccount(provider.connection, withdrawalEscrow)).amount);
+      expect(escrowed).to.equal(10 * 1e6);
+
+      const before = Number((await getAccount(provider.connection, userTokenAccount)).amount);
+      await program.methods
+        .processWithdrawals()
+        .accounts({
+          vault,
+          assetVault,
+          shareMint,
+          feeRecipient,
+          withdrawalEscrow,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts([
+          { pubkey: withdrawalRequest, isWritable: true, isSigner: false },
+          { pubkey: userTokenAccount, isWritable: true, isSigner: false },
+          { pubkey: payer.publicKey, isWritable: true, isSigner: false },
+        ])
+        .rpc();
+
+      const received = Number((await getAccount(provider.connection, userTokenAccount)).amount) - before;
+      expect(received).to.be.greaterThan(0);
+      vaultAccount = await program.account.vault.fetch(vault);
+      expect(vaultAccount.queuedShares.toNumber()).to.equal(0);
+      expect(vaultAccount.nextWithdrawalToFill.toNumber()).to.equal(1);
+      expect(await provider.connection.getAccountInfo(withdrawalRequest)).to.be.null;
+    });
+
+    function withdrawalRequestAt(id: number): PublicKey {
+      return PublicKey.findProgramAddressSync(
+        [Buffer.from("withdrawal_request"), vault.toBuffer(), new anchor.BN(id).toArrayLike(Buffer, "le", 8)],
+        program.programId
+      )[0];
+    }
+
+    async function requestWithdrawal(withdrawalRequest: PublicKey, shares: anchor.BN, receiverTokenAccount: PublicKey) {
+      await program.methods
+        .requestWithdrawal(shares)
+        .accounts({
+          owner: payer.publicKey,
+          vault,
+          withdrawalRequest,
+          ownerShareAccount: userShareAccount,
+          withdrawalEscrow,
+          receiverTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([payer])
+        .rpc();
+    }
+
+    async function cancelWithdrawal(withdrawalRequest: PublicKey) {
+      await program.methods
+        .cancelWithdrawal()
+        .accounts({
+          owner: payer.publicKey,
+          vault,
+          withdrawalRequest,
+          ownerShareAccount: userShareAccount,
+          withdrawalEscrow,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([payer])
+        .rpc();
+    }
+
+    it("Skips a request whose receiver was closed and returns its shares on cancel", async () => {
+      const shares = new anchor.BN(10 * 1e6);
+      const withdrawalRequest = withdrawalRequestAt(1);
+      const receiver = await createAccount(provider.connection, payer, assetMint, payer.publicKey, Keypair.generate());
+      await requestWithdrawal(withdrawalRequest, shares, receiver);
+      await closeAccount(provider.connection, payer, receiver, payer.publicKey, payer);
+
+      await program.methods
+        .processWithdrawals()
+        .accounts({
+          vault,
+          assetVault,
+          shareMint,
+          feeRecipient,
+          withdrawalEscrow,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts([
+          { pubkey: withdrawalRequest, isWritable: true, isSigner: false },
+          { pubkey: receiver, isWritable: true, isSigner: false },
+          { pubkey: payer.publicKey, isWritable: true, isSigner: false },
+        ])
+        .rpc();
+
+      // The queue moves on and no longer blocks direct withdrawals
+      let vaultAccount = await program.account.vault.fetch(vault);
+      expect(vaultAccount.queuedShares.toNumber()).to.equal(0);
+      expect(vaultAccount.nextWithdrawalToFill.toNumber()).to.equal(2);
+      const request = await program.account.withdrawalRequest.fetch(withdrawalRequest);
+      expect(request.shares.toNumber()).to.equal(10 * 1e6);
+
+      const before = Number((await getAccount(provider.connection, userShareAccount)).amount);
+      await cancelWithdrawal(withdrawalRequest);
+
+      const returned = Number((await getAccount(provider.connection, userShareAccount)).amount) - before;
+      expect(returned).to.equal(10 * 1e6);
+      vaultAccount = await program.account.vault.fetch(vault);
+      expect(vaultAccount.queuedShares.toNumber()).to.equal(0);
+      expect(await provider.connection.getAccountInfo(withdrawalRequest)).to.be.null;
+    });
+
+    it("Cancels a queued withdrawal request", async () => {
+      const withdrawalRequest = withdrawalRequestAt(2);
+      await requestWithdrawal(withdrawalRequest, new anchor.BN(5 * 1e6), userTokenAccount);
+      const before = Number((await getAccount(provider.connection, userShareAccount)).amount);
+
+      await cancelWithdrawal(withdrawalRequest);
+
+      const returned = Number((await getAccount(provider.connection, userShareAccount)).amount) - before;
+      expect(returned).to.equal(5 * 1e6);
+      const vaultAccount = await program.account.vault.fetch(vault);
+      expect(vaultAccount.queuedShares.toNumber()).to.equal(0);
+      expect(vaultAccount.nextWithdrawalToFill.toNumber()).to.equal(3);
+      expect(await provider.connection.getAccountInfo(withdrawalRequest)).to.be.null;
+    });
+
+    it("Withdraws idle assets beyond what queued requests are owed", async () => {
+      const withdrawalRequest = withdrawalRequestAt(3);
+      await requestWithdrawal(withdrawalRequest, new anchor.BN(20 * 1e6), userTokenAccount);
+
+      async function vaultWithdraw(assets: number) {
+        await program.methods
+          .vaultWithdraw(new anchor.BN(assets))
+          .accounts({
+            user: payer.publicKey,
+            vault,
+            assetVault,
+            shareMint,
+            feeRecipient,
+            userShareAccount,
+            receiverTokenAccount: userTokenAccount,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([payer])
+          .rpc();
+      }
+
+      // About 40 is idle and the queued request is owed about 20
+      const before = await balance(userTokenAccount);
+      await vaultWithdraw(10 * 1e6);
+      expect((await balance(userTokenAccount)) - before).to.equal(10 * 1e6);
+
+      try {
+        await vaultWithdraw(25 * 1e6);
+        expect.fail("Expected InsufficientLiquidity");
+      } catch (err) {
+        expect(err.toString()).to.include("InsufficientLiquidity");
+      }
+
+      await cancelWithdrawal(withdrawalRequest);
+      const vaultAccount = await program.account.vault.fetch(vault);
+      expect(vaultAccount.queuedShares.toNumber()).to.equal(0);
+    });
+
+    it("Rejects allocations to an account that is not a market", async () => {
+      try {
+        await program.methods
+          .setAllocations([{ market: assetMint, targetBps: 10000, cap: new anchor.BN(1_000 * 1e6) }])
+          .accounts({
+            curator: payer.publicKey,
+            vault,
+            systemProgram: SystemProgram.programId,
+          })
+          .remainingAccounts([{ pubkey: assetMint, isWritable: false, isSigner: false }])
+          .signers([payer])
+          .rpc();
+        expect.fail("Expected InvalidVaultAccount");
+      } catch (err) {
+        expect(err.toString()).to.include("InvalidVaultAccount");
+      }
+    });
+
+    it("Skips a rebalance while allocations are within the threshold", async () => {
+      try {
+        await program.methods
+          .rebalanceVault()
+          .accounts({
+            allocator: payer.publicKey,
+            vault,
+            assetVault,
+            shareMint,
+            feeRecipient,
+            withdrawalEscrow,
+            lendingProgram: program.programId,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([payer])
+          .rpc();
+        expect.fail("Expected VaultRebalanceNotNeeded");
+      } catch (err) {
+        expect(err.toString()).to.include("VaultRebalanceNotNeeded");
+      }
+    });
+
+    it("Rejects withdrawing more than the vault holds", async () => {
+      try {
+        await program.methods
+          .vaultWithdraw(new anchor.BN(1_000 * 1e6))
+          .accounts({
+            user: payer.publicKey,
+            vault,
+            assetVault,
+            shareMint,
+            feeRecipient,
+            userShareAccount,
+            receiverTokenAccount: userTokenAccount,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([payer])
+          .rpc();
+        expect.fail("Expected InsufficientLiquidity");
+      } catch (err) {
+        expect(err.toString()).to.include("InsufficientLiquidity");
+      }
+    });
+  });
+
   describe("Market Creation and Trading", () => {
     let creator: Keypair;
     let assetMint: PublicKey;
     let supplyMint: PublicKey;
     let reserveVault: PublicKey;
+    let collateralVault: PublicKey;
     let market: PublicKey;
     let marketBump: number;
     let oracle: PublicKey; // Mock oracle
@@ -126,6 +1014,15 @@ describe("solana-defi-lending-protocol", () => {
         [Buffer.from("market"), assetMint.toBuffer()],
         program.programId
       );
+
+      // Create collateral vault (supply tokens owned by the market PDA)
+      collateralVault = await createAccount(
+        provider.connection,
+        creator,
+        supplyMint,
+        market,
+        Keypair.generate()
+      );
     });
 
     it("Creates a new lending market", async () => {
@@ -141,6 +1038,7 @@ describe("solana-defi-lending-protocol", () => {
             assetMint,
             supplyMint,
             reserveVault,
+            collateralVault,
             oracle,
             market,
             systemProgram: SystemProgram.programId,
@@ -160,9 +1058,514 @@ describe("solana-defi-lending-protocol", () => {
       }
     });
 
-    it("Supplies assets to market", async () => {
-      try {
-        const supplier = Keypair.generate();
+    it("Timelocks adding a market to a vault until the guardian can veto it", async () => {
+      const guardian = Keypair.generate();
+      const [vault] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), creator.publicKey.toBuffer(), new anchor.BN(1).toArrayLike(Buffer, "le", 2)],
+        program.programId
+      );
+      const shareMint = await createMint(provider.connection, creator, vault, null, 6);
+      const assetVault = await createAccount(provider.connection, creator, assetMint, vault, Keypair.generate());
+      const feeRecipient = await createAccount(provider.connection, creator, shareMint, creator.publicKey);
+      const withdrawalEscrow = await createAccount(provider.connection, creator, shareMint, vault, Keypair.generate());
+
+      await program.methods
+        .createVault(1, 1, 500, 0, 0, new anchor.BN(86400))
+        .accounts({
+          owner: creator.publicKey,
+          vault,
+          assetMint,
+          assetVault,
+          shareMint,
+          feeRecipient,
+          withdrawalEscrow,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([creator])
+        .rpc();
+      await program.methods
+        .setVaultRoles(creator.publicKey, guardian.publicKey)
+        .accounts({ owner: creator.publicKey, vault })
+        .signers([creator])
+        .rpc();
+
+      // Adding a market directly is rejected
+      try {
+        await program.methods
+          .setAllocations([{ market, targetBps: 10000, cap: new anchor.BN(1_000 * 1e6) }])
+          .accounts({ curator: creator.publicKey, vault, systemProgram: SystemProgram.programId })
+          .remainingAccounts([{ pubkey: market, isWritable: false, isSigner: false }])
+          .signers([creator])
+          .rpc();
+        expect.fail("Expected VaultCapIncreaseTimelocked");
+      } catch (err) {
+        expect(err.toString()).to.include("VaultCapIncreaseTimelocked");
+      }
+
+      const [pendingCap] = PublicKey.findProgramAddressSync(
+        [Buffer.from("pending_vault_cap"), vault.toBuffer(), market.toBuffer()],
+        program.programId
+      );
+      await program.methods
+        .submitVaultCap(new anchor.BN(1_000 * 1e6))
+        .accounts({
+          curator: creator.publicKey,
+          vault,
+          market,
+          pendingCap,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([creator])
+        .rpc();
+
+      const pending = await program.account.pendingVaultCap.fetch(pendingCap);
+      expect(pending.cap.toNumber()).to.equal(1_000 * 1e6);
+
+      try {
+        await program.methods
+          .acceptVaultCap()
+          .accounts({
+            curator: creator.publicKey,
+            vault,
+            pendingCap,
+            submitter: creator.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([creator])
+          .rpc();
+        expect.fail("Expected PendingChangeTimelockActive");
+      } catch (err) {
+        expect(err.toString()).to.include("PendingChangeTimelockActive");
+      }
+
+      await program.methods
+        .revokeVaultCap()
+        .accounts({ authority: guardian.publicKey, vault, pendingCap, submitter: creator.publicKey })
+        .signers([guardian])
+        .rpc();
+
+      expect(await provider.connection.getAccountInfo(pendingCap)).to.be.null;
+      const vaultAccount = await program.account.vault.fetch(vault);
+      expect(vaultAccount.allocations).to.have.length(0);
+    });
+
+    it("Timelocks replacing a vault guardian", async () => {
+      const guardian = Keypair.generate();
+      const replacement = Keypair.generate();
+      const [vault] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), creator.publicKey.toBuffer(), new anchor.BN(3).toArrayLike(Buffer, "le", 2)],
+        program.programId
+      );
+      const shareMint = await createMint(provider.connection, creator, vault, null, 6);
+      const assetVault = await createAccount(provider.connection, creator, assetMint, vault, Keypair.generate());
+      const feeRecipient = await createAccount(provider.connection, creator, shareMint, creator.publicKey, Keypair.generate());
+      const withdrawalEscrow = await createAccount(provider.connection, creator, shareMint, vault, Keypair.generate());
+
+      await program.methods
+        .createVault(3, 1, 500, 0, 0, new anchor.BN(86400))
+        .accounts({
+          owner: creator.publicKey,
+          vault,
+          assetMint,
+          assetVault,
+          shareMint,
+          feeRecipient,
+          withdrawalEscrow,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([creator])
+        .rpc();
+
+      async function setVaultRoles(newGuardian: PublicKey) {
+        await program.methods
+          .setVaultRoles(creator.publicKey, newGuardian)
+          .accounts({ owner: creator.publicKey, vault })
+          .signers([creator])
+          .rpc();
+      }
+
+      // The first guardian applies immediately, replacing it does not
+      await setVaultRoles(guardian.publicKey);
+      try {
+        await setVaultRoles(replacement.publicKey);
+        expect.fail("Expected VaultGuardianTimelocked");
+      } catch (err) {
+        expect(err.toString()).to.include("VaultGuardianTimelocked");
+      }
+
+      const [pendingGuardian] = PublicKey.findProgramAddressSync(
+        [Buffer.from("pending_vault_guardian"), vault.toBuffer()],
+        program.programId
+      );
+      await program.methods
+        .submitVaultGuardian(replacement.publicKey)
+        .accounts({ owner: creator.publicKey, vault, pendingGuardian, systemProgram: SystemProgram.programId })
+        .signers([creator])
+        .rpc();
+
+      try {
+        await program.methods
+          .acceptVaultGuardian()
+          .accounts({ owner: creator.publicKey, vault, pendingGuardian })
+          .signers([creator])
+          .rpc();
+        expect.fail("Expected PendingChangeTimelockActive");
+      } catch (err) {
+        expect(err.toString()).to.include("PendingChangeTimelockActive");
+      }
+
+      await program.methods
+        .revokeVaultGuardian()
+        .accounts({ authority: guardian.publicKey, vault, pendingGuardian, owner: creator.publicKey })
+        .signers([guardian])
+        .rpc();
+
+      expect(await provider.connection.getAccountInfo(pendingGuardian)).to.be.null;
+      const vaultAccount = await program.account.vault.fetch(vault);
+      expect(vaultAccount.guardian.toString()).to.equal(guardian.publicKey.toString());
+    });
+
+    it("Configures the price circuit breaker", async () => {
+      await program.methods
+        .setCircuitBreakerConfig(1500, new anchor.BN(300), new anchor.BN(1800))
+        .accounts({
+          authority: authority.publicKey,
+          globalConfig,
+          market,
+        })
+        .rpc();
+
+      const marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.priceDeviationBps).to.equal(1500);
+      expect(marketAccount.priceDeviationWindow.toNumber()).to.equal(300);
+      expect(marketAccount.circuitBreakerCooldown.toNumber()).to.equal(1800);
+    });
+
+    it("Rejects circuit breaker config from non-authority", async () => {
+      try {
+        await program.methods
+          .setCircuitBreakerConfig(100, new anchor.BN(60), new anchor.BN(60))
+          .accounts({
+            authority: creator.publicKey,
+            globalConfig,
+            market,
+          })
+          .signers([creator])
+          .rpc();
+        expect.fail("Non-authority should not update market config");
+      } catch (err) {
+        expect(err.toString()).to.include("Unauthorized");
+      }
+    });
+
+    it("Configures the reserve outflow limit", async () => {
+      const outflowLimit = new anchor.BN(500 * 1e6);
+      await program.methods
+        .setOutflowLimit(outflowLimit, new anchor.BN(3600))
+        .accounts({
+          authority: authority.publicKey,
+          globalConfig,
+          market,
+        })
+        .rpc();
+
+      const marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.outflowLimit.toString()).to.equal(outflowLimit.toString());
+      expect(marketAccount.outflowWindow.toNumber()).to.equal(3600);
+    });
+
+    it("Configures isolated collateral mode", async () => {
+      const debtCeiling = new anchor.BN(1_000_000 * 1e6);
+      await program.methods
+        .setIsolationConfig(true, false, debtCeiling)
+        .accounts({
+          authority: authority.publicKey,
+          globalConfig,
+          market,
+        })
+        .rpc();
+
+      const marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.isolatedCollateral).to.be.true;
+      expect(marketAccount.isolationBorrowable).to.be.false;
+      expect(marketAccount.isolationDebtCeiling.toString()).to.equal(debtCeiling.toString());
+
+      // Reset so later tests use the market as regular collateral
+      await program.methods
+        .setIsolationConfig(false, false, new anchor.BN(0))
+        .accounts({
+          authority: authority.publicKey,
+          globalConfig,
+          market,
+        })
+        .rpc();
+    });
+
+    it("Sets the market borrow factor", async () => {
+      await program.methods
+        .setBorrowFactor(8000)
+        .accounts({
+          authority: authority.publicKey,
+          globalConfig,
+          market,
+        })
+        .rpc();
+
+      const marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.borrowFactorBps).to.equal(8000);
+    });
+
+    it("Configures the Dutch-auction liquidation bonus", async () => {
+      await program.methods
+        .setLiquidationBonus(200, 1000, new anchor.BN(1800))
+        .accounts({
+          authority: authority.publicKey,
+          globalConfig,
+          market,
+        })
+        .rpc();
+
+      const marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.minLiquidationBonusBps).to.equal(200);
+      expect(marketAccount.maxLiquidationBonusBps).to.equal(1000);
+      expect(marketAccount.liquidationAuctionDuration.toNumber()).to.equal(1800);
+    });
+
+    it("Sets the liquidation protocol fee", async () => {
+      await program.methods
+        .setLiquidationProtocolFee(2000)
+        .accounts({
+          authority: authority.publicKey,
+          globalConfig,
+          market,
+        })
+        .rpc();
+
+      const marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.liquidationProtocolFeeBps).to.equal(2000);
+    });
+
+    it("Configures auction liquidations", async () => {
+      const threshold = new anchor.BN(1_000_000_000_000);
+      await program.methods
+        .setAuctionLiquidation(threshold, new anchor.BN(600))
+        .accounts({
+          authority: authority.publicKey,
+          globalConfig,
+          market,
+        })
+        .rpc();
+
+      const marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.largeLiquidationThreshold.toString()).to.equal(threshold.toString());
+      expect(marketAccount.largeLiquidationAuctionDuration.toNumber()).to.equal(600);
+    });
+
+    it("Rejects a liquidation bonus whose minimum bonus exceeds the maximum", async () => {
+      try {
+        await program.methods
+          .setLiquidationBonus(1000, 200, new anchor.BN(1800))
+          .accounts({
+            authority: authority.publicKey,
+            globalConfig,
+            market,
+          })
+          .rpc();
+        expect.fail("Expected InvalidMarketConfig");
+      } catch (err) {
+        expect(err.toString()).to.include("InvalidMarketConfig");
+      }
+    });
+
+    it("Rejects a market that is both isolated and isolation-borrowable", async () => {
+      try {
+        await program.methods
+          .setIsolationConfig(true, true, new anchor.BN(0))
+          .accounts({
+            authority: authority.publicKey,
+            globalConfig,
+            market,
+          })
+          .rpc();
+        expect.fail("Expected InvalidMarketConfig");
+      } catch (err) {
+        expect(err.toString()).to.include("InvalidMarketConfig");
+      }
+    });
+
+    it("Creates an e-mode category and adds the market", async () => {
+      const [emodeCategory] = PublicKey.findProgramAddressSync(
+        [Buffer.from("emode_category"), Buffer.from([1])],
+        program.programId
+      );
+
+      await program.methods
+        .createEmodeCategory(1, 9000, 9300, 200)
+        .accounts({
+          authority: authority.publicKey,
+          globalConfig,
+          emodeCategory,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+      await program.methods
+        .addEmodeMarket()
+        .accounts({
+          authority: authority.publicKey,
+          globalConfig,
+          emodeCategory,
+          market,
+        })
+        .rpc();
+
+      const category = await program.account.eModeCategory.fetch(emodeCategory);
+      expect(category.ltvBps).to.equal(9000);
+      expect(category.liquidationThresholdBps).to.equal(9300);
+      expect(category.markets.map((m) => m.toString())).to.include(market.toString());
+    });
+
+    it("Creates an insurance fund and stakes into it", async () => {
+      const [insuranceFund] = PublicKey.findProgramAddressSync(
+        [Buffer.from("insurance_fund"), assetMint.toBuffer()],
+        program.programId
+      );
+
+      // Share mint and vault are controlled by the insurance fund PDA
+      const shareMint = await createMint(
+        provider.connection,
+        creator,
+        insuranceFund,
+        null,
+        6
+      );
+      const insuranceVault = await createAccount(
+        provider.connection,
+        creator,
+        assetMint,
+        insuranceFund,
+        Keypair.generate()
+      );
+
+      await program.methods
+        .createInsuranceFund(new anchor.BN(7 * 86400), 3000, 2000)
+        .accounts({
+          authority: authority.publicKey,
+          globalConfig,
+          market,
+          insuranceFund,
+          shareMint,
+          vault: insuranceVault,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+      const fund = await program.account.insuranceFund.fetch(insuranceFund);
+      expect(fund.maxSlashBps).to.equal(3000);
+      expect(fund.feeShareBps).to.equal(2000);
+
+      const marketAccount = await program.account.market.fetch(market);
+      expect(marketAccount.insuranceFund.toString()).to.equal(insuranceFund.toString());
+
+      const stakerTokenAccount = await createAccount(
+        provider.connection,
+        creator,
+        assetMint,
+        creator.publicKey
+      );
+      const stakerShareAccount = await createAccount(
+        provider.connection,
+        creator,
+        shareMint,
+        creator.publicKey
+      );
+      await mintTo(provider.connection, creator, assetMint, stakerTokenAccount, creator, 1000 * 1e6);
+
+      await program.methods
+        .stakeInsurance(new anchor.BN(1000 * 1e6))
+        .accounts({
+          staker: creator.publicKey,
+          insuranceFund,
+          vault: insuranceVault,
+          shareMint,
+          stakerTokenAccount,
+          stakerShareAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([creator])
+        .rpc();
+
+      const shares = await getMint(provider.connection, shareMint);
+      expect(shares.supply.toString()).to.equal((1000 * 1e6).toString());
+    });
+
+    it("Creates an obligation", async () => {
+      const [obligation] = PublicKey.findProgramAddressSync(
+        [Buffer.from("obligation"), creator.publicKey.toBuffer()],
+        program.programId
+      );
+
+      await program.methods
+        .initObligation()
+        .accounts({
+          owner: creator.publicKey,
+          obligation,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([creator])
+        .rpc();
+
+      const obligationAccount = await program.account.obligation.fetch(obligation);
+      expect(obligationAccount.owner.toString()).to.equal(creator.publicKey.toString());
+      expect(obligationAccount.emodeCategory).to.equal(0);
+      expect(obligationAccount.deposits).to.have.length(0);
+    });
+
+    it("Configures auto-deleverage on the obligation", async () => {
+      const [obligation] = PublicKey.findProgramAddressSync(
+        [Buffer.from("obligation"), creator.publicKey.toBuffer()],
+        program.programId
+      );
+
+      await program.methods
+        .configureAutoDeleverage(11500, 1000, 100)
+        .accounts({
+          owner: creator.publicKey,
+          obligation,
+        })
+        .signers([creator])
+        .rpc();
+
+      const obligationAccount = await program.account.obligation.fetch(obligation);
+      expect(obligationAccount.autoDeleverageHealthFactorBps).to.equal(11500);
+      expect(obligationAccount.autoDeleverageRepayBps).to.equal(1000);
+      expect(obligationAccount.autoDeleverageMaxSlippageBps).to.equal(100);
+    });
+
+    it("Rejects an auto-deleverage trigger at or below a health factor of 1.0", async () => {
+      const [obligation] = PublicKey.findProgramAddressSync(
+        [Buffer.from("obligation"), creator.publicKey.toBuffer()],
+        program.programId
+      );
+
+      try {
+        await program.methods
+          .configureAutoDeleverage(9000, 1000, 100)
+          .accounts({
+            owner: creator.publicKey,
+            obligation,
+          })
+          .signers([creator])
+          .rpc();
+        expect.fail("Expected InvalidAutoDeleverageConfig");
+      } catch (err) {
+        expect(err.toString()).to.include("InvalidAutoDeleverageConfig");
+      }
+    });
+
+    it("Supplies assets to market", async () => {
+      try {
+        const supplier = Keypair.generate();
         const supplyAmount = 1000 * 1e6; // 1000 tokens
 
         // Airdrop to supplier
@@ -220,4 +1623,1393 @@ describe("solana-defi-lending-protocol", () => {
       }
     });
   });
+
+  describe("Liquidations", () => {
+    let collateral: TestMarket;
+    let debt: TestMarket;
+    let borrower: Keypair;
+    let obligation: PublicKey;
+    let flashBorrower: Keypair;
+    let flashObligation: PublicKey;
+    let liquidator: Keypair;
+    let treasurySupplyAccount: PublicKey;
+    let treasuryCollateralAccount: PublicKey;
+
+    before(async () => {
+      collateral = await createTestMarket(1);
+      debt = await createTestMarket(1);
+      await supplyTo(debt, await newUser(), 1_000 * 1e6);
+
+      // 740 borrowed against 1000 of collateral at a 75% LTV and 80% threshold
+      borrower = await newUser();
+      obligation = await openObligation(borrower, collateral, 1_000 * 1e6);
+      await borrowFrom(debt, borrower, obligation, 740 * 1e6, [collateral, debt]);
+      flashBorrower = await newUser();
+      flashObligation = await openObligation(flashBorrower, collateral, 1_000 * 1e6);
+      await borrowFrom(debt, flashBorrower, flashObligation, 740 * 1e6, [collateral, debt]);
+
+      liquidator = await newUser();
+      await mintTo(
+        provider.connection,
+        payer,
+        debt.assetMint,
+        await tokenAccount(debt.assetMint, liquidator.publicKey),
+        payer,
+        1_000 * 1e6
+      );
+      treasurySupplyAccount = await createAccount(
+        provider.connection,
+        payer,
+        collateral.supplyMint,
+        treasury,
+        Keypair.generate()
+      );
+      treasuryCollateralAccount = await createAccount(
+        provider.connection,
+        payer,
+        collateral.assetMint,
+        treasury,
+        Keypair.generate()
+      );
+    });
+
+    async function liquidateToSupplyTokens(repayAmount: number) {
+      await program.methods
+        .liquidateToSupplyTokens(new anchor.BN(repayAmount), new anchor.BN(0))
+        .accounts({
+          liquidator: liquidator.publicKey,
+          obligation,
+          borrowMarket: debt.market,
+          collateralMarket: collateral.market,
+          liquidatorTokenAccount: await tokenAccount(debt.assetMint, liquidator.publicKey),
+          borrowReserveVault: debt.reserveVault,
+          collateralVault: collateral.collateralVault,
+          liquidatorSupplyAccount: await tokenAccount(collateral.supplyMint, liquidator.publicKey),
+          globalConfig,
+          treasurySupplyAccount,
+          borrowOracle: debt.oracle,
+          collateralOracle: collateral.oracle,
+          emodeCategory: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(positionAccounts([collateral, debt]))
+        .signers([liquidator])
+        .rpc();
+    }
+
+    async function flashLiquidateBegin(repayAmount: number) {
+      return program.methods
+        .flashLiquidateBegin(new anchor.BN(repayAmount), new anchor.BN(0))
+        .accounts({
+          liquidator: liquidator.publicKey,
+          obligation: flashObligation,
+          borrowMarket: debt.market,
+          collateralMarket: collateral.market,
+          collateralReserveVault: collateral.reserveVault,
+          collateralVault: collateral.collateralVault,
+          collateralSupplyMint: collateral.supplyMint,
+          liquidatorCollateralAccount: await tokenAccount(collateral.assetMint, liquidator.publicKey),
+          globalConfig,
+          treasuryCollateralAccount,
+          borrowOracle: debt.oracle,
+          collateralOracle: collateral.oracle,
+          emodeCategory: null,
+          instructionsSysvar: SYSVAR_INSTRUCTIONS_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(positionAccounts([collateral, debt]))
+        .signers([liquidator]);
+    }
+
+    it("Rejects liquidating a healthy obligation", async () => {
+      try {
+        await liquidateToSupplyTokens(100 * 1e6);
+        expect.fail("Expected LiquidationNotNeeded");
+      } catch (err) {
+        expect(err.toString()).to.include("LiquidationNotNeeded");
+      }
+    });
+
+    it("Liquidates for supply tokens once the collateral price drops", async () => {
+      // 900 * 80% = 720 of liquidation threshold against 740 of debt
+      await setPrice(collateral.oracle, 0.9);
+
+      const repayAmount = 100 * 1e6;
+      const liquidatorSupplyAccount = await tokenAccount(collateral.supplyMint, liquidator.publicKey);
+      const supplyBefore = await balance(liquidatorSupplyAccount);
+      const debtBefore = await debtOf(obligation, debt);
+
+      await liquidateToSupplyTokens(repayAmount);
+
+      // At least the repaid value in collateral, plus most of the 5% bonus
+      const seized = (await balance(liquidatorSupplyAccount)) - supplyBefore;
+      expect(seized).to.be.greaterThan(repayAmount / 0.9);
+      expect(seized).to.be.lessThan((repayAmount * 1.05) / 0.9);
+      expect(await balance(treasurySupplyAccount)).to.be.greaterThan(0);
+
+      // Interest accrued since the borrow is repaid first
+      const repaid = debtBefore - (await debtOf(obligation, debt));
+      expect(repaid).to.be.closeTo(repayAmount, 1e4);
+    });
+
+    it("Rejects a flash liquidation without a matching end", async () => {
+      try {
+        await (await flashLiquidateBegin(100 * 1e6)).rpc();
+        expect.fail("Expected MissingFlashLiquidationEnd");
+      } catch (err) {
+        expect(err.toString()).to.include("MissingFlashLiquidationEnd");
+      }
+    });
+
+    it("Seizes collateral and repays the debt in one flash liquidation", async () => {
+      const repayAmount = 100 * 1e6;
+      const liquidatorTokenAccount = await tokenAccount(debt.assetMint, liquidator.publicKey);
+      const liquidatorCollateralAccount = await tokenAccount(collateral.assetMint, liquidator.publicKey);
+      const tokensBefore = await balance(liquidatorTokenAccount);
+      const collateralBefore = await balance(liquidatorCollateralAccount);
+      const debtBefore = await debtOf(flashObligation, debt);
+
+      const end = await program.methods
+        .flashLiquidateEnd()
+        .accounts({
+          liquidator: liquidator.publicKey,
+          obligation: flashObligation,
+          borrowMarket: debt.market,
+          liquidatorTokenAccount,
+          borrowReserveVault: debt.reserveVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .instruction();
+      await (await flashLiquidateBegin(repayAmount)).postInstructions([end]).rpc();
+
+      expect(tokensBefore - (await balance(liquidatorTokenAccount))).to.equal(repayAmount);
+      expect((await balance(liquidatorCollateralAccount)) - collateralBefore).to.be.greaterThan(repayAmount / 0.9);
+      expect(debtBefore - (await debtOf(flashObligation, debt))).to.be.closeTo(repayAmount, 1e4);
+
+      const obligationAccount = await program.account.obligation.fetch(flashObligation);
+      expect(obligationAccount.flashLiquidationRepay.toNumber()).to.equal(0);
+    });
+  });
+
+  describe("Bad debt", () => {
+    let collateral: TestMarket;
+    let debt: TestMarket;
+    let borrower: Keypair;
+    let obligation: PublicKey;
+    let liquidator: Keypair;
+    let treasurySupplyAccount: PublicKey;
+    let treasuryTokenAccount: PublicKey;
+
+    before(async () => {
+      collateral = await createTestMarket(1);
+      debt = await createTestMarket(1);
+      await supplyTo(debt, await newUser(), 1_000 * 1e6);
+
+      borrower = await newUser();
+      obligation = await openObligation(borrower, collateral, 1_000 * 1e6);
+      await borrowFrom(debt, borrower, obligation, 740 * 1e6, [collateral, debt]);
+
+      liquidator = await newUser();
+      await mintTo(
+        provider.connection,
+        payer,
+        debt.assetMint,
+        await tokenAccount(debt.assetMint, liquidator.publicKey),
+        payer,
+        1_000 * 1e6
+      );
+      treasurySupplyAccount = await createAccount(
+        provider.connection,
+        payer,
+        collateral.supplyMint,
+        treasury,
+        Keypair.generate()
+      );
+
+      // Treasury insurance buffer for the debt asset
+      treasuryTokenAccount = await createAccount(provider.connection, payer, debt.assetMint, treasury, Keypair.generate());
+      await mintTo(provider.connection, payer, debt.assetMint, treasuryTokenAccount, payer, 100 * 1e6);
+    });
+
+    async function socializeBadDebt() {
+      await program.methods
+        .socializeBadDebt()
+        .accounts({
+          obligation,
+          market: debt.market,
+          reserveVault: debt.reserveVault,
+          globalConfig,
+          treasury,
+          treasuryTokenAccount,
+          insuranceFund: null,
+          insuranceVault: null,
+          isolatedCollateralMarket: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+    }
+
+    it("Rejects socializing debt while the obligation still has collateral", async () => {
+      try {
+        await socializeBadDebt();
+        expect.fail("Expected NoBadDebt");
+      } catch (err) {
+        expect(err.toString()).to.include("NoBadDebt");
+      }
+    });
+
+    it("Seizes all collateral once it is worth less than the debt", async () => {
+      // 1000 of collateral at $0.50 can't cover 740 of debt plus the 5% bonus
+      await setPrice(collateral.oracle, 0.5);
+
+      await program.methods
+        .liquidateToSupplyTokens(new anchor.BN(740 * 1e6), new anchor.BN(0))
+        .accounts({
+          liquidator: liquidator.publicKey,
+          obligation,
+          borrowMarket: debt.market,
+          collateralMarket: collateral.market,
+          liquidatorTokenAccount: await tokenAccount(debt.assetMint, liquidator.publicKey),
+          borrowReserveVault: debt.reserveVault,
+          collateralVault: collateral.collateralVault,
+          liquidatorSupplyAccount: await tokenAccount(collateral.supplyMint, liquidator.publicKey),
+          globalConfig,
+          treasurySupplyAccount,
+          borrowOracle: debt.oracle,
+          collateralOracle: collateral.oracle,
+          emodeCategory: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(positionAccounts([collateral, debt]))
+        .signers([liquidator])
+        .rpc();
+
+      // The repayment is scaled down to the $500 of collateral, bonus included
+      const obligationAccount = await program.account.obligation.fetch(obligation);
+      expect(obligationAccount.deposits).to.have.length(0);
+      expect(await debtOf(obligation, debt)).to.be.closeTo(740 * 1e6 - (500 * 1e6) / 1.05, 1e6);
+    });
+
+    it("Covers bad debt from the treasury buffer and socializes the rest", async () => {
+      const marketBefore = await program.account.market.fetch(debt.market);
+      const reserveBefore = await balance(debt.reserveVault);
+      const badDebt = await debtOf(obligation, debt);
+
+      await socializeBadDebt();
+
+      const obligationAccount = await program.account.obligation.fetch(obligation);
+      expect(obligationAccount.borrows).to.have.length(0);
+
+      // The whole buffer moves into the reserve
+      expect(await balance(treasuryTokenAccount)).to.equal(0);
+      expect((await balance(debt.reserveVault)) - reserveBefore).to.equal(100 * 1e6);
+
+      // Suppliers absorb what the buffer and protocol fees don't cover
+      const marketAfter = await program.account.market.fetch(debt.market);
+      const borrowedDrop = marketBefore.totalBorrowed.toNumber() - marketAfter.totalBorrowed.toNumber();
+      const suppliedDrop = marketBefore.totalSupplied.toNumber() - marketAfter.totalSupplied.toNumber();
+      expect(borrowedDrop).to.be.closeTo(badDebt, 1e4);
+      expect(suppliedDrop).to.be.closeTo(badDebt - 100 * 1e6, 1e4);
+    });
+  });
+
+  describe("Repaying with collateral", () => {
+    let collateral: TestMarket;
+    let debt: TestMarket;
+    let pool: TestPool;
+    let borrower: Keypair;
+    let obligation: PublicKey;
+
+    before(async () => {
+      collateral = await createTestMarket(1);
+      debt = await createTestMarket(1);
+      await supplyTo(debt, await newUser(), 1_000 * 1e6);
+      pool = await createTestPool(collateral.assetMint, debt.assetMint, 1_000_000 * 1e6);
+
+      borrower = await newUser();
+      obligation = await openObligation(borrower, collateral, 1_000 * 1e6);
+      await borrowFrom(debt, borrower, obligation, 500 * 1e6, [collateral, debt]);
+    });
+
+    async function repayWithCollateral(supplyTokens: number, minAmountOut: number) {
+      await program.methods
+        .repayWithCollateral(new anchor.BN(supplyTokens), new anchor.BN(minAmountOut))
+        .accounts({
+          owner: borrower.publicKey,
+          obligation,
+          borrowMarket: debt.market,
+          collateralMarket: collateral.market,
+          borrowReserveVault: debt.reserveVault,
+          collateralReserveVault: collateral.reserveVault,
+          collateralVault: collateral.collateralVault,
+          collateralSupplyMint: collateral.supplyMint,
+          ownerTokenAccount: await tokenAccount(debt.assetMint, borrower.publicKey),
+          borrowOracle: debt.oracle,
+          collateralOracle: collateral.oracle,
+          emodeCategory: null,
+          globalConfig,
+          swapProgram: mockAmm.programId,
+          swapPool: pool.pool,
+          swapPoolSourceVault: pool.vaultA,
+          swapPoolDestinationVault: pool.vaultB,
+          swapAuthority,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(positionAccounts([collateral, debt]))
+        .signers([borrower])
+        .rpc();
+    }
+
+    it("Repays debt by selling collateral through the mock AMM", async () => {
+      const supplyTokens = 200 * 1e6;
+      const debtBefore = await debtOf(obligation, debt);
+      const collateralBefore = await collateralOf(obligation, collateral);
+      const reserveBefore = await balance(collateral.reserveVault);
+
+      await repayWithCollateral(supplyTokens, 0);
+
+      // The collateral market has no borrows, so supply tokens still redeem 1:1
+      expect(collateralBefore - (await collateralOf(obligation, collateral))).to.equal(supplyTokens);
+      expect(reserveBefore - (await balance(collateral.reserveVault))).to.equal(supplyTokens);
+
+      // 0.3% fee plus a little price impact on the 1:1 pool
+      const repaid = debtBefore - (await debtOf(obligation, debt));
+      expect(repaid).to.be.greaterThan(supplyTokens * 0.99);
+      expect(repaid).to.be.lessThan(supplyTokens * 0.997);
+    });
+
+    it("Rejects a swap below the minimum output", async () => {
+      try {
+        await repayWithCollateral(100 * 1e6, 100 * 1e6);
+        expect.fail("Expected SlippageExceeded");
+      } catch (err) {
+        expect(err.toString()).to.include("SlippageExceeded");
+      }
+    });
+
+    it("Rejects a repayment that lowers the health factor", async () => {
+      // Dump collateral into the pool so it sells far below the oracle price
+      const dumpAmount = 1_000_000 * 1e6;
+      const payerCollateralAccount = await tokenAccount(collateral.assetMint, payer.publicKey);
+      await mintTo(provider.connection, payer, collateral.assetMint, payerCollateralAccount, payer, dumpAmount);
+      await mockAmm.methods
+        .swap(new anchor.BN(dumpAmount), new anchor.BN(0))
+        .accounts({
+          pool: pool.pool,
+          authority: payer.publicKey,
+          source: payerCollateralAccount,
+          destination: await tokenAccount(debt.assetMint, payer.publicKey),
+          poolSourceVault: pool.vaultA,
+          poolDestinationVault: pool.vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([payer])
+        .rpc();
+
+      const debtBefore = await debtOf(obligation, debt);
+      const collateralBefore = await collateralOf(obligation, collateral);
+      try {
+        await repayWithCollateral(100 * 1e6, 0);
+        expect.fail("Expected HealthFactorDecreased");
+      } catch (err) {
+        expect(err.toString()).to.include("HealthFactorDecreased");
+      }
+      expect(await debtOf(obligation, debt)).to.equal(debtBefore);
+      expect(await collateralOf(obligation, collateral)).to.equal(collateralBefore);
+    });
+  });
+
+  describe("Swapping positions", () => {
+    let sourceCollateral: TestMarket;
+    let destinationCollateral: TestMarket;
+    let oldDebt: TestMarket;
+    let newDebt: TestMarket;
+    let collateralPool: TestPool;
+    let debtPool: TestPool;
+    let borrower: Keypair;
+    let obligation: PublicKey;
+
+    before(async () => {
+      sourceCollateral = await createTestMarket(1);
+      // A low LTV makes the destination worth less towards the borrow limit
+      destinationCollateral = await createTestMarket(1, 3000, 8000);
+      oldDebt = await createTestMarket(1);
+      newDebt = await createTestMarket(1);
+      await supplyTo(oldDebt, await newUser(), 1_000 * 1e6);
+      await supplyTo(newDebt, await newUser(), 1_000 * 1e6);
+      collateralPool = await createTestPool(
+        sourceCollateral.assetMint,
+        destinationCollateral.assetMint,
+        1_000_000 * 1e6
+      );
+      debtPool = await createTestPool(newDebt.assetMint, oldDebt.assetMint, 1_000_000 * 1e6);
+
+      borrower = await newUser();
+      obligation = await openObligation(borrower, sourceCollateral, 1_000 * 1e6);
+      await borrowFrom(oldDebt, borrower, obligation, 500 * 1e6, [sourceCollateral, oldDebt]);
+    });
+
+    // The obligation's current deposits, then borrows, as health check accounts
+    async function currentPositions() {
+      const markets = [sourceCollateral, destinationCollateral, oldDebt, newDebt];
+      const account = await program.account.obligation.fetch(obligation);
+      return positionAccounts(
+        [...account.deposits, ...account.borrows].map((p) => markets.find((m) => m.market.equals(p.market))!)
+      );
+    }
+
+    async function swapCollateral(supplyTokens: number) {
+      await program.methods
+        .swapCollateral(new anchor.BN(supplyTokens), new anchor.BN(0))
+        .accounts({
+          owner: borrower.publicKey,
+          obligation,
+          sourceMarket: sourceCollateral.market,
+          destinationMarket: destinationCollateral.market,
+          sourceReserveVault: sourceCollateral.reserveVault,
+          destinationReserveVault: destinationCollateral.reserveVault,
+          sourceCollateralVault: sourceCollateral.collateralVault,
+          destinationCollateralVault: destinationCollateral.collateralVault,
+          sourceSupplyMint: sourceCollateral.supplyMint,
+          destinationSupplyMint: destinationCollateral.supplyMint,
+          sourceOracle: sourceCollateral.oracle,
+          destinationOracle: destinationCollateral.oracle,
+          emodeCategory: null,
+          globalConfig,
+          swapProgram: mockAmm.programId,
+          swapPool: collateralPool.pool,
+          swapPoolSourceVault: collateralPool.vaultA,
+          swapPoolDestinationVault: collateralPool.vaultB,
+          swapAuthority,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(await currentPositions())
+        .signers([borrower])
+        .rpc();
+    }
+
+    async function swapDebt(borrowAmount: number) {
+      await program.methods
+        .swapDebt(new anchor.BN(borrowAmount), new anchor.BN(0))
+        .accounts({
+          owner: borrower.publicKey,
+          obligation,
+          repayMarket: oldDebt.market,
+          borrowMarket: newDebt.market,
+          repayReserveVault: oldDebt.reserveVault,
+          borrowReserveVault: newDebt.reserveVault,
+          ownerTokenAccount: await tokenAccount(oldDebt.assetMint, borrower.publicKey),
+          repayOracle: oldDebt.oracle,
+          borrowOracle: newDebt.oracle,
+          emodeCategory: null,
+          isolatedCollateralMarket: null,
+          globalConfig,
+          swapProgram: mockAmm.programId,
+          swapPool: debtPool.pool,
+          swapPoolSourceVault: debtPool.vaultA,
+          swapPoolDestinationVault: debtPool.vaultB,
+          swapAuthority,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(await currentPositions())
+        .signers([borrower])
+        .rpc();
+    }
+
+    it("Rotates collateral into another market", async () => {
+      const supplyTokens = 400 * 1e6;
+      const debtBefore = await debtOf(obligation, oldDebt);
+      const reserveBefore = await balance(destinationCollateral.reserveVault);
+
+      await swapCollateral(supplyTokens);
+
+      expect(await collateralOf(obligation, sourceCollateral)).to.equal(1_000 * 1e6 - supplyTokens);
+
+      // The output is supplied 1:1 into the empty destination market
+      const received = await collateralOf(obligation, destinationCollateral);
+      expect(received).to.be.greaterThan(supplyTokens * 0.99);
+      expect(received).to.be.lessThan(supplyTokens * 0.997);
+      expect((await balance(destinationCollateral.reserveVault)) - reserveBefore).to.equal(received);
+      expect(await debtOf(obligation, oldDebt)).to.equal(debtBefore);
+    });
+
+    it("Rejects a collateral swap that breaks the borrow limit", async () => {
+      // 200 * 75% + ~797 * 30% of borrow limit against 500 of debt
+      const sourceBefore = await collateralOf(obligation, sourceCollateral);
+      const destinationBefore = await collateralOf(obligation, destinationCollateral);
+      try {
+        await swapCollateral(400 * 1e6);
+        expect.fail("Expected WithdrawWouldCauseLiquidation");
+      } catch (err) {
+        expect(err.toString()).to.include("WithdrawWouldCauseLiquidation");
+      }
+      expect(await collateralOf(obligation, sourceCollateral)).to.equal(sourceBefore);
+      expect(await collateralOf(obligation, destinationCollateral)).to.equal(destinationBefore);
+    });
+
+    it("Refinances a borrow into another debt asset", async () => {
+      const borrowAmount = 200 * 1e6;
+      const oldDebtBefore = await debtOf(obligation, oldDebt);
+
+      await swapDebt(borrowAmount);
+
+      expect(await debtOf(obligation, newDebt)).to.equal(borrowAmount);
+      const repaid = oldDebtBefore - (await debtOf(obligation, oldDebt));
+      expect(repaid).to.be.greaterThan(borrowAmount * 0.99);
+      expect(repaid).to.be.lessThan(borrowAmount * 0.997);
+    });
+
+    it("Rejects a debt swap that breaks the borrow limit", async () => {
+      // Repays the ~300 of old debt but leaves 600 of new debt against ~570 of borrow limit
+      const oldDebtBefore = await debtOf(obligation, oldDebt);
+      const newDebtBefore = await debtOf(obligation, newDebt);
+      try {
+        await swapDebt(400 * 1e6);
+        expect.fail("Expected BorrowWouldCauseLiquidation");
+      } catch (err) {
+        expect(err.toString()).to.include("BorrowWouldCauseLiquidation");
+      }
+      expect(await debtOf(obligation, oldDebt)).to.equal(oldDebtBefore);
+      expect(await debtOf(obligation, newDebt)).to.equal(newDebtBefore);
+    });
+  });
+
+  describe("Reserve outflow limit", () => {
+    let m: TestMarket;
+    let supplier: Keypair;
+    let supplierSupplyAccount: PublicKey;
+
+    before(async () => {
+      m = await createTestMarket(1);
+      supplier = await newUser();
+      supplierSupplyAccount = await supplyTo(m, supplier, 1_000 * 1e6);
+
+      await program.methods
+        .setOutflowLimit(new anchor.BN(150 * 1e6), new anchor.BN(3600))
+        .accounts({
+          authority: authority.publicKey,
+          globalConfig,
+          market: m.market,
+        })
+        .rpc();
+    });
+
+    async function withdraw(supplyTokens: number, reserveVault = m.reserveVault) {
+      await program.methods
+        .withdraw(new anchor.BN(supplyTokens))
+        .accounts({
+          user: supplier.publicKey,
+          market: m.market,
+          userSupplyAccount: supplierSupplyAccount,
+          supplyMint: m.supplyMint,
+          reserveVault,
+          userTokenAccount: await tokenAccount(m.assetMint, supplier.publicKey),
+          oracle: m.oracle,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([supplier])
+        .rpc();
+    }
+
+    it("Rejects withdrawals beyond the outflow limit in a window", async () => {
+      await withdraw(100 * 1e6);
+
+      // 100 already left the reserve this window; another 100 would exceed 150
+      try {
+        await withdraw(100 * 1e6);
+        expect.fail("Expected OutflowLimitExceeded");
+      } catch (err) {
+        expect(err.toString()).to.include("OutflowLimitExceeded");
+      }
+      expect(await balance(m.reserveVault)).to.equal(900 * 1e6);
+    });
+
+    it("Rejects supplying into a token account that is not the reserve vault", async () => {
+      // Supplying to one's own account would mint supply tokens and credit inflow for free
+      const ownAccount = await tokenAccount(m.assetMint, supplier.publicKey);
+      await mintTo(provider.connection, payer, m.assetMint, ownAccount, payer, 1_000 * 1e6);
+      try {
+        await program.methods
+          .supply(new anchor.BN(1_000 * 1e6))
+          .accounts({
+            user: supplier.publicKey,
+            market: m.market,
+            userTokenAccount: ownAccount,
+            reserveVault: ownAccount,
+            supplyMint: m.supplyMint,
+            userSupplyAccount: supplierSupplyAccount,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([supplier])
+          .rpc();
+        expect.fail("Expected InvalidMarketConfig");
+      } catch (err) {
+        expect(err.toString()).to.include("InvalidMarketConfig");
+      }
+    });
+
+    it("Rejects withdrawing from a token account that is not the reserve vault", async () => {
+      const decoy = await createAccount(provider.connection, payer, m.assetMint, m.market, Keypair.generate());
+      try {
+        await withdraw(10 * 1e6, decoy);
+        expect.fail("Expected InvalidMarketConfig");
+      } catch (err) {
+        expect(err.toString()).to.include("InvalidMarketConfig");
+      }
+    });
+  });
+
+  describe("Insurance fund unstaking", () => {
+    interface StakedFund {
+      insuranceFund: PublicKey;
+      shareMint: PublicKey;
+      vault: PublicKey;
+      stakeCooldown: PublicKey;
+      stakerTokenAccount: PublicKey;
+      stakerShareAccount: PublicKey;
+    }
+
+    let staker: Keypair;
+
+    before(async () => {
+      staker = await newUser();
+    });
+
+    // Create an insurance fund for a fresh market, stake 1000 into it and request to unstake it all
+    async function stakedFund(cooldownPeriod: number): Promise<StakedFund> {
+      const m = await createTestMarket(1);
+      const [insuranceFund] = PublicKey.findProgramAddressSync(
+        [Buffer.from("insurance_fund"), m.assetMint.toBuffer()],
+        program.programId
+      );
+      const shareMint = await createMint(provider.connection, payer, insuranceFund, null, 6);
+      const vault = await createAccount(provider.connection, payer, m.assetMint, insuranceFund, Keypair.generate());
+
+      await program.methods
+        .createInsuranceFund(new anchor.BN(cooldownPeriod), 3000, 2000)
+        .accounts({
+          authority: authority.publicKey,
+          globalConfig,
+          market: m.market,
+          insuranceFund,
+          shareMint,
+          vault,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+      const stakerTokenAccount = await tokenAccount(m.assetMint, staker.publicKey);
+      const stakerShareAccount = await tokenAccount(shareMint, staker.publicKey);
+      await mintTo(provider.connection, payer, m.assetMint, stakerTokenAccount, payer, 1_000 * 1e6);
+      await program.methods
+        .stakeInsurance(new anchor.BN(1_000 * 1e6))
+        .accounts({
+          staker: staker.publicKey,
+          insuranceFund,
+          vault,
+          shareMint,
+          stakerTokenAccount,
+          stakerShareAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([staker])
+        .rpc();
+
+      const [stakeCooldown] = PublicKey.findProgramAddressSync(
+        [Buffer.from("insurance_cooldown"), insuranceFund.toBuffer(), staker.publicKey.toBuffer()],
+        program.programId
+      );
+      await program.methods
+        .requestUnstake(new anchor.BN(1_000 * 1e6))
+        .accounts({
+          staker: staker.publicKey,
+          insuranceFund,
+          stakeCooldown,
+          stakerShareAccount,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([staker])
+        .rpc();
+
+      return { insuranceFund, shareMint, vault, stakeCooldown, stakerTokenAccount, stakerShareAccount };
+    }
+
+    async function unstake(fund: StakedFund, shares: number) {
+      await program.methods
+        .unstake(new anchor.BN(shares))
+        .accounts({
+          staker: staker.publicKey,
+          insuranceFund: fund.insuranceFund,
+          stakeCooldown: fund.stakeCooldown,
+          vault: fund.vault,
+          shareMint: fund.shareMint,
+          stakerShareAccount: fund.stakerShareAccount,
+          stakerTokenAccount: fund.stakerTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([staker])
+        .rpc();
+    }
+
+    it("Rejects unstaking before the cooldown ends", async () => {
+      const fund = await stakedFund(7 * 86400);
+      try {
+        await unstake(fund, 1_000 * 1e6);
+        expect.fail("Expected InsuranceCooldownActive");
+      } catch (err) {
+        expect(err.toString()).to.include("InsuranceCooldownActive");
+      }
+      expect(await balance(fund.stakerShareAccount)).to.equal(1_000 * 1e6);
+    });
+
+    it("Unstakes within the window after the cooldown", async () => {
+      // A zero cooldown opens the unstake window as soon as it is requested
+      const fund = await stakedFund(0);
+      await unstake(fund, 400 * 1e6);
+
+      expect(await balance(fund.stakerShareAccount)).to.equal(600 * 1e6);
+      const cooldown = await program.account.stakeCooldown.fetch(fund.stakeCooldown);
+      expect(cooldown.shares.toNumber()).to.equal(600 * 1e6);
+      // One virtual share and asset round the first staker's redemption down by at most one unit
+      expect(await balance(fund.stakerTokenAccount)).to.be.closeTo(400 * 1e6, 1);
+    });
+  });
+
+  describe("Leveraged positions", () => {
+    let collateral: TestMarket;
+    let debt: TestMarket;
+    let pool: TestPool;
+
+    before(async () => {
+      collateral = await createTestMarket(1);
+      debt = await createTestMarket(1);
+      await supplyTo(debt, await newUser(), 10_000 * 1e6);
+      pool = await createTestPool(debt.assetMint, collateral.assetMint, 1_000_000 * 1e6);
+    });
+
+    // A user with an empty obligation and 100 of the collateral asset
+    async function newLeverageUser(): Promise<{ owner: Keypair; obligation: PublicKey }> {
+      const owner = await newUser();
+      const [obligation] = PublicKey.findProgramAddressSync(
+        [Buffer.from("obligation"), owner.publicKey.toBuffer()],
+        program.programId
+      );
+      await program.methods
+        .initObligation()
+        .accounts({
+          owner: owner.publicKey,
+          obligation,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([owner])
+        .rpc();
+      await mintTo(
+        provider.connection,
+        payer,
+        collateral.assetMint,
+        await tokenAccount(collateral.assetMint, owner.publicKey),
+        payer,
+        100 * 1e6
+      );
+      return { owner, obligation };
+    }
+
+    async function openLeveragedPosition(
+      owner: Keypair,
+      obligation: PublicKey,
+      depositAmount: number,
+      targetLeverageBps: number,
+      minAmountOut: number
+    ): Promise<string> {
+      return program.methods
+        .openLeveragedPosition(new anchor.BN(depositAmount), targetLeverageBps, new anchor.BN(minAmountOut))
+        .accounts({
+          owner: owner.publicKey,
+          obligation,
+          borrowMarket: debt.market,
+          collateralMarket: collateral.market,
+          borrowReserveVault: debt.reserveVault,
+          collateralReserveVault: collateral.reserveVault,
+          collateralVault: collateral.collateralVault,
+          collateralSupplyMint: collateral.supplyMint,
+          ownerCollateralAccount: await tokenAccount(collateral.assetMint, owner.publicKey),
+          borrowOracle: debt.oracle,
+          collateralOracle: collateral.oracle,
+          emodeCategory: null,
+          globalConfig,
+          swapProgram: mockAmm.programId,
+          swapPool: pool.pool,
+          swapPoolSourceVault: pool.vaultA,
+          swapPoolDestinationVault: pool.vaultB,
+          swapAuthority,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(positionAccounts([collateral, debt]))
+        .signers([owner])
+        .rpc({ commitment: "confirmed" });
+    }
+
+    it("Opens a leveraged position in one instruction", async () => {
+      const { owner, obligation } = await newLeverageUser();
+      const ownerCollateralAccount = await tokenAccount(collateral.assetMint, owner.publicKey);
+      const collateralVaultBefore = await balance(collateral.collateralVault);
+
+      // 2.5x on 100 of collateral borrows 150 and swaps it into more collateral
+      const signature = await openLeveragedPosition(owner, obligation, 100 * 1e6, 25_000, 0);
+
+      expect(await balance(ownerCollateralAccount)).to.equal(0);
+      expect(await debtOf(obligation, debt)).to.equal(150 * 1e6);
+
+      // The deposit plus the swap output, supplied 1:1 into the fresh market
+      const supplyTokens = await collateralOf(obligation, collateral);
+      expect(supplyTokens - 100 * 1e6).to.be.greaterThan(150 * 1e6 * 0.99);
+      expect(supplyTokens - 100 * 1e6).to.be.lessThan(150 * 1e6 * 0.997);
+      expect((await balance(collateral.collateralVault)) - collateralVaultBefore).to.equal(supplyTokens);
+
+      // ~249.5 of collateral at an 80% threshold against 150 of debt
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const parser = new anchor.EventParser(program.programId, new anchor.BorshCoder(program.idl));
+      const opened = [...parser.parseLogs(tx!.meta!.logMessages!)].find(
+        (e) => e.name.toLowerCase() === "leveragedpositionopened"
+      );
+      const healthFactor = opened!.data.healthFactor as number;
+      expect(healthFactor).to.be.at.least(10_000);
+      expect(healthFactor).to.be.closeTo((supplyTokens * 0.8 * 10_000) / (150 * 1e6), 5);
+    });
+
+    it("Rejects a swap below the minimum output", async () => {
+      const { owner, obligation } = await newLeverageUser();
+      try {
+        await openLeveragedPosition(owner, obligation, 100 * 1e6, 25_000, 150 * 1e6);
+        expect.fail("Expected SlippageExceeded");
+      } catch (err) {
+        expect(err.toString()).to.include("SlippageExceeded");
+      }
+      expect(await debtOf(obligation, debt)).to.equal(0);
+    });
+
+    it("Rejects leverage that breaks the borrow limit", async () => {
+      // 5x borrows 400 against ~499 of collateral, above its 75% LTV
+      const { owner, obligation } = await newLeverageUser();
+      try {
+        await openLeveragedPosition(owner, obligation, 100 * 1e6, 50_000, 0);
+        expect.fail("Expected BorrowWouldCauseLiquidation");
+      } catch (err) {
+        expect(err.toString()).to.include("BorrowWouldCauseLiquidation");
+      }
+      expect(await debtOf(obligation, debt)).to.equal(0);
+      expect(await collateralOf(obligation, collateral)).to.equal(0);
+    });
+  });
+
+  describe("Liquidation auctions", () => {
+    let collateral: TestMarket;
+    let cancelCollateral: TestMarket;
+    let debt: TestMarket;
+    let obligation: PublicKey;
+    let cancelObligation: PublicKey;
+    let bidders: Keypair[];
+    let treasurySupplyAccount: PublicKey;
+
+    before(async () => {
+      collateral = await createTestMarket(1);
+      cancelCollateral = await createTestMarket(1);
+      debt = await createTestMarket(1);
+      await supplyTo(debt, await newUser(), 2_000 * 1e6);
+
+      // 740 borrowed against 1000 of collateral at a 75% LTV and 80% threshold
+      const borrower = await newUser();
+      obligation = await openObligation(borrower, collateral, 1_000 * 1e6);
+      await borrowFrom(debt, borrower, obligation, 740 * 1e6, [collateral, debt]);
+      const cancelBorrower = await newUser();
+      cancelObligation = await openObligation(cancelBorrower, cancelCollateral, 1_000 * 1e6);
+      await borrowFrom(debt, cancelBorrower, cancelObligation, 740 * 1e6, [cancelCollateral, debt]);
+
+      bidders = [await newUser(), await newUser()];
+      for (const bidder of bidders) {
+        await mintTo(
+          provider.connection,
+          payer,
+          debt.assetMint,
+          await tokenAccount(debt.assetMint, bidder.publicKey),
+          payer,
+          1_000 * 1e6
+        );
+      }
+      treasurySupplyAccount = await createAccount(
+        provider.connection,
+        payer,
+        collateral.supplyMint,
+        treasury,
+        Keypair.generate()
+      );
+
+      // 900 * 80% = 720 of liquidation threshold against 740 of debt
+      await setPrice(collateral.oracle, 0.9);
+      await setPrice(cancelCollateral.oracle, 0.9);
+    });
+
+    function auctionAccounts(obligationKey: PublicKey) {
+      const [auction] = PublicKey.findProgramAddressSync(
+        [Buffer.from("liquidation_auction"), obligationKey.toBuffer()],
+        program.programId
+      );
+      const [bidVault] = PublicKey.findProgramAddressSync(
+        [Buffer.from("auction_vault"), auction.toBuffer()],
+        program.programId
+      );
+      return { auction, bidVault };
+    }
+
+    async function startAuction(obligationKey: PublicKey, c: TestMarket, duration: number) {
+      // Bidding time is fixed when the auction starts
+      await program.methods
+        .setAuctionLiquidation(new anchor.BN(50 * 1e6), new anchor.BN(duration))
+        .accounts({
+          authority: authority.publicKey,
+          globalConfig,
+          market: debt.market,
+        })
+        .rpc();
+
+      const { auction, bidVault } = auctionAccounts(obligationKey);
+      await program.methods
+        .startLiquidationAuction(new anchor.BN(100 * 1e6))
+        .accounts({
+          creator: payer.publicKey,
+          obligation: obligationKey,
+          borrowMarket: debt.market,
+          collateralMarket: c.market,
+          borrowAssetMint: debt.assetMint,
+          borrowOracle: debt.oracle,
+          collateralOracle: c.oracle,
+          emodeCategory: null,
+          auction,
+          bidVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .remainingAccounts(positionAccounts([c, debt]))
+        .signers([payer])
+        .rpc();
+    }
+
+    async function bid(obligationKey: PublicKey, bidder: Keypair, supplyTokens: number, previous: Keypair | null) {
+      const { auction, bidVault } = auctionAccounts(obligationKey);
+      await program.methods
+        .bidLiquidationAuction(new anchor.BN(supplyTokens))
+        .accounts({
+          bidder: bidder.publicKey,
+          auction,
+          bidVault,
+          bidderTokenAccount: await tokenAccount(debt.assetMint, bidder.publicKey),
+          previousBidderTokenAccount: previous ? await tokenAccount(debt.assetMint, previous.publicKey) : null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([bidder])
+        .rpc();
+    }
+
+    async function cancelAuction(bestBidder: Keypair) {
+      const { auction, bidVault } = auctionAccounts(cancelObligation);
+      await program.methods
+        .cancelLiquidationAuction()
+        .accounts({
+          creator: payer.publicKey,
+          auction,
+          bidVault,
+          obligation: cancelObligation,
+          emodeCategory: null,
+          bestBidderTokenAccount: await tokenAccount(debt.assetMint, bestBidder.publicKey),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(positionAccounts([cancelCollateral, debt]))
+        .rpc();
+    }
+
+    it("Starts an auction that locks the obligation", async () => {
+      await startAuction(obligation, collateral, 3);
+
+      const { auction } = auctionAccounts(obligation);
+      const auctionAccount = await program.account.liquidationAuction.fetch(auction);
+      expect(auctionAccount.repayAmount.toNumber()).to.equal(100 * 1e6);
+      expect(auctionAccount.supplyTokens.toNumber()).to.be.greaterThan((100 * 1e6) / 0.9);
+      expect(auctionAccount.startHealthFactor).to.be.lessThan(10000);
+
+      const obligationAccount = await program.account.obligation.fetch(obligation);
+      expect(obligationAccount.liquidationAuction.equals(auction)).to.be.true;
+    });
+
+    it("Escrows bids and refunds the outbid bidder", async () => {
+      const { auction, bidVault } = auctionAccounts(obligation);
+      const lot = (await program.account.liquidationAuction.fetch(auction)).supplyTokens.toNumber();
+      const [first, second] = bidders;
+      const firstAccount = await tokenAccount(debt.assetMint, first.publicKey);
+      const firstBefore = await balance(firstAccount);
+
+      await bid(obligation, first, lot, null);
+      expect(firstBefore - (await balance(firstAccount))).to.equal(100 * 1e6);
+      expect(await balance(bidVault)).to.equal(100 * 1e6);
+
+      try {
+        await bid(obligation, second, lot, first);
+        expect.fail("Expected BidNotBetter");
+      } catch (err) {
+        expect(err.toString()).to.include("BidNotBetter");
+      }
+
+      await bid(obligation, second, 112 * 1e6, first);
+      expect(await balance(firstAccount)).to.equal(firstBefore);
+      expect(await balance(bidVault)).to.equal(100 * 1e6);
+
+      const auctionAccount = await program.account.liquidationAuction.fetch(auction);
+      expect(auctionAccount.bestBidder.equals(second.publicKey)).to.be.true;
+      expect(auctionAccount.bestBid.toNumber()).to.equal(112 * 1e6);
+    });
+
+    it("Settles the best bid after the auction ends", async () => {
+      const { auction } = auctionAccounts(obligation);
+      const winner = bidders[1];
+      const winnerSupplyAccount = await tokenAccount(collateral.supplyMint, winner.publicKey);
+      const debtBefore = await debtOf(obligation, debt);
+      const collateralBefore = await collateralOf(obligation, collateral);
+
+      await new Promise((resolve) => setTimeout(resolve, 4000));
+      await program.methods
+        .settleLiquidationAuction()
+        .accounts({
+          creator: payer.publicKey,
+          auction,
+          bidVault: auctionAccounts(obligation).bidVault,
+          obligation,
+          borrowMarket: debt.market,
+          collateralMarket: collateral.market,
+          borrowReserveVault: debt.reserveVault,
+          collateralVault: collateral.collateralVault,
+          winnerSupplyAccount,
+          globalConfig,
+          treasurySupplyAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      // The bid implies a bonus under 5%, of which the treasury takes its share
+      const won = await balance(winnerSupplyAccount);
+      expect(won + (await balance(treasurySupplyAccount))).to.equal(112 * 1e6);
+      expect(won).to.be.greaterThan(111 * 1e6);
+      expect(collateralBefore - (await collateralOf(obligation, collateral))).to.equal(112 * 1e6);
+      expect(debtBefore - (await debtOf(obligation, debt))).to.be.closeTo(100 * 1e6, 1e4);
+
+      const obligationAccount = await program.account.obligation.fetch(obligation);
+      expect(obligationAccount.liquidationAuction.equals(PublicKey.default)).to.be.true;
+      expect(await provider.connection.getAccountInfo(auction)).to.be.null;
+    });
+
+    it("Rejects cancelling while the health factor holds", async () => {
+      await startAuction(cancelObligation, cancelCollateral, 600);
+      const { auction } = auctionAccounts(cancelObligation);
+      const lot = (await program.account.liquidationAuction.fetch(auction)).supplyTokens.toNumber();
+      await bid(cancelObligation, bidders[0], lot, null);
+
+      try {
+        await cancelAuction(bidders[0]);
+        expect.fail("Expected AuctionNotCancellable");
+      } catch (err) {
+        expect(err.toString()).to.include("AuctionNotCancellable");
+      }
+    });
+
+    it("Cancels the auction and refunds the bid once the health factor falls", async () => {
+      const bidderAccount = await tokenAccount(debt.assetMint, bidders[0].publicKey);
+      const bidderBefore = await balance(bidderAccount);
+
+      // 800 * 80% = 640 against 740 of debt, well below the starting health factor
+      await setPrice(cancelCollateral.oracle, 0.8);
+      await cancelAuction(bidders[0]);
+
+      expect((await balance(bidderAccount)) - bidderBefore).to.equal(100 * 1e6);
+      const { auction } = auctionAccounts(cancelObligation);
+      expect(await provider.connection.getAccountInfo(auction)).to.be.null;
+
+      // Back to the fixed-price instructions, with the debt untouched
+      const obligationAccount = await program.account.obligation.fetch(cancelObligation);
+      expect(obligationAccount.liquidationAuction.equals(PublicKey.default)).to.be.true;
+      expect(await debtOf(cancelObligation, debt)).to.be.closeTo(740 * 1e6, 1e5);
+    });
+  });
+
+  describe("Vault rebalancing", () => {
+    let m: TestMarket;
+    let vault: PublicKey;
+    let assetVault: PublicKey;
+    let shareMint: PublicKey;
+    let feeRecipient: PublicKey;
+    let withdrawalEscrow: PublicKey;
+    let vaultSupplyAccount: PublicKey;
+
+    before(async () => {
+      m = await createTestMarket(1);
+      [vault] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), payer.publicKey.toBuffer(), new anchor.BN(2).toArrayLike(Buffer, "le", 2)],
+        program.programId
+      );
+      shareMint = await createMint(provider.connection, payer, vault, null, 6);
+      assetVault = await createAccount(provider.connection, payer, m.assetMint, vault, Keypair.generate());
+      feeRecipient = await createAccount(provider.connection, payer, shareMint, payer.publicKey, Keypair.generate());
+      withdrawalEscrow = await createAccount(provider.connection, payer, shareMint, vault, Keypair.generate());
+      vaultSupplyAccount = await createAccount(provider.connection, payer, m.supplyMint, vault, Keypair.generate());
+
+      // Balanced strategy, no fees and no timelock
+      await program.methods
+        .createVault(2, 1, 500, 0, 0, new anchor.BN(0))
+        .accounts({
+          owner: payer.publicKey,
+          vault,
+          assetMint: m.assetMint,
+          assetVault,
+          shareMint,
+          feeRecipient,
+          withdrawalEscrow,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([payer])
+        .rpc();
+
+      const [pendingCap] = PublicKey.findProgramAddressSync(
+        [Buffer.from("pending_vault_cap"), vault.toBuffer(), m.market.toBuffer()],
+        program.programId
+      );
+      await program.methods
+        .submitVaultCap(new anchor.BN(1_000 * 1e6))
+        .accounts({ curator: payer.publicKey, vault, market: m.market, pendingCap, systemProgram: SystemProgram.programId })
+        .signers([payer])
+        .rpc();
+      await program.methods
+        .acceptVaultCap()
+        .accounts({ curator: payer.publicKey, vault, pendingCap, submitter: payer.publicKey, systemProgram: SystemProgram.programId })
+        .signers([payer])
+        .rpc();
+      await setAllocations(1_000 * 1e6);
+
+      const userTokenAccount = await tokenAccount(m.assetMint, payer.publicKey);
+      await mintTo(provider.connection, payer, m.assetMint, userTokenAccount, payer, 500 * 1e6);
+      await program.methods
+        .vaultDeposit(new anchor.BN(500 * 1e6))
+        .accounts({
+          user: payer.publicKey,
+          vault,
+          assetVault,
+          shareMint,
+          feeRecipient,
+          userTokenAccount,
+          receiverShareAccount: await tokenAccount(shareMint, payer.publicKey),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([payer])
+        .rpc();
+    });
+
+    async function setAllocations(cap: number) {
+      await program.methods
+        .setAllocations([{ market: m.market, targetBps: 10000, cap: new anchor.BN(cap) }])
+        .accounts({ curator: payer.publicKey, vault, systemProgram: SystemProgram.programId })
+        .remainingAccounts([{ pubkey: m.market, isWritable: false, isSigner: false }])
+        .signers([payer])
+        .rpc();
+    }
+
+    async function rebalance(reserveVault: PublicKey) {
+      await program.methods
+        .rebalanceVault()
+        .accounts({
+          allocator: payer.publicKey,
+          vault,
+          assetVault,
+          shareMint,
+          feeRecipient,
+          withdrawalEscrow,
+          lendingProgram: program.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts([
+          { pubkey: m.market, isWritable: true, isSigner: false },
+          { pubkey: reserveVault, isWritable: true, isSigner: false },
+          { pubkey: m.supplyMint, isWritable: true, isSigner: false },
+          { pubkey: vaultSupplyAccount, isWritable: true, isSigner: false },
+          { pubkey: m.oracle, isWritable: false, isSigner: false },
+        ])
+        .signers([payer])
+        .rpc();
+    }
+
+    async function currentSupplyTokens(): Promise<number> {
+      const vaultAccount = await program.account.vault.fetch(vault);
+      return vaultAccount.allocations[0].currentSupplyTokens.toNumber();
+    }
+
+    it("Rejects a rebalance with a token account that is not the market's reserve", async () => {
+      // A decoy balance would overstate how much the market can pay out
+      const decoy = await createAccount(provider.connection, payer, m.assetMint, payer.publicKey, Keypair.generate());
+      await mintTo(provider.connection, payer, m.assetMint, decoy, payer, 10_000 * 1e6);
+      try {
+        await rebalance(decoy);
+        expect.fail("Expected InvalidVaultAccount");
+      } catch (err) {
+        expect(err.toString()).to.include("InvalidVaultAccount");
+      }
+      expect(await currentSupplyTokens()).to.equal(0);
+    });
+
+    it("Supplies idle assets to the market", async () => {
+      await rebalance(m.reserveVault);
+
+      expect(await balance(assetVault)).to.equal(0);
+      expect(await balance(m.reserveVault)).to.equal(500 * 1e6);
+      expect(await currentSupplyTokens()).to.equal(await balance(vaultSupplyAccount));
+      expect(await balance(vaultSupplyAccount)).to.be.greaterThan(0);
+    });
+
+    it("Withdraws from the market when its cap is lowered", async () => {
+      // Markets are one per asset, so moving funds out means shrinking the target
+      await setAllocations(200 * 1e6);
+      const supplyTokensBefore = await currentSupplyTokens();
+
+      await rebalance(m.reserveVault);
+
+      expect(await balance(assetVault)).to.be.closeTo(300 * 1e6, 1e3);
+      expect(await balance(m.reserveVault)).to.be.closeTo(200 * 1e6, 1e3);
+      const supplyTokensAfter = await currentSupplyTokens();
+      expect(supplyTokensAfter).to.be.lessThan(supplyTokensBefore);
+      expect(supplyTokensAfter).to.equal(await balance(vaultSupplyAccount));
+    });
+  });
+
+  describe("Auto-deleverage", () => {
+    let collateral: TestMarket;
+    let debt: TestMarket;
+    let pool: TestPool;
+    let keeper: Keypair;
+    let borrowers: Keypair[];
+    let obligations: PublicKey[];
+
+    before(async () => {
+      collateral = await createTestMarket(1);
+      debt = await createTestMarket(1);
+      await supplyTo(debt, await newUser(), 2_000 * 1e6);
+      pool = await createTestPool(collateral.assetMint, debt.assetMint, 1_000_000 * 1e6);
+      keeper = await newUser();
+
+      // 600 borrowed against 1000 of collateral: a health factor of 1.33, triggering below 1.25
+      borrowers = [await newUser(), await newUser()];
+      obligations = [];
+      for (const borrower of borrowers) {
+        const obligation = await openObligation(borrower, collateral, 1_000 * 1e6);
+        await borrowFrom(debt, borrower, obligation, 600 * 1e6, [collateral, debt]);
+        await program.methods
+          .configureAutoDeleverage(12500, 10000, 100)
+          .accounts({ owner: borrower.publicKey, obligation })
+          .signers([borrower])
+          .rpc();
+        obligations.push(obligation);
+      }
+    });
+
+    async function autoDeleverage(index: number) {
+      await program.methods
+        .autoDeleverage()
+        .accounts({
+          keeper: keeper.publicKey,
+          obligation: obligations[index],
+          borrowMarket: debt.market,
+          collateralMarket: collateral.market,
+          borrowReserveVault: debt.reserveVault,
+          collateralReserveVault: collateral.reserveVault,
+          collateralVault: collateral.collateralVault,
+          collateralSupplyMint: collateral.supplyMint,
+          keeperCollateralAccount: await tokenAccount(collateral.assetMint, keeper.publicKey),
+          ownerTokenAccount: await tokenAccount(debt.assetMint, borrowers[index].publicKey),
+          borrowOracle: debt.oracle,
+          collateralOracle: collateral.oracle,
+          emodeCategory: null,
+          globalConfig,
+          swapProgram: mockAmm.programId,
+          swapPool: pool.pool,
+          swapPoolSourceVault: pool.vaultA,
+          swapPoolDestinationVault: pool.vaultB,
+          swapAuthority,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(positionAccounts([collateral, debt]))
+        .signers([keeper])
+        .rpc();
+    }
+
+    it("Rejects deleveraging above the owner's trigger", async () => {
+      try {
+        await autoDeleverage(0);
+        expect.fail("Expected AutoDeleverageNotTriggered");
+      } catch (err) {
+        expect(err.toString()).to.include("AutoDeleverageNotTriggered");
+      }
+    });
+
+    it("Repays the debt and refunds the excess swap output to the owner", async () => {
+      // 900 * 80% = 720 against 600 of debt is a health factor of 1.2
+      await setPrice(collateral.oracle, 0.9);
+
+      const ownerTokenAccount = await tokenAccount(debt.assetMint, borrowers[0].publicKey);
+      const ownerBefore = await balance(ownerTokenAccount);
+      const collateralBefore = await collateralOf(obligations[0], collateral);
+      const supplyBefore = (await program.account.market.fetch(debt.market)).totalSupplied.toNumber();
+
+      await autoDeleverage(0);
+
+      // ~667 of collateral at the oracle price sells for ~664 on the 1:1 pool
+      expect(await debtOf(obligations[0], debt)).to.equal(0);
+      expect((await balance(ownerTokenAccount)) - ownerBefore).to.be.closeTo(64 * 1e6, 2e6);
+      expect(collateralBefore - (await collateralOf(obligations[0], collateral))).to.be.closeTo(670 * 1e6, 1e6);
+      expect(await balance(await tokenAccount(collateral.assetMint, keeper.publicKey))).to.be.greaterThan(0);
+
+      // The excess is not credited to suppliers
+      const supplyAfter = (await program.account.market.fetch(debt.market)).totalSupplied.toNumber();
+      expect(supplyAfter - supplyBefore).to.be.lessThan(1e6);
+
+      // The swap authority's delegation over the collateral reserve is revoked
+      const reserve = await getAccount(provider.connection, collateral.reserveVault);
+      expect(reserve.delegate).to.be.null;
+      expect(Number(reserve.delegatedAmount)).to.equal(0);
+    });
+
+    it("Rejects a swap below the owner's slippage limit", async () => {
+      // Dump collateral into the pool so it sells far below the oracle price
+      const dumpAmount = 1_000_000 * 1e6;
+      const payerCollateralAccount = await tokenAccount(collateral.assetMint, payer.publicKey);
+      await mintTo(provider.connection, payer, collateral.assetMint, payerCollateralAccount, payer, dumpAmount);
+      await mockAmm.methods
+        .swap(new anchor.BN(dumpAmount), new anchor.BN(0))
+        .accounts({
+          pool: pool.pool,
+          authority: payer.publicKey,
+          source: payerCollateralAccount,
+          destination: await tokenAccount(debt.assetMint, payer.publicKey),
+          poolSourceVault: pool.vaultA,
+          poolDestinationVault: pool.vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([payer])
+        .rpc();
+
+      const debtBefore = await debtOf(obligations[1], debt);
+      try {
+        await autoDeleverage(1);
+        expect.fail("Expected SlippageExceeded");
+      } catch (err) {
+        expect(err.toString()).to.include("SlippageExceeded");
+      }
+      expect(await debtOf(obligations[1], debt)).to.equal(debtBefore);
+    });
+  });
 });